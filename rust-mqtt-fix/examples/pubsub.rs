@@ -6,7 +6,6 @@ use std::{
 use embedded_io_adapters::tokio_1::FromTokio;
 use rust_mqtt::{
     client::{client::MqttClient, client_config::ClientConfig},
-    packet::v5::reason_codes::ReasonCode,
     utils::rng_generator::CountingRng,
 };
 use tokio::net::TcpStream;
@@ -17,10 +16,7 @@ async fn main() {
 
     let addr = SocketAddr::new(Ipv4Addr::new(127, 0, 0, 1).into(), 1883);
 
-    let connection = TcpStream::connect(addr)
-        .await
-        .map_err(|_| ReasonCode::NetworkError)
-        .unwrap();
+    let connection = TcpStream::connect(addr).await.unwrap();
     let connection = FromTokio::<TcpStream>::new(connection);
     let mut config = ClientConfig::new(
         rust_mqtt::client::client_config::MqttVersion::MQTTv5,
@@ -0,0 +1,78 @@
+#![no_main]
+
+//! Plays arbitrary Bytes as the broker side of the connection. The first Byte selects the
+//! size of chunks returned by the transport so partial reads are exercised as well.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::{Context, Poll, Waker};
+
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+use libfuzzer_sys::fuzz_target;
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::{ClientConfig, MqttVersion};
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+struct FuzzTransport<'a> {
+    incoming: &'a [u8],
+    chunk: usize,
+}
+
+impl ErrorType for FuzzTransport<'_> {
+    type Error = ErrorKind;
+}
+
+impl Read for FuzzTransport<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.chunk).min(self.incoming.len());
+        buf[..len].copy_from_slice(&self.incoming[..len]);
+        self.incoming = &self.incoming[len..];
+        Ok(len)
+    }
+}
+
+impl Write for FuzzTransport<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(buf.len())
+    }
+}
+
+/// Transport never returns `Pending`, so a single poll finishes the future.
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    match future
+        .as_mut()
+        .poll(&mut Context::from_waker(Waker::noop()))
+    {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("Fuzz transport never blocks"),
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((chunk, incoming)) = data.split_first() else {
+        return;
+    };
+    let mut write_buffer = [0; 128];
+    let mut recv_buffer = [0; 128];
+    let config = ClientConfig::<4, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        FuzzTransport {
+            incoming,
+            chunk: (*chunk as usize).max(1),
+        },
+        &mut write_buffer,
+        128,
+        &mut recv_buffer,
+        128,
+        config,
+    );
+
+    block_on(async {
+        if client.connect_to_broker().await.is_err() {
+            return;
+        }
+        while client.receive_message().await.is_ok() {}
+    });
+});
@@ -0,0 +1,49 @@
+#![no_main]
+
+//! Feeds arbitrary Bytes to every packet decoder. Decoders have to return an error
+//! for malformed input, never panic or loop forever.
+
+use libfuzzer_sys::fuzz_target;
+use rust_mqtt::packet::v5::auth_packet::AuthPacket;
+use rust_mqtt::packet::v5::connack_packet::ConnackPacket;
+use rust_mqtt::packet::v5::disconnect_packet::DisconnectPacket;
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::pingresp_packet::PingrespPacket;
+use rust_mqtt::packet::v5::property::Property;
+use rust_mqtt::packet::v5::puback_packet::PubackPacket;
+use rust_mqtt::packet::v5::pubcomp_packet::PubcompPacket;
+use rust_mqtt::packet::v5::publish_packet::PublishPacket;
+use rust_mqtt::packet::v5::pubrec_packet::PubrecPacket;
+use rust_mqtt::packet::v5::pubrel_packet::PubrelPacket;
+use rust_mqtt::packet::v5::suback_packet::SubackPacket;
+use rust_mqtt::packet::v5::unsuback_packet::UnsubackPacket;
+use rust_mqtt::utils::buffer_reader::BuffReader;
+
+const BUFFER_LEN: usize = 1024;
+
+fn decode<'a, P: Packet<'a>>(data: &'a [u8]) {
+    let mut packet = P::new();
+    if packet
+        .decode(&mut BuffReader::new(data, data.len()))
+        .is_ok()
+    {
+        // Successfully decoded packet has to be encodable again
+        let mut buffer = [0u8; BUFFER_LEN];
+        let _ = packet.encode(&mut buffer, BUFFER_LEN);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    decode::<ConnackPacket<4>>(data);
+    decode::<PublishPacket<4>>(data);
+    decode::<PubackPacket<4>>(data);
+    decode::<PubrecPacket<4>>(data);
+    decode::<PubrelPacket<4>>(data);
+    decode::<PubcompPacket<4>>(data);
+    decode::<SubackPacket<4, 4>>(data);
+    decode::<UnsubackPacket<4, 4>>(data);
+    decode::<PingrespPacket>(data);
+    decode::<DisconnectPacket<4>>(data);
+    decode::<AuthPacket<4>>(data);
+    let _ = Property::decode(&mut BuffReader::new(data, data.len()));
+});
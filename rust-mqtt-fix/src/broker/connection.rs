@@ -0,0 +1,508 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::future::poll_fn;
+
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+
+use crate::encoding::variable_byte_integer::VariableByteIntegerDecoder;
+use crate::packet::v5::connack_packet::ConnackPacket;
+use crate::packet::v5::connect_packet::ConnectPacket;
+use crate::packet::v5::disconnect_packet::DisconnectPacket;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::pingreq_packet::PingreqPacket;
+use crate::packet::v5::pingresp_packet::PingrespPacket;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::property_kind::{AuthenticationMethod, TopicAlias};
+use crate::packet::v5::puback_packet::PubackPacket;
+use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::packet::v5::suback_packet::SubackPacket;
+use crate::packet::v5::subscription_packet::SubscriptionPacket;
+use crate::packet::v5::unsuback_packet::UnsubackPacket;
+use crate::packet::v5::unsubscription_packet::UnsubscriptionPacket;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::select::{select, Either};
+use crate::utils::types::{BufferError, EncodedString};
+
+use super::state::Message;
+use super::{Broker, BrokerError};
+use crate::utils::topic::valid_topic_name;
+
+/// Maximal number of properties decoded from the client packet, further properties are ignored.
+const MAX_PROPERTIES: usize = 8;
+/// Maximal number of topic filters in one `SUBSCRIBE` or `UNSUBSCRIBE` packet.
+pub const MAX_FILTERS: usize = 8;
+
+/// Collects incoming bytes until there is a whole packet at the start of the buffer. State is
+/// kept outside of the read future, so the future can be dropped between reads.
+struct PacketReader<'b> {
+    buffer: &'b mut [u8],
+    filled: usize,
+}
+
+impl<'b> PacketReader<'b> {
+    fn new(buffer: &'b mut [u8]) -> Self {
+        Self { buffer, filled: 0 }
+    }
+
+    /// Returns length of the first packet if it is complete.
+    fn complete(&self) -> Result<Option<usize>, ReasonCode> {
+        let mut rem_len: [u8; 4] = [0; 4];
+        let mut len_len = 0;
+        loop {
+            if len_len == 4 {
+                return Err(ReasonCode::MalformedPacket);
+            }
+            if 1 + len_len >= self.filled {
+                return Ok(None);
+            }
+            rem_len[len_len] = self.buffer[1 + len_len];
+            len_len += 1;
+            if rem_len[len_len - 1] & 0x80 == 0 {
+                break;
+            }
+        }
+        let rem_len =
+            VariableByteIntegerDecoder::decode(rem_len).map_err(|_| ReasonCode::MalformedPacket)?;
+        let total = 1 + len_len + rem_len as usize;
+        if total > self.buffer.len() {
+            warn!("Packet of {} bytes does not fit into receive buffer", total);
+            return Err(ReasonCode::PacketTooLarge);
+        }
+        Ok(if total <= self.filled {
+            Some(total)
+        } else {
+            None
+        })
+    }
+
+    async fn next_packet<T: Read>(&mut self, io: &mut T) -> Result<usize, BrokerError<T::Error>> {
+        loop {
+            if let Some(len) = self.complete().map_err(BrokerError::Protocol)? {
+                return Ok(len);
+            }
+            let read = io
+                .read(&mut self.buffer[self.filled..])
+                .await
+                .map_err(BrokerError::Transport)?;
+            if read == 0 {
+                return Err(BrokerError::ConnectionClosed);
+            }
+            self.filled += read;
+        }
+    }
+
+    fn packet(&self, len: usize) -> &[u8] {
+        &self.buffer[..len]
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.buffer.copy_within(len..self.filled, 0);
+        self.filled -= len;
+    }
+}
+
+struct PacketWriter<'b, T> {
+    io: T,
+    buffer: &'b mut [u8],
+    packet_identifier: u16,
+}
+
+impl<T: Write> PacketWriter<'_, T> {
+    async fn send<'p, P: Packet<'p>>(
+        &mut self,
+        packet: &mut P,
+    ) -> Result<(), BrokerError<T::Error>> {
+        let len = packet
+            .encode(self.buffer, self.buffer.len())
+            .map_err(BrokerError::Encode)?;
+        self.write(len).await
+    }
+
+    async fn write(&mut self, len: usize) -> Result<(), BrokerError<T::Error>> {
+        let mut written = 0;
+        while written < len {
+            let n = self
+                .io
+                .write(&self.buffer[written..len])
+                .await
+                .map_err(BrokerError::Transport)?;
+            if n == 0 {
+                return Err(BrokerError::ConnectionClosed);
+            }
+            written += n;
+        }
+        Ok(())
+    }
+
+    async fn send_message(&mut self, message: &Message) -> Result<(), BrokerError<T::Error>> {
+        let mut publish = PublishPacket::<'_, 0>::new();
+        publish.add_topic_name(&message.topic);
+        publish.add_message(&message.payload);
+        publish.add_retain(message.retain);
+        if message.qos > 0 {
+            self.packet_identifier = self.packet_identifier.wrapping_add(1).max(1);
+            publish.add_qos(QualityOfService::QoS1);
+            publish.add_identifier(self.packet_identifier);
+        }
+        self.send(&mut publish).await
+    }
+
+    async fn send_disconnect(&mut self, reason: ReasonCode) -> Result<(), BrokerError<T::Error>> {
+        let mut disconnect = DisconnectPacket::<'_, 0>::new();
+        disconnect.disconnect_reason = reason.into();
+        self.send(&mut disconnect).await
+    }
+
+    async fn send_connack(
+        &mut self,
+        reason: ReasonCode,
+        properties: &[Property<'_>],
+    ) -> Result<(), BrokerError<T::Error>> {
+        let mut connack = ConnackPacket::<'_, 6>::new();
+        connack.connect_reason_code = reason.into();
+        for property in properties {
+            connack.property_len += property.encoded_len() as u32 + 1;
+            connack.push_to_properties(property.clone());
+        }
+        self.send(&mut connack).await
+    }
+
+    /// Refuses the connection with `CONNACK` carrying `reason`.
+    async fn refuse(&mut self, reason: ReasonCode) -> BrokerError<T::Error> {
+        match self.send_connack(reason, &[]).await {
+            Ok(()) => BrokerError::Refused(reason),
+            Err(err) => err,
+        }
+    }
+}
+
+/// Removes the session when the connection ends. Will is published unless the client
+/// disconnected properly.
+struct SessionGuard<
+    'b,
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+> {
+    broker: &'b Broker<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>,
+    session: u32,
+    publish_will: bool,
+}
+
+impl<
+        const CLIENTS: usize,
+        const SUBSCRIPTIONS: usize,
+        const RETAINED: usize,
+        const QUEUE: usize,
+    > Drop for SessionGuard<'_, CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>
+{
+    fn drop(&mut self) {
+        let (session, publish_will) = (self.session, self.publish_will);
+        self.broker
+            .with_state(|state| state.disconnect(session, publish_will));
+    }
+}
+
+fn decode_reason(err: BufferError) -> ReasonCode {
+    match err {
+        BufferError::InvalidProperty(_) => ReasonCode::ProtocolError,
+        _ => ReasonCode::MalformedPacket,
+    }
+}
+
+fn decode_error<E>(err: BufferError) -> BrokerError<E> {
+    BrokerError::Protocol(decode_reason(err))
+}
+
+pub(crate) async fn serve<
+    T: Read + Write,
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+>(
+    broker: &Broker<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>,
+    network: T,
+    buffer: &mut [u8],
+    recv_buffer: &mut [u8],
+) -> Result<(), BrokerError<T::Error>> {
+    let max_packet_size = recv_buffer.len() as u32;
+    let mut reader = PacketReader::new(recv_buffer);
+    let mut writer = PacketWriter {
+        io: network,
+        buffer,
+        packet_identifier: 0,
+    };
+
+    let len = reader.next_packet(&mut writer.io).await?;
+    let mut guard = accept(broker, reader.packet(len), &mut writer, max_packet_size).await?;
+    reader.consume(len);
+
+    let res = run(&mut guard, &mut reader, &mut writer).await;
+    if let Err(BrokerError::Protocol(reason)) = res {
+        warn!("Closing connection, protocol violation: {}", reason);
+        // Connection is closed anyway, failure of the DISCONNECT is not interesting
+        let _ = writer.send_disconnect(reason).await;
+    }
+    res
+}
+
+async fn accept<
+    'b,
+    T: Read + Write,
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+>(
+    broker: &'b Broker<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>,
+    packet: &[u8],
+    writer: &mut PacketWriter<'_, T>,
+    max_packet_size: u32,
+) -> Result<SessionGuard<'b, CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>, BrokerError<T::Error>> {
+    let mut connect = ConnectPacket::<'_, MAX_PROPERTIES, MAX_PROPERTIES>::new();
+    if let Err(err) = connect.decode(&mut BuffReader::new(packet, packet.len())) {
+        if err == BufferError::PacketTypeMismatch {
+            return Err(BrokerError::Protocol(ReasonCode::ProtocolError));
+        }
+        if connect.protocol_version < 5 {
+            // Older clients understand only MQTT 3.1.1 CONNACK - unacceptable protocol version
+            writer.buffer[..4].copy_from_slice(&[0x20, 0x02, 0x00, 0x01]);
+            writer.write(4).await?;
+            return Err(BrokerError::Refused(ReasonCode::UnsupportedProtocolVersion));
+        }
+        return Err(writer.refuse(decode_reason(err)).await);
+    }
+    if connect.get::<AuthenticationMethod>().is_some() {
+        return Err(writer.refuse(ReasonCode::BadAuthMethod).await);
+    }
+
+    let will = if connect.connect_flags & 0x04 != 0 {
+        let qos = match connect.will_qos() {
+            QualityOfService::QoS0 => 0,
+            QualityOfService::QoS1 => 1,
+            _ => return Err(writer.refuse(ReasonCode::QoSNotSupported).await),
+        };
+        if !valid_topic_name(connect.will_topic.string) {
+            return Err(writer.refuse(ReasonCode::TopicNameInvalid).await);
+        }
+        match Message::new(
+            connect.will_topic.string,
+            connect.will_payload.bin,
+            qos,
+            connect.will_retain(),
+        ) {
+            Some(will) => Some(will),
+            None => return Err(writer.refuse(ReasonCode::PacketTooLarge).await),
+        }
+    } else {
+        None
+    };
+
+    let client_id = connect.client_id.string;
+    let session = match broker.with_state(|state| state.connect(client_id, will)) {
+        Ok(session) => session,
+        Err(reason) => return Err(writer.refuse(reason).await),
+    };
+    let guard = SessionGuard {
+        broker,
+        session,
+        publish_will: true,
+    };
+    info!("Client {} connected", client_id);
+
+    let assigned = broker
+        .with_state(|state| state.client_id(session))
+        .unwrap_or_default();
+    let mut properties = Vec::<Property<'_>, 5>::new();
+    // Pushes can not fail, vector is large enough for all properties
+    let _ = properties.push(Property::MaximumQoS(1));
+    let _ = properties.push(Property::SharedSubscriptionAvailable(0));
+    let _ = properties.push(Property::SubscriptionIdentifierAvailable(0));
+    let _ = properties.push(Property::MaximumPacketSize(max_packet_size));
+    if client_id.is_empty() {
+        let _ = properties.push(Property::AssignedClientIdentifier(EncodedString {
+            string: assigned.as_str(),
+            len: assigned.len() as u16,
+        }));
+    }
+    writer
+        .send_connack(ReasonCode::Success, &properties)
+        .await?;
+    Ok(guard)
+}
+
+async fn run<
+    T: Read + Write,
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+>(
+    guard: &mut SessionGuard<'_, CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>,
+    reader: &mut PacketReader<'_>,
+    writer: &mut PacketWriter<'_, T>,
+) -> Result<(), BrokerError<T::Error>> {
+    let broker = guard.broker;
+    let session = guard.session;
+    loop {
+        let event = select(
+            reader.next_packet(&mut writer.io),
+            poll_fn(|cx| broker.with_state(|state| state.poll_message(session, cx.waker()))),
+        )
+        .await;
+        match event {
+            Either::First(len) => {
+                let len = len?;
+                let done = handle(guard, reader.packet(len), writer).await?;
+                reader.consume(len);
+                if done {
+                    return Ok(());
+                }
+            }
+            Either::Second(Some(message)) => writer.send_message(&message).await?,
+            Either::Second(None) => {
+                info!("Session taken over, closing connection");
+                writer.send_disconnect(ReasonCode::SessionTakeOver).await?;
+                return Err(BrokerError::SessionTakenOver);
+            }
+        }
+    }
+}
+
+/// Handles one packet from the client. Returns `true` if the client disconnected.
+async fn handle<
+    T: Read + Write,
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+>(
+    guard: &mut SessionGuard<'_, CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>,
+    packet: &[u8],
+    writer: &mut PacketWriter<'_, T>,
+) -> Result<bool, BrokerError<T::Error>> {
+    let broker = guard.broker;
+    let session = guard.session;
+    let mut buff_reader = BuffReader::new(packet, packet.len());
+    match PacketType::from(packet[0]) {
+        PacketType::Publish => {
+            let mut publish = PublishPacket::<'_, MAX_PROPERTIES>::new();
+            publish.decode(&mut buff_reader).map_err(decode_error)?;
+            let qos = match publish.fixed_header & 0x06 {
+                0x00 => 0,
+                0x02 => 1,
+                0x04 => return Err(BrokerError::Protocol(ReasonCode::QoSNotSupported)),
+                _ => return Err(BrokerError::Protocol(ReasonCode::MalformedPacket)),
+            };
+            if publish.get::<TopicAlias>().is_some() {
+                return Err(BrokerError::Protocol(ReasonCode::TopicAliasInvalid));
+            }
+            let topic = publish.topic_name.string;
+            if !valid_topic_name(topic) {
+                return Err(BrokerError::Protocol(ReasonCode::TopicNameInvalid));
+            }
+            let retain = publish.fixed_header & 0x01 != 0;
+            let reason = match Message::new(topic, publish.message.unwrap_or(&[]), qos, retain) {
+                Some(message) => {
+                    match broker.with_state(|state| state.publish(Some(session), &message)) {
+                        0 => ReasonCode::NoMatchingSubscribers,
+                        _ => ReasonCode::Success,
+                    }
+                }
+                None => {
+                    warn!("Message for topic {} is too large, dropped", topic);
+                    ReasonCode::QuotaExceeded
+                }
+            };
+            if qos == 1 {
+                let mut puback = PubackPacket::<'_, 0>::new();
+                puback.packet_identifier = publish.packet_identifier;
+                puback.reason_code = reason.into();
+                writer.send(&mut puback).await?;
+            }
+        }
+        PacketType::Subscribe => {
+            let mut subscribe = SubscriptionPacket::<'_, MAX_FILTERS, MAX_PROPERTIES>::new();
+            subscribe.decode(&mut buff_reader).map_err(decode_error)?;
+            let mut suback = SubackPacket::<'_, MAX_FILTERS, 0>::new();
+            suback.packet_identifier = subscribe.packet_identifier;
+            for filter in subscribe.topic_filters.iter() {
+                // Reserved bits have to be zero and QoS 3 does not exist
+                if filter.sub_options & 0xC0 != 0 || filter.sub_options & 0x03 == 0x03 {
+                    return Err(BrokerError::Protocol(ReasonCode::MalformedPacket));
+                }
+                let reason = broker.with_state(|state| {
+                    state.subscribe(session, filter.filter.string, filter.sub_options)
+                });
+                let _ = suback.reason_codes.push(reason);
+            }
+            writer.send(&mut suback).await?;
+        }
+        PacketType::Unsubscribe => {
+            let mut unsubscribe = UnsubscriptionPacket::<'_, MAX_FILTERS, MAX_PROPERTIES>::new();
+            unsubscribe.decode(&mut buff_reader).map_err(decode_error)?;
+            let mut unsuback = UnsubackPacket::<'_, MAX_FILTERS, 0>::new();
+            unsuback.packet_identifier = unsubscribe.packet_identifier;
+            for filter in unsubscribe.topic_filters.iter() {
+                let reason =
+                    broker.with_state(|state| state.unsubscribe(session, filter.filter.string));
+                let _ = unsuback.reason_codes.push(reason);
+            }
+            writer.send(&mut unsuback).await?;
+        }
+        PacketType::Pingreq => {
+            let mut pingreq = PingreqPacket::new();
+            pingreq.decode(&mut buff_reader).map_err(decode_error)?;
+            let mut pingresp = PingrespPacket::new();
+            writer.send(&mut pingresp).await?;
+        }
+        PacketType::Puback => {
+            // Messages are not redelivered, acknowledgement only has to be well formed
+            let mut puback = PubackPacket::<'_, MAX_PROPERTIES>::new();
+            puback.decode(&mut buff_reader).map_err(decode_error)?;
+        }
+        PacketType::Disconnect => {
+            let mut disconnect = DisconnectPacket::<'_, MAX_PROPERTIES>::new();
+            disconnect.decode(&mut buff_reader).map_err(decode_error)?;
+            guard.publish_will =
+                disconnect.disconnect_reason == u8::from(ReasonCode::DisconnectWithWillMessage);
+            debug!(
+                "Client disconnected with reason {}",
+                disconnect.disconnect_reason
+            );
+            return Ok(true);
+        }
+        packet_type => {
+            warn!("Unexpected {:?} packet from client", packet_type);
+            return Err(BrokerError::Protocol(ReasonCode::ProtocolError));
+        }
+    }
+    Ok(false)
+}
@@ -0,0 +1,177 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Minimal MQTTv5 broker with fixed capacity, intended as a local hub for a handful of
+//! devices - for example ESP32 in access point mode collecting data from sensor nodes
+//! without internet connection. Available with the `broker` feature.
+//!
+//! The broker supports QoS 0 and 1, retained messages, will messages and `+` / `#`
+//! wildcard subscriptions. Sessions live only as long as the network connection, messages
+//! are not redelivered, QoS 2, shared subscriptions, topic aliases and enhanced
+//! authentication are not supported and credentials are not checked.
+//!
+//! Each accepted connection is served by its own task calling [`Broker::serve`] with shared
+//! reference to the broker. Reads of the network driver are restarted whenever message for
+//! the client is queued, so the driver read has to be cancel safe (embassy-net `TcpSocket`
+//! and tokio sockets are). Keep alive is not enforced by the broker, it is up to the caller
+//! to drop the `serve` future after inactivity - will of the client is published then.
+
+pub mod connection;
+pub mod state;
+
+use core::cell::RefCell;
+use core::fmt::{Debug, Display, Formatter};
+
+use critical_section::Mutex;
+use embedded_io_async::{Read, Write};
+
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::types::BufferError;
+
+use self::state::{BrokerState, Message};
+use crate::utils::topic::valid_topic_name;
+
+pub use self::connection::MAX_FILTERS;
+pub use self::state::{MAX_CLIENT_ID_LEN, MAX_PAYLOAD_LEN, MAX_TOPIC_LEN};
+
+/// Error ending the client connection served by the broker. Generic `E` is the error type
+/// of the network driver.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BrokerError<E> {
+    /// Network driver reported an error.
+    Transport(E),
+    /// Outgoing packet could not be encoded into the send buffer.
+    Encode(BufferError),
+    /// Client violated the MQTT protocol, connection was closed with the reason code.
+    Protocol(ReasonCode),
+    /// Connection was refused in `CONNACK` with the reason code.
+    Refused(ReasonCode),
+    /// Another connection with the same client identifier took over the session.
+    SessionTakenOver,
+    /// Connection was closed by the client without `DISCONNECT` packet.
+    ConnectionClosed,
+}
+
+impl<E: Debug> Display for BrokerError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            BrokerError::Transport(err) => write!(f, "Network error: {:?}!", err),
+            BrokerError::Encode(err) => write!(f, "Packet encoding failed: {}", err),
+            BrokerError::Protocol(reason) => {
+                write!(f, "Client violated protocol: {}", reason)
+            }
+            BrokerError::Refused(reason) => write!(f, "Connection refused: {}", reason),
+            BrokerError::SessionTakenOver => write!(f, "Session was taken over!"),
+            BrokerError::ConnectionClosed => write!(f, "Connection closed by client!"),
+        }
+    }
+}
+
+/// Broker serving up to `CLIENTS` connected clients with `SUBSCRIPTIONS` subscriptions in
+/// total, `RETAINED` retained messages and queue of `QUEUE` messages for every client.
+/// Messages over full queue are dropped. All storage is allocated inline, so the broker can
+/// be placed into `static`.
+pub struct Broker<
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+> {
+    state: Mutex<RefCell<BrokerState<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>>>,
+}
+
+impl<
+        const CLIENTS: usize,
+        const SUBSCRIPTIONS: usize,
+        const RETAINED: usize,
+        const QUEUE: usize,
+    > Broker<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>
+{
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(RefCell::new(BrokerState::new())),
+        }
+    }
+
+    /// Serves one client connection until the client disconnects or the connection fails.
+    /// `buffer` is used for outgoing packets, `recv_buffer` for incoming packets - its length
+    /// is announced to the client as maximum packet size. Returns `Ok` after `DISCONNECT`
+    /// from the client.
+    pub async fn serve<T: Read + Write>(
+        &self,
+        network: T,
+        buffer: &mut [u8],
+        recv_buffer: &mut [u8],
+    ) -> Result<(), BrokerError<T::Error>> {
+        connection::serve(self, network, buffer, recv_buffer).await
+    }
+
+    /// Publishes message from the broker itself to subscribed clients. Returns number of
+    /// clients the message was queued for.
+    pub fn publish(
+        &self,
+        topic: &str,
+        payload: &[u8],
+        qos: QualityOfService,
+        retain: bool,
+    ) -> Result<usize, ReasonCode> {
+        let qos = match qos {
+            QualityOfService::QoS0 => 0,
+            QualityOfService::QoS1 => 1,
+            _ => return Err(ReasonCode::QoSNotSupported),
+        };
+        if !valid_topic_name(topic) {
+            return Err(ReasonCode::TopicNameInvalid);
+        }
+        let message =
+            Message::new(topic, payload, qos, retain).ok_or(ReasonCode::PacketTooLarge)?;
+        Ok(self.with_state(|state| state.publish(None, &message)))
+    }
+
+    /// Number of currently connected clients.
+    pub fn connected_clients(&self) -> usize {
+        self.with_state(|state| state.session_count())
+    }
+
+    pub(crate) fn with_state<R>(
+        &self,
+        f: impl FnOnce(&mut BrokerState<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>) -> R,
+    ) -> R {
+        critical_section::with(|cs| f(&mut self.state.borrow_ref_mut(cs)))
+    }
+}
+
+impl<
+        const CLIENTS: usize,
+        const SUBSCRIPTIONS: usize,
+        const RETAINED: usize,
+        const QUEUE: usize,
+    > Default for Broker<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
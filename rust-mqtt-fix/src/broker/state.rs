@@ -0,0 +1,333 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::Write;
+use core::task::{Poll, Waker};
+
+use heapless::{Deque, String, Vec};
+
+use crate::packet::v5::reason_codes::ReasonCode;
+
+use crate::utils::topic::{topic_matches, valid_topic_filter};
+
+/// Maximal length of the client identifier accepted by the broker.
+pub const MAX_CLIENT_ID_LEN: usize = 23;
+/// Maximal length of topic names and topic filters kept by the broker.
+pub const MAX_TOPIC_LEN: usize = 64;
+/// Maximal payload length of queued, retained and will messages.
+pub const MAX_PAYLOAD_LEN: usize = 256;
+
+/// Application message stored by the broker - queued for the client, retained or will.
+/// QoS is stored as level (0 or 1).
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub topic: String<MAX_TOPIC_LEN>,
+    pub payload: Vec<u8, MAX_PAYLOAD_LEN>,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+impl Message {
+    /// Returns `None` if topic or payload do not fit into the message.
+    pub fn new(topic: &str, payload: &[u8], qos: u8, retain: bool) -> Option<Self> {
+        let mut msg_topic = String::new();
+        msg_topic.push_str(topic).ok()?;
+        Some(Self {
+            topic: msg_topic,
+            payload: Vec::from_slice(payload).ok()?,
+            qos,
+            retain,
+        })
+    }
+
+    fn copy_with(&self, qos: u8, retain: bool) -> Self {
+        Self {
+            topic: self.topic.clone(),
+            payload: self.payload.clone(),
+            qos,
+            retain,
+        }
+    }
+}
+
+struct Subscription {
+    session: u32,
+    filter: String<MAX_TOPIC_LEN>,
+    qos: u8,
+    no_local: bool,
+    retain_as_published: bool,
+}
+
+struct Session<const QUEUE: usize> {
+    id: u32,
+    client_id: String<MAX_CLIENT_ID_LEN>,
+    will: Option<Message>,
+    queue: Deque<Message, QUEUE>,
+    waker: Option<Waker>,
+}
+
+impl<const QUEUE: usize> Session<QUEUE> {
+    fn enqueue(&mut self, message: Message) {
+        if self.queue.push_back(message).is_err() {
+            warn!(
+                "Queue of client {} is full, message dropped",
+                self.client_id.as_str()
+            );
+            return;
+        }
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Shared state of the broker - connected clients, their subscriptions and queued messages
+/// and retained messages. Sessions live only as long as the network connection.
+pub(crate) struct BrokerState<
+    const CLIENTS: usize,
+    const SUBSCRIPTIONS: usize,
+    const RETAINED: usize,
+    const QUEUE: usize,
+> {
+    next_id: u32,
+    sessions: Vec<Session<QUEUE>, CLIENTS>,
+    subscriptions: Vec<Subscription, SUBSCRIPTIONS>,
+    retained: Vec<Message, RETAINED>,
+}
+
+impl<
+        const CLIENTS: usize,
+        const SUBSCRIPTIONS: usize,
+        const RETAINED: usize,
+        const QUEUE: usize,
+    > BrokerState<CLIENTS, SUBSCRIPTIONS, RETAINED, QUEUE>
+{
+    pub const fn new() -> Self {
+        Self {
+            next_id: 1,
+            sessions: Vec::new(),
+            subscriptions: Vec::new(),
+            retained: Vec::new(),
+        }
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Registers new session and returns its identifier. Existing session with the same
+    /// client identifier is taken over - its will is published and its connection is woken
+    /// up so it can send `DISCONNECT` to the client.
+    pub fn connect(&mut self, client_id: &str, will: Option<Message>) -> Result<u32, ReasonCode> {
+        let session_id = self.next_id;
+        let mut id = String::new();
+        if client_id.is_empty() {
+            // Client without identifier gets one assigned by the broker
+            let _ = write!(id, "auto-{}", session_id);
+        } else if id.push_str(client_id).is_err() {
+            return Err(ReasonCode::ClientIdNotValid);
+        }
+        if let Some(old) = self
+            .sessions
+            .iter()
+            .find(|session| session.client_id == id)
+            .map(|session| session.id)
+        {
+            info!("Client {} taken over by new connection", client_id);
+            self.disconnect(old, true);
+        }
+        let session = Session {
+            id: session_id,
+            client_id: id,
+            will,
+            queue: Deque::new(),
+            waker: None,
+        };
+        if self.sessions.push(session).is_err() {
+            warn!("Client {} refused, broker is full", client_id);
+            return Err(ReasonCode::ServerBusy);
+        }
+        self.next_id = self.next_id.wrapping_add(1).max(1);
+        Ok(session_id)
+    }
+
+    /// Removes session together with its subscriptions, will is published if `publish_will`
+    /// is set. Removing already removed session does nothing.
+    pub fn disconnect(&mut self, session: u32, publish_will: bool) {
+        let Some(pos) = self.sessions.iter().position(|s| s.id == session) else {
+            return;
+        };
+        let mut removed = self.sessions.swap_remove(pos);
+        self.subscriptions.retain(|sub| sub.session != session);
+        if let Some(waker) = removed.waker.take() {
+            waker.wake();
+        }
+        if let Some(will) = removed.will.take() {
+            if publish_will {
+                debug!("Publishing will of client {}", removed.client_id.as_str());
+                self.publish(None, &will);
+            }
+        }
+    }
+
+    pub fn is_connected(&self, session: u32) -> bool {
+        self.sessions.iter().any(|s| s.id == session)
+    }
+
+    pub fn client_id(&self, session: u32) -> Option<String<MAX_CLIENT_ID_LEN>> {
+        self.sessions
+            .iter()
+            .find(|s| s.id == session)
+            .map(|s| s.client_id.clone())
+    }
+
+    /// Adds or replaces subscription of the session and queues matching retained messages.
+    /// Returns reason code for SUBACK.
+    pub fn subscribe(&mut self, session: u32, filter: &str, options: u8) -> u8 {
+        if filter.starts_with("$share/") {
+            return ReasonCode::SharedSubscriptionNotSupported.into();
+        }
+        if !valid_topic_filter(filter) {
+            return ReasonCode::TopicFilterInvalid.into();
+        }
+        let mut sub_filter = String::new();
+        if sub_filter.push_str(filter).is_err() {
+            return ReasonCode::TopicFilterInvalid.into();
+        }
+        // QoS 2 is downgraded to QoS 1
+        let qos = (options & 0x03).min(1);
+        let subscription = Subscription {
+            session,
+            filter: sub_filter,
+            qos,
+            no_local: options & 0x04 != 0,
+            retain_as_published: options & 0x08 != 0,
+        };
+        let existing = self
+            .subscriptions
+            .iter_mut()
+            .find(|sub| sub.session == session && sub.filter == subscription.filter);
+        let is_new = existing.is_none();
+        match existing {
+            Some(sub) => *sub = subscription,
+            None => {
+                if self.subscriptions.push(subscription).is_err() {
+                    warn!("Subscription table is full");
+                    return ReasonCode::QuotaExceeded.into();
+                }
+            }
+        }
+
+        let retain_handling = (options >> 4) & 0x03;
+        if retain_handling == 0 || (retain_handling == 1 && is_new) {
+            if let Some(target) = self.sessions.iter_mut().find(|s| s.id == session) {
+                for retained in self.retained.iter() {
+                    if topic_matches(filter, &retained.topic) {
+                        target.enqueue(retained.copy_with(retained.qos.min(qos), true));
+                    }
+                }
+            }
+        }
+        qos
+    }
+
+    /// Removes subscription of the session, returns reason code for UNSUBACK.
+    pub fn unsubscribe(&mut self, session: u32, filter: &str) -> u8 {
+        match self
+            .subscriptions
+            .iter()
+            .position(|sub| sub.session == session && sub.filter == filter)
+        {
+            Some(pos) => {
+                self.subscriptions.swap_remove(pos);
+                ReasonCode::Success.into()
+            }
+            None => ReasonCode::NoSubscriptionExisted.into(),
+        }
+    }
+
+    /// Stores retained message and queues message for every session with matching
+    /// subscription. `from` is the publishing session, used for the no local option.
+    /// Returns number of sessions the message was queued for.
+    pub fn publish(&mut self, from: Option<u32>, message: &Message) -> usize {
+        if message.retain {
+            self.retain(message);
+        }
+
+        let mut matched = 0;
+        for session in self.sessions.iter_mut() {
+            let mut granted: Option<u8> = None;
+            let mut retain = false;
+            for sub in self.subscriptions.iter() {
+                if sub.session != session.id
+                    || (sub.no_local && from == Some(session.id))
+                    || !topic_matches(&sub.filter, &message.topic)
+                {
+                    continue;
+                }
+                granted = Some(granted.map_or(sub.qos, |qos| qos.max(sub.qos)));
+                retain |= sub.retain_as_published && message.retain;
+            }
+            if let Some(qos) = granted {
+                session.enqueue(message.copy_with(message.qos.min(qos), retain));
+                matched += 1;
+            }
+        }
+        matched
+    }
+
+    fn retain(&mut self, message: &Message) {
+        let existing = self
+            .retained
+            .iter()
+            .position(|retained| retained.topic == message.topic);
+        match (existing, message.payload.is_empty()) {
+            (Some(pos), true) => {
+                self.retained.swap_remove(pos);
+            }
+            (Some(pos), false) => self.retained[pos] = message.clone(),
+            (None, true) => {}
+            (None, false) => {
+                if self.retained.push(message.clone()).is_err() {
+                    warn!("Retained message store is full, message not retained");
+                }
+            }
+        }
+    }
+
+    /// Takes next queued message of the session. Registers `waker` if the queue is empty,
+    /// returns `Ready(None)` if the session does not exist anymore.
+    pub fn poll_message(&mut self, session: u32, waker: &Waker) -> Poll<Option<Message>> {
+        let Some(session) = self.sessions.iter_mut().find(|s| s.id == session) else {
+            return Poll::Ready(None);
+        };
+        match session.queue.pop_front() {
+            Some(message) => Poll::Ready(Some(message)),
+            None => {
+                session.waker = Some(waker.clone());
+                Poll::Pending
+            }
+        }
+    }
+}
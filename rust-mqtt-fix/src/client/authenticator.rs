@@ -0,0 +1,77 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Display, Formatter};
+
+/// Error reported by the `Authenticator` during the enhanced authentication exchange.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AuthError {
+    /// Authentication data sent by the broker could not be parsed.
+    MalformedChallenge,
+    /// Broker could not prove that it knows the credentials.
+    ServerVerificationFailed,
+    /// Broker used different authentication method than the client.
+    MethodMismatch,
+    /// Authentication data does not fit into the authenticator buffers.
+    BufferTooSmall,
+    /// Step of the exchange came in unexpected order.
+    UnexpectedStep,
+    /// Client has no authenticator set.
+    NotConfigured,
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            AuthError::MalformedChallenge => {
+                write!(f, "Authentication data from broker is malformed!")
+            }
+            AuthError::ServerVerificationFailed => write!(f, "Broker verification failed!"),
+            AuthError::MethodMismatch => write!(f, "Broker used different authentication method!"),
+            AuthError::BufferTooSmall => write!(f, "Authentication data does not fit into buffer!"),
+            AuthError::UnexpectedStep => write!(f, "Unexpected step of authentication exchange!"),
+            AuthError::NotConfigured => write!(f, "Authenticator is not configured!"),
+        }
+    }
+}
+
+/// Authenticator drives the MQTTv5 enhanced authentication. Client sends the
+/// `AuthenticationMethod` and the data from `start` in CONNECT (or in AUTH with
+/// `ReAuthenticate` reason code during the session). Every AUTH packet with
+/// `ContinueAuth` reason code from the broker is passed to `challenge` and answered
+/// with the new `response`. Data of the CONNACK or AUTH packet which finishes
+/// the exchange are passed to `finish`.
+pub trait Authenticator {
+    /// Name of the authentication method, e.g. `SCRAM-SHA-256`.
+    fn method(&self) -> &str;
+    /// Starts new exchange, the initial data are available in `response`.
+    fn start(&mut self) -> Result<(), AuthError>;
+    /// Processes the broker challenge, the answer is available in `response`.
+    fn challenge(&mut self, data: &[u8]) -> Result<(), AuthError>;
+    /// Verifies the data sent by the broker with the successful result.
+    fn finish(&mut self, data: &[u8]) -> Result<(), AuthError>;
+    /// Authentication data which should be sent to the broker in the next packet.
+    fn response(&self) -> &[u8];
+}
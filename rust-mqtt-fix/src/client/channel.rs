@@ -0,0 +1,117 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Forwarding of received messages into an embassy-sync `Channel`, so the messages can be
+//! processed by other tasks while the client task keeps polling the broker.
+
+use core::fmt::{Display, Formatter};
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::{Channel, TrySendError};
+use embedded_io_async::{Read, Write};
+use rand_core::RngCore;
+
+use crate::queue::OverflowPolicy;
+
+use super::client::MqttClient;
+use super::client_error::ClientError;
+use super::owned_message::{OwnedMessage, OwnedMessageError};
+use super::raw_client::ReceivedMessage;
+
+/// Reason why the message was not forwarded into the channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ForwardError {
+    /// Message does not fit the `OwnedMessage` capacity.
+    Message(OwnedMessageError),
+    /// Channel is full and the policy is `OverflowPolicy::RejectNewest`.
+    Full,
+}
+
+impl Display for ForwardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ForwardError::Message(err) => write!(f, "Could not copy message: {}", err),
+            ForwardError::Full => write!(f, "Channel is full!"),
+        }
+    }
+}
+
+/// Copies the `message` into `OwnedMessage` and sends it into the `channel` without waiting.
+/// When the channel is full, `OverflowPolicy::DropOldest` removes the oldest messages from
+/// the channel and `OverflowPolicy::RejectNewest` drops the `message`.
+pub fn forward_message<M: RawMutex, const TOPIC: usize, const PAYLOAD: usize, const N: usize>(
+    channel: &Channel<M, OwnedMessage<TOPIC, PAYLOAD>, N>,
+    message: &ReceivedMessage<'_>,
+    policy: OverflowPolicy,
+) -> Result<(), ForwardError> {
+    let mut owned = OwnedMessage::try_from(message).map_err(ForwardError::Message)?;
+    loop {
+        match channel.try_send(owned) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(rejected)) => match policy {
+                OverflowPolicy::RejectNewest => return Err(ForwardError::Full),
+                OverflowPolicy::DropOldest => {
+                    let _ = channel.try_receive();
+                    owned = rejected;
+                }
+            },
+        }
+    }
+}
+
+/// Receives messages with the `client` and forwards them into the `channel` with
+/// `forward_message`. Messages which could not be forwarded are dropped and logged.
+/// The client has to be connected and subscribed. Function runs until the client
+/// fails and returns the error, e.g. to reconnect.
+pub async fn forward_messages<
+    'a,
+    T,
+    const MAX_PROPERTIES: usize,
+    R,
+    M,
+    const TOPIC: usize,
+    const PAYLOAD: usize,
+    const N: usize,
+>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    channel: &Channel<M, OwnedMessage<TOPIC, PAYLOAD>, N>,
+    policy: OverflowPolicy,
+) -> ClientError<T::Error>
+where
+    T: Read + Write,
+    R: RngCore,
+    M: RawMutex,
+{
+    loop {
+        match client.receive_message_with_properties().await {
+            Ok(message) => {
+                if let Err(err) = forward_message(channel, &message, policy) {
+                    warn!("Dropping message: {}", err);
+                }
+            }
+            Err(err) => return err,
+        }
+    }
+}
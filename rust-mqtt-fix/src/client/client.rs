@@ -27,16 +27,36 @@ use heapless::Vec;
 use rand_core::RngCore;
 
 use crate::client::client_config::ClientConfig;
+#[cfg(feature = "codec")]
+use crate::codec::Codec;
+#[cfg(feature = "compression")]
+use crate::compression::{self, ContentEncoding, CONTENT_ENCODING};
+#[cfg(feature = "encryption")]
+use crate::encryption::PayloadProtection;
+use crate::packet::v5::property::Property;
 use crate::packet::v5::publish_packet::QualityOfService::{self, QoS1};
 use crate::packet::v5::reason_codes::ReasonCode;
 
-use super::raw_client::{Event, RawMqttClient};
+use super::authenticator::Authenticator;
+use super::credentials::CredentialsProvider;
+use super::duplicate_filter::{DuplicateFilter, DuplicateMode};
+use super::packet_observer::PacketObserver;
+use super::stats::ClientStats;
+use crate::utils::clock::Clock;
+
+use super::client_error::{ClientError, ProtocolViolation};
+use super::connection_state::ConnectionState;
+use super::raw_client::{Event, RawMqttClient, ReceivedMessage};
 
 pub struct MqttClient<'a, T, const MAX_PROPERTIES: usize, R: RngCore>
 where
     T: Read + Write,
 {
     raw: RawMqttClient<'a, T, MAX_PROPERTIES, R>,
+    #[cfg(feature = "encryption")]
+    protection: Option<&'a mut (dyn PayloadProtection + Send)>,
+    #[cfg(feature = "compression")]
+    decompression_buffer: Option<&'a mut [u8]>,
 }
 
 impl<'a, T, const MAX_PROPERTIES: usize, R> MqttClient<'a, T, MAX_PROPERTIES, R>
@@ -61,21 +81,135 @@ where
                 recv_buffer_len,
                 config,
             ),
+            #[cfg(feature = "encryption")]
+            protection: None,
+            #[cfg(feature = "compression")]
+            decompression_buffer: None,
         }
     }
 
+    /// Returns the state of the connection, e.g. to drive a status LED.
+    pub fn state(&self) -> ConnectionState {
+        self.raw.state()
+    }
+
+    /// Returns true if the broker accepted the connection and the session is usable.
+    pub fn is_connected(&self) -> bool {
+        self.raw.is_connected()
+    }
+
+    /// Sets new network connection after the previous one was closed by `disconnect` or lost,
+    /// `connect_to_broker` can be called again afterwards.
+    pub fn set_network_driver(&mut self, network_driver: T) -> Result<(), ClientError<T::Error>> {
+        self.raw.set_network_driver(network_driver)
+    }
+
+    /// Method sets the authenticator used for the MQTTv5 enhanced authentication
+    /// (e.g. `ScramSha256`). Challenges from the broker are answered during
+    /// `connect_to_broker` and `reauthenticate`.
+    pub fn set_authenticator(&mut self, authenticator: &'a mut (dyn Authenticator + Send)) {
+        self.raw.set_authenticator(authenticator);
+    }
+
+    /// Method sets the provider of time limited credentials (e.g. `AzureSasProvider` or
+    /// `JwtProvider`), which are generated again before every connect and replace the client
+    /// id, username and password of the `ClientConfig`.
+    pub fn set_credentials_provider(
+        &mut self,
+        credentials: &'a mut (dyn CredentialsProvider + Send),
+    ) {
+        self.raw.set_credentials_provider(credentials);
+    }
+
+    /// Method sets the observer called with every sent and received packet, e.g. `PcapWriter`
+    /// to capture the traffic for Wireshark. Timestamps are taken from the `clock`.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: &'a mut (dyn PacketObserver + Send),
+        clock: &'a (dyn Clock + Sync),
+    ) {
+        self.raw.set_packet_observer(observer, clock);
+    }
+
+    /// Method sets the statistics updated by the client (packet and byte counters, errors,
+    /// acknowledgement latency and ping round trip measured with the `clock`). Statistics
+    /// can be read from other tasks while the client is running.
+    pub fn set_stats(&mut self, stats: &'a ClientStats, clock: &'a (dyn Clock + Sync)) {
+        self.raw.set_stats(stats, clock);
+    }
+
+    /// Returns the statistics set by `set_stats`.
+    pub fn stats(&self) -> Option<&'a ClientStats> {
+        self.raw.stats()
+    }
+
+    /// Method sets the protection of the message payloads, e.g. `PayloadCipher`. Payloads of
+    /// the sent messages are encrypted and received messages are decrypted and verified,
+    /// messages failing the verification are acknowledged and reported as
+    /// `ClientError::Encryption`.
+    #[cfg(feature = "encryption")]
+    pub fn set_payload_protection(&mut self, protection: &'a mut (dyn PayloadProtection + Send)) {
+        self.protection = Some(protection);
+    }
+
+    /// Method sets the buffer for the decompressed payloads. Received messages with the
+    /// `content-encoding` user property are decompressed into the buffer, which can be larger
+    /// than the receive buffer. Without the buffer, messages are returned compressed and can
+    /// be streamed by `ReceivedMessage::decompress_with`.
+    #[cfg(feature = "compression")]
+    pub fn set_decompression_buffer(&mut self, buffer: &'a mut [u8]) {
+        self.decompression_buffer = Some(buffer);
+    }
+
+    /// Method sets the filter of QoS 1 messages redelivered by the broker after a reconnect,
+    /// e.g. `RecentMessages`. With `DuplicateMode::Flag` the redelivered messages are marked
+    /// by `ReceivedMessage::is_duplicate`, with `DuplicateMode::Suppress` they are only
+    /// acknowledged.
+    pub fn set_duplicate_filter(
+        &mut self,
+        filter: &'a mut (dyn DuplicateFilter + Send),
+        mode: DuplicateMode,
+    ) {
+        self.raw.set_duplicate_filter(filter, mode);
+    }
+
     /// Method allows client connect to server. Client is connecting to the specified broker
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the connection to the broker fails, method returns Err variable that contains
-    /// Reason codes returned from the broker.
-    pub async fn connect_to_broker<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    /// Reason codes returned from the broker. On success method returns `true` if the
+    /// broker resumed the session (see `ClientConfig::clean_start`), so subscriptions made
+    /// before are still active and the application does not need to subscribe again.
+    pub async fn connect_to_broker(&mut self) -> Result<bool, ClientError<T::Error>> {
         self.raw.connect_to_broker().await?;
 
-        match self.raw.poll::<0>().await? {
-            Event::Connack => Ok(()),
-            Event::Disconnect(reason) => Err(reason),
-            // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+        loop {
+            match self.raw.poll::<0>().await? {
+                Event::Connack(session_present) => return Ok(session_present),
+                Event::Auth(ReasonCode::ContinueAuth) => continue,
+                Event::Disconnect(reason, reason_string) => {
+                    return Err(ClientError::rejected(reason, reason_string))
+                }
+                // If an application message comes at this moment, it is lost.
+                event => return Err(unexpected(&event)),
+            }
+        }
+    }
+
+    /// Method re-authenticates the connected client with the authenticator set by
+    /// `set_authenticator`. Method returns once the broker accepts the credentials.
+    pub async fn reauthenticate(&mut self) -> Result<(), ClientError<T::Error>> {
+        self.raw.reauthenticate().await?;
+
+        loop {
+            match self.raw.poll::<0>().await? {
+                Event::Auth(ReasonCode::Success) => return Ok(()),
+                Event::Auth(ReasonCode::ContinueAuth) => continue,
+                Event::Disconnect(reason, reason_string) => {
+                    return Err(ClientError::rejected(reason, reason_string))
+                }
+                // If an application message comes at this moment, it is lost.
+                event => return Err(unexpected(&event)),
+            }
         }
     }
 
@@ -83,7 +217,7 @@ where
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the disconnect from the broker fails, method returns Err variable that contains
     /// Reason codes returned from the broker.
-    pub async fn disconnect<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    pub async fn disconnect(&mut self) -> Result<(), ClientError<T::Error>> {
         self.raw.disconnect().await?;
         Ok(())
     }
@@ -98,25 +232,115 @@ where
         message: &'b [u8],
         qos: QualityOfService,
         retain: bool,
-    ) -> Result<(), ReasonCode> {
+    ) -> Result<(), ClientError<T::Error>> {
+        self.send_message_with_properties(topic_name, message, qos, retain, &Vec::<_, 0>::new())
+            .await
+    }
+
+    /// Method serializes the `value` with the `codec` into the `payload_buffer` and sends it
+    /// like `send_message_with_properties` with the `ContentType` and `PayloadFormat`
+    /// properties of the codec. Payload which does not fit the buffer is reported as
+    /// `ClientError::Encode(BufferError::InsufficientBufferSize)`.
+    #[cfg(feature = "codec")]
+    pub async fn publish_typed<'b, V: serde::Serialize + ?Sized>(
+        &'b mut self,
+        topic_name: &'b str,
+        value: &V,
+        codec: Codec,
+        qos: QualityOfService,
+        retain: bool,
+        payload_buffer: &'b mut [u8],
+    ) -> Result<(), ClientError<T::Error>> {
+        let len = codec
+            .encode(value, payload_buffer)
+            .map_err(|err| ClientError::Encode(err.into()))?;
+        self.send_message_with_properties(
+            topic_name,
+            &payload_buffer[..len],
+            qos,
+            retain,
+            &codec.properties(),
+        )
+        .await
+    }
+
+    /// Method compresses the `message` into the `payload_buffer` and sends it like
+    /// `send_message` with the `content-encoding` user property. Message which does not
+    /// get shorter by the compression is sent uncompressed without the property.
+    #[cfg(feature = "compression")]
+    pub async fn publish_compressed<'b>(
+        &'b mut self,
+        topic_name: &'b str,
+        message: &'b [u8],
+        qos: QualityOfService,
+        retain: bool,
+        payload_buffer: &'b mut [u8],
+    ) -> Result<(), ClientError<T::Error>> {
+        match compression::compress(message, payload_buffer) {
+            Ok(len) if len < message.len() => {
+                let mut properties = Vec::<_, 1>::new();
+                let _ = properties.push(ContentEncoding::Heatshrink.property());
+                self.send_message_with_properties(
+                    topic_name,
+                    &payload_buffer[..len],
+                    qos,
+                    retain,
+                    &properties,
+                )
+                .await
+            }
+            _ => self.send_message(topic_name, message, qos, retain).await,
+        }
+    }
+
+    /// Method works the same way as `send_message` but additionally attaches the PUBLISH
+    /// properties from the `properties` Vec (e.g. `MessageExpiryInterval` or `ContentType`).
+    /// Properties which are not allowed for the PUBLISH packet are skipped.
+    pub async fn send_message_with_properties<'b, const PROPERTIES: usize>(
+        &'b mut self,
+        topic_name: &'b str,
+        message: &'b [u8],
+        qos: QualityOfService,
+        retain: bool,
+        properties: &Vec<Property<'b>, PROPERTIES>,
+    ) -> Result<(), ClientError<T::Error>> {
+        #[cfg(feature = "encryption")]
+        let identifier = match self.protection.as_deref_mut() {
+            Some(protection) => {
+                let sealed = protection
+                    .seal(topic_name, message)
+                    .map_err(ClientError::Encryption)?;
+                // Ciphertext is not UTF-8 text any more
+                let properties: Vec<Property<'b>, PROPERTIES> = properties
+                    .iter()
+                    .filter(|property| !matches!(property, Property::PayloadFormat(_)))
+                    .cloned()
+                    .collect();
+                self.raw
+                    .send_message_with_properties(topic_name, sealed, qos, retain, &properties)
+                    .await?
+            }
+            None => {
+                self.raw
+                    .send_message_with_properties(topic_name, message, qos, retain, properties)
+                    .await?
+            }
+        };
+        #[cfg(not(feature = "encryption"))]
         let identifier = self
             .raw
-            .send_message(topic_name, message, qos, retain)
+            .send_message_with_properties(topic_name, message, qos, retain, properties)
             .await?;
 
         // QoS1
         if qos == QoS1 {
             match self.raw.poll::<0>().await? {
-                Event::Puback(ack_identifier) => {
-                    if identifier == ack_identifier {
-                        Ok(())
-                    } else {
-                        Err(ReasonCode::PacketIdentifierNotFound)
-                    }
+                Event::Puback(ack_identifier) => check_identifier(identifier, ack_identifier),
+                Event::Disconnect(reason, reason_string) => {
+                    Err(ClientError::rejected(reason, reason_string))
                 }
-                Event::Disconnect(reason) => Err(reason),
                 // If an application message comes at this moment, it is lost.
-                _ => Err(ReasonCode::ImplementationSpecificError),
+                event => Err(unexpected(&event)),
             }
         } else {
             Ok(())
@@ -130,20 +354,16 @@ where
     pub async fn subscribe_to_topics<'b, const TOPICS: usize>(
         &'b mut self,
         topic_names: &'b Vec<&'b str, TOPICS>,
-    ) -> Result<(), ReasonCode> {
+    ) -> Result<(), ClientError<T::Error>> {
         let identifier = self.raw.subscribe_to_topics(topic_names).await?;
 
         match self.raw.poll::<TOPICS>().await? {
-            Event::Suback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
+            Event::Suback(ack_identifier) => check_identifier(identifier, ack_identifier),
+            Event::Disconnect(reason, reason_string) => {
+                Err(ClientError::rejected(reason, reason_string))
             }
-            Event::Disconnect(reason) => Err(reason),
             // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+            event => Err(unexpected(&event)),
         }
     }
 
@@ -153,20 +373,16 @@ where
     pub async fn unsubscribe_from_topic<'b>(
         &'b mut self,
         topic_name: &'b str,
-    ) -> Result<(), ReasonCode> {
+    ) -> Result<(), ClientError<T::Error>> {
         let identifier = self.raw.unsubscribe_from_topic(topic_name).await?;
 
         match self.raw.poll::<0>().await? {
-            Event::Unsuback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
+            Event::Unsuback(ack_identifier) => check_identifier(identifier, ack_identifier),
+            Event::Disconnect(reason, reason_string) => {
+                Err(ClientError::rejected(reason, reason_string))
             }
-            Event::Disconnect(reason) => Err(reason),
             // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+            event => Err(unexpected(&event)),
         }
     }
 
@@ -176,49 +392,93 @@ where
     pub async fn subscribe_to_topic<'b>(
         &'b mut self,
         topic_name: &'b str,
-    ) -> Result<(), ReasonCode> {
+    ) -> Result<(), ClientError<T::Error>> {
         let mut topic_names = Vec::<&'b str, 1>::new();
         topic_names.push(topic_name).unwrap();
 
         let identifier = self.raw.subscribe_to_topics(&topic_names).await?;
 
         match self.raw.poll::<1>().await? {
-            Event::Suback(ack_identifier) => {
-                if identifier == ack_identifier {
-                    Ok(())
-                } else {
-                    Err(ReasonCode::PacketIdentifierNotFound)
-                }
+            Event::Suback(ack_identifier) => check_identifier(identifier, ack_identifier),
+            Event::Disconnect(reason, reason_string) => {
+                Err(ClientError::rejected(reason, reason_string))
             }
-            Event::Disconnect(reason) => Err(reason),
             // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+            event => Err(unexpected(&event)),
         }
     }
 
     /// Method allows client receive a message. The work of this method strictly depends on the
     /// network implementation passed in the `ClientConfig`. It expects the PUBLISH packet
     /// from the broker.
-    pub async fn receive_message<'b>(&'b mut self) -> Result<(&'b str, &'b [u8]), ReasonCode> {
-        match self.raw.poll::<0>().await? {
-            Event::Message(topic, payload) => Ok((topic, payload)),
-            Event::Disconnect(reason) => Err(reason),
+    pub async fn receive_message(&mut self) -> Result<(&str, &[u8]), ClientError<T::Error>> {
+        let message = self.receive_message_with_properties().await?;
+        Ok((message.topic, message.payload))
+    }
+
+    /// Method works the same way as `receive_message` but returns the whole message with
+    /// QoS, retain flag and PUBLISH properties (e.g. `ContentType`).
+    pub async fn receive_message_with_properties(
+        &mut self,
+    ) -> Result<ReceivedMessage<'_>, ClientError<T::Error>> {
+        #[allow(unused_mut)]
+        let mut message = match self.raw.poll::<0>().await? {
+            Event::Message(message) => message,
+            Event::Disconnect(reason, reason_string) => {
+                return Err(ClientError::rejected(reason, reason_string))
+            }
             // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+            event => return Err(unexpected(&event)),
+        };
+        #[cfg(feature = "encryption")]
+        if let Some(protection) = self.protection.as_deref_mut() {
+            message.payload = protection
+                .open(message.topic, message.payload)
+                .map_err(ClientError::Encryption)?;
+        }
+        #[cfg(feature = "compression")]
+        if let (Some(buffer), Some(_)) = (
+            self.decompression_buffer.as_deref_mut(),
+            message.content_encoding(),
+        ) {
+            let len = message
+                .decompress(buffer)
+                .map_err(ClientError::Compression)?;
+            message.payload = &buffer[..len];
+            message.properties.retain(|property| {
+                !matches!(property, Property::UserProperty(pair) if pair.name.string == CONTENT_ENCODING)
+            });
         }
+        Ok(message)
     }
 
     /// Method allows client send PING message to the broker specified in the `ClientConfig`.
     /// If there is expectation for long running connection. Method should be executed
     /// regularly by the timer that counts down the session expiry interval.
-    pub async fn send_ping<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    pub async fn send_ping(&mut self) -> Result<(), ClientError<T::Error>> {
         self.raw.send_ping().await?;
 
         match self.raw.poll::<0>().await? {
             Event::Pingresp => Ok(()),
-            Event::Disconnect(reason) => Err(reason),
+            Event::Disconnect(reason, reason_string) => {
+                Err(ClientError::rejected(reason, reason_string))
+            }
             // If an application message comes at this moment, it is lost.
-            _ => Err(ReasonCode::ImplementationSpecificError),
+            event => Err(unexpected(&event)),
         }
     }
 }
+
+fn check_identifier<E>(expected: u16, received: u16) -> Result<(), ClientError<E>> {
+    if expected == received {
+        Ok(())
+    } else {
+        Err(ClientError::Protocol(
+            ProtocolViolation::PacketIdentifierMismatch { expected, received },
+        ))
+    }
+}
+
+fn unexpected<E>(event: &Event<'_>) -> ClientError<E> {
+    ClientError::Protocol(ProtocolViolation::UnexpectedPacket(event.packet_type()))
+}
@@ -22,12 +22,66 @@
  * SOFTWARE.
  */
 
-use heapless::Vec;
+use core::fmt::{Display, Formatter};
+
+use heapless::{String, Vec};
 use rand_core::RngCore;
 
-use crate::packet::v5::property::Property;
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::property::{Property, PropertyValidator, PropertyViolation};
 use crate::packet::v5::publish_packet::QualityOfService;
-use crate::utils::types::{BinaryData, EncodedString};
+use crate::utils::types::{BinaryData, EncodedString, StringPair};
+
+/// Maximal number of problems reported by `ConfigError`.
+pub const MAX_CONFIG_ISSUES: usize = 8;
+/// Maximal length of the string owned by the config, see `ConfigString`.
+pub const MAX_OWNED_STRING_LEN: usize = 64;
+
+/// String of the config, either borrowed for the lifetime of the config or owned by it, so
+/// e.g. a client id formatted at runtime does not have to be kept alive elsewhere.
+/// ```ignore
+/// let mut client_id = heapless::String::<MAX_OWNED_STRING_LEN>::new();
+/// write!(client_id, "sensor-{}", serial)?;
+/// let config = ClientConfigBuilder::new(MqttVersion::MQTTv5, rng)
+///     .client_id(client_id)
+///     .build()?;
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigString<'a> {
+    Borrowed(&'a str),
+    Owned(String<MAX_OWNED_STRING_LEN>),
+}
+
+impl<'a> ConfigString<'a> {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ConfigString::Borrowed(string) => string,
+            ConfigString::Owned(string) => string.as_str(),
+        }
+    }
+
+    fn encoded(&self) -> EncodedString<'_> {
+        encoded_string(self.as_str())
+    }
+}
+
+impl<'a> Default for ConfigString<'a> {
+    fn default() -> Self {
+        ConfigString::Borrowed("")
+    }
+}
+
+impl<'a> From<&'a str> for ConfigString<'a> {
+    fn from(string: &'a str) -> Self {
+        ConfigString::Borrowed(string)
+    }
+}
+
+impl<'a> From<String<MAX_OWNED_STRING_LEN>> for ConfigString<'a> {
+    fn from(string: String<MAX_OWNED_STRING_LEN>) -> Self {
+        ConfigString::Owned(string)
+    }
+}
 
 #[derive(Clone, PartialEq)]
 pub enum MqttVersion {
@@ -44,12 +98,14 @@ pub enum MqttVersion {
 /// implementation. This implementation is used for generating packet identifiers.
 /// There is counting rng implementation in the `utils` module that can be used.
 /// Examples of the configurations can be found in the integration tests.
+/// `ClientConfigBuilder` creates the config checked by `validate`. Config is not changed
+/// by the client, so it can be reused for every reconnect.
 #[derive(Clone)]
 pub struct ClientConfig<'a, const MAX_PROPERTIES: usize, T: RngCore> {
     pub max_subscribe_qos: QualityOfService,
     pub keep_alive: u16,
     pub username_flag: bool,
-    pub username: EncodedString<'a>,
+    pub username: ConfigString<'a>,
     pub password_flag: bool,
     pub password: BinaryData<'a>,
     pub properties: Vec<Property<'a>, MAX_PROPERTIES>,
@@ -57,10 +113,17 @@ pub struct ClientConfig<'a, const MAX_PROPERTIES: usize, T: RngCore> {
     pub mqtt_version: MqttVersion,
     pub rng: T,
     pub will_flag: bool,
-    pub will_topic: EncodedString<'a>,
+    pub will_topic: ConfigString<'a>,
     pub will_payload: BinaryData<'a>,
     pub will_retain: bool,
-    pub client_id: EncodedString<'a>,
+    pub will_qos: QualityOfService,
+    pub client_id: ConfigString<'a>,
+    /// Broker discards the existing session on connect. If not set, broker resumes the
+    /// session kept from the previous connection.
+    pub clean_start: bool,
+    /// Seconds the broker keeps the session after the connection closes, zero means the
+    /// session ends with the connection.
+    pub session_expiry_interval: u32,
 }
 
 impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfig<'a, MAX_PROPERTIES, T> {
@@ -69,7 +132,7 @@ impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfig<'a, MAX_PROPERTIE
             max_subscribe_qos: QualityOfService::QoS0,
             keep_alive: 60,
             username_flag: false,
-            username: EncodedString::new(),
+            username: ConfigString::default(),
             password_flag: false,
             password: BinaryData::new(),
             properties: Vec::<Property<'a>, MAX_PROPERTIES>::new(),
@@ -77,10 +140,13 @@ impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfig<'a, MAX_PROPERTIE
             mqtt_version: version,
             rng,
             will_flag: false,
-            will_topic: EncodedString::new(),
+            will_topic: ConfigString::default(),
             will_payload: BinaryData::new(),
             will_retain: false,
-            client_id: EncodedString::new(),
+            will_qos: QualityOfService::QoS0,
+            client_id: ConfigString::default(),
+            clean_start: true,
+            session_expiry_interval: 0,
         }
     }
 
@@ -88,29 +154,32 @@ impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfig<'a, MAX_PROPERTIE
         self.max_subscribe_qos = qos;
     }
 
-    pub fn add_will(&mut self, topic: &'a str, payload: &'a [u8], retain: bool) {
-        let mut topic_s = EncodedString::new();
-        topic_s.string = topic;
-        topic_s.len = topic.len() as u16;
-
+    pub fn add_will(
+        &mut self,
+        topic: impl Into<ConfigString<'a>>,
+        payload: &'a [u8],
+        retain: bool,
+    ) {
         let mut payload_d = BinaryData::new();
         payload_d.bin = payload;
         payload_d.len = payload.len() as u16;
 
         self.will_flag = true;
         self.will_retain = retain;
-        self.will_topic = topic_s;
+        self.will_topic = topic.into();
         self.will_payload = payload_d;
     }
 
+    /// Method sets the QoS the broker uses to publish the will message, QoS 0 by default.
+    pub fn add_will_qos(&mut self, qos: QualityOfService) {
+        self.will_qos = qos;
+    }
+
     /// Method adds the username array and also sets the username flag so client
     /// will use it for the authentication
-    pub fn add_username(&mut self, username: &'a str) {
-        let mut username_s: EncodedString = EncodedString::new();
-        username_s.string = username;
-        username_s.len = username.len() as u16;
+    pub fn add_username(&mut self, username: impl Into<ConfigString<'a>>) {
         self.username_flag = true;
-        self.username = username_s;
+        self.username = username.into();
     }
     /// Method adds the password array and also sets the password flag so client
     /// will use it for the authentication
@@ -122,28 +191,473 @@ impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfig<'a, MAX_PROPERTIE
         self.password_flag = true;
     }
 
-    /// Method adds the property to the properties Vec if there is still space. Otherwise do nothing.
-    pub fn add_property(&mut self, prop: Property<'a>) {
-        if self.properties.len() < MAX_PROPERTIES {
-            self.properties.push(prop);
-        }
+    /// Method adds the property to the properties Vec. Returns `ConfigIssue::TooManyProperties`
+    /// if there is no space left.
+    pub fn add_property(&mut self, prop: Property<'a>) -> Result<(), ConfigError> {
+        self.properties
+            .push(prop)
+            .map_err(|_| ConfigError::from(ConfigIssue::TooManyProperties))
     }
 
     /// Method encode the `max_packet_size` attribute as property to the properties Vec.
+    /// Already present `MaximumPacketSize` property is replaced, so the property is never
+    /// duplicated.
     pub fn add_max_packet_size_as_prop(&mut self) -> u32 {
-        if self.properties.len() < MAX_PROPERTIES {
-            let prop = Property::MaximumPacketSize(self.max_packet_size);
-            self.properties.push(prop);
-            return 5;
+        if self.set_property(Property::MaximumPacketSize(self.max_packet_size)) {
+            5
+        } else {
+            0
+        }
+    }
+
+    /// Method sets whether the broker starts a new session on connect.
+    pub fn add_clean_start(&mut self, clean_start: bool) {
+        self.clean_start = clean_start;
+    }
+
+    /// Method sets the session expiry interval (in seconds) sent in CONNECT.
+    pub fn add_session_expiry_interval(&mut self, seconds: u32) {
+        self.session_expiry_interval = seconds;
+    }
+
+    /// Method sets how many QoS 1 and QoS 2 publications the client is willing
+    /// to process concurrently. Zero is not allowed by the specification.
+    pub fn add_receive_maximum(&mut self, receive_maximum: u16) -> Result<(), ConfigError> {
+        self.replace_property(Property::ReceiveMaximum(receive_maximum))
+    }
+
+    /// Method sets the highest topic alias the client accepts from the broker.
+    pub fn add_topic_alias_maximum(&mut self, topic_alias_maximum: u16) -> Result<(), ConfigError> {
+        self.replace_property(Property::TopicAliasMaximum(topic_alias_maximum))
+    }
+
+    /// Method asks the broker to return the response information in CONNACK.
+    pub fn add_request_response_information(&mut self, request: bool) -> Result<(), ConfigError> {
+        self.replace_property(Property::RequestResponseInformation(request as u8))
+    }
+
+    /// Method asks the broker to return reason strings and user properties on failures.
+    pub fn add_request_problem_information(&mut self, request: bool) -> Result<(), ConfigError> {
+        self.replace_property(Property::RequestProblemInformation(request as u8))
+    }
+
+    /// Method adds user property, user properties can be added more than once.
+    pub fn add_user_property(&mut self, name: &'a str, value: &'a str) -> Result<(), ConfigError> {
+        self.add_property(Property::UserProperty(StringPair {
+            name: EncodedString {
+                string: name,
+                len: name.len() as u16,
+            },
+            value: EncodedString {
+                string: value,
+                len: value.len() as u16,
+            },
+        }))
+    }
+
+    /// Replaces the property of the same kind or pushes it, `ConfigIssue::TooManyProperties`
+    /// is returned if there is no space left. Setters of the single properties above return
+    /// the same error.
+    fn replace_property(&mut self, prop: Property<'a>) -> Result<(), ConfigError> {
+        if self.set_property(prop) {
+            Ok(())
+        } else {
+            Err(ConfigError::from(ConfigIssue::TooManyProperties))
+        }
+    }
+
+    /// Replaces the property of the same kind or pushes it if there is still space.
+    /// Returns if the property is present in the properties Vec.
+    fn set_property(&mut self, prop: Property<'a>) -> bool {
+        let identifier = u8::from(&prop);
+        if let Some(existing) = self
+            .properties
+            .iter_mut()
+            .find(|p| u8::from(&**p) == identifier)
+        {
+            *existing = prop;
+            return true;
+        }
+        self.properties.push(prop).is_ok()
+    }
+
+    pub fn add_client_id(&mut self, client_id: impl Into<ConfigString<'a>>) {
+        self.client_id = client_id.into();
+    }
+
+    /// Client id as encoded in CONNECT.
+    pub fn encoded_client_id(&self) -> EncodedString<'_> {
+        self.client_id.encoded()
+    }
+
+    /// Username as encoded in CONNECT.
+    pub fn encoded_username(&self) -> EncodedString<'_> {
+        self.username.encoded()
+    }
+
+    /// Will topic as encoded in CONNECT.
+    pub fn encoded_will_topic(&self) -> EncodedString<'_> {
+        self.will_topic.encoded()
+    }
+
+    /// Returns the properties sent in CONNECT: properties from the properties Vec allowed
+    /// in CONNECT, `max_packet_size` and non-zero `session_expiry_interval`. Config fields
+    /// take precedence over the same properties in the Vec. `max_packet_size` is left out
+    /// when there is no space for it, returns `None` if the other properties do not fit into
+    /// `MAX_PROPERTIES`.
+    pub fn connect_properties(&self) -> Option<Vec<Property<'a>, MAX_PROPERTIES>> {
+        let mut properties = Vec::new();
+        for property in self.properties.iter().filter(|p| {
+            p.connect_property()
+                && !matches!(
+                    p,
+                    Property::MaximumPacketSize(_) | Property::SessionExpiryInterval(_)
+                )
+        }) {
+            properties.push(property.clone()).ok()?;
+        }
+        let session_expiry = self.session_expiry_interval != 0;
+        // Broker does not limit the packet size without the property
+        if properties.len() + (session_expiry as usize) < MAX_PROPERTIES {
+            let _ = properties.push(Property::MaximumPacketSize(self.max_packet_size));
+        }
+        if session_expiry {
+            properties
+                .push(Property::SessionExpiryInterval(
+                    self.session_expiry_interval,
+                ))
+                .ok()?;
+        }
+        Some(properties)
+    }
+
+    /// Method checks the config against the MQTTv5 rules and limits of the client. All found
+    /// problems are returned in the error.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut err = ConfigError::default();
+        if self.mqtt_version != MqttVersion::MQTTv5 {
+            err.push(ConfigIssue::UnsupportedProtocolVersion);
+        }
+
+        let client_id = self.client_id.as_str();
+        if client_id.chars().any(char::is_control) {
+            err.push(ConfigIssue::InvalidClientId);
+        }
+        if client_id.is_empty() && !self.clean_start {
+            err.push(ConfigIssue::EmptyClientIdWithoutCleanStart);
+        }
+        for (field, len, used) in [
+            (ConfigField::ClientId, client_id.len(), true),
+            (
+                ConfigField::Username,
+                self.username.as_str().len(),
+                self.username_flag,
+            ),
+            (
+                ConfigField::Password,
+                self.password.bin.len(),
+                self.password_flag,
+            ),
+            (
+                ConfigField::WillTopic,
+                self.will_topic.as_str().len(),
+                self.will_flag,
+            ),
+            (
+                ConfigField::WillPayload,
+                self.will_payload.bin.len(),
+                self.will_flag,
+            ),
+        ] {
+            if used && len > u16::MAX as usize {
+                err.push(ConfigIssue::StringTooLong(field));
+            }
+        }
+        let user_property_too_long = self.properties.iter().any(|p| match p {
+            Property::UserProperty(pair) => {
+                pair.name.string.len() > u16::MAX as usize
+                    || pair.value.string.len() > u16::MAX as usize
+            }
+            _ => false,
+        });
+        if user_property_too_long {
+            err.push(ConfigIssue::StringTooLong(ConfigField::UserProperty));
+        }
+        if self.will_flag
+            && (self.will_topic.as_str().is_empty()
+                || self.will_topic.as_str().contains(['+', '#', '\0']))
+        {
+            err.push(ConfigIssue::InvalidWillTopic);
+        }
+
+        if self.max_subscribe_qos == QualityOfService::QoS2
+            || self.max_subscribe_qos == QualityOfService::INVALID
+            || self.will_qos == QualityOfService::INVALID
+        {
+            err.push(ConfigIssue::UnsupportedQoS);
+        }
+
+        match self.connect_properties() {
+            Some(properties) => {
+                let mut validator = PropertyValidator::new(PacketType::Connect);
+                if let Some(violation) = properties
+                    .iter()
+                    .find_map(|property| validator.check(property).err())
+                {
+                    err.push(ConfigIssue::InvalidProperty(violation));
+                }
+            }
+            None => err.push(ConfigIssue::TooManyProperties),
+        }
+
+        if err.issues.is_empty() {
+            Ok(())
+        } else {
+            Err(err)
         }
-        0
     }
+}
 
-    pub fn add_client_id(&mut self, client_id: &'a str) {
-        let mut client_id_s = EncodedString::new();
-        client_id_s.string = client_id;
-        client_id_s.len = client_id.len() as u16;
+/// Part of the config referred by `ConfigIssue`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigField {
+    ClientId,
+    Username,
+    Password,
+    WillTopic,
+    WillPayload,
+    UserProperty,
+}
+
+/// Problem of the client config found by `ClientConfig::validate`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConfigIssue {
+    /// Client supports only MQTTv5.
+    UnsupportedProtocolVersion,
+    /// Client identifier contains control characters.
+    InvalidClientId,
+    /// Broker assigns the client identifier to the client with empty identifier, such
+    /// session can not be resumed.
+    EmptyClientIdWithoutCleanStart,
+    /// String or binary data is longer than 65535 bytes.
+    StringTooLong(ConfigField),
+    /// Will topic is empty or contains wildcards.
+    InvalidWillTopic,
+    /// Maximal subscribe QoS or will QoS is not supported by the client.
+    UnsupportedQoS,
+    /// CONNECT property breaks the MQTTv5 property rules.
+    InvalidProperty(PropertyViolation),
+    /// Properties do not fit into `MAX_PROPERTIES`.
+    TooManyProperties,
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigIssue::UnsupportedProtocolVersion => {
+                write!(f, "MQTT protocol version is not supported!")
+            }
+            ConfigIssue::InvalidClientId => {
+                write!(f, "Client identifier contains control characters!")
+            }
+            ConfigIssue::EmptyClientIdWithoutCleanStart => {
+                write!(f, "Empty client identifier requires clean start!")
+            }
+            ConfigIssue::StringTooLong(field) => write!(f, "{:?} is too long!", field),
+            ConfigIssue::InvalidWillTopic => {
+                write!(f, "Will topic is empty or contains wildcards!")
+            }
+            ConfigIssue::UnsupportedQoS => write!(f, "Subscribe QoS is not supported!"),
+            ConfigIssue::InvalidProperty(violation) => write!(f, "{}", violation),
+            ConfigIssue::TooManyProperties => write!(f, "Properties do not fit into config!"),
+        }
+    }
+}
+
+/// Error returned by `ClientConfigBuilder::build` listing all problems of the config.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConfigError {
+    pub issues: Vec<ConfigIssue, MAX_CONFIG_ISSUES>,
+}
+
+impl From<ConfigIssue> for ConfigError {
+    fn from(issue: ConfigIssue) -> Self {
+        let mut err = ConfigError::default();
+        err.push(issue);
+        err
+    }
+}
+
+impl ConfigError {
+    fn push(&mut self, issue: ConfigIssue) {
+        if !self.issues.contains(&issue) {
+            let _ = self.issues.push(issue);
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Invalid client config:")?;
+        for issue in &self.issues {
+            write!(f, " {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builder of `ClientConfig` which checks the config in `build`. Strings are borrowed for
+/// the lifetime of the config or owned by it (`ConfigString`), so the same config can be used
+/// for every reconnect.
+/// ```ignore
+/// let config = ClientConfigBuilder::new(MqttVersion::MQTTv5, rng)
+///     .client_id("sensor-1")
+///     .username("user")
+///     .password(b"secret")
+///     .max_subscribe_qos(QualityOfService::QoS1)
+///     .build()?;
+/// ```
+pub struct ClientConfigBuilder<'a, const MAX_PROPERTIES: usize, T: RngCore> {
+    config: ClientConfig<'a, MAX_PROPERTIES, T>,
+    properties_dropped: bool,
+}
+
+impl<'a, const MAX_PROPERTIES: usize, T: RngCore> ClientConfigBuilder<'a, MAX_PROPERTIES, T> {
+    pub fn new(version: MqttVersion, rng: T) -> Self {
+        Self {
+            config: ClientConfig::new(version, rng),
+            properties_dropped: false,
+        }
+    }
+
+    pub fn client_id(mut self, client_id: impl Into<ConfigString<'a>>) -> Self {
+        self.config.client_id = client_id.into();
+        self
+    }
+
+    pub fn username(mut self, username: impl Into<ConfigString<'a>>) -> Self {
+        self.config.username = username.into();
+        self.config.username_flag = true;
+        self
+    }
+
+    pub fn password(mut self, password: &'a [u8]) -> Self {
+        self.config.password = binary_data(password);
+        self.config.password_flag = true;
+        self
+    }
+
+    pub fn will(
+        mut self,
+        topic: impl Into<ConfigString<'a>>,
+        payload: &'a [u8],
+        retain: bool,
+    ) -> Self {
+        self.config.will_topic = topic.into();
+        self.config.will_payload = binary_data(payload);
+        self.config.will_retain = retain;
+        self.config.will_flag = true;
+        self
+    }
+
+    pub fn will_qos(mut self, qos: QualityOfService) -> Self {
+        self.config.will_qos = qos;
+        self
+    }
+
+    pub fn keep_alive(mut self, seconds: u16) -> Self {
+        self.config.keep_alive = seconds;
+        self
+    }
+
+    pub fn max_packet_size(mut self, max_packet_size: u32) -> Self {
+        self.config.max_packet_size = max_packet_size;
+        self
+    }
+
+    pub fn max_subscribe_qos(mut self, qos: QualityOfService) -> Self {
+        self.config.max_subscribe_qos = qos;
+        self
+    }
+
+    pub fn clean_start(mut self, clean_start: bool) -> Self {
+        self.config.clean_start = clean_start;
+        self
+    }
+
+    pub fn session_expiry_interval(mut self, seconds: u32) -> Self {
+        self.config.session_expiry_interval = seconds;
+        self
+    }
+
+    pub fn receive_maximum(self, receive_maximum: u16) -> Self {
+        self.replace_property(Property::ReceiveMaximum(receive_maximum))
+    }
+
+    pub fn topic_alias_maximum(self, topic_alias_maximum: u16) -> Self {
+        self.replace_property(Property::TopicAliasMaximum(topic_alias_maximum))
+    }
+
+    pub fn request_response_information(self, request: bool) -> Self {
+        self.replace_property(Property::RequestResponseInformation(request as u8))
+    }
+
+    pub fn request_problem_information(self, request: bool) -> Self {
+        self.replace_property(Property::RequestProblemInformation(request as u8))
+    }
+
+    pub fn user_property(self, name: &'a str, value: &'a str) -> Self {
+        self.property(Property::UserProperty(StringPair {
+            name: encoded_string(name),
+            value: encoded_string(value),
+        }))
+    }
+
+    /// Adds the property, properties which do not fit are reported by `build`.
+    pub fn property(mut self, property: Property<'a>) -> Self {
+        if self.config.properties.push(property).is_err() {
+            self.properties_dropped = true;
+        }
+        self
+    }
+
+    /// Checks the config with `ClientConfig::validate` and returns it.
+    pub fn build(self) -> Result<ClientConfig<'a, MAX_PROPERTIES, T>, ConfigError> {
+        let mut err = match self.config.validate() {
+            Ok(()) => ConfigError::default(),
+            Err(err) => err,
+        };
+        if self.properties_dropped {
+            err.push(ConfigIssue::TooManyProperties);
+        }
+        if err.issues.is_empty() {
+            Ok(self.config)
+        } else {
+            Err(err)
+        }
+    }
+
+    fn replace_property(mut self, property: Property<'a>) -> Self {
+        if !self.config.set_property(property) {
+            self.properties_dropped = true;
+        }
+        self
+    }
+}
+
+fn encoded_string(string: &str) -> EncodedString<'_> {
+    EncodedString {
+        string,
+        len: string.len() as u16,
+    }
+}
 
-        self.client_id = client_id_s
+fn binary_data(bin: &[u8]) -> BinaryData<'_> {
+    BinaryData {
+        bin,
+        len: bin.len() as u16,
     }
 }
@@ -0,0 +1,232 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Debug, Display, Formatter};
+
+use heapless::String;
+
+use crate::client::authenticator::AuthError;
+use crate::client::connection_state::ConnectionState;
+use crate::client::credentials::CredentialsError;
+#[cfg(feature = "compression")]
+use crate::compression::CompressionError;
+#[cfg(feature = "encryption")]
+use crate::encryption::EncryptionError;
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::property::PropertyViolation;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::types::BufferError;
+
+/// Maximal number of bytes of the broker reason string kept in `ClientError::BrokerRejected`.
+/// Longer reason strings are truncated.
+pub const MAX_REASON_STRING_LEN: usize = 64;
+
+/// Kind of MQTT protocol violation detected by the client.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolViolation {
+    /// Client received packet which is not expected at this point or which is never
+    /// sent from the server to the client.
+    UnexpectedPacket(PacketType),
+    /// Acknowledgement carries different packet identifier than the request.
+    PacketIdentifierMismatch { expected: u16, received: u16 },
+    /// Incoming packet contains property which breaks the MQTTv5 property rules.
+    InvalidProperty(PropertyViolation),
+    /// Remaining length of the incoming packet could not be decoded.
+    MalformedRemainingLength,
+    /// Selected MQTT version is not supported by the client.
+    UnsupportedProtocolVersion,
+    /// Broker reported present session although the client requested clean start.
+    UnexpectedSessionPresent,
+}
+
+impl Display for ProtocolViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ProtocolViolation::UnexpectedPacket(packet_type) => {
+                write!(f, "Unexpected {:?} packet received!", packet_type)
+            }
+            ProtocolViolation::PacketIdentifierMismatch { expected, received } => write!(
+                f,
+                "Packet identifier mismatch, expected {} but received {}!",
+                expected, received
+            ),
+            ProtocolViolation::InvalidProperty(violation) => write!(f, "{}", violation),
+            ProtocolViolation::MalformedRemainingLength => {
+                write!(f, "Remaining length of packet is malformed!")
+            }
+            ProtocolViolation::UnsupportedProtocolVersion => {
+                write!(f, "MQTT protocol version is not supported!")
+            }
+            ProtocolViolation::UnexpectedSessionPresent => {
+                write!(
+                    f,
+                    "Session present flag set although clean start was requested!"
+                )
+            }
+        }
+    }
+}
+
+/// Error returned by the client operations. Generic `E` is the error type of the
+/// network driver.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ClientError<E> {
+    /// Network driver reported an error.
+    Transport(E),
+    /// Outgoing packet could not be encoded into the send buffer.
+    Encode(BufferError),
+    /// Incoming packet could not be decoded.
+    Decode(BufferError),
+    /// Broker violated the MQTT protocol.
+    Protocol(ProtocolViolation),
+    /// Broker refused the request. Contains the reason code and the reason string
+    /// property if the broker sent one.
+    BrokerRejected {
+        reason: ReasonCode,
+        reason_string: Option<String<MAX_REASON_STRING_LEN>>,
+    },
+    /// Enhanced authentication exchange failed on the client side.
+    Auth(AuthError),
+    /// Credentials provider could not generate the credentials for CONNECT.
+    Credentials(CredentialsError),
+    /// Operation did not finish in time.
+    Timeout,
+    /// Client is not connected to the broker or the connection was closed.
+    NotConnected,
+    /// Operation is not allowed in the current connection state, e.g. publishing before
+    /// CONNACK or after the connection was lost.
+    InvalidState(ConnectionState),
+    /// Payload could not be protected or the received payload failed verification.
+    #[cfg(feature = "encryption")]
+    Encryption(EncryptionError),
+    /// Received payload could not be decompressed.
+    #[cfg(feature = "compression")]
+    Compression(CompressionError),
+}
+
+impl<E> ClientError<E> {
+    /// Creates error from the packet decoding error. Property rule violations are
+    /// reported as protocol violations.
+    pub fn from_decode_error(err: BufferError) -> Self {
+        match err {
+            BufferError::InvalidProperty(violation) => {
+                ClientError::Protocol(ProtocolViolation::InvalidProperty(violation))
+            }
+            err => ClientError::Decode(err),
+        }
+    }
+
+    /// Creates `BrokerRejected` error, `reason_string` is truncated to `MAX_REASON_STRING_LEN`.
+    pub fn rejected(reason: ReasonCode, reason_string: Option<&str>) -> Self {
+        ClientError::BrokerRejected {
+            reason,
+            reason_string: reason_string.map(|s| {
+                let mut truncated = String::new();
+                for c in s.chars() {
+                    if truncated.push(c).is_err() {
+                        break;
+                    }
+                }
+                truncated
+            }),
+        }
+    }
+
+    /// Returns `true` if the error means that the connection to the broker is not usable
+    /// (transport error, timeout, closed connection or client which is not connected).
+    /// Such operation can be retried after reconnect.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            ClientError::Transport(_) | ClientError::Timeout | ClientError::NotConnected => true,
+            ClientError::InvalidState(state) => !state.is_connected(),
+            _ => false,
+        }
+    }
+
+    /// Converts the transport error with `f`, other variants are kept as they are.
+    pub fn map_transport<F>(self, f: impl FnOnce(E) -> F) -> ClientError<F> {
+        match self {
+            ClientError::Transport(err) => ClientError::Transport(f(err)),
+            ClientError::Encode(err) => ClientError::Encode(err),
+            ClientError::Decode(err) => ClientError::Decode(err),
+            ClientError::Protocol(violation) => ClientError::Protocol(violation),
+            ClientError::BrokerRejected {
+                reason,
+                reason_string,
+            } => ClientError::BrokerRejected {
+                reason,
+                reason_string,
+            },
+            ClientError::Auth(err) => ClientError::Auth(err),
+            ClientError::Credentials(err) => ClientError::Credentials(err),
+            ClientError::Timeout => ClientError::Timeout,
+            ClientError::NotConnected => ClientError::NotConnected,
+            ClientError::InvalidState(state) => ClientError::InvalidState(state),
+            #[cfg(feature = "encryption")]
+            ClientError::Encryption(err) => ClientError::Encryption(err),
+            #[cfg(feature = "compression")]
+            ClientError::Compression(err) => ClientError::Compression(err),
+        }
+    }
+
+    /// Returns reason code sent by the broker if the request was rejected.
+    pub fn reason_code(&self) -> Option<&ReasonCode> {
+        match self {
+            ClientError::BrokerRejected { reason, .. } => Some(reason),
+            _ => None,
+        }
+    }
+}
+
+impl<E: Debug> Display for ClientError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ClientError::Transport(err) => write!(f, "Transport error: {:?}!", err),
+            ClientError::Encode(err) => write!(f, "Could not encode packet: {}", err),
+            ClientError::Decode(err) => write!(f, "Could not decode packet: {}", err),
+            ClientError::Protocol(violation) => write!(f, "Protocol violation: {}", violation),
+            ClientError::BrokerRejected {
+                reason,
+                reason_string: Some(reason_string),
+            } => write!(f, "Broker rejected request: {} ({})", reason, reason_string),
+            ClientError::BrokerRejected {
+                reason,
+                reason_string: None,
+            } => write!(f, "Broker rejected request: {}", reason),
+            ClientError::Auth(err) => write!(f, "Authentication failed: {}", err),
+            ClientError::Credentials(err) => write!(f, "Credentials not available: {}", err),
+            ClientError::Timeout => write!(f, "Operation timed out!"),
+            ClientError::NotConnected => write!(f, "Client is not connected!"),
+            ClientError::InvalidState(state) => {
+                write!(f, "Operation is not allowed, client is {}!", state)
+            }
+            #[cfg(feature = "encryption")]
+            ClientError::Encryption(err) => write!(f, "Payload protection failed: {}", err),
+            #[cfg(feature = "compression")]
+            ClientError::Compression(err) => write!(f, "Payload decompression failed: {}", err),
+        }
+    }
+}
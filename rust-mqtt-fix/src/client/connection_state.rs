@@ -0,0 +1,109 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Display, Formatter};
+
+use crate::packet::v5::reason_codes::ReasonCode;
+
+/// Reason why the client lost the connection to the broker.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DisconnectReason {
+    /// Broker closed the session with DISCONNECT carrying the reason code.
+    Broker(ReasonCode),
+    /// Broker refused the connection with the reason code in CONNACK.
+    Rejected(ReasonCode),
+    /// Network driver failed or the connection was closed.
+    Transport,
+    /// Broker violated the protocol or sent malformed packet.
+    Protocol,
+    /// Enhanced authentication failed on the client side during connect.
+    Auth,
+}
+
+/// State of the client connection. Client is created with `TcpConnected` state, `Connected`
+/// is reached when the broker accepts CONNECT and `Idle` after `disconnect`. Failures which
+/// make the connection unusable drop the network driver and move the client to
+/// `Disconnected`; new driver can be set with `set_network_driver`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectionState {
+    /// Client has no network connection, it was closed by `disconnect`.
+    Idle,
+    /// Network connection is open and CONNECT was not sent yet.
+    TcpConnected,
+    /// CONNECT was sent, client waits for CONNACK (or AUTH exchange).
+    AwaitingConnack,
+    /// Broker accepted the connection, session is usable.
+    Connected,
+    /// DISCONNECT is being sent.
+    Disconnecting,
+    /// Connection was lost or refused.
+    Disconnected(DisconnectReason),
+}
+
+impl ConnectionState {
+    /// Returns true if the session is usable.
+    pub fn is_connected(&self) -> bool {
+        *self == ConnectionState::Connected
+    }
+
+    /// Returns true if the client holds network connection.
+    pub fn has_network(&self) -> bool {
+        matches!(
+            self,
+            ConnectionState::TcpConnected
+                | ConnectionState::AwaitingConnack
+                | ConnectionState::Connected
+                | ConnectionState::Disconnecting
+        )
+    }
+}
+
+impl Display for ConnectionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConnectionState::Idle => write!(f, "idle"),
+            ConnectionState::TcpConnected => write!(f, "TCP connected"),
+            ConnectionState::AwaitingConnack => write!(f, "awaiting CONNACK"),
+            ConnectionState::Connected => write!(f, "connected"),
+            ConnectionState::Disconnecting => write!(f, "disconnecting"),
+            ConnectionState::Disconnected(DisconnectReason::Broker(reason)) => {
+                write!(f, "disconnected by broker ({})", reason)
+            }
+            ConnectionState::Disconnected(DisconnectReason::Rejected(reason)) => {
+                write!(f, "rejected by broker ({})", reason)
+            }
+            ConnectionState::Disconnected(DisconnectReason::Transport) => {
+                write!(f, "disconnected (transport error)")
+            }
+            ConnectionState::Disconnected(DisconnectReason::Protocol) => {
+                write!(f, "disconnected (protocol error)")
+            }
+            ConnectionState::Disconnected(DisconnectReason::Auth) => {
+                write!(f, "disconnected (authentication failed)")
+            }
+        }
+    }
+}
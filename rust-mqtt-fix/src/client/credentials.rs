@@ -0,0 +1,61 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Display, Formatter};
+
+/// Error reported by the `CredentialsProvider` when the credentials can not be generated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CredentialsError {
+    /// Generated credentials do not fit into the provider buffers.
+    BufferTooSmall,
+    /// Key is malformed, e.g. not valid base64 or not a valid curve point.
+    InvalidKey,
+    /// Claim or identifier contains characters which can not be encoded.
+    InvalidClaim,
+}
+
+impl Display for CredentialsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CredentialsError::BufferTooSmall => {
+                write!(f, "Credentials do not fit into buffer!")
+            }
+            CredentialsError::InvalidKey => write!(f, "Credentials key is malformed!"),
+            CredentialsError::InvalidClaim => write!(f, "Credentials claim can not be encoded!"),
+        }
+    }
+}
+
+/// Provider of the time limited credentials, e.g. SAS tokens or JWTs. Client calls `refresh`
+/// before every CONNECT and sends the returned client id, username and password instead of
+/// the values from `ClientConfig`. Values which are `None` are taken from the config.
+pub trait CredentialsProvider {
+    /// Generates new credentials for the next connection.
+    fn refresh(&mut self) -> Result<(), CredentialsError>;
+    /// Client identifier required by the broker.
+    fn client_id(&self) -> Option<&str>;
+    fn username(&self) -> Option<&str>;
+    fn password(&self) -> Option<&[u8]>;
+}
@@ -0,0 +1,138 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Detection of QoS 1 messages redelivered by the broker. After a reconnect the broker sends
+//! again the messages which were not acknowledged, with the DUP flag set, although the client
+//! may have already delivered them. `DuplicateFilter` set by
+//! `RawMqttClient::set_duplicate_filter` remembers recently received messages, redeliveries
+//! are flagged by `ReceivedMessage::is_duplicate` or acknowledged and dropped.
+
+use heapless::Deque;
+
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::buffer_reader::BuffReader;
+
+/// What the client does with a redelivered message found by the filter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DuplicateMode {
+    /// Message is returned with `ReceivedMessage::is_duplicate` set.
+    Flag,
+    /// Message is acknowledged but not returned by `poll`.
+    Suppress,
+}
+
+/// Identity of a QoS 1 message, packet identifier and hash of the topic and payload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MessageKey {
+    pub packet_identifier: u16,
+    pub hash: u32,
+}
+
+impl MessageKey {
+    pub fn new(packet_identifier: u16, topic: &str, payload: &[u8]) -> Self {
+        // FNV-1a, topic and payload are separated so that moving bytes between them
+        // changes the hash
+        let mut hash: u32 = 0x811c_9dc5;
+        for byte in topic.bytes().chain([0xff]).chain(payload.iter().copied()) {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        Self {
+            packet_identifier,
+            hash,
+        }
+    }
+
+    /// Returns the key and the DUP flag of the QoS 1 PUBLISH packet `packet`, `None` for
+    /// other packets and QoS levels or if the packet is malformed.
+    pub fn from_publish(packet: &[u8]) -> Option<(Self, bool)> {
+        let mut reader = BuffReader::new(packet, packet.len());
+        let first_byte = reader.read_u8().ok()?;
+        if first_byte >> 4 != 3
+            || QualityOfService::from(first_byte & 0x06) != QualityOfService::QoS1
+        {
+            return None;
+        }
+        let remaining_len = reader.read_variable_byte_int().ok()? as usize;
+        let end = reader.position + remaining_len;
+        let topic = reader.read_string().ok()?.string;
+        let packet_identifier = reader.read_u16().ok()?;
+        let properties_len = reader.read_variable_byte_int().ok()? as usize;
+        let start = reader.position + properties_len;
+        let payload = packet.get(start..end)?;
+        Some((
+            Self::new(packet_identifier, topic, payload),
+            first_byte & 0x08 != 0,
+        ))
+    }
+}
+
+/// Memory of recently received QoS 1 messages.
+pub trait DuplicateFilter {
+    /// Remembers the `key` and returns true if it was already remembered.
+    fn insert(&mut self, key: MessageKey) -> bool;
+
+    /// Forgets all remembered messages.
+    fn clear(&mut self);
+}
+
+/// `DuplicateFilter` remembering the last `N` messages.
+#[derive(Debug, Default)]
+pub struct RecentMessages<const N: usize> {
+    keys: Deque<MessageKey, N>,
+}
+
+impl<const N: usize> RecentMessages<N> {
+    pub const fn new() -> Self {
+        Self { keys: Deque::new() }
+    }
+
+    /// Number of remembered messages.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl<const N: usize> DuplicateFilter for RecentMessages<N> {
+    fn insert(&mut self, key: MessageKey) -> bool {
+        if self.keys.iter().any(|known| *known == key) {
+            return true;
+        }
+        if self.keys.is_full() {
+            self.keys.pop_front();
+        }
+        let _ = self.keys.push_back(key);
+        false
+    }
+
+    fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
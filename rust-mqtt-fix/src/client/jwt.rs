@@ -0,0 +1,241 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Debug, Formatter, Write};
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use heapless::{String, Vec};
+use hmac::{Hmac, Mac};
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use sha2::Sha256;
+
+use super::credentials::{CredentialsError, CredentialsProvider};
+use crate::utils::clock::Clock;
+
+/// Capacity of the generated JWT.
+pub const MAX_JWT_LEN: usize = 512;
+/// Capacity of the client id set by `JwtProvider::set_client_id`.
+pub const MAX_JWT_CLIENT_ID_LEN: usize = 128;
+/// Maximal length of the HS256 secret.
+pub const MAX_HS256_SECRET_LEN: usize = 64;
+
+/// Key signing the JWT.
+#[derive(Clone)]
+pub enum JwtKey {
+    /// HMAC-SHA256 with the shared secret.
+    Hs256(Vec<u8, MAX_HS256_SECRET_LEN>),
+    /// ECDSA P-256 with SHA-256, signatures are deterministic (RFC 6979).
+    Es256(SigningKey),
+}
+
+impl JwtKey {
+    pub fn hs256(secret: &[u8]) -> Result<Self, CredentialsError> {
+        Ok(JwtKey::Hs256(
+            Vec::from_slice(secret).map_err(|_| CredentialsError::InvalidKey)?,
+        ))
+    }
+
+    /// ES256 key from the 32 byte big endian private scalar.
+    pub fn es256(private_key: &[u8; 32]) -> Result<Self, CredentialsError> {
+        SigningKey::from_bytes(private_key.into())
+            .map(JwtKey::Es256)
+            .map_err(|_| CredentialsError::InvalidKey)
+    }
+
+    /// Value of the `alg` header.
+    pub fn algorithm(&self) -> &'static str {
+        match self {
+            JwtKey::Hs256(_) => "HS256",
+            JwtKey::Es256(_) => "ES256",
+        }
+    }
+
+    fn sign(&self, data: &[u8], signature: &mut [u8; 64]) -> usize {
+        match self {
+            JwtKey::Hs256(secret) => {
+                // Key length is not limited by HMAC
+                let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+                mac.update(data);
+                signature[..32].copy_from_slice(&mac.finalize().into_bytes());
+                32
+            }
+            JwtKey::Es256(key) => {
+                let sig: Signature = key.sign(data);
+                signature.copy_from_slice(&sig.to_bytes());
+                64
+            }
+        }
+    }
+}
+
+// Key material is not printed
+impl Debug for JwtKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "JwtKey({})", self.algorithm())
+    }
+}
+
+/// `CredentialsProvider` sending a JWT as the password, signed with HS256 or ES256. Every
+/// connect generates a new token with the `aud`, `iat` and `exp` claims valid for `ttl`
+/// seconds. Client id and username are taken from the config unless they are set on the
+/// provider. Clock has to return the UNIX time, e.g. from the RTC or SNTP.
+pub struct JwtProvider<'a> {
+    key: JwtKey,
+    audience: &'a str,
+    ttl: u32,
+    clock: &'a (dyn Clock + Sync),
+    client_id: Option<String<MAX_JWT_CLIENT_ID_LEN>>,
+    username: Option<&'a str>,
+    token: String<MAX_JWT_LEN>,
+}
+
+impl<'a> JwtProvider<'a> {
+    pub fn new(
+        audience: &'a str,
+        key: JwtKey,
+        ttl: u32,
+        clock: &'a (dyn Clock + Sync),
+    ) -> Result<Self, CredentialsError> {
+        // Audience is written into the JSON claims without escaping
+        if audience
+            .chars()
+            .any(|c| c == '"' || c == '\\' || c.is_control())
+        {
+            return Err(CredentialsError::InvalidClaim);
+        }
+        Ok(Self {
+            key,
+            audience,
+            ttl,
+            clock,
+            client_id: None,
+            username: None,
+            token: String::new(),
+        })
+    }
+
+    /// Provider for the Google Cloud IoT style brokers: audience is the project id, client id
+    /// has the form `projects/{project}/locations/{region}/registries/{registry}/devices/{device}`
+    /// and the username is ignored by the broker.
+    pub fn google_cloud_iot(
+        project: &'a str,
+        region: &str,
+        registry: &str,
+        device: &str,
+        key: JwtKey,
+        ttl: u32,
+        clock: &'a (dyn Clock + Sync),
+    ) -> Result<Self, CredentialsError> {
+        let mut provider = Self::new(project, key, ttl, clock)?;
+        let mut client_id = String::new();
+        write!(
+            client_id,
+            "projects/{}/locations/{}/registries/{}/devices/{}",
+            project, region, registry, device
+        )
+        .map_err(|_| CredentialsError::BufferTooSmall)?;
+        provider.client_id = Some(client_id);
+        provider.username = Some("unused");
+        Ok(provider)
+    }
+
+    pub fn set_client_id(&mut self, client_id: &str) -> Result<(), CredentialsError> {
+        let mut owned = String::new();
+        owned
+            .push_str(client_id)
+            .map_err(|_| CredentialsError::BufferTooSmall)?;
+        self.client_id = Some(owned);
+        Ok(())
+    }
+
+    pub fn set_username(&mut self, username: &'a str) {
+        self.username = Some(username);
+    }
+
+    /// Token generated by the last `refresh`.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    fn generate(&mut self, issued_at: u64, expiry: u64) -> Result<(), core::fmt::Error> {
+        let mut json = String::<MAX_JWT_LEN>::new();
+        write!(json, r#"{{"alg":"{}","typ":"JWT"}}"#, self.key.algorithm())?;
+        self.token.clear();
+        push_base64url(&mut self.token, json.as_bytes())?;
+        self.token.write_char('.')?;
+
+        json.clear();
+        write!(
+            json,
+            r#"{{"aud":"{}","iat":{},"exp":{}}}"#,
+            self.audience, issued_at, expiry
+        )?;
+        push_base64url(&mut self.token, json.as_bytes())?;
+
+        let mut signature = [0; 64];
+        let len = self.key.sign(self.token.as_bytes(), &mut signature);
+        self.token.write_char('.')?;
+        push_base64url(&mut self.token, &signature[..len])
+    }
+}
+
+impl<'a> CredentialsProvider for JwtProvider<'a> {
+    fn refresh(&mut self) -> Result<(), CredentialsError> {
+        let issued_at = self.clock.now_micros() / 1_000_000;
+        let expiry = issued_at + self.ttl as u64;
+        self.generate(issued_at, expiry)
+            .map_err(|_| CredentialsError::BufferTooSmall)
+    }
+
+    fn client_id(&self) -> Option<&str> {
+        self.client_id.as_deref()
+    }
+
+    fn username(&self) -> Option<&str> {
+        self.username
+    }
+
+    fn password(&self) -> Option<&[u8]> {
+        Some(self.token.as_bytes())
+    }
+}
+
+/// Appends unpadded base64url of the `data`.
+fn push_base64url<const N: usize>(
+    out: &mut String<N>,
+    data: &[u8],
+) -> Result<(), core::fmt::Error> {
+    // Whole groups of 3 bytes encode without padding, so the chunks can be concatenated
+    let mut encoded = [0; 64];
+    for chunk in data.chunks(48) {
+        let len = URL_SAFE_NO_PAD
+            .encode_slice(chunk, &mut encoded)
+            .map_err(|_| core::fmt::Error)?;
+        // Base64 is ASCII
+        out.write_str(core::str::from_utf8(&encoded[..len]).unwrap_or_default())?;
+    }
+    Ok(())
+}
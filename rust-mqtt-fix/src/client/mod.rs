@@ -22,8 +22,27 @@
  * SOFTWARE.
  */
 
+pub mod authenticator;
+#[cfg(feature = "embassy-sync")]
+pub mod channel;
 #[allow(clippy::module_inception)]
 pub mod client;
 #[allow(unused_must_use)]
 pub mod client_config;
+pub mod client_error;
+pub mod connection_state;
+pub mod credentials;
+pub mod duplicate_filter;
+#[cfg(feature = "cloud-auth")]
+pub mod jwt;
+pub mod owned_message;
+pub mod packet_observer;
+#[cfg(feature = "std")]
+pub mod pcap;
 pub mod raw_client;
+#[cfg(feature = "cloud-auth")]
+pub mod sas;
+#[cfg(feature = "scram")]
+pub mod scram;
+pub mod sn_client;
+pub mod stats;
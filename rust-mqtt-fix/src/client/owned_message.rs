@@ -0,0 +1,182 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Display, Formatter};
+
+use heapless::{String, Vec};
+
+use crate::packet::v5::property::Property;
+use crate::packet::v5::property_kind::{
+    ContentType, CorrelationData, MessageExpiryInterval, PayloadFormat, ResponseTopic,
+};
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::types::{BinaryData, EncodedString};
+
+use super::raw_client::{Event, ReceivedMessage};
+
+/// Maximal length of the `ContentType` property kept in `OwnedMessage`.
+pub const MAX_CONTENT_TYPE_LEN: usize = 32;
+/// Maximal length of the `CorrelationData` property kept in `OwnedMessage`.
+pub const MAX_CORRELATION_DATA_LEN: usize = 32;
+
+/// Error of the conversion into `OwnedMessage`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OwnedMessageError {
+    /// Event is not an application message.
+    NotMessage,
+    /// Topic is longer than the `TOPIC` capacity.
+    TopicTooLong,
+    /// Payload is longer than the `PAYLOAD` capacity.
+    PayloadTooLong,
+    /// Response topic, content type or correlation data does not fit its capacity.
+    PropertyTooLong,
+}
+
+impl Display for OwnedMessageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            OwnedMessageError::NotMessage => write!(f, "Event is not a message!"),
+            OwnedMessageError::TopicTooLong => write!(f, "Topic does not fit the message!"),
+            OwnedMessageError::PayloadTooLong => write!(f, "Payload does not fit the message!"),
+            OwnedMessageError::PropertyTooLong => {
+                write!(f, "Message property does not fit the message!")
+            }
+        }
+    }
+}
+
+/// Received application message copied out of the client receive buffer, so it stays valid
+/// after the next `poll` and can be sent to another task. Generic constants set the capacity
+/// of the topic (and response topic) and of the payload. Only the properties needed to
+/// process the message are kept, user properties are dropped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OwnedMessage<const TOPIC: usize, const PAYLOAD: usize> {
+    pub topic: String<TOPIC>,
+    pub payload: Vec<u8, PAYLOAD>,
+    pub qos: QualityOfService,
+    pub retain: bool,
+    pub payload_format: Option<u8>,
+    pub message_expiry_interval: Option<u32>,
+    pub content_type: Option<String<MAX_CONTENT_TYPE_LEN>>,
+    pub response_topic: Option<String<TOPIC>>,
+    pub correlation_data: Option<Vec<u8, MAX_CORRELATION_DATA_LEN>>,
+    /// Message was redelivered by the broker, see `ReceivedMessage::is_duplicate`.
+    pub duplicate: bool,
+}
+
+impl<const TOPIC: usize, const PAYLOAD: usize> OwnedMessage<TOPIC, PAYLOAD> {
+    /// Returns the message borrowing from `self` in the form returned by the client,
+    /// e.g. to decode the payload with the codec selected by the content type.
+    pub fn as_received(&self) -> ReceivedMessage<'_> {
+        let mut properties = Vec::new();
+        if let Some(format) = self.payload_format {
+            let _ = properties.push(Property::PayloadFormat(format));
+        }
+        if let Some(interval) = self.message_expiry_interval {
+            let _ = properties.push(Property::MessageExpiryInterval(interval));
+        }
+        if let Some(content_type) = &self.content_type {
+            let _ = properties.push(Property::ContentType(encoded_string(content_type)));
+        }
+        if let Some(response_topic) = &self.response_topic {
+            let _ = properties.push(Property::ResponseTopic(encoded_string(response_topic)));
+        }
+        if let Some(data) = &self.correlation_data {
+            let _ = properties.push(Property::CorrelationData(BinaryData {
+                bin: data,
+                len: data.len() as u16,
+            }));
+        }
+        ReceivedMessage {
+            topic: &self.topic,
+            payload: &self.payload,
+            qos: self.qos,
+            retain: self.retain,
+            properties,
+            duplicate: self.duplicate,
+        }
+    }
+}
+
+fn encoded_string(string: &str) -> EncodedString<'_> {
+    EncodedString {
+        string,
+        len: string.len() as u16,
+    }
+}
+
+fn copy_str<const N: usize>(
+    string: &str,
+    err: OwnedMessageError,
+) -> Result<String<N>, OwnedMessageError> {
+    let mut owned = String::new();
+    owned.push_str(string).map_err(|_| err)?;
+    Ok(owned)
+}
+
+impl<'a, const TOPIC: usize, const PAYLOAD: usize> TryFrom<&ReceivedMessage<'a>>
+    for OwnedMessage<TOPIC, PAYLOAD>
+{
+    type Error = OwnedMessageError;
+
+    fn try_from(message: &ReceivedMessage<'a>) -> Result<Self, Self::Error> {
+        let property_err = OwnedMessageError::PropertyTooLong;
+        Ok(Self {
+            topic: copy_str(message.topic, OwnedMessageError::TopicTooLong)?,
+            payload: Vec::from_slice(message.payload)
+                .map_err(|_| OwnedMessageError::PayloadTooLong)?,
+            qos: message.qos,
+            retain: message.retain,
+            payload_format: message.get::<PayloadFormat>(),
+            message_expiry_interval: message.get::<MessageExpiryInterval>(),
+            content_type: message
+                .get::<ContentType>()
+                .map(|content_type| copy_str(content_type, property_err))
+                .transpose()?,
+            response_topic: message
+                .get::<ResponseTopic>()
+                .map(|topic| copy_str(topic, property_err))
+                .transpose()?,
+            correlation_data: message
+                .get::<CorrelationData>()
+                .map(|data| Vec::from_slice(data).map_err(|_| property_err))
+                .transpose()?,
+            duplicate: message.duplicate,
+        })
+    }
+}
+
+impl<'a, const TOPIC: usize, const PAYLOAD: usize> TryFrom<&Event<'a>>
+    for OwnedMessage<TOPIC, PAYLOAD>
+{
+    type Error = OwnedMessageError;
+
+    fn try_from(event: &Event<'a>) -> Result<Self, Self::Error> {
+        match event {
+            Event::Message(message) => Self::try_from(message),
+            _ => Err(OwnedMessageError::NotMessage),
+        }
+    }
+}
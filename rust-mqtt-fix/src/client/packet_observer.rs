@@ -0,0 +1,179 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Wire level tracing of the client. `PacketObserver` set by
+//! `RawMqttClient::set_packet_observer` is called with every sent and received packet.
+
+use heapless::Vec;
+
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::clock::Clock;
+use crate::utils::types::BufferError;
+
+/// Maximal number of properties kept in `PacketSummary`, further properties are skipped.
+pub const MAX_SUMMARY_PROPERTIES: usize = 8;
+
+/// Direction of the observed packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    /// Packet sent by the client to the broker.
+    Outbound,
+    /// Packet received by the client from the broker.
+    Inbound,
+}
+
+/// Fields of the packet useful for debugging.
+#[derive(Debug, Clone)]
+pub struct PacketSummary<'a> {
+    pub packet_type: PacketType,
+    /// Packet identifier of PUBLISH with QoS > 0, acknowledgements, SUBSCRIBE and UNSUBSCRIBE.
+    pub packet_identifier: Option<u16>,
+    /// QoS of PUBLISH.
+    pub qos: Option<QualityOfService>,
+    /// Topic of PUBLISH or the first topic filter of SUBSCRIBE and UNSUBSCRIBE.
+    pub topic: Option<&'a str>,
+    pub properties: Vec<Property<'a>, MAX_SUMMARY_PROPERTIES>,
+}
+
+impl<'a> PacketSummary<'a> {
+    /// Decodes the summary of the whole MQTTv5 packet `packet`.
+    pub fn decode(packet: &'a [u8]) -> Result<Self, BufferError> {
+        let mut reader = BuffReader::new(packet, packet.len());
+        let first_byte = reader.read_u8()?;
+        let remaining_len = reader.read_variable_byte_int()? as usize;
+        let end = reader.position + remaining_len;
+        if end > packet.len() {
+            return Err(BufferError::InsufficientBufferSize);
+        }
+
+        let mut summary = PacketSummary {
+            packet_type: PacketType::from(first_byte),
+            packet_identifier: None,
+            qos: None,
+            topic: None,
+            properties: Vec::new(),
+        };
+        match summary.packet_type {
+            PacketType::Connect => {
+                reader.read_string()?;
+                // Version, flags and keep alive
+                reader.increment_position(4);
+                summary.decode_properties(&mut reader)?;
+            }
+            PacketType::Connack => {
+                reader.increment_position(2);
+                summary.decode_properties(&mut reader)?;
+            }
+            PacketType::Publish => {
+                let qos = QualityOfService::from(first_byte & 0x06);
+                summary.topic = Some(reader.read_string()?.string);
+                if qos != QualityOfService::QoS0 {
+                    summary.packet_identifier = Some(reader.read_u16()?);
+                }
+                summary.qos = Some(qos);
+                summary.decode_properties(&mut reader)?;
+            }
+            PacketType::Puback | PacketType::Pubrec | PacketType::Pubrel | PacketType::Pubcomp => {
+                summary.packet_identifier = Some(reader.read_u16()?);
+                // Reason code and properties can be omitted
+                if reader.position + 1 < end {
+                    reader.increment_position(1);
+                    summary.decode_properties(&mut reader)?;
+                }
+            }
+            PacketType::Subscribe | PacketType::Unsubscribe => {
+                summary.packet_identifier = Some(reader.read_u16()?);
+                summary.decode_properties(&mut reader)?;
+                if reader.position < end {
+                    summary.topic = Some(reader.read_string()?.string);
+                }
+            }
+            PacketType::Suback | PacketType::Unsuback => {
+                summary.packet_identifier = Some(reader.read_u16()?);
+                summary.decode_properties(&mut reader)?;
+            }
+            PacketType::Disconnect | PacketType::Auth => {
+                if reader.position + 1 < end {
+                    reader.increment_position(1);
+                    summary.decode_properties(&mut reader)?;
+                }
+            }
+            PacketType::Pingreq | PacketType::Pingresp | PacketType::Reserved => {}
+        }
+        Ok(summary)
+    }
+
+    fn decode_properties(&mut self, reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
+        let len = reader.read_variable_byte_int()? as usize;
+        let end = reader.position + len;
+        while reader.position < end {
+            let property = Property::decode(reader)?;
+            // Properties over the capacity are skipped
+            let _ = self.properties.push(property);
+        }
+        if reader.position != end {
+            return Err(BufferError::DecodingError);
+        }
+        Ok(())
+    }
+}
+
+/// Packet passed to the `PacketObserver`.
+#[derive(Debug, Clone)]
+pub struct ObservedPacket<'a> {
+    pub direction: Direction,
+    /// Time from the `Clock` of the client in microseconds.
+    pub timestamp_micros: u64,
+    /// Whole packet as sent or received.
+    pub raw: &'a [u8],
+    /// Decoded summary, error if the packet is malformed.
+    pub summary: Result<PacketSummary<'a>, BufferError>,
+}
+
+/// Callback receiving every packet sent and received by the client. Callback runs inside the
+/// client operation, so it should not block.
+pub trait PacketObserver {
+    fn on_packet(&mut self, packet: &ObservedPacket<'_>);
+}
+
+/// Observer registered in the client together with its clock.
+pub(crate) struct Observer<'a> {
+    pub(crate) observer: &'a mut (dyn PacketObserver + Send),
+    pub(crate) clock: &'a (dyn Clock + Sync),
+}
+
+impl<'a> Observer<'a> {
+    pub(crate) fn notify(&mut self, direction: Direction, raw: &[u8]) {
+        self.observer.on_packet(&ObservedPacket {
+            direction,
+            timestamp_micros: self.clock.now_micros(),
+            raw,
+            summary: PacketSummary::decode(raw),
+        });
+    }
+}
@@ -0,0 +1,190 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! `PacketObserver` writing the packets in the pcap format, so the capture can be opened in
+//! Wireshark. Packets are wrapped into fake IPv4 / TCP segments between the client
+//! `10.0.0.1:49152` and the broker `10.0.0.2:1883`, the stream starts with TCP handshake.
+
+use std::io::{self, Write};
+
+use crate::client::packet_observer::{Direction, ObservedPacket, PacketObserver};
+
+const CLIENT: ([u8; 4], u16) = ([10, 0, 0, 1], 49152);
+const BROKER: ([u8; 4], u16) = ([10, 0, 0, 2], 1883);
+// LINKTYPE_RAW, packets start with the IP header
+const LINKTYPE_RAW: u32 = 101;
+const SNAPLEN: u32 = 65535;
+const HEADERS_LEN: usize = 40;
+const MAX_SEGMENT_LEN: usize = 65535 - HEADERS_LEN;
+
+const SYN: u8 = 0x02;
+const PSH: u8 = 0x08;
+const ACK: u8 = 0x10;
+
+/// Writes the observed packets to `W` in the pcap format. Errors of the writer are kept and
+/// returned by `finish`, packets observed after an error are dropped.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    // Next sequence number of the client and the broker
+    client_seq: u32,
+    broker_seq: u32,
+    handshake_written: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Writes the pcap file header to `writer`.
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut header = [0; 24];
+        header[0..4].copy_from_slice(&0xa1b2_c3d4_u32.to_le_bytes());
+        header[4..6].copy_from_slice(&2_u16.to_le_bytes());
+        header[6..8].copy_from_slice(&4_u16.to_le_bytes());
+        header[16..20].copy_from_slice(&SNAPLEN.to_le_bytes());
+        header[20..24].copy_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        writer.write_all(&header)?;
+        Ok(Self {
+            writer,
+            client_seq: 1000,
+            broker_seq: 5000,
+            handshake_written: false,
+            error: None,
+        })
+    }
+
+    /// Flushes and returns the writer or the first error which occurred.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write_packet(&mut self, packet: &ObservedPacket<'_>) -> io::Result<()> {
+        let timestamp = packet.timestamp_micros;
+        if !self.handshake_written {
+            self.handshake_written = true;
+            self.write_segment(timestamp, Direction::Outbound, SYN, &[])?;
+            self.write_segment(timestamp, Direction::Inbound, SYN | ACK, &[])?;
+            self.write_segment(timestamp, Direction::Outbound, ACK, &[])?;
+        }
+        for chunk in packet.raw.chunks(MAX_SEGMENT_LEN) {
+            self.write_segment(timestamp, packet.direction, PSH | ACK, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn write_segment(
+        &mut self,
+        timestamp: u64,
+        direction: Direction,
+        flags: u8,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let (source, destination, seq, ack) = match direction {
+            Direction::Outbound => (CLIENT, BROKER, self.client_seq, self.broker_seq),
+            Direction::Inbound => (BROKER, CLIENT, self.broker_seq, self.client_seq),
+        };
+        let len = HEADERS_LEN + payload.len();
+        let mut headers = [0; HEADERS_LEN];
+
+        // IPv4 header
+        headers[0] = 0x45;
+        headers[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+        // Don't fragment, TTL 64, TCP
+        headers[6] = 0x40;
+        headers[8] = 64;
+        headers[9] = 6;
+        headers[12..16].copy_from_slice(&source.0);
+        headers[16..20].copy_from_slice(&destination.0);
+        let checksum = checksum(0, &headers[..20]);
+        headers[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+        // TCP header
+        headers[20..22].copy_from_slice(&source.1.to_be_bytes());
+        headers[22..24].copy_from_slice(&destination.1.to_be_bytes());
+        headers[24..28].copy_from_slice(&seq.to_be_bytes());
+        if flags & ACK != 0 {
+            headers[28..32].copy_from_slice(&ack.to_be_bytes());
+        }
+        headers[32] = 5 << 4;
+        headers[33] = flags;
+        headers[34..36].copy_from_slice(&u16::MAX.to_be_bytes());
+        let mut pseudo_header = [0; 12];
+        pseudo_header[0..4].copy_from_slice(&source.0);
+        pseudo_header[4..8].copy_from_slice(&destination.0);
+        pseudo_header[9] = 6;
+        pseudo_header[10..12].copy_from_slice(&((len - 20) as u16).to_be_bytes());
+        let sum = sum(sum(sum(0, &pseudo_header), &headers[20..]), payload);
+        headers[36..38].copy_from_slice(&fold(sum).to_be_bytes());
+
+        let mut record = [0; 16];
+        record[0..4].copy_from_slice(&((timestamp / 1_000_000) as u32).to_le_bytes());
+        record[4..8].copy_from_slice(&((timestamp % 1_000_000) as u32).to_le_bytes());
+        record[8..12].copy_from_slice(&(len as u32).to_le_bytes());
+        record[12..16].copy_from_slice(&(len as u32).to_le_bytes());
+        self.writer.write_all(&record)?;
+        self.writer.write_all(&headers)?;
+        self.writer.write_all(payload)?;
+
+        // SYN takes one sequence number
+        let advance = payload.len() as u32 + u32::from(flags & SYN != 0);
+        match direction {
+            Direction::Outbound => self.client_seq = self.client_seq.wrapping_add(advance),
+            Direction::Inbound => self.broker_seq = self.broker_seq.wrapping_add(advance),
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> PacketObserver for PcapWriter<W> {
+    fn on_packet(&mut self, packet: &ObservedPacket<'_>) {
+        if self.error.is_none() {
+            if let Err(err) = self.write_packet(packet) {
+                error!("Could not write packet capture");
+                self.error = Some(err);
+            }
+        }
+    }
+}
+
+/// One's complement sum of `data` as big endian 16 bit words added to `sum`. Data of odd
+/// length are padded by zero.
+fn sum(sum: u32, data: &[u8]) -> u32 {
+    data.chunks(2).fold(sum, |sum, word| {
+        let word = u16::from_be_bytes([word[0], word.get(1).copied().unwrap_or(0)]);
+        sum + u32::from(word)
+    })
+}
+
+fn fold(mut sum: u32) -> u16 {
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn checksum(initial: u32, data: &[u8]) -> u16 {
+    fold(sum(initial, data))
+}
@@ -3,9 +3,9 @@ use heapless::Vec;
 use rand_core::RngCore;
 
 use crate::{
-    encoding::variable_byte_integer::{VariableByteInteger, VariableByteIntegerDecoder},
     network::NetworkConnection,
     packet::v5::{
+        auth_packet::AuthPacket,
         connack_packet::ConnackPacket,
         connect_packet::ConnectPacket,
         disconnect_packet::DisconnectPacket,
@@ -13,6 +13,8 @@ use crate::{
         packet_type::PacketType,
         pingreq_packet::PingreqPacket,
         pingresp_packet::PingrespPacket,
+        property::Property,
+        property_kind::{AuthenticationData, AuthenticationMethod, PropertyKind, ReasonString},
         puback_packet::PubackPacket,
         publish_packet::{PublishPacket, QualityOfService},
         reason_codes::ReasonCode,
@@ -21,31 +23,160 @@ use crate::{
         unsuback_packet::UnsubackPacket,
         unsubscription_packet::UnsubscriptionPacket,
     },
-    utils::{buffer_reader::BuffReader, buffer_writer::BuffWriter, types::BufferError},
+    utils::{
+        buffer_reader::BuffReader,
+        types::{BinaryData, BufferError, EncodedString},
+    },
 };
 
+use super::authenticator::{AuthError, Authenticator};
 use super::client_config::{ClientConfig, MqttVersion};
+use super::client_error::{ClientError, ProtocolViolation};
+use super::connection_state::{ConnectionState, DisconnectReason};
+use super::credentials::CredentialsProvider;
+use super::duplicate_filter::{DuplicateFilter, DuplicateMode, MessageKey};
+use super::packet_observer::{Direction, Observer, PacketObserver};
+use super::stats::{ClientStats, StatsRecorder};
+use crate::utils::clock::Clock;
+
+/// Maximal number of properties of the received PUBLISH packet kept in `ReceivedMessage`.
+pub const MAX_MESSAGE_PROPERTIES: usize = 5;
+
+/// Application message received from the broker.
+#[derive(Debug, Clone)]
+pub struct ReceivedMessage<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QualityOfService,
+    pub retain: bool,
+    pub properties: Vec<Property<'a>, MAX_MESSAGE_PROPERTIES>,
+    /// Message was redelivered with the DUP flag and the duplicate filter already saw it.
+    pub duplicate: bool,
+}
+
+impl<'a> ReceivedMessage<'a> {
+    /// Returns value of the first property of kind `K`, e.g. `message.get::<ContentType>()`
+    pub fn get<K: PropertyKind<'a>>(&self) -> Option<K::Value> {
+        self.properties.iter().find_map(K::from_property)
+    }
 
+    /// Returns true if the message is a redelivery of a message already received, detected
+    /// by the filter set by `set_duplicate_filter`. Always false without the filter.
+    pub fn is_duplicate(&self) -> bool {
+        self.duplicate
+    }
+}
+
+// Events are short lived and the client has no allocator to box the message.
+#[allow(clippy::large_enum_variant)]
 pub enum Event<'a> {
-    Connack,
+    /// Broker accepted the connection, `true` if the broker resumed the existing session.
+    Connack(bool),
     Puback(u16),
     Suback(u16),
     Unsuback(u16),
     Pingresp,
-    Message(&'a str, &'a [u8]),
-    Disconnect(ReasonCode),
+    Message(ReceivedMessage<'a>),
+    /// Broker closed the connection with the reason code and optional reason string.
+    Disconnect(ReasonCode, Option<&'a str>),
+    /// Step of the enhanced authentication exchange was processed. `ContinueAuth` means
+    /// that the challenge was answered, `Success` finishes the re-authentication.
+    Auth(ReasonCode),
 }
 
-pub struct RawMqttClient<'a, T, const MAX_PROPERTIES: usize, R: RngCore>
+impl<'a> Event<'a> {
+    /// Returns type of the packet which produced the event.
+    pub fn packet_type(&self) -> PacketType {
+        match self {
+            Event::Connack(_) => PacketType::Connack,
+            Event::Puback(_) => PacketType::Puback,
+            Event::Suback(_) => PacketType::Suback,
+            Event::Unsuback(_) => PacketType::Unsuback,
+            Event::Pingresp => PacketType::Pingresp,
+            Event::Message(_) => PacketType::Publish,
+            Event::Disconnect(_, _) => PacketType::Disconnect,
+            Event::Auth(_) => PacketType::Auth,
+        }
+    }
+}
+
+/// Network connection together with the state of the MQTT session on top of it. Connection
+/// is present exactly in the states which have network (`ConnectionState::has_network`).
+struct Link<T>
 where
     T: Read + Write,
 {
     connection: Option<NetworkConnection<T>>,
+    state: ConnectionState,
+}
+
+impl<T> Link<T>
+where
+    T: Read + Write,
+{
+    /// Returns the connection if the current state is one of the `allowed` states.
+    fn connection(
+        &mut self,
+        allowed: &[ConnectionState],
+    ) -> Result<&mut NetworkConnection<T>, ClientError<T::Error>> {
+        if !allowed.contains(&self.state) {
+            return Err(ClientError::InvalidState(self.state));
+        }
+        Ok(self.network())
+    }
+
+    /// Returns the connection, the state has to be checked by `connection` before.
+    fn network(&mut self) -> &mut NetworkConnection<T> {
+        self.connection.as_mut().unwrap()
+    }
+
+    /// Drops the network connection and moves to the `state` without network.
+    fn close(&mut self, state: ConnectionState) {
+        trace!("Closing connection, client is {}", state);
+        self.connection = None;
+        self.state = state;
+    }
+
+    /// Closes the connection if the `err` makes it unusable and returns the error.
+    fn fail(&mut self, err: ClientError<T::Error>) -> ClientError<T::Error> {
+        let connecting = self.state == ConnectionState::AwaitingConnack;
+        let reason = match &err {
+            ClientError::Transport(_) | ClientError::NotConnected => {
+                Some(DisconnectReason::Transport)
+            }
+            ClientError::Decode(_) | ClientError::Protocol(_) => Some(DisconnectReason::Protocol),
+            ClientError::BrokerRejected { reason, .. } if connecting => {
+                Some(DisconnectReason::Rejected(*reason))
+            }
+            ClientError::Auth(_) if connecting => Some(DisconnectReason::Auth),
+            _ => None,
+        };
+        if let Some(reason) = reason {
+            if self.state.has_network() {
+                self.close(ConnectionState::Disconnected(reason));
+            }
+        }
+        err
+    }
+}
+
+pub struct RawMqttClient<'a, T, const MAX_PROPERTIES: usize, R: RngCore>
+where
+    T: Read + Write,
+{
+    link: Link<T>,
     buffer: &'a mut [u8],
     buffer_len: usize,
     recv_buffer: &'a mut [u8],
     recv_buffer_len: usize,
     config: ClientConfig<'a, MAX_PROPERTIES, R>,
+    authenticator: Option<&'a mut (dyn Authenticator + Send)>,
+    credentials: Option<&'a mut (dyn CredentialsProvider + Send)>,
+    observer: Option<Observer<'a>>,
+    stats: Option<StatsRecorder<'a>>,
+    duplicates: Option<(&'a mut (dyn DuplicateFilter + Send), DuplicateMode)>,
+    // Bytes of the packet received so far, the packet is completed by the next poll
+    received: usize,
 }
 
 impl<'a, T, const MAX_PROPERTIES: usize, R> RawMqttClient<'a, T, MAX_PROPERTIES, R>
@@ -62,48 +193,175 @@ where
         config: ClientConfig<'a, MAX_PROPERTIES, R>,
     ) -> Self {
         Self {
-            connection: Some(NetworkConnection::new(network_driver)),
+            link: Link {
+                connection: Some(NetworkConnection::new(network_driver)),
+                state: ConnectionState::TcpConnected,
+            },
             buffer,
             buffer_len,
             recv_buffer,
             recv_buffer_len,
             config,
+            authenticator: None,
+            credentials: None,
+            observer: None,
+            stats: None,
+            duplicates: None,
+            received: 0,
         }
     }
 
-    async fn connect_to_broker_v5<'b>(&'b mut self) -> Result<(), ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
+    /// Method sets the authenticator used for the MQTTv5 enhanced authentication. Authentication
+    /// method and data are sent in CONNECT and AUTH packets from the broker are answered
+    /// by the authenticator.
+    pub fn set_authenticator(&mut self, authenticator: &'a mut (dyn Authenticator + Send)) {
+        self.authenticator = Some(authenticator);
+    }
+
+    /// Method sets the provider of the credentials generated before every CONNECT, they take
+    /// precedence over the client id, username and password of the config.
+    pub fn set_credentials_provider(
+        &mut self,
+        credentials: &'a mut (dyn CredentialsProvider + Send),
+    ) {
+        self.credentials = Some(credentials);
+    }
+
+    /// Method sets the observer called with every sent and received packet, timestamps are
+    /// taken from the `clock`.
+    pub fn set_packet_observer(
+        &mut self,
+        observer: &'a mut (dyn PacketObserver + Send),
+        clock: &'a (dyn Clock + Sync),
+    ) {
+        self.observer = Some(Observer { observer, clock });
+    }
+
+    /// Method sets the statistics updated by the client, latencies are measured with the
+    /// `clock`. Statistics can be read from other tasks while the client is running.
+    pub fn set_stats(&mut self, stats: &'a ClientStats, clock: &'a (dyn Clock + Sync)) {
+        self.stats = Some(StatsRecorder::new(stats, clock));
+    }
+
+    /// Returns the statistics set by `set_stats`.
+    pub fn stats(&self) -> Option<&'a ClientStats> {
+        self.stats.as_ref().map(|recorder| recorder.stats)
+    }
+
+    /// Method sets the filter remembering received QoS 1 messages. Messages redelivered
+    /// by the broker with the DUP flag which the filter already saw are handled by `mode`.
+    /// The filter is kept when the client reconnects.
+    pub fn set_duplicate_filter(
+        &mut self,
+        filter: &'a mut (dyn DuplicateFilter + Send),
+        mode: DuplicateMode,
+    ) {
+        self.duplicates = Some((filter, mode));
+    }
+
+    /// Returns the state of the connection.
+    pub fn state(&self) -> ConnectionState {
+        self.link.state
+    }
+
+    /// Returns true if the broker accepted the connection and the session is usable.
+    pub fn is_connected(&self) -> bool {
+        self.link.state.is_connected()
+    }
+
+    /// Sets new network connection after the previous one was closed by `disconnect` or lost.
+    /// Client moves to `TcpConnected` and `connect_to_broker` can be called again.
+    pub fn set_network_driver(&mut self, network_driver: T) -> Result<(), ClientError<T::Error>> {
+        if self.link.state.has_network() {
+            return Err(ClientError::InvalidState(self.link.state));
+        }
+        self.link = Link {
+            connection: Some(NetworkConnection::new(network_driver)),
+            state: ConnectionState::TcpConnected,
+        };
+        self.received = 0;
+        Ok(())
+    }
+
+    async fn connect_to_broker_v5<'b>(&'b mut self) -> Result<(), ClientError<T::Error>> {
+        if self.link.state != ConnectionState::TcpConnected {
+            return Err(ClientError::InvalidState(self.link.state));
+        }
+        if let Some(authenticator) = self.authenticator.as_deref_mut() {
+            authenticator.start().map_err(ClientError::Auth)?;
+        }
+        if let Some(credentials) = self.credentials.as_deref_mut() {
+            credentials.refresh().map_err(ClientError::Credentials)?;
         }
         let len = {
             let mut connect = ConnectPacket::<'b, MAX_PROPERTIES, 0>::new();
             connect.keep_alive = self.config.keep_alive;
-            self.config.add_max_packet_size_as_prop();
-            connect.property_len = connect.add_properties(&self.config.properties);
-            if self.config.username_flag {
-                connect.add_username(&self.config.username);
+            connect.add_clean_start(self.config.clean_start);
+            let Some(properties) = self.config.connect_properties() else {
+                error!("Config properties do not fit into CONNECT properties!");
+                return Err(ClientError::Encode(BufferError::InsufficientBufferSize));
+            };
+            connect.property_len = connect.add_properties(&properties);
+            if let Some(authenticator) = self.authenticator.as_deref() {
+                let properties = auth_properties(authenticator);
+                if connect.properties.len() + properties.len() > MAX_PROPERTIES {
+                    error!("Authentication properties do not fit into CONNECT properties!");
+                    return Err(ClientError::Encode(BufferError::InsufficientBufferSize));
+                }
+                connect.property_len += connect.add_properties(&properties);
+            }
+            let credentials = self.credentials.as_deref();
+            match credentials.and_then(|credentials| credentials.username()) {
+                Some(username) => connect.add_username(&EncodedString {
+                    string: username,
+                    len: username.len() as u16,
+                }),
+                None if self.config.username_flag => {
+                    connect.add_username(&self.config.encoded_username())
+                }
+                None => {}
             }
-            if self.config.password_flag {
-                connect.add_password(&self.config.password)
+            match credentials.and_then(|credentials| credentials.password()) {
+                Some(password) => connect.add_password(&BinaryData {
+                    bin: password,
+                    len: password.len() as u16,
+                }),
+                None if self.config.password_flag => connect.add_password(&self.config.password),
+                None => {}
             }
             if self.config.will_flag {
                 connect.add_will(
-                    &self.config.will_topic,
+                    &self.config.encoded_will_topic(),
                     &self.config.will_payload,
                     self.config.will_retain,
-                )
+                );
+                connect.add_will_qos(self.config.will_qos);
+            }
+            match credentials.and_then(|credentials| credentials.client_id()) {
+                Some(client_id) => connect.add_client_id(&EncodedString {
+                    string: client_id,
+                    len: client_id.len() as u16,
+                }),
+                None => connect.add_client_id(&self.config.encoded_client_id()),
             }
-            connect.add_client_id(&self.config.client_id);
             connect.encode(self.buffer, self.buffer_len)
         };
 
         if let Err(err) = len {
-            error!("[DECODE ERR]: {}", err);
-            return Err(ReasonCode::BuffError);
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
-        let conn = self.connection.as_mut().unwrap();
+        let conn = self.link.connection(&[ConnectionState::TcpConnected])?;
         trace!("Sending connect");
-        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
+        self.link.state = ConnectionState::AwaitingConnack;
 
         Ok(())
     }
@@ -112,33 +370,94 @@ where
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the connection to the broker fails, method returns Err variable that contains
     /// Reason codes returned from the broker.
-    pub async fn connect_to_broker<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    pub async fn connect_to_broker(&mut self) -> Result<(), ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
             MqttVersion::MQTTv5 => self.connect_to_broker_v5().await,
         }
     }
 
-    async fn disconnect_v5<'b>(&'b mut self) -> Result<(), ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
+    async fn reauthenticate_v5(&mut self) -> Result<(), ClientError<T::Error>> {
+        let Some(authenticator) = self.authenticator.as_deref_mut() else {
+            return Err(ClientError::Auth(AuthError::NotConfigured));
+        };
+        if self.link.state != ConnectionState::Connected {
+            return Err(ClientError::InvalidState(self.link.state));
+        }
+        authenticator.start().map_err(ClientError::Auth)?;
+        let len = encode_auth(
+            self.buffer,
+            self.buffer_len,
+            ReasonCode::ReAuthenticate,
+            authenticator,
+        );
+
+        if let Err(err) = len {
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
-        let conn = self.connection.as_mut().unwrap();
+        let conn = self.link.connection(&[ConnectionState::Connected])?;
+        trace!("Sending re-authentication");
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
+
+        Ok(())
+    }
+
+    /// Method starts re-authentication of the connected client with the authenticator set
+    /// by `set_authenticator`. Challenges from the broker are answered by `poll` which
+    /// returns `Event::Auth(ReasonCode::Success)` once the broker accepts the credentials.
+    pub async fn reauthenticate(&mut self) -> Result<(), ClientError<T::Error>> {
+        match self.config.mqtt_version {
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
+            MqttVersion::MQTTv5 => self.reauthenticate_v5().await,
+        }
+    }
+
+    async fn disconnect_v5<'b>(&'b mut self) -> Result<(), ClientError<T::Error>> {
+        match self.link.state {
+            ConnectionState::Connected => {}
+            // Session was not established, DISCONNECT must not be sent before CONNACK
+            ConnectionState::TcpConnected | ConnectionState::AwaitingConnack => {
+                self.link.close(ConnectionState::Idle);
+                return Ok(());
+            }
+            state => return Err(ClientError::InvalidState(state)),
+        }
+        self.link.state = ConnectionState::Disconnecting;
+        let conn = self.link.connection(&[ConnectionState::Disconnecting])?;
         trace!("Creating disconnect packet!");
         let mut disconnect = DisconnectPacket::<'b, MAX_PROPERTIES>::new();
         let len = disconnect.encode(self.buffer, self.buffer_len);
         if let Err(err) = len {
-            warn!("[DECODE ERR]: {}", err);
-            let _ = self.connection.take();
-            return Err(ReasonCode::BuffError);
+            warn!("[ENCODE ERR]: {}", err);
+            self.link.close(ConnectionState::Idle);
+            return Err(ClientError::Encode(err));
         }
 
-        if let Err(_e) = conn.send(&self.buffer[0..len.unwrap()]).await {
+        if let Err(_e) = send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        {
             warn!("Could not send DISCONNECT packet");
         }
 
         // Drop connection
-        let _ = self.connection.take();
+        self.link.close(ConnectionState::Idle);
         Ok(())
     }
 
@@ -146,24 +465,24 @@ where
     /// in the `ClientConfig`. Method selects proper implementation of the MQTT version based on the config.
     /// If the disconnect from the broker fails, method returns Err variable that contains
     /// Reason codes returned from the broker.
-    pub async fn disconnect<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    pub async fn disconnect(&mut self) -> Result<(), ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
             MqttVersion::MQTTv5 => self.disconnect_v5().await,
         }
     }
 
-    async fn send_message_v5<'b>(
+    async fn send_message_v5<'b, const PROPERTIES: usize>(
         &'b mut self,
         topic_name: &'b str,
         message: &'b [u8],
         qos: QualityOfService,
         retain: bool,
-    ) -> Result<u16, ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
-        }
-        let conn = self.connection.as_mut().unwrap();
+        properties: &Vec<Property<'b>, PROPERTIES>,
+    ) -> Result<u16, ClientError<T::Error>> {
+        let conn = self.link.connection(&[ConnectionState::Connected])?;
         let identifier: u16 = self.config.rng.next_u32() as u16;
         //self.rng.next_u32() as u16;
         let len = {
@@ -173,15 +492,23 @@ where
             packet.add_identifier(identifier);
             packet.add_message(message);
             packet.add_retain(retain);
+            packet.property_len = packet.add_properties(properties);
             packet.encode(self.buffer, self.buffer_len)
         };
 
         if let Err(err) = len {
-            error!("[DECODE ERR]: {}", err);
-            return Err(ReasonCode::BuffError);
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
         trace!("Sending message");
-        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
 
         Ok(identifier)
     }
@@ -195,21 +522,38 @@ where
         message: &'b [u8],
         qos: QualityOfService,
         retain: bool,
-    ) -> Result<u16, ReasonCode> {
+    ) -> Result<u16, ClientError<T::Error>> {
+        self.send_message_with_properties(topic_name, message, qos, retain, &Vec::<_, 0>::new())
+            .await
+    }
+
+    /// Method works the same way as `send_message` but additionally attaches the PUBLISH
+    /// properties from the `properties` Vec (e.g. `MessageExpiryInterval` or `ContentType`).
+    /// Properties which are not allowed for the PUBLISH packet are skipped.
+    pub async fn send_message_with_properties<'b, const PROPERTIES: usize>(
+        &'b mut self,
+        topic_name: &'b str,
+        message: &'b [u8],
+        qos: QualityOfService,
+        retain: bool,
+        properties: &Vec<Property<'b>, PROPERTIES>,
+    ) -> Result<u16, ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
-            MqttVersion::MQTTv5 => self.send_message_v5(topic_name, message, qos, retain).await,
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
+            MqttVersion::MQTTv5 => {
+                self.send_message_v5(topic_name, message, qos, retain, properties)
+                    .await
+            }
         }
     }
 
     async fn subscribe_to_topics_v5<'b, const TOPICS: usize>(
         &'b mut self,
         topic_names: &'b Vec<&'b str, TOPICS>,
-    ) -> Result<u16, ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
-        }
-        let conn = self.connection.as_mut().unwrap();
+    ) -> Result<u16, ClientError<T::Error>> {
+        let conn = self.link.connection(&[ConnectionState::Connected])?;
         let identifier: u16 = self.config.rng.next_u32() as u16;
         let len = {
             let mut subs = SubscriptionPacket::<'b, TOPICS, MAX_PROPERTIES>::new();
@@ -221,11 +565,18 @@ where
         };
 
         if let Err(err) = len {
-            error!("[DECODE ERR]: {}", err);
-            return Err(ReasonCode::BuffError);
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
 
-        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
 
         Ok(identifier)
     }
@@ -237,9 +588,11 @@ where
     pub async fn subscribe_to_topics<'b, const TOPICS: usize>(
         &'b mut self,
         topic_names: &'b Vec<&'b str, TOPICS>,
-    ) -> Result<u16, ReasonCode> {
+    ) -> Result<u16, ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
             MqttVersion::MQTTv5 => self.subscribe_to_topics_v5(topic_names).await,
         }
     }
@@ -250,9 +603,11 @@ where
     pub async fn unsubscribe_from_topic<'b>(
         &'b mut self,
         topic_name: &'b str,
-    ) -> Result<u16, ReasonCode> {
+    ) -> Result<u16, ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
             MqttVersion::MQTTv5 => self.unsubscribe_from_topic_v5(topic_name).await,
         }
     }
@@ -260,11 +615,8 @@ where
     async fn unsubscribe_from_topic_v5<'b>(
         &'b mut self,
         topic_name: &'b str,
-    ) -> Result<u16, ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
-        }
-        let conn = self.connection.as_mut().unwrap();
+    ) -> Result<u16, ClientError<T::Error>> {
+        let conn = self.link.connection(&[ConnectionState::Connected])?;
         let identifier = self.config.rng.next_u32() as u16;
 
         let len = {
@@ -275,30 +627,41 @@ where
         };
 
         if let Err(err) = len {
-            error!("[DECODE ERR]: {}", err);
-            return Err(ReasonCode::BuffError);
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
-        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
 
         Ok(identifier)
     }
 
-    async fn send_ping_v5<'b>(&'b mut self) -> Result<(), ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
-        }
-        let conn = self.connection.as_mut().unwrap();
+    async fn send_ping_v5(&mut self) -> Result<(), ClientError<T::Error>> {
+        let conn = self.link.connection(&[ConnectionState::Connected])?;
         let len = {
             let mut packet = PingreqPacket::new();
             packet.encode(self.buffer, self.buffer_len)
         };
 
         if let Err(err) = len {
-            error!("[DECODE ERR]: {}", err);
-            return Err(ReasonCode::BuffError);
+            error!("[ENCODE ERR]: {}", err);
+            return Err(ClientError::Encode(err));
         }
 
-        conn.send(&self.buffer[0..len.unwrap()]).await?;
+        send_packet(
+            conn,
+            &mut self.observer,
+            &mut self.stats,
+            &self.buffer[0..len.unwrap()],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
 
         Ok(())
     }
@@ -306,96 +669,215 @@ where
     /// Method allows client send PING message to the broker specified in the `ClientConfig`.
     /// If there is expectation for long running connection. Method should be executed
     /// regularly by the timer that counts down the session expiry interval.
-    pub async fn send_ping<'b>(&'b mut self) -> Result<(), ReasonCode> {
+    pub async fn send_ping(&mut self) -> Result<(), ClientError<T::Error>> {
         match self.config.mqtt_version {
-            MqttVersion::MQTTv3 => Err(ReasonCode::UnsupportedProtocolVersion),
+            MqttVersion::MQTTv3 => Err(ClientError::Protocol(
+                ProtocolViolation::UnsupportedProtocolVersion,
+            )),
             MqttVersion::MQTTv5 => self.send_ping_v5().await,
         }
     }
 
-    pub async fn poll<'b, const MAX_TOPICS: usize>(&'b mut self) -> Result<Event<'b>, ReasonCode> {
-        if self.connection.is_none() {
-            return Err(ReasonCode::NetworkError);
+    /// Method waits for the next packet from the broker and returns it as `Event`. Waiting
+    /// can be cancelled - if the future is dropped, partially received packet is kept and
+    /// completed by the next call, provided the network driver read is cancel safe.
+    pub async fn poll<'b, const MAX_TOPICS: usize>(
+        &'b mut self,
+    ) -> Result<Event<'b>, ClientError<T::Error>> {
+        let stats = self.stats();
+        let res = self.poll_packet::<MAX_TOPICS>().await;
+        if let (Some(stats), Err(err)) = (stats, &res) {
+            stats.record_error(err);
         }
+        res
+    }
 
-        let conn = self.connection.as_mut().unwrap();
+    async fn poll_packet<'b, const MAX_TOPICS: usize>(
+        &'b mut self,
+    ) -> Result<Event<'b>, ClientError<T::Error>> {
+        let stats = self.stats();
+        let conn = self
+            .link
+            .connection(&[ConnectionState::AwaitingConnack, ConnectionState::Connected])?;
 
         trace!("Waiting for a packet");
 
-        let read = { receive_packet(self.buffer, self.buffer_len, self.recv_buffer, conn).await? };
+        let mut read = receive_packet(
+            self.buffer,
+            self.buffer_len,
+            self.recv_buffer,
+            &mut self.received,
+            conn,
+        )
+        .await
+        .map_err(|err| self.link.fail(err))?;
+        self.notify_received(read);
+        // Suppressed duplicates are acknowledged before the packet is decoded into the event
+        // borrowing the buffer, the next packet is received in their place
+        let mut duplicate = self.check_duplicate(read);
+        while duplicate && self.suppress_duplicates() {
+            let identifier = MessageKey::from_publish(&self.buffer[..read])
+                .map_or(0, |(key, _)| key.packet_identifier);
+            debug!("Suppressing redelivered message {}", identifier);
+            self.send_puback(identifier).await?;
+            read = receive_packet(
+                self.buffer,
+                self.buffer_len,
+                self.recv_buffer,
+                &mut self.received,
+                self.link.network(),
+            )
+            .await
+            .map_err(|err| self.link.fail(err))?;
+            self.notify_received(read);
+            duplicate = self.check_duplicate(read);
+        }
 
         let buf_reader = BuffReader::new(self.buffer, read);
 
-        match PacketType::from(buf_reader.peek_u8().map_err(|_| ReasonCode::BuffError)?) {
-            PacketType::Reserved
+        match PacketType::from(
+            buf_reader
+                .peek_u8()
+                .map_err(|err| self.link.fail(ClientError::Decode(err)))?,
+        ) {
+            packet_type @ (PacketType::Reserved
             | PacketType::Connect
             | PacketType::Subscribe
             | PacketType::Unsubscribe
-            | PacketType::Pingreq => Err(ReasonCode::ProtocolError),
-            PacketType::Pubrec | PacketType::Pubrel | PacketType::Pubcomp | PacketType::Auth => {
-                Err(ReasonCode::ImplementationSpecificError)
+            | PacketType::Pingreq
+            | PacketType::Pubrec
+            | PacketType::Pubrel
+            | PacketType::Pubcomp) => {
+                error!("Received unexpected packet {:?}", packet_type);
+                Err(self
+                    .link
+                    .fail(ClientError::Protocol(ProtocolViolation::UnexpectedPacket(
+                        packet_type,
+                    ))))
             }
             PacketType::Connack => {
                 let mut packet = ConnackPacket::<'b, MAX_PROPERTIES>::new();
                 if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
-                    // if err == BufferError::PacketTypeMismatch {
-                    //     let mut disc = DisconnectPacket::<'b, MAX_PROPERTIES>::new();
-                    //     if disc.decode(&mut BuffReader::new(self.buffer, read)).is_ok() {
-                    //         error!("Client was disconnected with reason: ");
-                    //         return Err(ReasonCode::from(disc.disconnect_reason));
-                    //     }
-                    // }
                     error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
-                } else if packet.connect_reason_code != 0x00 {
-                    Err(ReasonCode::from(packet.connect_reason_code))
-                } else {
-                    Ok(Event::Connack)
+                    return Err(self.link.fail(ClientError::from_decode_error(err)));
+                }
+                if packet.connect_reason_code != 0x00 {
+                    return Err(self.link.fail(ClientError::rejected(
+                        ReasonCode::from(packet.connect_reason_code),
+                        packet.get::<ReasonString>(),
+                    )));
                 }
+                let session_present = packet.ack_flags & 0x01 != 0;
+                if session_present && self.config.clean_start {
+                    error!("Broker resumed session although clean start was requested");
+                    return Err(self.link.fail(ClientError::Protocol(
+                        ProtocolViolation::UnexpectedSessionPresent,
+                    )));
+                }
+                if let Some(authenticator) = self.authenticator.as_deref_mut() {
+                    if packet.get::<AuthenticationMethod>() != Some(authenticator.method()) {
+                        return Err(self.link.fail(ClientError::Auth(AuthError::MethodMismatch)));
+                    }
+                    authenticator
+                        .finish(packet.get::<AuthenticationData>().unwrap_or(&[]))
+                        .map_err(|err| self.link.fail(ClientError::Auth(err)))?;
+                }
+                if let Some(stats) = stats {
+                    stats.record_connect();
+                }
+                self.link.state = ConnectionState::Connected;
+                Ok(Event::Connack(session_present))
             }
-            PacketType::Puback => {
-                let reason: Result<[u16; 2], BufferError> = {
-                    let mut packet = PubackPacket::<'b, MAX_PROPERTIES>::new();
-                    packet
-                        .decode(&mut BuffReader::new(self.buffer, read))
-                        .map(|_| [packet.packet_identifier, packet.reason_code as u16])
+            PacketType::Auth => {
+                let mut packet = AuthPacket::<'b, MAX_PROPERTIES>::new();
+                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
+                    error!("[DECODE ERR]: {}", err);
+                    return Err(self.link.fail(ClientError::from_decode_error(err)));
+                }
+                let Some(authenticator) = self.authenticator.as_deref_mut() else {
+                    error!("Received AUTH packet without authenticator");
+                    return Err(self.link.fail(ClientError::Protocol(
+                        ProtocolViolation::UnexpectedPacket(PacketType::Auth),
+                    )));
                 };
 
-                if let Err(err) = reason {
-                    error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+                let reason = ReasonCode::from(packet.auth_reason);
+                if reason != ReasonCode::Success && reason != ReasonCode::ContinueAuth {
+                    return Err(self
+                        .link
+                        .fail(ClientError::rejected(reason, packet.get::<ReasonString>())));
+                }
+                if packet.get::<AuthenticationMethod>() != Some(authenticator.method()) {
+                    return Err(self.link.fail(ClientError::Auth(AuthError::MethodMismatch)));
+                }
+                let data = packet.get::<AuthenticationData>().unwrap_or(&[]);
+                if reason == ReasonCode::Success {
+                    authenticator
+                        .finish(data)
+                        .map_err(|err| self.link.fail(ClientError::Auth(err)))?;
+                    return Ok(Event::Auth(reason));
                 }
 
-                let res = reason.unwrap();
+                authenticator
+                    .challenge(data)
+                    .map_err(|err| self.link.fail(ClientError::Auth(err)))?;
+                let len = encode_auth(
+                    self.recv_buffer,
+                    self.recv_buffer_len,
+                    ReasonCode::ContinueAuth,
+                    authenticator,
+                );
+                if let Err(err) = len {
+                    error!("[ENCODE ERR]: {}", err);
+                    return Err(ClientError::Encode(err));
+                }
+                send_packet(
+                    self.link.network(),
+                    &mut self.observer,
+                    &mut self.stats,
+                    &self.recv_buffer[0..len.unwrap()],
+                )
+                .await
+                .map_err(|err| self.link.fail(err))?;
+                Ok(Event::Auth(reason))
+            }
+            PacketType::Puback => {
+                let mut packet = PubackPacket::<'b, MAX_PROPERTIES>::new();
+                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
+                    error!("[DECODE ERR]: {}", err);
+                    return Err(self.link.fail(ClientError::from_decode_error(err)));
+                }
 
-                if res[1] != 0 {
-                    return Err(ReasonCode::from(res[1] as u8));
+                if packet.reason_code != 0 {
+                    if let Some(stats) = stats {
+                        stats.record_publish_failure();
+                    }
+                    return Err(ClientError::rejected(
+                        ReasonCode::from(packet.reason_code),
+                        packet.get::<ReasonString>(),
+                    ));
                 }
 
-                Ok(Event::Puback(res[0]))
+                Ok(Event::Puback(packet.packet_identifier))
             }
             PacketType::Suback => {
-                let reason: Result<(u16, Vec<u8, MAX_TOPICS>), BufferError> = {
-                    let mut packet = SubackPacket::<'b, MAX_TOPICS, MAX_PROPERTIES>::new();
-                    packet
-                        .decode(&mut BuffReader::new(self.buffer, read))
-                        .map(|_| (packet.packet_identifier, packet.reason_codes))
-                };
-
-                if let Err(err) = reason {
+                let mut packet = SubackPacket::<'b, MAX_TOPICS, MAX_PROPERTIES>::new();
+                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
                     error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+                    return Err(self.link.fail(ClientError::from_decode_error(err)));
                 }
-                let (packet_identifier, reasons) = reason.unwrap();
-                for reason_code in &reasons {
+                for reason_code in &packet.reason_codes {
                     if *reason_code
                         != (<QualityOfService as Into<u8>>::into(self.config.max_subscribe_qos)
                             >> 1)
                     {
-                        return Err(ReasonCode::from(*reason_code));
+                        return Err(ClientError::rejected(
+                            ReasonCode::from(*reason_code),
+                            packet.get::<ReasonString>(),
+                        ));
                     }
                 }
-                Ok(Event::Suback(packet_identifier))
+                Ok(Event::Suback(packet.packet_identifier))
             }
             PacketType::Unsuback => {
                 let res: Result<u16, BufferError> = {
@@ -405,34 +887,28 @@ where
                         .map(|_| packet.packet_identifier)
                 };
 
-                if let Err(err) = res {
-                    error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
-                } else {
-                    Ok(Event::Unsuback(res.unwrap()))
+                match res {
+                    Ok(identifier) => Ok(Event::Unsuback(identifier)),
+                    Err(err) => {
+                        error!("[DECODE ERR]: {}", err);
+                        Err(self.link.fail(ClientError::from_decode_error(err)))
+                    }
                 }
             }
             PacketType::Pingresp => {
                 let mut packet = PingrespPacket::new();
                 if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
                     error!("[DECODE ERR]: {}", err);
-                    Err(ReasonCode::BuffError)
+                    Err(self.link.fail(ClientError::from_decode_error(err)))
                 } else {
                     Ok(Event::Pingresp)
                 }
             }
             PacketType::Publish => {
-                let mut packet = PublishPacket::<'b, 5>::new();
-                if let Err(err) = { packet.decode(&mut BuffReader::new(self.buffer, read)) } {
-                    // if err == BufferError::PacketTypeMismatch {
-                    //     let mut disc = DisconnectPacket::<'b, 5>::new();
-                    //     if disc.decode(&mut BuffReader::new(self.buffer, read)).is_ok() {
-                    //         error!("Client was disconnected with reason: ");
-                    //         return Err(ReasonCode::from(disc.disconnect_reason));
-                    //     }
-                    // }
+                let mut packet = PublishPacket::<'b, MAX_MESSAGE_PROPERTIES>::new();
+                if let Err(err) = packet.decode(&mut BuffReader::new(self.buffer, read)) {
                     error!("[DECODE ERR]: {}", err);
-                    return Err(ReasonCode::BuffError);
+                    return Err(self.link.fail(ClientError::from_decode_error(err)));
                 }
 
                 if (packet.fixed_header & 0x06)
@@ -444,119 +920,247 @@ where
                     {
                         let len = { puback.encode(self.recv_buffer, self.recv_buffer_len) };
                         if let Err(err) = len {
-                            error!("[DECODE ERR]: {}", err);
-                            return Err(ReasonCode::BuffError);
+                            error!("[ENCODE ERR]: {}", err);
+                            return Err(ClientError::Encode(err));
                         }
-                        conn.send(&self.recv_buffer[0..len.unwrap()]).await?;
+                        send_packet(
+                            self.link.network(),
+                            &mut self.observer,
+                            &mut self.stats,
+                            &self.recv_buffer[0..len.unwrap()],
+                        )
+                        .await
+                        .map_err(|err| self.link.fail(err))?;
                     }
                 }
 
-                Ok(Event::Message(
-                    packet.topic_name.string,
-                    packet.message.unwrap(),
-                ))
+                match packet.message {
+                    Some(payload) => Ok(Event::Message(ReceivedMessage {
+                        topic: packet.topic_name.string,
+                        payload,
+                        qos: QualityOfService::from(packet.fixed_header & 0x06),
+                        retain: packet.fixed_header & 0x01 != 0,
+                        properties: packet.properties,
+                        duplicate,
+                    })),
+                    None => Err(self
+                        .link
+                        .fail(ClientError::Decode(BufferError::DecodingError))),
+                }
             }
             PacketType::Disconnect => {
                 let mut disc = DisconnectPacket::<'b, 5>::new();
                 let res = disc.decode(&mut BuffReader::new(self.buffer, read));
 
                 match res {
-                    Ok(_) => Ok(Event::Disconnect(ReasonCode::from(disc.disconnect_reason))),
+                    Ok(_) => {
+                        if let Some(stats) = stats {
+                            stats.record_disconnect(disc.disconnect_reason);
+                        }
+                        let reason = ReasonCode::from(disc.disconnect_reason);
+                        self.link
+                            .close(ConnectionState::Disconnected(DisconnectReason::Broker(
+                                reason,
+                            )));
+                        Ok(Event::Disconnect(
+                            ReasonCode::from(disc.disconnect_reason),
+                            disc.get::<ReasonString>(),
+                        ))
+                    }
                     Err(err) => {
                         error!("[DECODE ERR]: {}", err);
-                        Err(ReasonCode::BuffError)
+                        Err(self.link.fail(ClientError::from_decode_error(err)))
                     }
                 }
             }
         }
     }
-}
-
-#[cfg(not(feature = "tls"))]
-async fn receive_packet<'c, T: Read + Write>(
-    buffer: &mut [u8],
-    buffer_len: usize,
-    recv_buffer: &mut [u8],
-    conn: &'c mut NetworkConnection<T>,
-) -> Result<usize, ReasonCode> {
-    use crate::utils::buffer_writer::RemLenError;
-
-    let target_len: usize;
-    let mut rem_len: Result<VariableByteInteger, RemLenError>;
-    let mut writer = BuffWriter::new(buffer, buffer_len);
-    let mut i = 0;
 
-    // Get len of packet
-    trace!("Reading lenght of packet");
-    loop {
-        trace!("    Reading in loop!");
-        let len: usize = conn
-            .receive(&mut recv_buffer[writer.position..(writer.position + 1)])
-            .await?;
-        trace!("    Received data!");
-        if len == 0 {
-            trace!("Zero byte len packet received, dropping connection.");
-            return Err(ReasonCode::NetworkError);
+    /// Reports the received packet of `len` bytes to the packet observer and statistics.
+    fn notify_received(&mut self, len: usize) {
+        if let Some(observer) = self.observer.as_mut() {
+            observer.notify(Direction::Inbound, &self.buffer[..len]);
         }
-        i += len;
-        if let Err(_e) = writer.insert_ref(len, &recv_buffer[writer.position..i]) {
-            error!("Error occurred during write to buffer!");
-            return Err(ReasonCode::BuffError);
+        if let Some(recorder) = self.stats.as_mut() {
+            recorder.on_receive(&self.buffer[..len]);
         }
-        if i > 1 {
-            rem_len = writer.get_rem_len();
-            if rem_len.is_ok() {
-                break;
-            }
-            if i >= 5 {
-                error!("Could not read len of packet!");
-                return Err(ReasonCode::NetworkError);
-            }
+    }
+
+    /// Remembers the received QoS 1 message in the duplicate filter and returns true if it
+    /// is a redelivery of a message the filter already saw.
+    fn check_duplicate(&mut self, len: usize) -> bool {
+        let Some((filter, _)) = self.duplicates.as_mut() else {
+            return false;
+        };
+        match MessageKey::from_publish(&self.buffer[..len]) {
+            Some((key, dup)) => filter.insert(key) && dup,
+            None => false,
         }
     }
-    trace!("Lenght done!");
-    let rem_len_len = i;
-    i = 0;
-    if let Ok(l) = VariableByteIntegerDecoder::decode(rem_len.unwrap()) {
-        trace!("Reading packet with target len {}", l);
-        target_len = l as usize;
-    } else {
-        error!("Could not decode len of packet!");
-        return Err(ReasonCode::BuffError);
+
+    fn suppress_duplicates(&self) -> bool {
+        matches!(self.duplicates, Some((_, DuplicateMode::Suppress)))
     }
 
-    loop {
-        if writer.position == target_len + rem_len_len {
-            trace!("Received packet with len: {}", (target_len + rem_len_len));
-            return Ok(target_len + rem_len_len);
-        }
-        let len: usize = conn
-            .receive(&mut recv_buffer[writer.position..writer.position + (target_len - i)])
-            .await?;
-        i += len;
-        if let Err(_e) =
-            writer.insert_ref(len, &recv_buffer[writer.position..(writer.position + i)])
-        {
-            error!("Error occurred during write to buffer!");
-            return Err(ReasonCode::BuffError);
+    async fn send_puback(&mut self, identifier: u16) -> Result<(), ClientError<T::Error>> {
+        let mut puback = PubackPacket::<'_, MAX_PROPERTIES>::new();
+        puback.packet_identifier = identifier;
+        puback.reason_code = 0x00;
+        let len = puback
+            .encode(self.recv_buffer, self.recv_buffer_len)
+            .map_err(|err| {
+                error!("[ENCODE ERR]: {}", err);
+                ClientError::Encode(err)
+            })?;
+        send_packet(
+            self.link.network(),
+            &mut self.observer,
+            &mut self.stats,
+            &self.recv_buffer[0..len],
+        )
+        .await
+        .map_err(|err| self.link.fail(err))
+    }
+}
+
+/// Authentication method and the current response of `authenticator` as properties.
+fn auth_properties<'b>(authenticator: &'b dyn Authenticator) -> Vec<Property<'b>, 2> {
+    let mut properties = Vec::new();
+    let method = authenticator.method();
+    let _ = properties.push(Property::AuthenticationMethod(EncodedString {
+        string: method,
+        len: method.len() as u16,
+    }));
+    let data = authenticator.response();
+    if !data.is_empty() {
+        let _ = properties.push(Property::AuthenticationData(BinaryData {
+            bin: data,
+            len: data.len() as u16,
+        }));
+    }
+    properties
+}
+
+/// Encodes AUTH packet with the `reason` and the current response of `authenticator`.
+fn encode_auth(
+    buffer: &mut [u8],
+    buffer_len: usize,
+    reason: ReasonCode,
+    authenticator: &dyn Authenticator,
+) -> Result<usize, BufferError> {
+    let mut auth = AuthPacket::<'_, 2>::new();
+    auth.auth_reason = reason.into();
+    auth.property_len = auth.add_properties(&auth_properties(authenticator));
+    auth.encode(buffer, buffer_len)
+}
+
+/// Sends the `packet` and reports it to the packet observer and statistics.
+async fn send_packet<T: Read + Write>(
+    conn: &mut NetworkConnection<T>,
+    observer: &mut Option<Observer<'_>>,
+    stats: &mut Option<StatsRecorder<'_>>,
+    packet: &[u8],
+) -> Result<(), ClientError<T::Error>> {
+    if let Some(observer) = observer {
+        observer.notify(Direction::Outbound, packet);
+    }
+    let res = conn.send(packet).await;
+    if let Some(recorder) = stats {
+        match &res {
+            Ok(()) => recorder.on_send(packet),
+            Err(err) => recorder.on_send_error(packet, err),
         }
     }
+    res
 }
 
-#[cfg(feature = "tls")]
-async fn receive_packet<'c, T: Read + Write>(
+/// Reads one packet into `buffer`. Number of already received bytes of the packet is kept in
+/// `received`, so reading continues where it stopped if the previous call was cancelled.
+/// Fixed header is read byte by byte to not read beyond the packet.
+async fn receive_packet<T: Read + Write>(
     buffer: &mut [u8],
     buffer_len: usize,
     recv_buffer: &mut [u8],
-    conn: &'c mut NetworkConnection<T>,
-) -> Result<usize, ReasonCode> {
-    trace!("Reading packet");
-    let mut writer = BuffWriter::new(buffer, buffer_len);
-    let len = conn.receive(recv_buffer).await?;
-    if let Err(_e) = writer.insert_ref(len, &recv_buffer[writer.position..(writer.position + len)])
-    {
-        error!("Error occurred during write to buffer!");
-        return Err(ReasonCode::BuffError);
-    }
-    Ok(len)
+    received: &mut usize,
+    conn: &mut NetworkConnection<T>,
+) -> Result<usize, ClientError<T::Error>> {
+    let res = receive_packet_part(buffer, buffer_len, recv_buffer, received, conn).await;
+    if res.is_err() {
+        *received = 0;
+    }
+    res
+}
+
+async fn receive_packet_part<T: Read + Write>(
+    buffer: &mut [u8],
+    buffer_len: usize,
+    recv_buffer: &mut [u8],
+    received: &mut usize,
+    conn: &mut NetworkConnection<T>,
+) -> Result<usize, ClientError<T::Error>> {
+    loop {
+        let position = *received;
+        let target_len = match packet_len(&buffer[..position])? {
+            Some(packet_len) => {
+                if packet_len > buffer_len || packet_len > recv_buffer.len() {
+                    error!(
+                        "Packet with len {} does not fit into the buffer!",
+                        packet_len
+                    );
+                    return Err(ClientError::Decode(BufferError::InsufficientBufferSize));
+                }
+                if position == packet_len {
+                    trace!("Received packet with len: {}", packet_len);
+                    *received = 0;
+                    return Ok(packet_len);
+                }
+                packet_len
+            }
+            // Next byte of the fixed header
+            None => position + 1,
+        };
+        if target_len > buffer_len || target_len > recv_buffer.len() {
+            error!("Receive buffer is too small!");
+            return Err(ClientError::Decode(BufferError::InsufficientBufferSize));
+        }
+
+        let len: usize = conn.receive(&mut recv_buffer[position..target_len]).await?;
+        if len == 0 {
+            trace!("Zero byte len packet received, dropping connection.");
+            return Err(ClientError::NotConnected);
+        }
+        buffer[position..position + len].copy_from_slice(&recv_buffer[position..position + len]);
+        *received += len;
+    }
+}
+
+/// Returns length of the whole packet if the fixed header in `header` is complete.
+fn packet_len<E>(header: &[u8]) -> Result<Option<usize>, ClientError<E>> {
+    use crate::encoding::variable_byte_integer::VariableByteIntegerDecoder;
+
+    let mut rem_len: [u8; 4] = [0; 4];
+    for (i, byte) in header.iter().skip(1).enumerate() {
+        if i == 4 {
+            break;
+        }
+        rem_len[i] = *byte;
+        if byte & 0x80 == 0 {
+            return match VariableByteIntegerDecoder::decode(rem_len) {
+                Ok(len) => Ok(Some(len as usize + i + 2)),
+                Err(_) => {
+                    error!("Could not decode len of packet!");
+                    Err(ClientError::Protocol(
+                        ProtocolViolation::MalformedRemainingLength,
+                    ))
+                }
+            };
+        }
+    }
+    if header.len() >= 5 {
+        error!("Could not read len of packet!");
+        return Err(ClientError::Protocol(
+            ProtocolViolation::MalformedRemainingLength,
+        ));
+    }
+    Ok(None)
 }
@@ -0,0 +1,167 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::Write;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use heapless::{String, Vec};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::credentials::{CredentialsError, CredentialsProvider};
+use crate::utils::clock::Clock;
+
+/// API version sent in the username, required by Azure IoT Hub.
+pub const AZURE_API_VERSION: &str = "2021-04-12";
+/// Capacity of the username `{host}/{device id}/?api-version=...`.
+pub const MAX_SAS_USERNAME_LEN: usize = 128;
+/// Capacity of the SAS token.
+pub const MAX_SAS_TOKEN_LEN: usize = 256;
+/// Maximal length of the decoded device key.
+pub const MAX_SAS_KEY_LEN: usize = 64;
+
+/// `CredentialsProvider` for Azure IoT Hub devices authenticated by a symmetric key. Every
+/// connect generates a new shared access signature token valid for `ttl` seconds, signed with
+/// HMAC-SHA256 over the resource URI `{host}/devices/{device id}` and the expiry. Client id is
+/// the device id and the username has the form `{host}/{device id}/?api-version=...`.
+/// Clock has to return the UNIX time, e.g. from the RTC or SNTP.
+pub struct AzureSasProvider<'a> {
+    host: &'a str,
+    device_id: &'a str,
+    key: Vec<u8, MAX_SAS_KEY_LEN>,
+    ttl: u32,
+    clock: &'a (dyn Clock + Sync),
+    username: String<MAX_SAS_USERNAME_LEN>,
+    token: String<MAX_SAS_TOKEN_LEN>,
+    expiry: u64,
+}
+
+impl<'a> AzureSasProvider<'a> {
+    /// Creates the provider for the device `device_id` of the hub `host`
+    /// (e.g. `my-hub.azure-devices.net`), `key` is the base64 encoded device key.
+    pub fn new(
+        host: &'a str,
+        device_id: &'a str,
+        key: &str,
+        ttl: u32,
+        clock: &'a (dyn Clock + Sync),
+    ) -> Result<Self, CredentialsError> {
+        // Decoding needs space for the estimated length, which can be up to 2 bytes longer
+        let mut decoded = [0; MAX_SAS_KEY_LEN + 3];
+        let len = STANDARD
+            .decode_slice(key, &mut decoded)
+            .map_err(|_| CredentialsError::InvalidKey)?;
+        let mut username = String::new();
+        write!(
+            username,
+            "{}/{}/?api-version={}",
+            host, device_id, AZURE_API_VERSION
+        )
+        .map_err(|_| CredentialsError::BufferTooSmall)?;
+        Ok(Self {
+            host,
+            device_id,
+            key: Vec::from_slice(&decoded[..len]).map_err(|_| CredentialsError::InvalidKey)?,
+            ttl,
+            clock,
+            username,
+            token: String::new(),
+            expiry: 0,
+        })
+    }
+
+    /// Token generated by the last `refresh`.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Expiry of the token as UNIX time in seconds.
+    pub fn expiry(&self) -> u64 {
+        self.expiry
+    }
+
+    fn generate(&mut self) -> Result<(), core::fmt::Error> {
+        self.token.clear();
+        self.token.write_str("SharedAccessSignature sr=")?;
+        let resource_start = self.token.len();
+        url_encode(&mut self.token, self.host)?;
+        url_encode(&mut self.token, "/devices/")?;
+        url_encode(&mut self.token, self.device_id)?;
+
+        // Key length is not limited by HMAC
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(self.token[resource_start..].as_bytes());
+        mac.update(b"\n");
+        let mut expiry = String::<20>::new();
+        write!(expiry, "{}", self.expiry)?;
+        mac.update(expiry.as_bytes());
+        let mut signature = [0; 44];
+        STANDARD
+            .encode_slice(mac.finalize().into_bytes(), &mut signature)
+            .map_err(|_| core::fmt::Error)?;
+
+        self.token.write_str("&sig=")?;
+        // Base64 is ASCII
+        url_encode(
+            &mut self.token,
+            core::str::from_utf8(&signature).unwrap_or_default(),
+        )?;
+        self.token.write_str("&se=")?;
+        self.token.write_str(&expiry)?;
+        Ok(())
+    }
+}
+
+impl<'a> CredentialsProvider for AzureSasProvider<'a> {
+    fn refresh(&mut self) -> Result<(), CredentialsError> {
+        self.expiry = self.clock.now_micros() / 1_000_000 + self.ttl as u64;
+        self.generate()
+            .map_err(|_| CredentialsError::BufferTooSmall)
+    }
+
+    fn client_id(&self) -> Option<&str> {
+        Some(self.device_id)
+    }
+
+    fn username(&self) -> Option<&str> {
+        Some(&self.username)
+    }
+
+    fn password(&self) -> Option<&[u8]> {
+        Some(self.token.as_bytes())
+    }
+}
+
+/// Percent-encodes all characters except the unreserved ones (RFC 3986).
+fn url_encode<const N: usize>(out: &mut String<N>, value: &str) -> Result<(), core::fmt::Error> {
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            out.write_char(byte as char)?;
+        } else {
+            write!(out, "%{:02X}", byte)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,265 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use heapless::Vec;
+use hmac::{Hmac, Mac};
+use rand_core::{CryptoRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use super::authenticator::{AuthError, Authenticator};
+
+/// Name of the SCRAM-SHA-256 authentication method.
+pub const SCRAM_SHA_256: &str = "SCRAM-SHA-256";
+/// Capacity of the buffer for the message sent to the broker.
+pub const SCRAM_MESSAGE_LEN: usize = 256;
+/// Default upper bound of the iteration count accepted from the broker.
+/// PBKDF2 runs `i` HMAC rounds, so an unbounded count lets the broker stall the client.
+pub const MAX_ITERATIONS: u32 = 100_000;
+
+/// Number of random bytes of the client nonce, encoded to 20 base64 characters.
+const NONCE_LEN: usize = 15;
+const NONCE_B64_LEN: usize = 20;
+const MAX_SALT_LEN: usize = 64;
+const KEY_LEN: usize = 32;
+/// Base64 of the GS2 header `n,,` - channel binding is not supported.
+const CHANNEL_BINDING: &[u8] = b"c=biws";
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Initial,
+    ClientFirst,
+    ClientFinal,
+    Done,
+}
+
+/// SCRAM-SHA-256 authenticator (RFC 5802, RFC 7677) for the MQTTv5 enhanced
+/// authentication. Client proves the knowledge of the password without sending it
+/// and verifies the server signature sent in CONNACK (or AUTH after re-authentication).
+/// Rng is used for the client nonce so it has to be cryptographically secure.
+/// Username and password are used as they are, SASLprep is not applied.
+pub struct ScramSha256<'a, R: RngCore + CryptoRng> {
+    username: &'a str,
+    password: &'a str,
+    rng: R,
+    max_iterations: u32,
+    state: State,
+    nonce: [u8; NONCE_B64_LEN],
+    response: Vec<u8, SCRAM_MESSAGE_LEN>,
+    auth_message: Vec<u8, { 3 * SCRAM_MESSAGE_LEN }>,
+    server_signature: [u8; KEY_LEN],
+}
+
+impl<'a, R: RngCore + CryptoRng> ScramSha256<'a, R> {
+    pub fn new(username: &'a str, password: &'a str, rng: R) -> Self {
+        Self {
+            username,
+            password,
+            rng,
+            max_iterations: MAX_ITERATIONS,
+            state: State::Initial,
+            nonce: [0; NONCE_B64_LEN],
+            response: Vec::new(),
+            auth_message: Vec::new(),
+            server_signature: [0; KEY_LEN],
+        }
+    }
+
+    /// Sets the highest iteration count accepted in the server-first message,
+    /// defaults to [`MAX_ITERATIONS`].
+    pub fn with_max_iterations(mut self, max_iterations: u32) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Returns `true` once the server signature was verified.
+    pub fn is_done(&self) -> bool {
+        self.state == State::Done
+    }
+
+    fn client_first(&mut self) -> Result<(), AuthError> {
+        let mut random = [0u8; NONCE_LEN];
+        self.rng.fill_bytes(&mut random);
+        STANDARD
+            .encode_slice(random, &mut self.nonce)
+            .map_err(|_| AuthError::BufferTooSmall)?;
+
+        self.response.clear();
+        push(&mut self.response, b"n,,n=")?;
+        for c in self.username.bytes() {
+            match c {
+                b',' => push(&mut self.response, b"=2C")?,
+                b'=' => push(&mut self.response, b"=3D")?,
+                c => push(&mut self.response, &[c])?,
+            }
+        }
+        push(&mut self.response, b",r=")?;
+        push(&mut self.response, &self.nonce)?;
+
+        // client-first-message-bare starts the AuthMessage
+        self.auth_message.clear();
+        push(&mut self.auth_message, &self.response[3..])
+    }
+
+    fn client_final(&mut self, server_first: &[u8]) -> Result<(), AuthError> {
+        let message =
+            core::str::from_utf8(server_first).map_err(|_| AuthError::MalformedChallenge)?;
+        let mut attributes = message.split(',');
+        let nonce = attribute(attributes.next(), "r=")?;
+        let salt = attribute(attributes.next(), "s=")?;
+        let iterations: u32 = attribute(attributes.next(), "i=")?
+            .parse()
+            .map_err(|_| AuthError::MalformedChallenge)?;
+        if iterations == 0 {
+            return Err(AuthError::MalformedChallenge);
+        }
+        if iterations > self.max_iterations {
+            error!("Server requested too many SCRAM iterations!");
+            return Err(AuthError::MalformedChallenge);
+        }
+        if !nonce.as_bytes().starts_with(&self.nonce) || nonce.len() == self.nonce.len() {
+            error!("Server nonce does not extend the client nonce!");
+            return Err(AuthError::ServerVerificationFailed);
+        }
+        let mut salt_buf = [0u8; MAX_SALT_LEN];
+        let salt_len = STANDARD
+            .decode_slice(salt, &mut salt_buf)
+            .map_err(|_| AuthError::MalformedChallenge)?;
+
+        let mut salted_password = [0u8; KEY_LEN];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            self.password.as_bytes(),
+            &salt_buf[..salt_len],
+            iterations,
+            &mut salted_password,
+        );
+
+        self.response.clear();
+        push(&mut self.response, CHANNEL_BINDING)?;
+        push(&mut self.response, b",r=")?;
+        push(&mut self.response, nonce.as_bytes())?;
+
+        push(&mut self.auth_message, b",")?;
+        push(&mut self.auth_message, server_first)?;
+        push(&mut self.auth_message, b",")?;
+        push(&mut self.auth_message, &self.response)?;
+
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key: [u8; KEY_LEN] = Sha256::digest(client_key).into();
+        let client_signature = hmac(&stored_key, &self.auth_message);
+        let mut proof = [0u8; KEY_LEN];
+        for (i, byte) in proof.iter_mut().enumerate() {
+            *byte = client_key[i] ^ client_signature[i];
+        }
+        let server_key = hmac(&salted_password, b"Server Key");
+        self.server_signature = hmac(&server_key, &self.auth_message);
+
+        let mut proof_b64 = [0u8; 44];
+        let proof_len = STANDARD
+            .encode_slice(proof, &mut proof_b64)
+            .map_err(|_| AuthError::BufferTooSmall)?;
+        push(&mut self.response, b",p=")?;
+        push(&mut self.response, &proof_b64[..proof_len])
+    }
+
+    fn verify(&self, server_final: &[u8]) -> Result<(), AuthError> {
+        let message =
+            core::str::from_utf8(server_final).map_err(|_| AuthError::MalformedChallenge)?;
+        if message.starts_with("e=") {
+            error!("Broker refused SCRAM authentication");
+            return Err(AuthError::ServerVerificationFailed);
+        }
+        let signature = attribute(message.split(',').next(), "v=")?;
+        let mut decoded = [0u8; KEY_LEN + 3];
+        let len = STANDARD
+            .decode_slice(signature, &mut decoded)
+            .map_err(|_| AuthError::MalformedChallenge)?;
+        // compare without early exit
+        let diff = decoded[..len]
+            .iter()
+            .zip(self.server_signature.iter())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b));
+        if len != KEY_LEN || diff != 0 {
+            error!("Server signature does not match!");
+            return Err(AuthError::ServerVerificationFailed);
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: RngCore + CryptoRng> Authenticator for ScramSha256<'a, R> {
+    fn method(&self) -> &str {
+        SCRAM_SHA_256
+    }
+
+    fn start(&mut self) -> Result<(), AuthError> {
+        self.state = State::Initial;
+        self.client_first()?;
+        self.state = State::ClientFirst;
+        Ok(())
+    }
+
+    fn challenge(&mut self, data: &[u8]) -> Result<(), AuthError> {
+        if self.state != State::ClientFirst {
+            return Err(AuthError::UnexpectedStep);
+        }
+        self.client_final(data)?;
+        self.state = State::ClientFinal;
+        Ok(())
+    }
+
+    fn finish(&mut self, data: &[u8]) -> Result<(), AuthError> {
+        if self.state != State::ClientFinal {
+            return Err(AuthError::UnexpectedStep);
+        }
+        self.verify(data)?;
+        self.response.clear();
+        self.state = State::Done;
+        Ok(())
+    }
+
+    fn response(&self) -> &[u8] {
+        &self.response
+    }
+}
+
+fn push<const N: usize>(buffer: &mut Vec<u8, N>, data: &[u8]) -> Result<(), AuthError> {
+    buffer
+        .extend_from_slice(data)
+        .map_err(|_| AuthError::BufferTooSmall)
+}
+
+/// Returns value of the SCRAM attribute with the `prefix` (e.g. `r=`).
+fn attribute<'b>(attribute: Option<&'b str>, prefix: &str) -> Result<&'b str, AuthError> {
+    attribute
+        .and_then(|a| a.strip_prefix(prefix))
+        .ok_or(AuthError::MalformedChallenge)
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; KEY_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC takes key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
@@ -0,0 +1,440 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Debug, Display, Formatter};
+
+use crate::network::datagram::DatagramTransport;
+use crate::packet::sn::msg_type::MsgType;
+use crate::packet::sn::sn_packet::{ReturnCode, SnPacket, SnQoS, TopicId, TopicRef};
+use crate::utils::types::BufferError;
+
+/// Error returned by the MQTT-SN client operations. Generic `E` is the error type of the
+/// datagram transport.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SnClientError<E> {
+    /// Datagram transport reported an error.
+    Transport(E),
+    /// Outgoing message could not be encoded into the send buffer.
+    Encode(BufferError),
+    /// Incoming message could not be decoded.
+    Decode(BufferError),
+    /// Gateway refused the request with the return code.
+    Rejected(ReturnCode),
+    /// QoS 2 is not supported, QoS -1 is allowed only with predefined and short topic ids.
+    InvalidQoS,
+    /// Client is not connected to the gateway or the gateway closed the session.
+    NotConnected,
+}
+
+impl<E: Debug> Display for SnClientError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SnClientError::Transport(err) => write!(f, "Transport error: {:?}!", err),
+            SnClientError::Encode(err) => write!(f, "Could not encode message: {}", err),
+            SnClientError::Decode(err) => write!(f, "Could not decode message: {}", err),
+            SnClientError::Rejected(code) => write!(f, "Gateway rejected request: {:?}!", code),
+            SnClientError::InvalidQoS => write!(f, "QoS is not allowed for this message!"),
+            SnClientError::NotConnected => write!(f, "Client is not connected!"),
+        }
+    }
+}
+
+/// Message received from the gateway by `MqttSnClient::poll`.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SnEvent<'a> {
+    /// Application message. QoS 1 messages are already acknowledged.
+    Message {
+        topic: TopicId,
+        data: &'a [u8],
+        retain: bool,
+    },
+    /// Gateway assigned the topic id to the topic name, sent before the first message from
+    /// topic matched by a wildcard subscription. Registration is already acknowledged.
+    Registered { topic_id: u16, topic_name: &'a str },
+    /// Response to the ping. After `check_in` it means that all buffered messages were sent
+    /// and the client can go back to sleep.
+    Pingresp,
+    /// Gateway closed the session.
+    Disconnect,
+}
+
+/// MQTT-SN v1.2 client talking to a single gateway over the datagram transport.
+///
+/// Client does not retransmit - methods waiting for the acknowledgement wait forever when the
+/// datagram is lost, wrap them into a timeout of the used executor and repeat the request.
+/// Messages and registrations received while waiting for an acknowledgement are acknowledged
+/// to the gateway but not reported.
+pub struct MqttSnClient<'a, D: DatagramTransport> {
+    transport: D,
+    gateway: D::Address,
+    client_id: &'a str,
+    buffer: &'a mut [u8],
+    recv_buffer: &'a mut [u8],
+    msg_id: u16,
+    connected: bool,
+}
+
+impl<'a, D: DatagramTransport> MqttSnClient<'a, D> {
+    /// Creates client for the gateway on the `gateway` address. `buffer` is used for outgoing
+    /// and `recv_buffer` for incoming datagrams, both have to fit the longest message.
+    pub fn new(
+        transport: D,
+        gateway: D::Address,
+        client_id: &'a str,
+        buffer: &'a mut [u8],
+        recv_buffer: &'a mut [u8],
+    ) -> Self {
+        Self {
+            transport,
+            gateway,
+            client_id,
+            buffer,
+            recv_buffer,
+            msg_id: 0,
+            connected: false,
+        }
+    }
+
+    /// Connects to the gateway (or wakes the sleeping client up). `duration` is the keep alive
+    /// period in seconds, with `clean_session` the gateway drops subscriptions and
+    /// registrations of the previous session.
+    pub async fn connect(
+        &mut self,
+        duration: u16,
+        clean_session: bool,
+    ) -> Result<(), SnClientError<D::Error>> {
+        self.send(SnPacket::Connect {
+            will: false,
+            clean_session,
+            duration,
+            client_id: self.client_id,
+        })
+        .await?;
+        match self.wait_for(MsgType::Connack, None).await? {
+            SnPacket::Connack {
+                return_code: ReturnCode::Accepted,
+            } => {
+                self.connected = true;
+                Ok(())
+            }
+            SnPacket::Connack { return_code } => Err(SnClientError::Rejected(return_code)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Registers the `topic_name` at the gateway and returns the topic id for `publish`.
+    pub async fn register(&mut self, topic_name: &str) -> Result<u16, SnClientError<D::Error>> {
+        self.check_connected()?;
+        let msg_id = self.next_msg_id();
+        self.send(SnPacket::Register {
+            topic_id: 0,
+            msg_id,
+            topic_name,
+        })
+        .await?;
+        match self.wait_for(MsgType::Regack, Some(msg_id)).await? {
+            SnPacket::Regack {
+                topic_id,
+                return_code: ReturnCode::Accepted,
+                ..
+            } => Ok(topic_id),
+            SnPacket::Regack { return_code, .. } => Err(SnClientError::Rejected(return_code)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Publishes the `data` to the `topic`. QoS 1 waits for the PUBACK, QoS -1 can be used
+    /// without connection with predefined and short topic ids.
+    pub async fn publish(
+        &mut self,
+        topic: TopicId,
+        data: &[u8],
+        qos: SnQoS,
+        retain: bool,
+    ) -> Result<(), SnClientError<D::Error>> {
+        match (qos, topic) {
+            (SnQoS::QoS2, _) | (SnQoS::QoSMinusOne, TopicId::Normal(_)) => {
+                return Err(SnClientError::InvalidQoS)
+            }
+            (SnQoS::QoSMinusOne, _) => {}
+            _ => self.check_connected()?,
+        }
+        let msg_id = if qos == SnQoS::QoS1 {
+            self.next_msg_id()
+        } else {
+            0
+        };
+        self.send(SnPacket::Publish {
+            dup: false,
+            qos,
+            retain,
+            topic,
+            msg_id,
+            data,
+        })
+        .await?;
+        if qos != SnQoS::QoS1 {
+            return Ok(());
+        }
+        match self.wait_for(MsgType::Puback, Some(msg_id)).await? {
+            SnPacket::Puback {
+                return_code: ReturnCode::Accepted,
+                ..
+            } => Ok(()),
+            SnPacket::Puback { return_code, .. } => Err(SnClientError::Rejected(return_code)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Subscribes to the `topic`, returns topic id assigned by the gateway (zero for wildcard
+    /// filters, the messages are preceded by `SnEvent::Registered`) and granted QoS.
+    pub async fn subscribe(
+        &mut self,
+        topic: TopicRef<'_>,
+        qos: SnQoS,
+    ) -> Result<(u16, SnQoS), SnClientError<D::Error>> {
+        self.check_connected()?;
+        let msg_id = self.next_msg_id();
+        self.send(SnPacket::Subscribe {
+            dup: false,
+            qos,
+            msg_id,
+            topic,
+        })
+        .await?;
+        match self.wait_for(MsgType::Suback, Some(msg_id)).await? {
+            SnPacket::Suback {
+                qos,
+                topic_id,
+                return_code: ReturnCode::Accepted,
+                ..
+            } => Ok((topic_id, qos)),
+            SnPacket::Suback { return_code, .. } => Err(SnClientError::Rejected(return_code)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Unsubscribes from the `topic`.
+    pub async fn unsubscribe(
+        &mut self,
+        topic: TopicRef<'_>,
+    ) -> Result<(), SnClientError<D::Error>> {
+        self.check_connected()?;
+        let msg_id = self.next_msg_id();
+        self.send(SnPacket::Unsubscribe { msg_id, topic }).await?;
+        self.wait_for(MsgType::Unsuback, Some(msg_id)).await?;
+        Ok(())
+    }
+
+    /// Sends PINGREQ and waits for the PINGRESP.
+    pub async fn ping(&mut self) -> Result<(), SnClientError<D::Error>> {
+        self.check_connected()?;
+        self.send(SnPacket::Pingreq { client_id: None }).await?;
+        self.wait_for(MsgType::Pingresp, None).await?;
+        Ok(())
+    }
+
+    /// Puts the client asleep for `duration` seconds. Gateway buffers messages for the client
+    /// until it checks in with `check_in` or connects again.
+    pub async fn sleep(&mut self, duration: u16) -> Result<(), SnClientError<D::Error>> {
+        self.check_connected()?;
+        self.send(SnPacket::Disconnect {
+            duration: Some(duration),
+        })
+        .await?;
+        self.wait_for(MsgType::Disconnect, None).await?;
+        Ok(())
+    }
+
+    /// Asks the gateway for messages buffered while the client was asleep. Call `poll` until
+    /// it returns `SnEvent::Pingresp`, the client is asleep again afterwards.
+    pub async fn check_in(&mut self) -> Result<(), SnClientError<D::Error>> {
+        self.check_connected()?;
+        self.send(SnPacket::Pingreq {
+            client_id: Some(self.client_id),
+        })
+        .await
+    }
+
+    /// Disconnects from the gateway, the gateway drops the session.
+    pub async fn disconnect(&mut self) -> Result<(), SnClientError<D::Error>> {
+        self.check_connected()?;
+        self.send(SnPacket::Disconnect { duration: None }).await?;
+        self.wait_for(MsgType::Disconnect, None).await?;
+        self.connected = false;
+        Ok(())
+    }
+
+    /// Waits for the next message from the gateway. Datagrams from other addresses and
+    /// messages which are not expected outside of the request are skipped.
+    pub async fn poll(&mut self) -> Result<SnEvent<'_>, SnClientError<D::Error>> {
+        let len = loop {
+            let (len, packet) =
+                receive(&mut self.transport, self.gateway, self.recv_buffer).await?;
+            match packet {
+                SnPacket::Publish { .. } | SnPacket::Register { .. } => {
+                    acknowledge(&mut self.transport, self.gateway, self.buffer, &packet).await?;
+                    break len;
+                }
+                SnPacket::Pingresp | SnPacket::Disconnect { .. } => break len,
+                packet => warn!("Unexpected MQTT-SN message {:?}", packet.msg_type()),
+            }
+        };
+        match SnPacket::decode(&self.recv_buffer[..len]).map_err(SnClientError::Decode)? {
+            SnPacket::Publish {
+                topic,
+                data,
+                retain,
+                ..
+            } => Ok(SnEvent::Message {
+                topic,
+                data,
+                retain,
+            }),
+            SnPacket::Register {
+                topic_id,
+                topic_name,
+                ..
+            } => Ok(SnEvent::Registered {
+                topic_id,
+                topic_name,
+            }),
+            SnPacket::Pingresp => Ok(SnEvent::Pingresp),
+            _ => {
+                self.connected = false;
+                Ok(SnEvent::Disconnect)
+            }
+        }
+    }
+
+    fn check_connected(&self) -> Result<(), SnClientError<D::Error>> {
+        if self.connected {
+            Ok(())
+        } else {
+            Err(SnClientError::NotConnected)
+        }
+    }
+
+    fn next_msg_id(&mut self) -> u16 {
+        self.msg_id = self.msg_id.wrapping_add(1).max(1);
+        self.msg_id
+    }
+
+    async fn send(&mut self, packet: SnPacket<'_>) -> Result<(), SnClientError<D::Error>> {
+        send(&mut self.transport, self.gateway, self.buffer, &packet).await
+    }
+
+    // Waits for the acknowledgement of type `msg_type` with the `msg_id`. Unrelated messages
+    // are acknowledged and dropped, DISCONNECT from the gateway ends the waiting.
+    async fn wait_for(
+        &mut self,
+        msg_type: MsgType,
+        msg_id: Option<u16>,
+    ) -> Result<SnPacket<'_>, SnClientError<D::Error>> {
+        let len = loop {
+            let (len, packet) =
+                receive(&mut self.transport, self.gateway, self.recv_buffer).await?;
+            if packet.msg_type() == msg_type && packet.msg_id() == msg_id {
+                break len;
+            }
+            match packet {
+                SnPacket::Disconnect { .. } => {
+                    self.connected = false;
+                    return Err(SnClientError::NotConnected);
+                }
+                SnPacket::Publish { .. } | SnPacket::Register { .. } => {
+                    warn!("MQTT-SN message received while waiting for acknowledgement dropped");
+                    acknowledge(&mut self.transport, self.gateway, self.buffer, &packet).await?;
+                }
+                packet => warn!("Unexpected MQTT-SN message {:?}", packet.msg_type()),
+            }
+        };
+        SnPacket::decode(&self.recv_buffer[..len]).map_err(SnClientError::Decode)
+    }
+}
+
+async fn send<D: DatagramTransport>(
+    transport: &mut D,
+    address: D::Address,
+    buffer: &mut [u8],
+    packet: &SnPacket<'_>,
+) -> Result<(), SnClientError<D::Error>> {
+    let len = packet.encode(buffer).map_err(SnClientError::Encode)?;
+    transport
+        .send_to(address, &buffer[..len])
+        .await
+        .map_err(SnClientError::Transport)
+}
+
+async fn receive<'b, D: DatagramTransport>(
+    transport: &mut D,
+    gateway: D::Address,
+    recv_buffer: &'b mut [u8],
+) -> Result<(usize, SnPacket<'b>), SnClientError<D::Error>> {
+    loop {
+        let (len, address) = transport
+            .receive_from(recv_buffer)
+            .await
+            .map_err(SnClientError::Transport)?;
+        if address != gateway {
+            trace!("Datagram from other address than the gateway skipped");
+            continue;
+        }
+        let recv_buffer: &'b [u8] = recv_buffer;
+        let packet = SnPacket::decode(&recv_buffer[..len]).map_err(SnClientError::Decode)?;
+        return Ok((len, packet));
+    }
+}
+
+// Sends REGACK for REGISTER and PUBACK for QoS 1 PUBLISH.
+async fn acknowledge<D: DatagramTransport>(
+    transport: &mut D,
+    gateway: D::Address,
+    buffer: &mut [u8],
+    packet: &SnPacket<'_>,
+) -> Result<(), SnClientError<D::Error>> {
+    let ack = match *packet {
+        SnPacket::Register {
+            topic_id, msg_id, ..
+        } => SnPacket::Regack {
+            topic_id,
+            msg_id,
+            return_code: ReturnCode::Accepted,
+        },
+        SnPacket::Publish {
+            qos: SnQoS::QoS1,
+            topic,
+            msg_id,
+            ..
+        } => SnPacket::Puback {
+            topic_id: topic.value(),
+            msg_id,
+            return_code: ReturnCode::Accepted,
+        },
+        _ => return Ok(()),
+    };
+    send(transport, gateway, buffer, &ack).await
+}
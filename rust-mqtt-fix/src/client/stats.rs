@@ -0,0 +1,420 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Runtime statistics of the client. `ClientStats` set by `RawMqttClient::set_stats` is
+//! updated by the client and can be read at any time from other tasks, e.g. to publish
+//! it periodically as telemetry.
+//!
+//! Counters are 32 bit and wrap around on overflow. They are written only by the client
+//! task and use plain atomic loads and stores, so they work also on targets without atomic
+//! read-modify-write instructions.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use heapless::Vec;
+
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::clock::Clock;
+
+use super::client_error::ClientError;
+use super::packet_observer::{Direction, PacketSummary};
+
+/// Maximal number of PUBLISH packets waiting for the acknowledgement whose latency is
+/// measured. When exceeded, the oldest packet is not measured.
+pub const MAX_PENDING_ACKS: usize = 8;
+
+/// Reason codes of the broker DISCONNECT counted separately, see
+/// `ClientStats::disconnects_with`. Other reasons share one counter.
+pub const TRACKED_DISCONNECT_REASONS: [ReasonCode; 15] = [
+    ReasonCode::Success,
+    ReasonCode::UnspecifiedError,
+    ReasonCode::MalformedPacket,
+    ReasonCode::ProtocolError,
+    ReasonCode::NotAuthorized,
+    ReasonCode::ServerBusy,
+    ReasonCode::ServerShuttingDown,
+    ReasonCode::KeepAliveTimeout,
+    ReasonCode::SessionTakeOver,
+    ReasonCode::PacketTooLarge,
+    ReasonCode::QuotaExceeded,
+    ReasonCode::AdministrativeAction,
+    ReasonCode::UseAnotherServer,
+    ReasonCode::ServerMoved,
+    ReasonCode::ConnectionRateExceeded,
+];
+
+const PACKET_TYPES: usize = 16;
+const DISCONNECT_REASONS: usize = TRACKED_DISCONNECT_REASONS.len();
+const NO_REASON: u32 = u32::MAX;
+const DUP_FLAG: u8 = 0x08;
+
+// Constants are used only as the array initializers
+#[allow(clippy::declare_interior_mutable_const)]
+const ZERO: AtomicU32 = AtomicU32::new(0);
+#[allow(clippy::declare_interior_mutable_const)]
+const ZEROS: [AtomicU32; PACKET_TYPES] = [ZERO; PACKET_TYPES];
+
+/// Minimal, average and maximal value of the measured round trip in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Latency {
+    /// Number of measurements.
+    pub samples: u32,
+    pub min_micros: u32,
+    pub avg_micros: u32,
+    pub max_micros: u32,
+}
+
+#[derive(Debug)]
+struct LatencyStats {
+    samples: AtomicU32,
+    min: AtomicU32,
+    avg: AtomicU32,
+    max: AtomicU32,
+}
+
+impl LatencyStats {
+    const fn new() -> Self {
+        Self {
+            samples: AtomicU32::new(0),
+            min: AtomicU32::new(0),
+            avg: AtomicU32::new(0),
+            max: AtomicU32::new(0),
+        }
+    }
+
+    fn record(&self, micros: u64) {
+        let micros = micros.min(u32::MAX as u64) as u32;
+        let samples = self.samples.load(Ordering::Relaxed).saturating_add(1);
+        let (min, avg, max) = if samples == 1 {
+            (micros, micros, micros)
+        } else {
+            let avg = self.avg.load(Ordering::Relaxed) as i64;
+            (
+                self.min.load(Ordering::Relaxed).min(micros),
+                (avg + (micros as i64 - avg) / samples as i64) as u32,
+                self.max.load(Ordering::Relaxed).max(micros),
+            )
+        };
+        self.min.store(min, Ordering::Relaxed);
+        self.avg.store(avg, Ordering::Relaxed);
+        self.max.store(max, Ordering::Relaxed);
+        self.samples.store(samples, Ordering::Relaxed);
+    }
+
+    fn get(&self) -> Latency {
+        Latency {
+            samples: self.samples.load(Ordering::Relaxed),
+            min_micros: self.min.load(Ordering::Relaxed),
+            avg_micros: self.avg.load(Ordering::Relaxed),
+            max_micros: self.max.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Statistics of the client connection. Structure is `Sync` and can be placed in a `static`.
+#[derive(Debug)]
+pub struct ClientStats {
+    packets: [[AtomicU32; PACKET_TYPES]; 2],
+    bytes: [[AtomicU32; PACKET_TYPES]; 2],
+    connects: AtomicU32,
+    publish_failures: AtomicU32,
+    retransmissions: AtomicU32,
+    transport_errors: AtomicU32,
+    protocol_errors: AtomicU32,
+    decode_errors: AtomicU32,
+    disconnects: AtomicU32,
+    last_disconnect_reason: AtomicU32,
+    disconnect_reasons: [AtomicU32; DISCONNECT_REASONS],
+    other_disconnects: AtomicU32,
+    ack_latency: LatencyStats,
+    ping_rtt: LatencyStats,
+}
+
+impl Default for ClientStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientStats {
+    pub const fn new() -> Self {
+        Self {
+            packets: [ZEROS; 2],
+            bytes: [ZEROS; 2],
+            connects: AtomicU32::new(0),
+            publish_failures: AtomicU32::new(0),
+            retransmissions: AtomicU32::new(0),
+            transport_errors: AtomicU32::new(0),
+            protocol_errors: AtomicU32::new(0),
+            decode_errors: AtomicU32::new(0),
+            disconnects: AtomicU32::new(0),
+            last_disconnect_reason: AtomicU32::new(NO_REASON),
+            disconnect_reasons: [ZERO; DISCONNECT_REASONS],
+            other_disconnects: AtomicU32::new(0),
+            ack_latency: LatencyStats::new(),
+            ping_rtt: LatencyStats::new(),
+        }
+    }
+
+    /// Number of packets of `packet_type` sent or received.
+    pub fn packets(&self, direction: Direction, packet_type: PacketType) -> u32 {
+        self.packets[direction as usize][type_index(packet_type)].load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes of the whole packets of `packet_type` sent or received.
+    pub fn bytes(&self, direction: Direction, packet_type: PacketType) -> u32 {
+        self.bytes[direction as usize][type_index(packet_type)].load(Ordering::Relaxed)
+    }
+
+    /// Number of connections accepted by the broker, reconnects are all but the first one.
+    pub fn connects(&self) -> u32 {
+        self.connects.load(Ordering::Relaxed)
+    }
+
+    /// Number of PUBLISH packets which could not be sent or were rejected by the broker
+    /// in PUBACK.
+    pub fn publish_failures(&self) -> u32 {
+        self.publish_failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of PUBLISH packets transmitted again: received from the broker with the DUP flag,
+    /// sent with the DUP flag or sent by `OutboundQueue` after a failed attempt.
+    pub fn retransmissions(&self) -> u32 {
+        self.retransmissions.load(Ordering::Relaxed)
+    }
+
+    /// Number of errors reported by the network driver.
+    pub fn transport_errors(&self) -> u32 {
+        self.transport_errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of MQTT protocol violations of the broker.
+    pub fn protocol_errors(&self) -> u32 {
+        self.protocol_errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of received packets which could not be decoded.
+    pub fn decode_errors(&self) -> u32 {
+        self.decode_errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of DISCONNECT packets received from the broker.
+    pub fn disconnects(&self) -> u32 {
+        self.disconnects.load(Ordering::Relaxed)
+    }
+
+    /// Reason code of the last DISCONNECT packet received from the broker.
+    pub fn last_disconnect_reason(&self) -> Option<ReasonCode> {
+        match self.last_disconnect_reason.load(Ordering::Relaxed) {
+            NO_REASON => None,
+            reason => Some(ReasonCode::from(reason as u8)),
+        }
+    }
+
+    /// Number of DISCONNECT packets received from the broker with `reason`. Returns 0 for
+    /// reasons not listed in `TRACKED_DISCONNECT_REASONS`, those are counted by
+    /// `other_disconnects`.
+    pub fn disconnects_with(&self, reason: ReasonCode) -> u32 {
+        match reason_index(u8::from(reason)) {
+            Some(index) => self.disconnect_reasons[index].load(Ordering::Relaxed),
+            None => 0,
+        }
+    }
+
+    /// Number of DISCONNECT packets received from the broker with a reason not listed in
+    /// `TRACKED_DISCONNECT_REASONS`.
+    pub fn other_disconnects(&self) -> u32 {
+        self.other_disconnects.load(Ordering::Relaxed)
+    }
+
+    /// Time between sending PUBLISH with QoS 1 and receiving its PUBACK.
+    pub fn ack_latency(&self) -> Latency {
+        self.ack_latency.get()
+    }
+
+    /// Time between sending PINGREQ and receiving PINGRESP.
+    pub fn ping_rtt(&self) -> Latency {
+        self.ping_rtt.get()
+    }
+
+    pub(crate) fn record_connect(&self) {
+        increment(&self.connects);
+    }
+
+    pub(crate) fn record_publish_failure(&self) {
+        increment(&self.publish_failures);
+    }
+
+    pub(crate) fn record_retransmission(&self) {
+        increment(&self.retransmissions);
+    }
+
+    pub(crate) fn record_disconnect(&self, reason: u8) {
+        increment(&self.disconnects);
+        self.last_disconnect_reason
+            .store(reason as u32, Ordering::Relaxed);
+        match reason_index(reason) {
+            Some(index) => increment(&self.disconnect_reasons[index]),
+            None => increment(&self.other_disconnects),
+        }
+    }
+
+    /// Counts the error returned by the client.
+    pub(crate) fn record_error<E>(&self, err: &ClientError<E>) {
+        match err {
+            ClientError::Transport(_) => increment(&self.transport_errors),
+            ClientError::Protocol(_) => increment(&self.protocol_errors),
+            ClientError::Decode(_) => increment(&self.decode_errors),
+            _ => {}
+        }
+    }
+
+    fn record_packet(&self, direction: Direction, packet: &[u8]) {
+        let index = type_index(PacketType::from(packet[0]));
+        increment(&self.packets[direction as usize][index]);
+        let bytes = &self.bytes[direction as usize][index];
+        bytes.store(
+            bytes
+                .load(Ordering::Relaxed)
+                .wrapping_add(packet.len() as u32),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+fn increment(counter: &AtomicU32) {
+    counter.store(
+        counter.load(Ordering::Relaxed).wrapping_add(1),
+        Ordering::Relaxed,
+    );
+}
+
+fn type_index(packet_type: PacketType) -> usize {
+    (u8::from(packet_type) >> 4) as usize
+}
+
+fn reason_index(reason: u8) -> Option<usize> {
+    TRACKED_DISCONNECT_REASONS
+        .iter()
+        .position(|tracked| u8::from(*tracked) == reason)
+}
+
+/// Updates `ClientStats` from the packets passing through the client and measures the
+/// acknowledgement latencies.
+pub(crate) struct StatsRecorder<'a> {
+    pub(crate) stats: &'a ClientStats,
+    clock: &'a (dyn Clock + Sync),
+    pending_acks: Vec<(u16, u64), MAX_PENDING_ACKS>,
+    ping_sent: Option<u64>,
+}
+
+impl<'a> StatsRecorder<'a> {
+    pub(crate) fn new(stats: &'a ClientStats, clock: &'a (dyn Clock + Sync)) -> Self {
+        Self {
+            stats,
+            clock,
+            pending_acks: Vec::new(),
+            ping_sent: None,
+        }
+    }
+
+    pub(crate) fn on_send(&mut self, packet: &[u8]) {
+        if packet.is_empty() {
+            return;
+        }
+        self.stats.record_packet(Direction::Outbound, packet);
+        match PacketType::from(packet[0]) {
+            PacketType::Publish => {
+                if packet[0] & DUP_FLAG != 0 {
+                    self.stats.record_retransmission();
+                }
+                let Ok(summary) = PacketSummary::decode(packet) else {
+                    return;
+                };
+                if let (Some(QualityOfService::QoS1), Some(identifier)) =
+                    (summary.qos, summary.packet_identifier)
+                {
+                    self.pending_acks
+                        .retain(|(pending, _)| *pending != identifier);
+                    if self.pending_acks.is_full() {
+                        self.pending_acks.remove(0);
+                    }
+                    let _ = self
+                        .pending_acks
+                        .push((identifier, self.clock.now_micros()));
+                }
+            }
+            PacketType::Pingreq => self.ping_sent = Some(self.clock.now_micros()),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn on_send_error<E>(&mut self, packet: &[u8], err: &ClientError<E>) {
+        self.stats.record_error(err);
+        if !packet.is_empty() && PacketType::from(packet[0]) == PacketType::Publish {
+            self.stats.record_publish_failure();
+        }
+    }
+
+    pub(crate) fn on_receive(&mut self, packet: &[u8]) {
+        if packet.is_empty() {
+            return;
+        }
+        self.stats.record_packet(Direction::Inbound, packet);
+        match PacketType::from(packet[0]) {
+            PacketType::Publish if packet[0] & DUP_FLAG != 0 => {
+                self.stats.record_retransmission();
+            }
+            PacketType::Puback => {
+                let Some(identifier) = PacketSummary::decode(packet)
+                    .ok()
+                    .and_then(|summary| summary.packet_identifier)
+                else {
+                    return;
+                };
+                if let Some(index) = self
+                    .pending_acks
+                    .iter()
+                    .position(|(pending, _)| *pending == identifier)
+                {
+                    let (_, sent) = self.pending_acks.swap_remove(index);
+                    self.stats
+                        .ack_latency
+                        .record(self.clock.now_micros().saturating_sub(sent));
+                }
+            }
+            PacketType::Pingresp => {
+                if let Some(sent) = self.ping_sent.take() {
+                    self.stats
+                        .ping_rtt
+                        .record(self.clock.now_micros().saturating_sub(sent));
+                }
+            }
+            _ => {}
+        }
+    }
+}
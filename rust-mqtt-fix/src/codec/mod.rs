@@ -0,0 +1,188 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Typed payloads with serde. `Codec` serializes the value into the message payload and sets
+//! the matching `ContentType` and `PayloadFormat` properties, `ReceivedMessage::decode`
+//! selects the codec by the content type of the received message. All codecs work without
+//! an allocator: JSON with serde-json-core and postcard with the `codec` feature, CBOR with
+//! the `codec-cbor` feature.
+
+use core::fmt::{Display, Formatter};
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::client::raw_client::ReceivedMessage;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::property_kind::ContentType;
+use crate::utils::types::{BufferError, EncodedString};
+
+/// Serialization format of the payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Codec {
+    /// JSON, content type `application/json`, UTF-8 payload.
+    Json,
+    /// postcard, content type `application/x-postcard`.
+    Postcard,
+    /// CBOR, content type `application/cbor`.
+    #[cfg(feature = "codec-cbor")]
+    Cbor,
+}
+
+/// Error of the payload serialization.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CodecError {
+    /// Serialized value does not fit into the buffer.
+    BufferTooSmall,
+    /// Value could not be serialized.
+    Serialize,
+    /// Payload could not be deserialized into the type.
+    Deserialize,
+    /// Message has no content type or the content type has no codec.
+    UnknownContentType,
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CodecError::BufferTooSmall => write!(f, "Serialized payload does not fit into buffer!"),
+            CodecError::Serialize => write!(f, "Value could not be serialized!"),
+            CodecError::Deserialize => write!(f, "Payload could not be deserialized!"),
+            CodecError::UnknownContentType => write!(f, "Content type of payload is not known!"),
+        }
+    }
+}
+
+impl From<CodecError> for BufferError {
+    fn from(err: CodecError) -> Self {
+        match err {
+            CodecError::BufferTooSmall => BufferError::InsufficientBufferSize,
+            CodecError::Serialize | CodecError::UnknownContentType => BufferError::EncodingError,
+            CodecError::Deserialize => BufferError::DecodingError,
+        }
+    }
+}
+
+impl Codec {
+    /// MIME type sent in the `ContentType` property.
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Codec::Json => "application/json",
+            Codec::Postcard => "application/x-postcard",
+            #[cfg(feature = "codec-cbor")]
+            Codec::Cbor => "application/cbor",
+        }
+    }
+
+    /// Value of the `PayloadFormat` property, 1 for UTF-8 text and 0 for binary data.
+    pub fn payload_format(&self) -> u8 {
+        match self {
+            Codec::Json => 1,
+            _ => 0,
+        }
+    }
+
+    /// Returns the codec for the `content_type`, parameters like `charset` are ignored.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        [
+            Codec::Json,
+            Codec::Postcard,
+            #[cfg(feature = "codec-cbor")]
+            Codec::Cbor,
+        ]
+        .into_iter()
+        .find(|codec| codec.content_type().eq_ignore_ascii_case(mime))
+    }
+
+    /// `ContentType` and `PayloadFormat` properties of the payload.
+    pub fn properties(&self) -> Vec<Property<'static>, 2> {
+        let content_type = self.content_type();
+        let mut properties = Vec::new();
+        let _ = properties.push(Property::PayloadFormat(self.payload_format()));
+        let _ = properties.push(Property::ContentType(EncodedString {
+            string: content_type,
+            len: content_type.len() as u16,
+        }));
+        properties
+    }
+
+    /// Serializes the `value` into the `buffer`, returns the length of the payload.
+    pub fn encode<V: Serialize + ?Sized>(
+        &self,
+        value: &V,
+        buffer: &mut [u8],
+    ) -> Result<usize, CodecError> {
+        match self {
+            Codec::Json => serde_json_core::to_slice(value, buffer).map_err(|err| match err {
+                serde_json_core::ser::Error::BufferFull => CodecError::BufferTooSmall,
+                _ => CodecError::Serialize,
+            }),
+            Codec::Postcard => postcard::to_slice(value, buffer)
+                .map(|payload| payload.len())
+                .map_err(|err| match err {
+                    postcard::Error::SerializeBufferFull => CodecError::BufferTooSmall,
+                    _ => CodecError::Serialize,
+                }),
+            #[cfg(feature = "codec-cbor")]
+            Codec::Cbor => {
+                let cursor = minicbor::encode::write::Cursor::new(buffer);
+                let mut serializer = minicbor_serde::Serializer::new(cursor);
+                match value.serialize(&mut serializer) {
+                    Ok(()) => Ok(serializer.into_encoder().into_writer().position()),
+                    Err(err) if err.as_write().is_some() => Err(CodecError::BufferTooSmall),
+                    Err(_) => Err(CodecError::Serialize),
+                }
+            }
+        }
+    }
+
+    /// Deserializes the `payload`, strings and byte slices can be borrowed from the payload.
+    pub fn decode<'de, V: Deserialize<'de>>(&self, payload: &'de [u8]) -> Result<V, CodecError> {
+        match self {
+            Codec::Json => serde_json_core::from_slice(payload)
+                .map(|(value, _)| value)
+                .map_err(|_| CodecError::Deserialize),
+            Codec::Postcard => postcard::from_bytes(payload).map_err(|_| CodecError::Deserialize),
+            #[cfg(feature = "codec-cbor")]
+            Codec::Cbor => minicbor_serde::from_slice(payload).map_err(|_| CodecError::Deserialize),
+        }
+    }
+}
+
+impl<'a> ReceivedMessage<'a> {
+    /// Returns the codec selected by the `ContentType` property of the message.
+    pub fn codec(&self) -> Option<Codec> {
+        self.get::<ContentType>().and_then(Codec::from_content_type)
+    }
+
+    /// Deserializes the payload with the codec selected by the `ContentType` property.
+    pub fn decode<V: Deserialize<'a>>(&self) -> Result<V, CodecError> {
+        self.codec()
+            .ok_or(CodecError::UnknownContentType)?
+            .decode(self.payload)
+    }
+}
@@ -0,0 +1,333 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Payload compression for links billed per byte. Payloads are compressed with heatshrink,
+//! LZSS with 256 byte window and 16 byte lookahead, which needs no allocator and only a few
+//! hundred bytes of memory to decompress. Compressed message carries the `content-encoding`
+//! user property, `ReceivedMessage::decompress_with` streams the decompressed payload in
+//! chunks, so it can be larger than the receive buffer.
+//!
+//! The bit stream is compatible with the heatshrink C library configured with
+//! `window_sz2 = 8` and `lookahead_sz2 = 4`.
+
+use core::fmt::{Display, Formatter};
+
+use crate::client::raw_client::ReceivedMessage;
+use crate::packet::v5::property::Property;
+use crate::utils::types::{BufferError, EncodedString, StringPair};
+
+/// Name of the user property carrying the encoding of the payload.
+pub const CONTENT_ENCODING: &str = "content-encoding";
+/// Base 2 logarithm of the window size.
+pub const WINDOW_BITS: u8 = 8;
+/// Base 2 logarithm of the longest back reference.
+pub const LOOKAHEAD_BITS: u8 = 4;
+
+const WINDOW_SIZE: usize = 1 << WINDOW_BITS;
+const LOOKAHEAD_SIZE: usize = 1 << LOOKAHEAD_BITS;
+// Back references shorter than this take more bits than the literals
+const MIN_MATCH: usize = (1 + WINDOW_BITS as usize + LOOKAHEAD_BITS as usize) / 9 + 1;
+// Decompressed bytes passed to the sink at once
+const CHUNK_SIZE: usize = 64;
+
+/// Compression algorithm of the payload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ContentEncoding {
+    /// heatshrink with 8 bit window and 4 bit lookahead.
+    Heatshrink,
+}
+
+impl ContentEncoding {
+    /// Value of the `content-encoding` user property.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContentEncoding::Heatshrink => "heatshrink;w=8;l=4",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "heatshrink;w=8;l=4" => Some(ContentEncoding::Heatshrink),
+            _ => None,
+        }
+    }
+
+    /// User property signalling the encoding, to be sent with the compressed payload.
+    pub fn property(&self) -> Property<'static> {
+        Property::UserProperty(StringPair {
+            name: encoded_string(CONTENT_ENCODING),
+            value: encoded_string(self.name()),
+        })
+    }
+}
+
+fn encoded_string(string: &'static str) -> EncodedString<'static> {
+    EncodedString {
+        string,
+        len: string.len() as u16,
+    }
+}
+
+/// Error of the payload compression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CompressionError {
+    /// Compressed or decompressed payload does not fit into the buffer.
+    BufferTooSmall,
+    /// Message has content encoding which is not supported.
+    UnknownEncoding,
+}
+
+impl Display for CompressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CompressionError::BufferTooSmall => write!(f, "Payload does not fit into buffer!"),
+            CompressionError::UnknownEncoding => write!(f, "Content encoding is not supported!"),
+        }
+    }
+}
+
+impl From<CompressionError> for BufferError {
+    fn from(err: CompressionError) -> Self {
+        match err {
+            CompressionError::BufferTooSmall => BufferError::InsufficientBufferSize,
+            CompressionError::UnknownEncoding => BufferError::DecodingError,
+        }
+    }
+}
+
+struct BitWriter<'a> {
+    output: &'a mut [u8],
+    len: usize,
+    // Bits already used in the last byte
+    used: u8,
+}
+
+impl<'a> BitWriter<'a> {
+    fn write(&mut self, bits: u8, value: u16) -> Result<(), CompressionError> {
+        for bit in (0..bits).rev() {
+            if self.used == 0 {
+                *self
+                    .output
+                    .get_mut(self.len)
+                    .ok_or(CompressionError::BufferTooSmall)? = 0;
+                self.len += 1;
+            }
+            if value >> bit & 1 != 0 {
+                self.output[self.len - 1] |= 0x80 >> self.used;
+            }
+            self.used = (self.used + 1) % 8;
+        }
+        Ok(())
+    }
+}
+
+struct BitReader<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    /// Returns `None` when the input ends, trailing bits of the last byte are padding.
+    fn read(&mut self, bits: u8) -> Option<u16> {
+        if self.position + bits as usize > self.input.len() * 8 {
+            return None;
+        }
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.input[self.position / 8];
+            value = value << 1 | (byte >> (7 - self.position % 8) & 1) as u16;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Compresses the `input` into the `output` and returns the length of the compressed payload.
+pub fn compress(input: &[u8], output: &mut [u8]) -> Result<usize, CompressionError> {
+    let mut writer = BitWriter {
+        output,
+        len: 0,
+        used: 0,
+    };
+    let mut position = 0;
+    while position < input.len() {
+        let (offset, len) = longest_match(input, position);
+        if len >= MIN_MATCH {
+            writer.write(1, 0)?;
+            writer.write(WINDOW_BITS, (offset - 1) as u16)?;
+            writer.write(LOOKAHEAD_BITS, (len - 1) as u16)?;
+            position += len;
+        } else {
+            writer.write(1, 1)?;
+            writer.write(8, input[position] as u16)?;
+            position += 1;
+        }
+    }
+    Ok(writer.len)
+}
+
+/// Returns distance and length of the longest match of the bytes at `position` in the window
+/// before it. Match can overlap the position, it is copied byte after byte.
+fn longest_match(input: &[u8], position: usize) -> (usize, usize) {
+    let max_len = LOOKAHEAD_SIZE.min(input.len() - position);
+    let mut best = (0, 0);
+    for start in position.saturating_sub(WINDOW_SIZE)..position {
+        let len = (0..max_len)
+            .take_while(|i| input[start + i] == input[position + i])
+            .count();
+        // Closer match wins for the same length
+        if len >= best.1 {
+            best = (position - start, len);
+        }
+    }
+    best
+}
+
+/// Output side of the decompression, window of the previous bytes and the chunk for the sink.
+struct Decoder {
+    window: [u8; WINDOW_SIZE],
+    total: usize,
+    chunk: [u8; CHUNK_SIZE],
+    chunk_len: usize,
+}
+
+impl Decoder {
+    fn emit<E>(
+        &mut self,
+        byte: u8,
+        sink: &mut impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<(), E> {
+        self.window[self.total % WINDOW_SIZE] = byte;
+        self.total += 1;
+        self.chunk[self.chunk_len] = byte;
+        self.chunk_len += 1;
+        if self.chunk_len == CHUNK_SIZE {
+            self.chunk_len = 0;
+            sink(&self.chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Byte `offset` positions back, bytes before the start of the payload are zero.
+    fn back(&self, offset: usize) -> u8 {
+        self.window[(self.total + WINDOW_SIZE - offset) % WINDOW_SIZE]
+    }
+}
+
+/// Decompresses the `input` and passes the output to the `sink` in chunks of at most
+/// 64 bytes. Returns the length of the decompressed payload.
+pub fn decompress_with<E>(
+    input: &[u8],
+    mut sink: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<usize, E> {
+    let mut reader = BitReader { input, position: 0 };
+    let mut decoder = Decoder {
+        window: [0; WINDOW_SIZE],
+        total: 0,
+        chunk: [0; CHUNK_SIZE],
+        chunk_len: 0,
+    };
+    loop {
+        match reader.read(1) {
+            Some(1) => match reader.read(8) {
+                Some(byte) => decoder.emit(byte as u8, &mut sink)?,
+                None => break,
+            },
+            Some(_) => {
+                let (Some(index), Some(count)) =
+                    (reader.read(WINDOW_BITS), reader.read(LOOKAHEAD_BITS))
+                else {
+                    break;
+                };
+                for _ in 0..=count {
+                    let byte = decoder.back(index as usize + 1);
+                    decoder.emit(byte, &mut sink)?;
+                }
+            }
+            None => break,
+        }
+    }
+    if decoder.chunk_len > 0 {
+        sink(&decoder.chunk[..decoder.chunk_len])?;
+    }
+    Ok(decoder.total)
+}
+
+/// Decompresses the `input` into the `output` and returns the length of the decompressed
+/// payload.
+pub fn decompress(input: &[u8], output: &mut [u8]) -> Result<usize, CompressionError> {
+    let mut len = 0;
+    decompress_with(input, |chunk| {
+        output
+            .get_mut(len..len + chunk.len())
+            .ok_or(CompressionError::BufferTooSmall)?
+            .copy_from_slice(chunk);
+        len += chunk.len();
+        Ok(())
+    })
+}
+
+impl<'a> ReceivedMessage<'a> {
+    /// Returns the value of the `content-encoding` user property.
+    pub fn content_encoding(&self) -> Option<&'a str> {
+        self.properties.iter().find_map(|property| match property {
+            Property::UserProperty(pair) if pair.name.string == CONTENT_ENCODING => {
+                Some(pair.value.string)
+            }
+            _ => None,
+        })
+    }
+
+    /// Passes the decompressed payload to the `sink` in chunks, payload without content
+    /// encoding is passed as it is. Returns the length of the payload.
+    pub fn decompress_with<E: From<CompressionError>>(
+        &self,
+        mut sink: impl FnMut(&[u8]) -> Result<(), E>,
+    ) -> Result<usize, E> {
+        match self.content_encoding() {
+            None => sink(self.payload).map(|_| self.payload.len()),
+            Some(name) => match ContentEncoding::from_name(name) {
+                Some(ContentEncoding::Heatshrink) => decompress_with(self.payload, sink),
+                None => Err(CompressionError::UnknownEncoding.into()),
+            },
+        }
+    }
+
+    /// Decompresses the payload into the `output` like `decompress_with` and returns
+    /// the length of the payload.
+    pub fn decompress(&self, output: &mut [u8]) -> Result<usize, CompressionError> {
+        let mut len = 0;
+        self.decompress_with(|chunk| {
+            output
+                .get_mut(len..len + chunk.len())
+                .ok_or(CompressionError::BufferTooSmall)?
+                .copy_from_slice(chunk);
+            len += chunk.len();
+            Ok(())
+        })?;
+        Ok(len)
+    }
+}
@@ -24,18 +24,16 @@
 
 use crate::utils::types::BufferError;
 
-/// VariableByteIntegerEncoder and VariableByteIntegerDecoder are implemented based on
-/// pseudo code which is introduced in MQTT version 5.0 OASIS standard accesible from
-/// https://docs.oasis-open.org/mqtt/mqtt/v5.0/os/mqtt-v5.0-os.html#_Toc3901107
+// VariableByteIntegerEncoder and VariableByteIntegerDecoder are implemented based on
+// pseudo code which is introduced in MQTT version 5.0 OASIS standard accesible from
+// https://docs.oasis-open.org/mqtt/mqtt/v5.0/os/mqtt-v5.0-os.html#_Toc3901107
 
 /// Variable byte integer encoder structure is help structure which implements function used to
 /// encode integer into MQTT variable byte integer format. This format is mainly used to encode
 /// lenghts stored in a packet.
 pub struct VariableByteIntegerEncoder;
 
-/// Variable byte integers error enumeration is used by both encoder and decoder for
-/// error notification.
-
+/// Variable byte integer is stored as array of maximal 4 Bytes.
 pub type VariableByteInteger = [u8; 4];
 
 impl VariableByteIntegerEncoder {
@@ -71,12 +69,13 @@ impl VariableByteIntegerEncoder {
         Ok(res)
     }
 
+    /// Returns number of Bytes used by the encoded integer, at most 4.
     pub fn len(var_int: VariableByteInteger) -> usize {
         let mut i: usize = 0;
         loop {
             let encoded_byte = var_int[i];
             i += 1;
-            if (encoded_byte & 128) == 0 {
+            if (encoded_byte & 128) == 0 || i == var_int.len() {
                 break;
             }
         }
@@ -103,13 +102,14 @@ impl VariableByteIntegerDecoder {
             encoded_byte = encoded[i];
             i += 1;
             ret += (encoded_byte & 127) as u32 * multiplier;
-            if multiplier > 128 * 128 * 128 {
-                return Err(BufferError::DecodingError);
-            }
-            multiplier *= 128;
             if (encoded_byte & 128) == 0 {
                 break;
             }
+            // Continuation bit is set on the last possible Byte
+            if i == encoded.len() {
+                return Err(BufferError::DecodingError);
+            }
+            multiplier *= 128;
         }
 
         Ok(ret)
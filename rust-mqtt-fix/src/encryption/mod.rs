@@ -0,0 +1,467 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! End-to-end protection of the message payloads with authenticated encryption, for brokers
+//! which are shared with untrusted clients. `PayloadCipher` set by
+//! `MqttClient::set_payload_protection` encrypts the payload of every sent message and
+//! decrypts and verifies every received message, `send_message` and `receive_message`
+//! callers see the plaintext.
+//!
+//! Protected payload starts with a header of version, algorithm, key id, sender id and 64-bit
+//! message counter, followed by the ciphertext and the 16 byte tag. Nonce is built from the key
+//! id, the sender id and the counter, so the device and the backend sharing the device key never
+//! produce the same nonce. The topic is authenticated as associated data, so a message can not
+//! be replayed to another topic. Receiver keeps a replay window for every sender.
+
+use core::fmt::{Debug, Display, Formatter};
+
+use aes_gcm::aead::consts::U12;
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce, Tag};
+use chacha20poly1305::ChaCha20Poly1305;
+use heapless::Vec;
+
+/// Version of the protected payload format.
+pub const VERSION: u8 = 1;
+/// Length of the header before the ciphertext.
+pub const HEADER_LEN: usize = 12;
+/// Length of the authentication tag after the ciphertext.
+pub const TAG_LEN: usize = 16;
+/// Number of bytes the protection adds to the payload.
+pub const OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+/// Number of counters before the highest received one which are still accepted once.
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// Authenticated encryption algorithm of the key.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Algorithm {
+    Aes128Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Algorithm {
+    fn id(&self) -> u8 {
+        match self {
+            Algorithm::Aes128Gcm => 1,
+            Algorithm::ChaCha20Poly1305 => 2,
+        }
+    }
+}
+
+/// Error of the payload protection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EncryptionError {
+    /// Protected or decrypted payload does not fit into the buffer of the cipher.
+    BufferTooSmall,
+    /// No key is active for sending.
+    NoActiveKey,
+    /// Key ring is full.
+    TooManyKeys,
+    /// Payload is too short or has unknown version or algorithm.
+    Malformed,
+    /// Payload was encrypted with a key which is not in the key ring.
+    UnknownKey(u8),
+    /// Payload or topic was modified or the key is wrong.
+    Authentication,
+    /// Counter of the message was already received or is too old.
+    Replay,
+    /// Replay windows of all senders are taken, message of a new sender can not be tracked.
+    TooManySenders,
+    /// Message counter reached its maximum, the key has to be rotated.
+    CounterExhausted,
+    /// Payload could not be encrypted, it exceeds the limits of the algorithm.
+    Encryption,
+}
+
+impl Display for EncryptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncryptionError::BufferTooSmall => write!(f, "Payload does not fit into buffer!"),
+            EncryptionError::NoActiveKey => write!(f, "No key is active for encryption!"),
+            EncryptionError::TooManyKeys => write!(f, "Key ring is full!"),
+            EncryptionError::Malformed => write!(f, "Protected payload is malformed!"),
+            EncryptionError::UnknownKey(id) => write!(f, "Key {} is not known!", id),
+            EncryptionError::Authentication => write!(f, "Payload authentication failed!"),
+            EncryptionError::Replay => write!(f, "Message was replayed!"),
+            EncryptionError::TooManySenders => write!(f, "Too many senders to track!"),
+            EncryptionError::CounterExhausted => write!(f, "Message counter is exhausted!"),
+            EncryptionError::Encryption => write!(f, "Payload encryption failed!"),
+        }
+    }
+}
+
+/// Payload protection used by the client for sent and received messages.
+pub trait PayloadProtection {
+    /// Returns the protected `payload` of a message to the `topic`.
+    fn seal(&mut self, topic: &str, payload: &[u8]) -> Result<&[u8], EncryptionError>;
+
+    /// Verifies the protected `payload` received on the `topic` and returns the plaintext.
+    fn open(&mut self, topic: &str, payload: &[u8]) -> Result<&[u8], EncryptionError>;
+}
+
+/// Key of the device with its id carried in the payload header. Only the key bytes are kept,
+/// the cipher is initialized for every message to keep the key ring small.
+#[derive(Clone)]
+pub struct PayloadKey {
+    id: u8,
+    algorithm: Algorithm,
+    key: [u8; 32],
+}
+
+impl PayloadKey {
+    pub fn aes128_gcm(id: u8, key: &[u8; 16]) -> Self {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(key);
+        Self {
+            id,
+            algorithm: Algorithm::Aes128Gcm,
+            key: bytes,
+        }
+    }
+
+    pub fn chacha20_poly1305(id: u8, key: &[u8; 32]) -> Self {
+        Self {
+            id,
+            algorithm: Algorithm::ChaCha20Poly1305,
+            key: *key,
+        }
+    }
+
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    fn nonce(&self, sender: u8, counter: u64) -> Nonce<U12> {
+        let mut nonce = Nonce::default();
+        nonce[0] = self.id;
+        nonce[1] = sender;
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        nonce
+    }
+
+    fn encrypt(
+        &self,
+        sender: u8,
+        counter: u64,
+        topic: &str,
+        data: &mut [u8],
+    ) -> Result<Tag, EncryptionError> {
+        let nonce = self.nonce(sender, counter);
+        // Encryption fails only for payloads over the limits of the algorithms
+        match self.algorithm {
+            Algorithm::Aes128Gcm => Aes128Gcm::new(self.key[..16].into())
+                .encrypt_in_place_detached(&nonce, topic.as_bytes(), data),
+            Algorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new(&self.key.into())
+                .encrypt_in_place_detached(&nonce, topic.as_bytes(), data),
+        }
+        .map_err(|_| EncryptionError::Encryption)
+    }
+
+    fn decrypt(
+        &self,
+        sender: u8,
+        counter: u64,
+        topic: &str,
+        data: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), EncryptionError> {
+        let nonce = self.nonce(sender, counter);
+        let tag = Tag::from_slice(tag);
+        match self.algorithm {
+            Algorithm::Aes128Gcm => Aes128Gcm::new(self.key[..16].into())
+                .decrypt_in_place_detached(&nonce, topic.as_bytes(), data, tag),
+            Algorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new(&self.key.into())
+                .decrypt_in_place_detached(&nonce, topic.as_bytes(), data, tag),
+        }
+        .map_err(|_| EncryptionError::Authentication)
+    }
+}
+
+// Key material is not printed
+impl Debug for PayloadKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PayloadKey")
+            .field("id", &self.id)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+/// Sliding window of the received message counters of one sender. The window lives in RAM, after
+/// restart of the receiver it accepts every counter again unless it is restored by
+/// `PayloadCipher::set_replay_window`, e.g. from the highest counter persisted to flash.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReplayWindow {
+    highest: Option<u64>,
+    // Bit n is set if counter `highest - n` was received
+    received: u64,
+}
+
+impl ReplayWindow {
+    pub const fn new() -> Self {
+        Self {
+            highest: None,
+            received: 0,
+        }
+    }
+
+    /// Creates the window rejecting `highest` and all older counters.
+    pub const fn after(highest: u64) -> Self {
+        Self {
+            highest: Some(highest),
+            received: u64::MAX,
+        }
+    }
+
+    /// Highest received counter.
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+
+    /// Returns true if the `counter` was not received yet and is not older than the window.
+    pub fn check(&self, counter: u64) -> bool {
+        match self.highest {
+            None => true,
+            Some(highest) if counter > highest => true,
+            Some(highest) => {
+                let age = highest - counter;
+                age < REPLAY_WINDOW && self.received & (1 << age) == 0
+            }
+        }
+    }
+
+    /// Marks the `counter` as received, it has to pass `check` before.
+    pub fn accept(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => self.received |= 1 << (highest - counter),
+            Some(highest) => {
+                let shift = counter - highest;
+                self.received = if shift < REPLAY_WINDOW {
+                    (self.received << shift) | 1
+                } else {
+                    1
+                };
+                self.highest = Some(counter);
+            }
+            None => {
+                self.received = 1;
+                self.highest = Some(counter);
+            }
+        }
+    }
+}
+
+/// `PayloadProtection` with a ring of up to `KEYS` keys and replay windows of up to `SENDERS`
+/// senders. Messages are sent with the active key, received messages are verified by the key
+/// selected by the id in the header, so the keys can be rotated while the old key is still used
+/// by the other side. Protected and decrypted payloads are written into the buffer passed to
+/// `new`, which has to hold the largest payload with `OVERHEAD`.
+///
+/// Nonce must never repeat for the same key: every party using the key (e.g. the device and the
+/// backend sending commands to it) needs its own sender id and the counter passed to `new` after
+/// restart has to be higher than any counter sent before, e.g. restored from flash where
+/// `counter()` is persisted.
+pub struct PayloadCipher<'a, const KEYS: usize, const SENDERS: usize> {
+    keys: Vec<PayloadKey, KEYS>,
+    windows: Vec<(u8, ReplayWindow), SENDERS>,
+    active: Option<u8>,
+    sender: u8,
+    counter: u64,
+    buffer: &'a mut [u8],
+}
+
+impl<'a, const KEYS: usize, const SENDERS: usize> PayloadCipher<'a, KEYS, SENDERS> {
+    /// Creates the cipher which sends messages as `sender` and the next one with `counter`.
+    pub fn new(buffer: &'a mut [u8], sender: u8, counter: u64) -> Self {
+        Self {
+            keys: Vec::new(),
+            windows: Vec::new(),
+            active: None,
+            sender,
+            counter,
+            buffer,
+        }
+    }
+
+    /// Adds the `key` to the key ring, key with the same id is replaced. The first key becomes
+    /// active.
+    pub fn add_key(&mut self, key: PayloadKey) -> Result<(), EncryptionError> {
+        let id = key.id;
+        match self.keys.iter_mut().find(|existing| existing.id == id) {
+            Some(existing) => *existing = key,
+            None => self
+                .keys
+                .push(key)
+                .map_err(|_| EncryptionError::TooManyKeys)?,
+        }
+        if self.active.is_none() {
+            self.active = Some(id);
+        }
+        Ok(())
+    }
+
+    /// Removes the key with the `id`, e.g. after all devices switched to the new key.
+    pub fn remove_key(&mut self, id: u8) {
+        self.keys.retain(|key| key.id != id);
+        if self.active == Some(id) {
+            self.active = None;
+        }
+    }
+
+    /// Selects the key used for sending.
+    pub fn set_active_key(&mut self, id: u8) -> Result<(), EncryptionError> {
+        if !self.keys.iter().any(|key| key.id == id) {
+            return Err(EncryptionError::UnknownKey(id));
+        }
+        self.active = Some(id);
+        Ok(())
+    }
+
+    /// Adds the `key` and makes it active.
+    pub fn rotate(&mut self, key: PayloadKey) -> Result<(), EncryptionError> {
+        let id = key.id;
+        self.add_key(key)?;
+        self.set_active_key(id)
+    }
+
+    pub fn active_key(&self) -> Option<u8> {
+        self.active
+    }
+
+    pub fn sender(&self) -> u8 {
+        self.sender
+    }
+
+    /// Counter of the next sent message.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+
+    /// Replay window of the `sender`, its `highest` counter can be persisted and restored after
+    /// restart by `set_replay_window`.
+    pub fn replay_window(&self, sender: u8) -> Option<ReplayWindow> {
+        self.windows
+            .iter()
+            .find(|(id, _)| *id == sender)
+            .map(|(_, window)| *window)
+    }
+
+    /// Sets the replay window of the `sender`, e.g. `ReplayWindow::after` the persisted counter.
+    pub fn set_replay_window(
+        &mut self,
+        sender: u8,
+        window: ReplayWindow,
+    ) -> Result<(), EncryptionError> {
+        match self.windows.iter_mut().find(|(id, _)| *id == sender) {
+            Some((_, existing)) => *existing = window,
+            None => self
+                .windows
+                .push((sender, window))
+                .map_err(|_| EncryptionError::TooManySenders)?,
+        }
+        Ok(())
+    }
+}
+
+impl<'a, const KEYS: usize, const SENDERS: usize> PayloadProtection
+    for PayloadCipher<'a, KEYS, SENDERS>
+{
+    fn seal(&mut self, topic: &str, payload: &[u8]) -> Result<&[u8], EncryptionError> {
+        let id = self.active.ok_or(EncryptionError::NoActiveKey)?;
+        let Some(key) = self.keys.iter().find(|key| key.id == id) else {
+            return Err(EncryptionError::UnknownKey(id));
+        };
+        let counter = self.counter;
+        let next = counter
+            .checked_add(1)
+            .ok_or(EncryptionError::CounterExhausted)?;
+        let len = payload.len() + OVERHEAD;
+        let out = self
+            .buffer
+            .get_mut(..len)
+            .ok_or(EncryptionError::BufferTooSmall)?;
+
+        out[0] = VERSION;
+        out[1] = key.algorithm().id();
+        out[2] = id;
+        out[3] = self.sender;
+        out[4..HEADER_LEN].copy_from_slice(&counter.to_be_bytes());
+        let (data, tag) = out[HEADER_LEN..].split_at_mut(payload.len());
+        data.copy_from_slice(payload);
+        tag.copy_from_slice(&key.encrypt(self.sender, counter, topic, data)?);
+        self.counter = next;
+        Ok(&self.buffer[..len])
+    }
+
+    fn open(&mut self, topic: &str, payload: &[u8]) -> Result<&[u8], EncryptionError> {
+        if payload.len() < OVERHEAD || payload[0] != VERSION {
+            return Err(EncryptionError::Malformed);
+        }
+        let id = payload[2];
+        let Some(key) = self.keys.iter().find(|key| key.id == id) else {
+            return Err(EncryptionError::UnknownKey(id));
+        };
+        if payload[1] != key.algorithm().id() {
+            return Err(EncryptionError::Malformed);
+        }
+        let sender = payload[3];
+        let mut counter = [0; 8];
+        counter.copy_from_slice(&payload[4..HEADER_LEN]);
+        let counter = u64::from_be_bytes(counter);
+        let window = self.windows.iter().position(|(id, _)| *id == sender);
+        if let Some(index) = window {
+            if !self.windows[index].1.check(counter) {
+                warn!("Replayed message {} of sender {} dropped", counter, sender);
+                return Err(EncryptionError::Replay);
+            }
+        }
+
+        let len = payload.len() - OVERHEAD;
+        let data = self
+            .buffer
+            .get_mut(..len)
+            .ok_or(EncryptionError::BufferTooSmall)?;
+        data.copy_from_slice(&payload[HEADER_LEN..HEADER_LEN + len]);
+        key.decrypt(sender, counter, topic, data, &payload[HEADER_LEN + len..])?;
+        // Window of a new sender is created only for an authentic message
+        match window {
+            Some(index) => self.windows[index].1.accept(counter),
+            None => {
+                let mut window = ReplayWindow::new();
+                window.accept(counter);
+                if self.windows.push((sender, window)).is_err() {
+                    warn!("Message of untracked sender {} dropped", sender);
+                    return Err(EncryptionError::TooManySenders);
+                }
+            }
+        }
+        Ok(&self.buffer[..len])
+    }
+}
@@ -0,0 +1,542 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! MQTT-SN v1.2 gateway translating MQTT-SN clients on a datagram network into a single MQTTv5
+//! connection toward the broker (aggregating gateway). Messages published by the clients are
+//! forwarded to the broker and messages from the broker are delivered to the clients with
+//! matching subscriptions.
+//!
+//! The gateway supports topic registration, predefined and short topic ids, QoS -1, 0 and 1
+//! and sleeping clients - messages for the sleeping client are buffered until it checks in
+//! with PINGREQ or connects again. Requests are acknowledged as soon as they are forwarded to
+//! the broker and REGISTER is sent right before the first PUBLISH without waiting for REGACK.
+//! Will messages, QoS 2 and retransmissions are not supported and keep alive of the clients
+//! is not enforced - the gateway has no clock.
+
+pub mod state;
+
+use core::fmt::{Debug, Display, Formatter};
+
+use embedded_io_async::{Read, Write};
+use heapless::{String, Vec};
+use rand_core::RngCore;
+
+use crate::client::client_error::ClientError;
+use crate::client::raw_client::{Event, RawMqttClient};
+use crate::network::datagram::DatagramTransport;
+use crate::packet::sn::sn_packet::{ReturnCode, SnPacket, SnQoS, TopicId};
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::select::{select, Either};
+use crate::utils::types::BufferError;
+
+use self::state::{GatewayState, Route, MAX_TOPIC_LEN};
+
+pub use self::state::{MAX_CLIENT_ID_LEN, MAX_PAYLOAD_LEN};
+
+/// Error stopping the gateway. Generic `D` is the error type of the datagram transport and
+/// `T` of the network driver of the broker connection.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SnGatewayError<D, T> {
+    /// Datagram transport reported an error.
+    Datagram(D),
+    /// Broker connection failed or the broker closed it.
+    Broker(ClientError<T>),
+    /// Message for the client could not be encoded into the send buffer.
+    Encode(BufferError),
+}
+
+impl<D: Debug, T: Debug> Display for SnGatewayError<D, T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SnGatewayError::Datagram(err) => write!(f, "Datagram transport error: {:?}!", err),
+            SnGatewayError::Broker(err) => write!(f, "Broker connection error: {}", err),
+            SnGatewayError::Encode(err) => write!(f, "Could not encode message: {}", err),
+        }
+    }
+}
+
+/// MQTT-SN gateway serving up to `CLIENTS` clients. `TOPICS` limits the topic registry and
+/// the registrations and subscriptions of each client, `QUEUE` the messages buffered for each
+/// sleeping client.
+pub struct MqttSnGateway<
+    'a,
+    D,
+    T,
+    R,
+    const MAX_PROPERTIES: usize,
+    const CLIENTS: usize,
+    const TOPICS: usize,
+    const QUEUE: usize,
+> where
+    D: DatagramTransport,
+    T: Read + Write,
+    R: RngCore,
+{
+    broker: RawMqttClient<'a, T, MAX_PROPERTIES, R>,
+    recv_buffer: &'a mut [u8],
+    clients: Clients<'a, D, CLIENTS, TOPICS, QUEUE>,
+}
+
+// Client side of the gateway, kept apart from the broker connection and the receive buffer
+// so it can be used while the received message or broker event is borrowed.
+struct Clients<'a, D, const CLIENTS: usize, const TOPICS: usize, const QUEUE: usize>
+where
+    D: DatagramTransport,
+{
+    transport: D,
+    gw_id: u8,
+    buffer: &'a mut [u8],
+    state: GatewayState<'a, D::Address, CLIENTS, TOPICS, QUEUE>,
+}
+
+type GatewayResult<D, T> = Result<
+    (),
+    SnGatewayError<<D as DatagramTransport>::Error, <T as embedded_io_async::ErrorType>::Error>,
+>;
+
+impl<
+        'a,
+        D,
+        T,
+        R,
+        const MAX_PROPERTIES: usize,
+        const CLIENTS: usize,
+        const TOPICS: usize,
+        const QUEUE: usize,
+    > MqttSnGateway<'a, D, T, R, MAX_PROPERTIES, CLIENTS, TOPICS, QUEUE>
+where
+    D: DatagramTransport,
+    T: Read + Write,
+    R: RngCore,
+{
+    /// Creates gateway with id `gw_id` serving clients on the `transport`, `broker` is the not
+    /// yet connected client of the broker. `buffer` is used for outgoing and `recv_buffer`
+    /// for incoming datagrams.
+    pub fn new(
+        transport: D,
+        broker: RawMqttClient<'a, T, MAX_PROPERTIES, R>,
+        gw_id: u8,
+        buffer: &'a mut [u8],
+        recv_buffer: &'a mut [u8],
+    ) -> Self {
+        Self {
+            broker,
+            recv_buffer,
+            clients: Clients {
+                transport,
+                gw_id,
+                buffer,
+                state: GatewayState::new(),
+            },
+        }
+    }
+
+    /// Sets the topic ids agreed in advance with the clients as `(topic id, topic name)` pairs.
+    pub fn set_predefined_topics(&mut self, topics: &'a [(u16, &'a str)]) {
+        self.clients.state.set_predefined(topics);
+    }
+
+    /// Connects the gateway to the broker, has to be called before `poll`.
+    pub async fn connect_to_broker(&mut self) -> Result<(), ClientError<T::Error>> {
+        self.broker.connect_to_broker().await?;
+        loop {
+            match self.broker.poll::<0>().await? {
+                Event::Connack(_) => return Ok(()),
+                Event::Auth(ReasonCode::ContinueAuth) => continue,
+                Event::Disconnect(reason, reason_string) => {
+                    return Err(ClientError::rejected(reason, reason_string))
+                }
+                event => {
+                    warn!("Unexpected {:?} packet before CONNACK", event.packet_type());
+                }
+            }
+        }
+    }
+
+    /// Sends PINGREQ to the broker, has to be called regularly to keep the broker connection
+    /// alive. PINGRESP is consumed by `poll`.
+    pub async fn ping_broker(&mut self) -> Result<(), ClientError<T::Error>> {
+        self.broker.send_ping().await
+    }
+
+    /// Number of client sessions, including the sleeping clients.
+    pub fn connected_clients(&self) -> usize {
+        self.clients.state.session_count()
+    }
+
+    /// Waits for the next datagram from the clients or the next packet from the broker and
+    /// handles it. Malformed datagrams and requests rejected by the broker are only logged.
+    pub async fn poll(&mut self) -> GatewayResult<D, T> {
+        // Event borrows the broker client, so it has to be dropped before handling the datagram
+        let (len, address) = match select(
+            self.clients.transport.receive_from(self.recv_buffer),
+            self.broker.poll::<1>(),
+        )
+        .await
+        {
+            Either::First(Ok(received)) => received,
+            Either::First(Err(err)) => return Err(SnGatewayError::Datagram(err)),
+            Either::Second(Ok(Event::Message(message))) => {
+                return self.clients.deliver(message.topic, message.payload).await
+            }
+            Either::Second(Ok(Event::Disconnect(reason, reason_string))) => {
+                return Err(SnGatewayError::Broker(ClientError::rejected(
+                    reason,
+                    reason_string,
+                )))
+            }
+            Either::Second(Ok(_)) => return Ok(()),
+            Either::Second(Err(ClientError::BrokerRejected { reason, .. })) => {
+                warn!("Broker rejected forwarded request: {}", reason);
+                return Ok(());
+            }
+            Either::Second(Err(err)) => return Err(SnGatewayError::Broker(err)),
+        };
+        match SnPacket::decode(&self.recv_buffer[..len]) {
+            Ok(packet) => self.clients.handle(&mut self.broker, address, packet).await,
+            Err(err) => {
+                warn!("Malformed MQTT-SN message dropped: {}", err);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'a, D, const CLIENTS: usize, const TOPICS: usize, const QUEUE: usize>
+    Clients<'a, D, CLIENTS, TOPICS, QUEUE>
+where
+    D: DatagramTransport,
+{
+    async fn handle<T, R, const MAX_PROPERTIES: usize>(
+        &mut self,
+        broker: &mut RawMqttClient<'_, T, MAX_PROPERTIES, R>,
+        address: D::Address,
+        packet: SnPacket<'_>,
+    ) -> GatewayResult<D, T>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        // Messages which do not need the session
+        match packet {
+            SnPacket::SearchGw { .. } => {
+                let gw_info = SnPacket::GwInfo {
+                    gw_id: self.gw_id,
+                    gw_address: &[],
+                };
+                return self.send(address, &gw_info).await;
+            }
+            SnPacket::Connect { will: true, .. } => {
+                warn!("Will messages are not supported by the gateway");
+                let connack = SnPacket::Connack {
+                    return_code: ReturnCode::NotSupported,
+                };
+                return self.send(address, &connack).await;
+            }
+            SnPacket::Connect {
+                clean_session,
+                client_id,
+                ..
+            } => {
+                return match self.state.connect(address, client_id, clean_session) {
+                    Ok((session, unused)) => {
+                        info!("MQTT-SN client {} connected", client_id);
+                        unsubscribe(broker, &unused).await?;
+                        let connack = SnPacket::Connack {
+                            return_code: ReturnCode::Accepted,
+                        };
+                        self.send(address, &connack).await?;
+                        self.flush(session).await
+                    }
+                    Err(return_code) => {
+                        self.send(address, &SnPacket::Connack { return_code }).await
+                    }
+                };
+            }
+            SnPacket::Pingreq {
+                client_id: Some(client_id),
+            } => {
+                if let Some(session) = self.state.find_client(client_id) {
+                    if self.state.is_asleep(session) && self.state.address(session) == address {
+                        self.flush(session).await?;
+                    }
+                }
+                return self.send(address, &SnPacket::Pingresp).await;
+            }
+            SnPacket::Pingreq { client_id: None } => {
+                return self.send(address, &SnPacket::Pingresp).await;
+            }
+            SnPacket::Publish {
+                qos: SnQoS::QoSMinusOne,
+                topic,
+                retain,
+                data,
+                ..
+            } => {
+                return match (topic, self.state.topic_name(topic)) {
+                    (TopicId::Normal(_), _) | (_, Err(_)) => {
+                        warn!("QoS -1 message with unknown topic id dropped");
+                        Ok(())
+                    }
+                    (_, Ok(name)) => {
+                        forward(broker, &name, data, QualityOfService::QoS0, retain).await
+                    }
+                };
+            }
+            _ => {}
+        }
+
+        let Some(session) = self.state.find(address) else {
+            warn!("MQTT-SN message from unknown client, session closed");
+            return self
+                .send(address, &SnPacket::Disconnect { duration: None })
+                .await;
+        };
+        match packet {
+            SnPacket::Register {
+                msg_id, topic_name, ..
+            } => {
+                let (topic_id, return_code) = match self.state.register(session, topic_name) {
+                    Ok(topic_id) => (topic_id, ReturnCode::Accepted),
+                    Err(return_code) => (0, return_code),
+                };
+                let regack = SnPacket::Regack {
+                    topic_id,
+                    msg_id,
+                    return_code,
+                };
+                self.send(address, &regack).await
+            }
+            SnPacket::Publish {
+                qos,
+                retain,
+                topic,
+                msg_id,
+                data,
+                ..
+            } => {
+                let result = match qos {
+                    SnQoS::QoS2 => Err(ReturnCode::NotSupported),
+                    _ => self.state.topic_name(topic),
+                };
+                let return_code = match result {
+                    Ok(name) => {
+                        let broker_qos = match qos {
+                            SnQoS::QoS1 => QualityOfService::QoS1,
+                            _ => QualityOfService::QoS0,
+                        };
+                        forward(broker, &name, data, broker_qos, retain).await?;
+                        if qos != SnQoS::QoS1 {
+                            return Ok(());
+                        }
+                        ReturnCode::Accepted
+                    }
+                    Err(return_code) => return_code,
+                };
+                let puback = SnPacket::Puback {
+                    topic_id: topic.value(),
+                    msg_id,
+                    return_code,
+                };
+                self.send(address, &puback).await
+            }
+            SnPacket::Subscribe {
+                qos, msg_id, topic, ..
+            } => {
+                let suback = match self.state.subscribe(session, topic, qos) {
+                    Ok(subscribed) => {
+                        if subscribed.new_filter {
+                            subscribe(broker, &subscribed.filter).await?;
+                        }
+                        SnPacket::Suback {
+                            qos: subscribed.qos,
+                            topic_id: subscribed.topic_id,
+                            msg_id,
+                            return_code: ReturnCode::Accepted,
+                        }
+                    }
+                    Err(return_code) => SnPacket::Suback {
+                        qos: SnQoS::QoS0,
+                        topic_id: 0,
+                        msg_id,
+                        return_code,
+                    },
+                };
+                self.send(address, &suback).await
+            }
+            SnPacket::Unsubscribe { msg_id, topic } => {
+                if let Some(filter) = self.state.unsubscribe(session, topic) {
+                    unsubscribe(broker, &[filter]).await?;
+                }
+                self.send(address, &SnPacket::Unsuback { msg_id }).await
+            }
+            SnPacket::Disconnect { duration: Some(_) } => {
+                debug!("MQTT-SN client went asleep");
+                self.state.sleep(session);
+                self.send(address, &SnPacket::Disconnect { duration: None })
+                    .await
+            }
+            SnPacket::Disconnect { duration: None } => {
+                debug!("MQTT-SN client disconnected");
+                let unused = self.state.remove(session);
+                unsubscribe(broker, &unused).await?;
+                self.send(address, &SnPacket::Disconnect { duration: None })
+                    .await
+            }
+            SnPacket::Regack { .. } | SnPacket::Puback { .. } => Ok(()),
+            packet => {
+                warn!("Unexpected MQTT-SN message {:?}", packet.msg_type());
+                Ok(())
+            }
+        }
+    }
+
+    // Delivers message from the broker to the awake clients and buffers it for the sleeping
+    async fn deliver<E>(
+        &mut self,
+        topic: &str,
+        data: &[u8],
+    ) -> Result<(), SnGatewayError<D::Error, E>> {
+        for session in 0..self.state.session_count() {
+            let Some(route) = self.state.route(session, topic) else {
+                continue;
+            };
+            if self.state.is_asleep(session) {
+                self.state.enqueue(session, topic, data);
+            } else {
+                self.publish(session, topic, data, route).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // Sends messages buffered for the sleeping client
+    async fn flush<E>(&mut self, session: usize) -> Result<(), SnGatewayError<D::Error, E>> {
+        while let Some(message) = self.state.dequeue(session) {
+            if let Some(route) = self.state.route(session, &message.topic) {
+                self.publish(session, &message.topic, &message.payload, route)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn publish<E>(
+        &mut self,
+        session: usize,
+        topic: &str,
+        data: &[u8],
+        route: Route,
+    ) -> Result<(), SnGatewayError<D::Error, E>> {
+        let address = self.state.address(session);
+        if let (true, TopicId::Normal(topic_id)) = (route.register, route.topic) {
+            let register = SnPacket::Register {
+                topic_id,
+                msg_id: self.state.next_msg_id(session),
+                topic_name: topic,
+            };
+            self.send(address, &register).await?;
+            self.state.mark_registered(session, topic_id);
+        }
+        let msg_id = match route.qos {
+            SnQoS::QoS1 => self.state.next_msg_id(session),
+            _ => 0,
+        };
+        let publish = SnPacket::Publish {
+            dup: false,
+            qos: route.qos,
+            retain: false,
+            topic: route.topic,
+            msg_id,
+            data,
+        };
+        self.send(address, &publish).await
+    }
+
+    async fn send<E>(
+        &mut self,
+        address: D::Address,
+        packet: &SnPacket<'_>,
+    ) -> Result<(), SnGatewayError<D::Error, E>> {
+        let len = packet.encode(self.buffer).map_err(SnGatewayError::Encode)?;
+        self.transport
+            .send_to(address, &self.buffer[..len])
+            .await
+            .map_err(SnGatewayError::Datagram)
+    }
+}
+
+async fn forward<T, R, E, const MAX_PROPERTIES: usize>(
+    broker: &mut RawMqttClient<'_, T, MAX_PROPERTIES, R>,
+    topic: &str,
+    data: &[u8],
+    qos: QualityOfService,
+    retain: bool,
+) -> Result<(), SnGatewayError<E, T::Error>>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    broker
+        .send_message(topic, data, qos, retain)
+        .await
+        .map(|_| ())
+        .map_err(SnGatewayError::Broker)
+}
+
+async fn subscribe<T, R, E, const MAX_PROPERTIES: usize>(
+    broker: &mut RawMqttClient<'_, T, MAX_PROPERTIES, R>,
+    filter: &String<MAX_TOPIC_LEN>,
+) -> Result<(), SnGatewayError<E, T::Error>>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    let mut filters = Vec::<_, 1>::new();
+    let _ = filters.push(filter.as_str());
+    broker
+        .subscribe_to_topics(&filters)
+        .await
+        .map(|_| ())
+        .map_err(SnGatewayError::Broker)
+}
+
+async fn unsubscribe<T, R, E, const MAX_PROPERTIES: usize>(
+    broker: &mut RawMqttClient<'_, T, MAX_PROPERTIES, R>,
+    filters: &[String<MAX_TOPIC_LEN>],
+) -> Result<(), SnGatewayError<E, T::Error>>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    for filter in filters {
+        broker
+            .unsubscribe_from_topic(filter)
+            .await
+            .map_err(SnGatewayError::Broker)?;
+    }
+    Ok(())
+}
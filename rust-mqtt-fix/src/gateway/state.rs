@@ -0,0 +1,409 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::str;
+
+use heapless::{Deque, String, Vec};
+
+use crate::packet::sn::sn_packet::{ReturnCode, SnQoS, TopicId, TopicRef};
+use crate::utils::topic::{topic_matches, valid_topic_filter, valid_topic_name};
+
+/// Maximal length of the client identifier accepted by the gateway.
+pub const MAX_CLIENT_ID_LEN: usize = 23;
+/// Maximal length of registered topic names and subscribed topic filters.
+pub const MAX_TOPIC_LEN: usize = 64;
+/// Maximal payload length of messages buffered for sleeping clients.
+pub const MAX_PAYLOAD_LEN: usize = 256;
+
+/// Message from the broker buffered for the sleeping client.
+#[derive(Debug, Clone)]
+pub(crate) struct Message {
+    pub topic: String<MAX_TOPIC_LEN>,
+    pub payload: Vec<u8, MAX_PAYLOAD_LEN>,
+}
+
+/// How the message is delivered to the client - QoS, topic id and whether the topic id has
+/// to be registered at the client first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Route {
+    pub qos: SnQoS,
+    pub topic: TopicId,
+    pub register: bool,
+}
+
+/// Result of the subscription. `new_filter` is set when no other subscription uses the
+/// filter and the gateway has to subscribe to it at the broker.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Subscribed {
+    pub topic_id: u16,
+    pub qos: SnQoS,
+    pub filter: String<MAX_TOPIC_LEN>,
+    pub new_filter: bool,
+}
+
+struct Subscription {
+    filter: String<MAX_TOPIC_LEN>,
+    qos: SnQoS,
+    // Predefined and short topic subscriptions are delivered with the same topic id
+    topic: Option<TopicId>,
+}
+
+struct Session<A, const TOPICS: usize, const QUEUE: usize> {
+    address: A,
+    client_id: String<MAX_CLIENT_ID_LEN>,
+    asleep: bool,
+    msg_id: u16,
+    registered: Vec<u16, TOPICS>,
+    subscriptions: Vec<Subscription, TOPICS>,
+    queue: Deque<Message, QUEUE>,
+}
+
+/// Client sessions of the MQTT-SN gateway and the topic registry shared by them. Sessions are
+/// addressed by index, which is valid until the next session is removed.
+pub(crate) struct GatewayState<'a, A, const CLIENTS: usize, const TOPICS: usize, const QUEUE: usize>
+{
+    predefined: &'a [(u16, &'a str)],
+    topics: Vec<String<MAX_TOPIC_LEN>, TOPICS>,
+    sessions: Vec<Session<A, TOPICS, QUEUE>, CLIENTS>,
+}
+
+impl<'a, A, const CLIENTS: usize, const TOPICS: usize, const QUEUE: usize>
+    GatewayState<'a, A, CLIENTS, TOPICS, QUEUE>
+where
+    A: Copy + PartialEq,
+{
+    pub const fn new() -> Self {
+        Self {
+            predefined: &[],
+            topics: Vec::new(),
+            sessions: Vec::new(),
+        }
+    }
+
+    pub fn set_predefined(&mut self, predefined: &'a [(u16, &'a str)]) {
+        self.predefined = predefined;
+    }
+
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn find(&self, address: A) -> Option<usize> {
+        self.sessions.iter().position(|s| s.address == address)
+    }
+
+    pub fn find_client(&self, client_id: &str) -> Option<usize> {
+        self.sessions.iter().position(|s| s.client_id == client_id)
+    }
+
+    pub fn address(&self, session: usize) -> A {
+        self.sessions[session].address
+    }
+
+    pub fn is_asleep(&self, session: usize) -> bool {
+        self.sessions[session].asleep
+    }
+
+    /// Creates session or resumes the existing session of the client, the client is awake
+    /// afterwards. Returns the session and filters which are not used anymore because the
+    /// clean session dropped them.
+    pub fn connect(
+        &mut self,
+        address: A,
+        client_id: &str,
+        clean_session: bool,
+    ) -> Result<(usize, Vec<String<MAX_TOPIC_LEN>, TOPICS>), ReturnCode> {
+        let mut id = String::new();
+        if client_id.is_empty() || id.push_str(client_id).is_err() {
+            return Err(ReturnCode::NotSupported);
+        }
+        if let Some(index) = self.find_client(client_id) {
+            let session = &mut self.sessions[index];
+            session.address = address;
+            session.asleep = false;
+            if !clean_session {
+                return Ok((index, Vec::new()));
+            }
+            session.registered.clear();
+            session.queue.clear();
+            let dropped = core::mem::take(&mut session.subscriptions);
+            return Ok((
+                index,
+                self.unused(dropped.into_iter().map(|sub| sub.filter)),
+            ));
+        }
+        let session = Session {
+            address,
+            client_id: id,
+            asleep: false,
+            msg_id: 0,
+            registered: Vec::new(),
+            subscriptions: Vec::new(),
+            queue: Deque::new(),
+        };
+        if self.sessions.push(session).is_err() {
+            warn!("Client {} refused, gateway is full", client_id);
+            return Err(ReturnCode::Congestion);
+        }
+        Ok((self.sessions.len() - 1, Vec::new()))
+    }
+
+    /// Removes the session, returns filters which are not used anymore.
+    pub fn remove(&mut self, session: usize) -> Vec<String<MAX_TOPIC_LEN>, TOPICS> {
+        let removed = self.sessions.swap_remove(session);
+        self.unused(removed.subscriptions.into_iter().map(|sub| sub.filter))
+    }
+
+    pub fn sleep(&mut self, session: usize) {
+        self.sessions[session].asleep = true;
+    }
+
+    pub fn next_msg_id(&mut self, session: usize) -> u16 {
+        let session = &mut self.sessions[session];
+        session.msg_id = session.msg_id.wrapping_add(1).max(1);
+        session.msg_id
+    }
+
+    /// Assigns topic id to the topic name registered by the client.
+    pub fn register(&mut self, session: usize, topic_name: &str) -> Result<u16, ReturnCode> {
+        if !valid_topic_name(topic_name) {
+            return Err(ReturnCode::NotSupported);
+        }
+        let topic_id = self.topic_id(topic_name).ok_or(ReturnCode::Congestion)?;
+        self.mark_registered(session, topic_id);
+        Ok(topic_id)
+    }
+
+    pub fn mark_registered(&mut self, session: usize, topic_id: u16) {
+        let registered = &mut self.sessions[session].registered;
+        if !registered.contains(&topic_id) && registered.push(topic_id).is_err() {
+            warn!("Registration table of the client is full");
+        }
+    }
+
+    /// Returns the topic name for the topic id of the incoming PUBLISH.
+    pub fn topic_name(&self, topic: TopicId) -> Result<String<MAX_TOPIC_LEN>, ReturnCode> {
+        let name = match topic {
+            TopicId::Normal(id) => self
+                .topics
+                .get((id as usize).wrapping_sub(1))
+                .map(|name| name.as_str()),
+            TopicId::Predefined(id) => self.predefined_name(id),
+            TopicId::Short(ref name) => str::from_utf8(name).ok(),
+        };
+        let mut topic_name = String::new();
+        match name {
+            Some(name) if valid_topic_name(name) && topic_name.push_str(name).is_ok() => {
+                Ok(topic_name)
+            }
+            _ => Err(ReturnCode::InvalidTopicId),
+        }
+    }
+
+    /// Adds or updates subscription of the session. Topic names without wildcards get
+    /// registered, granted QoS is at most 1.
+    pub fn subscribe(
+        &mut self,
+        session: usize,
+        topic: TopicRef<'_>,
+        qos: SnQoS,
+    ) -> Result<Subscribed, ReturnCode> {
+        let qos = match qos {
+            SnQoS::QoS0 => SnQoS::QoS0,
+            SnQoS::QoS1 | SnQoS::QoS2 => SnQoS::QoS1,
+            SnQoS::QoSMinusOne => return Err(ReturnCode::NotSupported),
+        };
+        let (sub_filter, delivery) = self.filter(topic)?;
+        let topic_id = match topic {
+            TopicRef::Name(name) if valid_topic_name(name) => self.register(session, name)?,
+            TopicRef::Predefined(id) => id,
+            _ => 0,
+        };
+
+        let new_filter = !self.is_used(&sub_filter);
+        let subscriptions = &mut self.sessions[session].subscriptions;
+        let subscription = Subscription {
+            filter: sub_filter.clone(),
+            qos,
+            topic: delivery,
+        };
+        match subscriptions
+            .iter_mut()
+            .find(|sub| sub.filter == sub_filter)
+        {
+            Some(sub) => *sub = subscription,
+            None => {
+                if subscriptions.push(subscription).is_err() {
+                    warn!("Subscription table of the client is full");
+                    return Err(ReturnCode::Congestion);
+                }
+            }
+        }
+        Ok(Subscribed {
+            topic_id,
+            qos,
+            filter: sub_filter,
+            new_filter,
+        })
+    }
+
+    /// Removes subscription of the session, returns the filter if it is not used anymore.
+    pub fn unsubscribe(
+        &mut self,
+        session: usize,
+        topic: TopicRef<'_>,
+    ) -> Option<String<MAX_TOPIC_LEN>> {
+        let (filter, _) = self.filter(topic).ok()?;
+        let subscriptions = &mut self.sessions[session].subscriptions;
+        let pos = subscriptions.iter().position(|sub| sub.filter == filter)?;
+        let removed = subscriptions.swap_remove(pos);
+        self.unused(core::iter::once(removed.filter)).pop()
+    }
+
+    /// Returns how the message from the broker on the `topic` is delivered to the session,
+    /// `None` if the session is not subscribed to it. Topics without predefined or short
+    /// topic id get id from the registry.
+    pub fn route(&mut self, session: usize, topic: &str) -> Option<Route> {
+        let mut qos = None;
+        let mut topic_id = None;
+        for sub in self.sessions[session].subscriptions.iter() {
+            if !topic_matches(&sub.filter, topic) {
+                continue;
+            }
+            qos = match qos {
+                Some(SnQoS::QoS1) => Some(SnQoS::QoS1),
+                _ => Some(sub.qos),
+            };
+            topic_id = topic_id.or(sub.topic);
+        }
+        let qos = qos?;
+        let topic = match topic_id {
+            Some(topic) => topic,
+            None => match self.topic_id(topic) {
+                Some(id) => TopicId::Normal(id),
+                None => {
+                    warn!("Topic registry is full, message on {} dropped", topic);
+                    return None;
+                }
+            },
+        };
+        let register = match topic {
+            TopicId::Normal(id) => !self.sessions[session].registered.contains(&id),
+            _ => false,
+        };
+        Some(Route {
+            qos,
+            topic,
+            register,
+        })
+    }
+
+    /// Buffers the message for the sleeping client, message is dropped if the queue is full
+    /// or the message is too long.
+    pub fn enqueue(&mut self, session: usize, topic: &str, payload: &[u8]) {
+        let session = &mut self.sessions[session];
+        let mut message_topic = String::new();
+        let message = match (message_topic.push_str(topic), Vec::from_slice(payload)) {
+            (Ok(()), Ok(payload)) => Message {
+                topic: message_topic,
+                payload,
+            },
+            _ => {
+                warn!("Message on {} too long to buffer, dropped", topic);
+                return;
+            }
+        };
+        if session.queue.push_back(message).is_err() {
+            warn!(
+                "Queue of client {} is full, message dropped",
+                session.client_id.as_str()
+            );
+        }
+    }
+
+    pub fn dequeue(&mut self, session: usize) -> Option<Message> {
+        self.sessions[session].queue.pop_front()
+    }
+
+    fn predefined_name(&self, id: u16) -> Option<&'a str> {
+        self.predefined
+            .iter()
+            .find(|(topic_id, _)| *topic_id == id)
+            .map(|(_, name)| *name)
+    }
+
+    // Topic filter of the SUBSCRIBE / UNSUBSCRIBE and the topic id used for the delivery
+    fn filter(
+        &self,
+        topic: TopicRef<'_>,
+    ) -> Result<(String<MAX_TOPIC_LEN>, Option<TopicId>), ReturnCode> {
+        let (name, delivery) = match topic {
+            TopicRef::Name(name) => (name, None),
+            TopicRef::Predefined(id) => (
+                self.predefined_name(id).ok_or(ReturnCode::InvalidTopicId)?,
+                Some(TopicId::Predefined(id)),
+            ),
+            // Short topic is delivered with the same id, it cannot contain wildcards
+            TopicRef::Short(ref name) => match str::from_utf8(name) {
+                Ok(short) if valid_topic_name(short) => (short, Some(TopicId::Short(*name))),
+                _ => return Err(ReturnCode::NotSupported),
+            },
+        };
+        let mut filter = String::new();
+        if !valid_topic_filter(name) || filter.push_str(name).is_err() {
+            return Err(ReturnCode::NotSupported);
+        }
+        Ok((filter, delivery))
+    }
+
+    // Registry id of the topic, new id is assigned if the topic is not registered yet
+    fn topic_id(&mut self, topic: &str) -> Option<u16> {
+        if let Some(pos) = self.topics.iter().position(|name| name == topic) {
+            return Some(pos as u16 + 1);
+        }
+        let mut name = String::new();
+        name.push_str(topic).ok()?;
+        self.topics.push(name).ok()?;
+        Some(self.topics.len() as u16)
+    }
+
+    fn is_used(&self, filter: &str) -> bool {
+        self.sessions
+            .iter()
+            .any(|s| s.subscriptions.iter().any(|sub| sub.filter == filter))
+    }
+
+    fn unused(
+        &self,
+        filters: impl Iterator<Item = String<MAX_TOPIC_LEN>>,
+    ) -> Vec<String<MAX_TOPIC_LEN>, TOPICS> {
+        let mut unused = Vec::new();
+        for filter in filters {
+            if !self.is_used(&filter) && !unused.contains(&filter) {
+                let _ = unused.push(filter);
+            }
+        }
+        unused
+    }
+}
@@ -0,0 +1,553 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Home Assistant MQTT discovery. `Discovery` describes the device of the node and its
+//! entities and publishes their retained config payloads to
+//! `homeassistant/<component>/<node_id>/<object_id>/config`. Availability of all entities
+//! is tied to the will message of the client: the node publishes `online` after connect and
+//! the broker publishes `offline` when the connection is lost. Payloads are written into
+//! a caller provided buffer without an allocator.
+
+use core::fmt::{Display, Formatter, Write as _};
+
+use embedded_io_async::{Read, Write};
+use heapless::String;
+use rand_core::RngCore;
+
+use crate::client::client::MqttClient;
+use crate::client::client_config::ClientConfig;
+use crate::client::client_error::ClientError;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::types::BufferError;
+
+/// Default discovery prefix of Home Assistant.
+pub const DEFAULT_PREFIX: &str = "homeassistant";
+/// Payload of the Home Assistant birth message sent to `<prefix>/status`.
+pub const BIRTH_PAYLOAD: &str = "online";
+/// Maximal length of the discovery topic.
+pub const MAX_DISCOVERY_TOPIC_LEN: usize = 128;
+
+/// Error of the discovery payload generation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiscoveryError {
+    /// Config payload or discovery topic does not fit into the buffer.
+    BufferTooSmall,
+    /// Node id or object id contains other characters than `[a-zA-Z0-9_-]`.
+    InvalidId,
+}
+
+impl Display for DiscoveryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DiscoveryError::BufferTooSmall => {
+                write!(f, "Discovery config does not fit into buffer!")
+            }
+            DiscoveryError::InvalidId => write!(f, "Node id or object id is not valid!"),
+        }
+    }
+}
+
+impl From<DiscoveryError> for BufferError {
+    fn from(err: DiscoveryError) -> Self {
+        match err {
+            DiscoveryError::BufferTooSmall => BufferError::InsufficientBufferSize,
+            DiscoveryError::InvalidId => BufferError::EncodingError,
+        }
+    }
+}
+
+/// Home Assistant entity platform.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Component {
+    Sensor,
+    BinarySensor,
+    Switch,
+    Light,
+    Button,
+}
+
+impl Component {
+    /// Name of the component used in the discovery topic.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+            Component::Switch => "switch",
+            Component::Light => "light",
+            Component::Button => "button",
+        }
+    }
+}
+
+/// State class of the sensor used by the Home Assistant long term statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StateClass {
+    Measurement,
+    Total,
+    TotalIncreasing,
+}
+
+impl StateClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateClass::Measurement => "measurement",
+            StateClass::Total => "total",
+            StateClass::TotalIncreasing => "total_increasing",
+        }
+    }
+}
+
+/// Device the entities belong to. `identifier` has to be unique, e.g. the MAC address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct DeviceInfo<'a> {
+    pub identifier: &'a str,
+    pub name: &'a str,
+    pub manufacturer: Option<&'a str>,
+    pub model: Option<&'a str>,
+    pub sw_version: Option<&'a str>,
+}
+
+impl<'a> DeviceInfo<'a> {
+    pub const fn new(identifier: &'a str, name: &'a str) -> Self {
+        Self {
+            identifier,
+            name,
+            manufacturer: None,
+            model: None,
+            sw_version: None,
+        }
+    }
+
+    pub const fn with_manufacturer(mut self, manufacturer: &'a str) -> Self {
+        self.manufacturer = Some(manufacturer);
+        self
+    }
+
+    pub const fn with_model(mut self, model: &'a str) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub const fn with_sw_version(mut self, sw_version: &'a str) -> Self {
+        self.sw_version = Some(sw_version);
+        self
+    }
+}
+
+/// Availability topic of the node. The same topic and `payload_not_available` are used as
+/// the will message, see `Availability::configure_will`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Availability<'a> {
+    pub topic: &'a str,
+    pub payload_available: &'a str,
+    pub payload_not_available: &'a str,
+}
+
+impl<'a> Availability<'a> {
+    /// Availability on the `topic` with the Home Assistant default payloads `online` and
+    /// `offline`.
+    pub const fn new(topic: &'a str) -> Self {
+        Self {
+            topic,
+            payload_available: "online",
+            payload_not_available: "offline",
+        }
+    }
+
+    /// Sets the retained will message of the `config`, so the broker marks the entities
+    /// unavailable when the node disconnects without the DISCONNECT packet.
+    pub fn configure_will<const MAX_PROPERTIES: usize, R: RngCore>(
+        &self,
+        config: &mut ClientConfig<'a, MAX_PROPERTIES, R>,
+    ) {
+        config.add_will(self.topic, self.payload_not_available.as_bytes(), true);
+    }
+}
+
+/// Entity announced to Home Assistant. `object_id` has to be unique within the node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Entity<'a> {
+    pub component: Component,
+    pub object_id: &'a str,
+    pub name: &'a str,
+    pub state_topic: Option<&'a str>,
+    pub command_topic: Option<&'a str>,
+    pub unit_of_measurement: Option<&'a str>,
+    pub device_class: Option<&'a str>,
+    pub state_class: Option<StateClass>,
+    pub value_template: Option<&'a str>,
+}
+
+impl<'a> Entity<'a> {
+    const fn new(component: Component, object_id: &'a str, name: &'a str) -> Self {
+        Self {
+            component,
+            object_id,
+            name,
+            state_topic: None,
+            command_topic: None,
+            unit_of_measurement: None,
+            device_class: None,
+            state_class: None,
+            value_template: None,
+        }
+    }
+
+    /// Sensor reporting its value on the `state_topic`.
+    pub const fn sensor(object_id: &'a str, name: &'a str, state_topic: &'a str) -> Self {
+        let mut entity = Self::new(Component::Sensor, object_id, name);
+        entity.state_topic = Some(state_topic);
+        entity
+    }
+
+    /// Binary sensor reporting `ON` or `OFF` on the `state_topic`.
+    pub const fn binary_sensor(object_id: &'a str, name: &'a str, state_topic: &'a str) -> Self {
+        let mut entity = Self::new(Component::BinarySensor, object_id, name);
+        entity.state_topic = Some(state_topic);
+        entity
+    }
+
+    /// Switch receiving `ON` or `OFF` on the `command_topic` and reporting the state on the
+    /// `state_topic`.
+    pub const fn switch(
+        object_id: &'a str,
+        name: &'a str,
+        state_topic: &'a str,
+        command_topic: &'a str,
+    ) -> Self {
+        let mut entity = Self::new(Component::Switch, object_id, name);
+        entity.state_topic = Some(state_topic);
+        entity.command_topic = Some(command_topic);
+        entity
+    }
+
+    /// On/off light with the default schema, topics work the same way as for the switch.
+    pub const fn light(
+        object_id: &'a str,
+        name: &'a str,
+        state_topic: &'a str,
+        command_topic: &'a str,
+    ) -> Self {
+        let mut entity = Self::new(Component::Light, object_id, name);
+        entity.state_topic = Some(state_topic);
+        entity.command_topic = Some(command_topic);
+        entity
+    }
+
+    /// Button receiving `PRESS` on the `command_topic`.
+    pub const fn button(object_id: &'a str, name: &'a str, command_topic: &'a str) -> Self {
+        let mut entity = Self::new(Component::Button, object_id, name);
+        entity.command_topic = Some(command_topic);
+        entity
+    }
+
+    pub const fn with_unit(mut self, unit_of_measurement: &'a str) -> Self {
+        self.unit_of_measurement = Some(unit_of_measurement);
+        self
+    }
+
+    pub const fn with_device_class(mut self, device_class: &'a str) -> Self {
+        self.device_class = Some(device_class);
+        self
+    }
+
+    pub const fn with_state_class(mut self, state_class: StateClass) -> Self {
+        self.state_class = Some(state_class);
+        self
+    }
+
+    /// Template extracting the value from the state payload, e.g. `{{ value_json.temp }}`.
+    pub const fn with_value_template(mut self, value_template: &'a str) -> Self {
+        self.value_template = Some(value_template);
+        self
+    }
+}
+
+/// Discovery of all entities of one node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Discovery<'a> {
+    pub prefix: &'a str,
+    pub node_id: &'a str,
+    pub device: DeviceInfo<'a>,
+    pub availability: Availability<'a>,
+    pub entities: &'a [Entity<'a>],
+}
+
+impl<'a> Discovery<'a> {
+    pub const fn new(
+        node_id: &'a str,
+        device: DeviceInfo<'a>,
+        availability: Availability<'a>,
+        entities: &'a [Entity<'a>],
+    ) -> Self {
+        Self {
+            prefix: DEFAULT_PREFIX,
+            node_id,
+            device,
+            availability,
+            entities,
+        }
+    }
+
+    /// Replaces the `homeassistant` discovery prefix.
+    pub const fn with_prefix(mut self, prefix: &'a str) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Returns `<prefix>/<component>/<node_id>/<object_id>/config`.
+    pub fn config_topic(
+        &self,
+        entity: &Entity<'_>,
+    ) -> Result<String<MAX_DISCOVERY_TOPIC_LEN>, DiscoveryError> {
+        if !is_valid_id(self.node_id) || !is_valid_id(entity.object_id) {
+            return Err(DiscoveryError::InvalidId);
+        }
+        let mut topic = String::new();
+        write!(
+            topic,
+            "{}/{}/{}/{}/config",
+            self.prefix,
+            entity.component.name(),
+            self.node_id,
+            entity.object_id
+        )
+        .map_err(|_| DiscoveryError::BufferTooSmall)?;
+        Ok(topic)
+    }
+
+    /// Writes the JSON config payload of the `entity` into the `buffer`, returns its length.
+    pub fn write_config(
+        &self,
+        entity: &Entity<'_>,
+        buffer: &mut [u8],
+    ) -> Result<usize, DiscoveryError> {
+        if !is_valid_id(self.node_id) || !is_valid_id(entity.object_id) {
+            return Err(DiscoveryError::InvalidId);
+        }
+        let mut json = JsonWriter::new(buffer);
+        json.open()?;
+        json.field("name", entity.name)?;
+        json.key("unique_id")?;
+        json.raw("\"")?;
+        json.escaped(self.node_id)?;
+        json.raw("_")?;
+        json.escaped(entity.object_id)?;
+        json.raw("\"")?;
+        json.optional_field("state_topic", entity.state_topic)?;
+        json.optional_field("command_topic", entity.command_topic)?;
+        json.optional_field("unit_of_measurement", entity.unit_of_measurement)?;
+        json.optional_field("device_class", entity.device_class)?;
+        json.optional_field(
+            "state_class",
+            entity.state_class.as_ref().map(StateClass::name),
+        )?;
+        json.optional_field("value_template", entity.value_template)?;
+        json.field("availability_topic", self.availability.topic)?;
+        json.field("payload_available", self.availability.payload_available)?;
+        json.field(
+            "payload_not_available",
+            self.availability.payload_not_available,
+        )?;
+        json.key("device")?;
+        json.open()?;
+        json.key("identifiers")?;
+        json.raw("[\"")?;
+        json.escaped(self.device.identifier)?;
+        json.raw("\"]")?;
+        json.field("name", self.device.name)?;
+        json.optional_field("manufacturer", self.device.manufacturer)?;
+        json.optional_field("model", self.device.model)?;
+        json.optional_field("sw_version", self.device.sw_version)?;
+        json.close()?;
+        json.close()?;
+        Ok(json.len)
+    }
+
+    /// Topic of the Home Assistant birth message, `<prefix>/status`.
+    pub fn status_topic(&self) -> Result<String<MAX_DISCOVERY_TOPIC_LEN>, DiscoveryError> {
+        let mut topic = String::new();
+        write!(topic, "{}/status", self.prefix).map_err(|_| DiscoveryError::BufferTooSmall)?;
+        Ok(topic)
+    }
+
+    /// Returns `true` if the message is the Home Assistant birth message. Home Assistant
+    /// sends it after restart and the node has to `announce` the entities again.
+    pub fn is_birth_message(&self, topic: &str, payload: &[u8]) -> bool {
+        payload == BIRTH_PAYLOAD.as_bytes()
+            && topic.len() == self.prefix.len() + "/status".len()
+            && topic.starts_with(self.prefix)
+            && topic.ends_with("/status")
+    }
+
+    /// Subscribes the `client` to the Home Assistant birth message topic.
+    pub async fn subscribe_status<T, const MAX_PROPERTIES: usize, R>(
+        &self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        let topic = self
+            .status_topic()
+            .map_err(|err| ClientError::Encode(err.into()))?;
+        client.subscribe_to_topic(&topic).await
+    }
+
+    /// Publishes the retained config of every entity with QoS 1, using the `buffer` for the
+    /// payload, followed by the retained `payload_available` on the availability topic. It
+    /// has to be called after every connect and after the birth message.
+    pub async fn announce<T, const MAX_PROPERTIES: usize, R>(
+        &self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        for entity in self.entities {
+            let topic = self
+                .config_topic(entity)
+                .map_err(|err| ClientError::Encode(err.into()))?;
+            let len = self
+                .write_config(entity, buffer)
+                .map_err(|err| ClientError::Encode(err.into()))?;
+            debug!("Announcing {} entity", entity.component.name());
+            client
+                .send_message(&topic, &buffer[..len], QualityOfService::QoS1, true)
+                .await?;
+        }
+        client
+            .send_message(
+                self.availability.topic,
+                self.availability.payload_available.as_bytes(),
+                QualityOfService::QoS1,
+                true,
+            )
+            .await
+    }
+}
+
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Minimal JSON object writer over a byte buffer.
+struct JsonWriter<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+    first: bool,
+}
+
+impl<'b> JsonWriter<'b> {
+    fn new(buffer: &'b mut [u8]) -> Self {
+        Self {
+            buffer,
+            len: 0,
+            first: true,
+        }
+    }
+
+    fn raw(&mut self, s: &str) -> Result<(), DiscoveryError> {
+        let end = self.len + s.len();
+        if end > self.buffer.len() {
+            return Err(DiscoveryError::BufferTooSmall);
+        }
+        self.buffer[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<(), DiscoveryError> {
+        self.first = true;
+        self.raw("{")
+    }
+
+    fn close(&mut self) -> Result<(), DiscoveryError> {
+        self.first = false;
+        self.raw("}")
+    }
+
+    fn escaped(&mut self, s: &str) -> Result<(), DiscoveryError> {
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            let escape = match c {
+                '"' => Some("\\\""),
+                '\\' => Some("\\\\"),
+                '\n' => Some("\\n"),
+                '\r' => Some("\\r"),
+                '\t' => Some("\\t"),
+                c if c < ' ' => None,
+                _ => continue,
+            };
+            self.raw(&s[start..i])?;
+            match escape {
+                Some(escape) => self.raw(escape)?,
+                None => {
+                    let mut unicode = String::<6>::new();
+                    let _ = write!(unicode, "\\u{:04x}", c as u32);
+                    self.raw(&unicode)?;
+                }
+            }
+            start = i + c.len_utf8();
+        }
+        self.raw(&s[start..])
+    }
+
+    fn key(&mut self, key: &str) -> Result<(), DiscoveryError> {
+        if !self.first {
+            self.raw(",")?;
+        }
+        self.first = false;
+        self.raw("\"")?;
+        self.raw(key)?;
+        self.raw("\":")
+    }
+
+    fn field(&mut self, key: &str, value: &str) -> Result<(), DiscoveryError> {
+        self.key(key)?;
+        self.raw("\"")?;
+        self.escaped(value)?;
+        self.raw("\"")
+    }
+
+    fn optional_field(&mut self, key: &str, value: Option<&str>) -> Result<(), DiscoveryError> {
+        match value {
+            Some(value) => self.field(key, value),
+            None => Ok(()),
+        }
+    }
+}
@@ -22,14 +22,29 @@
  * SOFTWARE.
  */
 
-#![macro_use]
 #![cfg_attr(not(feature = "std"), no_std)]
 #![allow(dead_code)]
 pub(crate) mod fmt;
 
+#[cfg(feature = "broker")]
+pub mod broker;
 pub mod client;
+#[cfg(feature = "codec")]
+pub mod codec;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod encoding;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod gateway;
+#[cfg(feature = "homeassistant")]
+pub mod homeassistant;
 pub mod network;
 pub mod packet;
+pub mod queue;
+#[cfg(feature = "sparkplug")]
+pub mod sparkplug;
+#[cfg(feature = "test-support")]
+pub mod test_support;
 pub mod tests;
 pub mod utils;
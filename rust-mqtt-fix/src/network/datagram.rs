@@ -0,0 +1,46 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::Debug;
+
+/// Connectionless transport carrying whole datagrams, used by the MQTT-SN client and gateway.
+/// Implement it for UDP sockets, ESP-NOW or any other link where single datagram holds single
+/// MQTT-SN message.
+#[allow(async_fn_in_trait)]
+pub trait DatagramTransport {
+    type Error: Debug;
+    /// Address of the peer, e.g. `SocketAddr` or MAC address of ESP-NOW peer.
+    type Address: Copy + PartialEq;
+
+    /// Send the whole `data` as single datagram to the `address`.
+    async fn send_to(&mut self, address: Self::Address, data: &[u8]) -> Result<(), Self::Error>;
+
+    /// Receive single datagram to the `buffer`, returns its length and address of the sender.
+    /// Datagrams longer than the buffer may be truncated. Has to be cancel safe, the gateway
+    /// drops this future when the broker connection delivers message first.
+    async fn receive_from(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, Self::Address), Self::Error>;
+}
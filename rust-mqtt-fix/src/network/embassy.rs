@@ -0,0 +1,184 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Helper which connects the client through an embassy-net `Stack`: resolves the broker host
+//! name, opens the TCP socket and sends CONNECT. Available with the `embassy-net` feature.
+//!
+//! Socket timeout is derived from the keep alive of the `ClientConfig`. The broker closes the
+//! connection after one and a half keep alive interval without a packet, so the socket gives up
+//! after the same time and TCP keep alive probes are sent after half of the interval to keep
+//! an idle connection open between PINGREQs.
+
+use core::fmt::{Display, Formatter};
+
+use embassy_net::dns::{self, DnsQueryType};
+use embassy_net::driver::Driver;
+use embassy_net::tcp::{self, TcpSocket};
+use embassy_net::Stack;
+use embassy_time::Duration;
+use rand_core::RngCore;
+
+use crate::client::client::MqttClient;
+use crate::client::client_config::ClientConfig;
+use crate::client::client_error::ClientError;
+
+/// Error of the `connect` and `connect_socket`, one variant for every step.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ConnectError {
+    /// Host name could not be resolved.
+    Dns(dns::Error),
+    /// DNS query returned no address for the host name.
+    NoAddress,
+    /// TCP connection to the broker failed.
+    Tcp(tcp::ConnectError),
+    /// Broker did not accept the MQTT connection.
+    Mqtt(ClientError<tcp::Error>),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConnectError::Dns(err) => write!(f, "DNS query failed: {:?}!", err),
+            ConnectError::NoAddress => write!(f, "Host name has no address!"),
+            ConnectError::Tcp(err) => write!(f, "TCP connection failed: {:?}!", err),
+            ConnectError::Mqtt(err) => write!(f, "MQTT connection failed: {}", err),
+        }
+    }
+}
+
+/// Buffers of the socket and of the client. `SOCKET` is the size of the TCP receive and
+/// transmit buffers, `PACKET` is the size of the client buffers and has to hold the largest
+/// packet sent or received. Buffers have to outlive the client, so they are usually placed
+/// in a static (e.g. `StaticCell`) or in the task future.
+pub struct ClientBuffers<const SOCKET: usize, const PACKET: usize> {
+    pub socket_rx: [u8; SOCKET],
+    pub socket_tx: [u8; SOCKET],
+    pub write: [u8; PACKET],
+    pub recv: [u8; PACKET],
+}
+
+impl<const SOCKET: usize, const PACKET: usize> ClientBuffers<SOCKET, PACKET> {
+    pub const fn new() -> Self {
+        Self {
+            socket_rx: [0; SOCKET],
+            socket_tx: [0; SOCKET],
+            write: [0; PACKET],
+            recv: [0; PACKET],
+        }
+    }
+}
+
+impl<const SOCKET: usize, const PACKET: usize> Default for ClientBuffers<SOCKET, PACKET> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Socket timeout and TCP keep alive interval for the MQTT `keep_alive` in seconds.
+/// Keep alive 0 disables both.
+pub fn socket_timeouts(keep_alive: u16) -> (Option<Duration>, Option<Duration>) {
+    if keep_alive == 0 {
+        return (None, None);
+    }
+    let keep_alive = u64::from(keep_alive) * 1000;
+    (
+        Some(Duration::from_millis(keep_alive * 3 / 2)),
+        Some(Duration::from_millis(keep_alive / 2)),
+    )
+}
+
+/// Resolves the `host` (host name or IPv4 address) and opens TCP connection to the `port`.
+/// Timeouts of the socket are set from the `keep_alive` in seconds.
+pub async fn connect_socket<'a, D: Driver>(
+    stack: &'a Stack<D>,
+    host: &str,
+    port: u16,
+    keep_alive: u16,
+    rx_buffer: &'a mut [u8],
+    tx_buffer: &'a mut [u8],
+) -> Result<TcpSocket<'a>, ConnectError> {
+    let addresses = stack
+        .dns_query(host, DnsQueryType::A)
+        .await
+        .map_err(ConnectError::Dns)?;
+    let address = *addresses.first().ok_or(ConnectError::NoAddress)?;
+    debug!("Connecting to {}:{}", host, port);
+
+    let mut socket = TcpSocket::new(stack, rx_buffer, tx_buffer);
+    let (timeout, tcp_keep_alive) = socket_timeouts(keep_alive);
+    socket.set_timeout(timeout);
+    socket.set_keep_alive(tcp_keep_alive);
+    socket
+        .connect((address, port))
+        .await
+        .map_err(ConnectError::Tcp)?;
+    Ok(socket)
+}
+
+/// Connects to the broker on the `host` and `port` and returns client with established
+/// MQTT session. The client owns the socket, buffers are borrowed for the client lifetime.
+/// Use `connect_socket` and `MqttClient::connect_to_broker` when the session present flag
+/// is needed.
+pub async fn connect<
+    'a,
+    D,
+    const MAX_PROPERTIES: usize,
+    R,
+    const SOCKET: usize,
+    const PACKET: usize,
+>(
+    stack: &'a Stack<D>,
+    host: &str,
+    port: u16,
+    config: ClientConfig<'a, MAX_PROPERTIES, R>,
+    buffers: &'a mut ClientBuffers<SOCKET, PACKET>,
+) -> Result<MqttClient<'a, TcpSocket<'a>, MAX_PROPERTIES, R>, ConnectError>
+where
+    D: Driver,
+    R: RngCore,
+{
+    let socket = connect_socket(
+        stack,
+        host,
+        port,
+        config.keep_alive,
+        &mut buffers.socket_rx,
+        &mut buffers.socket_tx,
+    )
+    .await?;
+    let mut client = MqttClient::new(
+        socket,
+        &mut buffers.write,
+        PACKET,
+        &mut buffers.recv,
+        PACKET,
+        config,
+    );
+    client
+        .connect_to_broker()
+        .await
+        .map_err(ConnectError::Mqtt)?;
+    Ok(client)
+}
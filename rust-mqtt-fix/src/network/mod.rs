@@ -22,8 +22,16 @@
  * SOFTWARE.
  */
 
-use crate::packet::v5::reason_codes::ReasonCode;
-use embedded_io_async::{Read, Write};
+pub mod datagram;
+#[cfg(feature = "embassy-net")]
+pub mod embassy;
+#[cfg(any(feature = "tls", feature = "rustls"))]
+pub mod tls;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+use crate::client::client_error::ClientError;
+use embedded_io_async::{ErrorType, Read, Write};
 
 pub struct NetworkConnection<T>
 where
@@ -42,21 +50,32 @@ where
         Self { io }
     }
 
-    /// Send the data from `buffer` via TCP connection.
-    pub async fn send(&mut self, buffer: &[u8]) -> Result<(), ReasonCode> {
-        let _ = self
-            .io
-            .write(buffer)
-            .await
-            .map_err(|_| ReasonCode::NetworkError)?;
+    /// Send the data from `buffer` via TCP connection. Method keeps writing until the whole
+    /// buffer is sent, zero length write means that the connection was closed.
+    pub async fn send(
+        &mut self,
+        buffer: &[u8],
+    ) -> Result<(), ClientError<<T as ErrorType>::Error>> {
+        let mut written = 0;
+        while written < buffer.len() {
+            let len = self
+                .io
+                .write(&buffer[written..])
+                .await
+                .map_err(ClientError::Transport)?;
+            if len == 0 {
+                return Err(ClientError::NotConnected);
+            }
+            written += len;
+        }
         Ok(())
     }
 
     /// Receive data to the `buffer` from TCP connection.
-    pub async fn receive(&mut self, buffer: &mut [u8]) -> Result<usize, ReasonCode> {
-        self.io
-            .read(buffer)
-            .await
-            .map_err(|_| ReasonCode::NetworkError)
+    pub async fn receive(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<usize, ClientError<<T as ErrorType>::Error>> {
+        self.io.read(buffer).await.map_err(ClientError::Transport)
     }
 }
@@ -0,0 +1,265 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! TLS 1.3 transport on top of embedded-tls, available with the `tls` feature. Connection
+//! uses the TLS_AES_128_GCM_SHA256 cipher suite, server certificate has to be signed directly
+//! by the pinned CA (intermediates are not supported) with ECDSA or Ed25519 key.
+
+use embedded_io::ErrorKind;
+use embedded_io_07::ErrorKind as ErrorKind07;
+use embedded_io_async::{ErrorType, Read, Write};
+use embedded_tls::webpki::CertVerifier;
+use embedded_tls::{
+    Aes128GcmSha256, Certificate, CryptoProvider, CryptoRngCore, SignatureScheme,
+    TlsConfig as EmbeddedTlsConfig, TlsConnection, TlsContext, TlsVerifier, UnsecureProvider,
+};
+
+use crate::network::tls::{TlsConfig, TlsError};
+
+pub use embedded_tls::{NoClock, TlsClock, TLS_RECORD_OVERHEAD};
+
+/// Maximal length of the server certificate chain which can be verified.
+pub const MAX_CERTIFICATE_CHAIN_LEN: usize = 4096;
+
+type Suite = Aes128GcmSha256;
+
+/// TLS transport over the `Read + Write` connection `T`. Transport errors are reported by
+/// their `ErrorKind`, embedded-tls does not keep the original error.
+pub struct EmbeddedTlsTransport<'a, T>
+where
+    T: Read + Write + 'a,
+{
+    conn: TlsConnection<'a, Compat<T>, Suite>,
+}
+
+impl<'a, T> EmbeddedTlsTransport<'a, T>
+where
+    T: Read + Write + 'a,
+{
+    /// Performs the TLS handshake over the connection `io`. Clock `C` provides the time for
+    /// the certificate validity check, with `NoClock` every certificate is treated as expired,
+    /// so it is usable only without `ca` (e.g. with PSK). Config without `ca` and `psk` is
+    /// rejected with `TlsError::Configuration` unless `insecure_skip_verify` is set, the server
+    /// certificate is not verified at all then.
+    ///
+    /// `read_buffer` has to fit the whole TLS record, 16640 Bytes unless the server is known to
+    /// send smaller records. `write_buffer` has to be larger than `TLS_RECORD_OVERHEAD`, longer
+    /// writes are split into more records.
+    pub async fn connect<C: TlsClock>(
+        io: T,
+        rng: impl CryptoRngCore,
+        config: &TlsConfig<'_>,
+        read_buffer: &'a mut [u8],
+        write_buffer: &'a mut [u8],
+    ) -> Result<Self, TlsError<ErrorKind>> {
+        if config.ca.is_none() && config.psk.is_none() && !config.insecure_skip_verify {
+            error!("TLS config has neither CA nor PSK, server could not be verified");
+            return Err(TlsError::Configuration);
+        }
+        let mut tls_config = EmbeddedTlsConfig::new();
+        if let Some(server_name) = config.server_name {
+            tls_config = tls_config.with_server_name(server_name);
+        }
+        if let Some(ca) = config.ca {
+            tls_config = tls_config.with_ca(Certificate::X509(ca));
+        }
+        if let Some(client_cert) = config.client_cert {
+            tls_config = tls_config
+                .with_cert(Certificate::X509(client_cert.certificate))
+                .with_priv_key(client_cert.private_key);
+        }
+        if let Some(psk) = config.psk {
+            tls_config = tls_config.with_psk(psk.key, &[psk.identity]);
+        }
+        let provider = Provider::<_, C> {
+            inner: UnsecureProvider::new::<Suite>(rng),
+            verifier: config.ca.map(|_| CertVerifier::new()),
+        };
+
+        let mut conn = TlsConnection::new(Compat(io), read_buffer, write_buffer);
+        if let Err(err) = conn.open(TlsContext::new(&tls_config, provider)).await {
+            error!("TLS handshake failed");
+            return Err(handshake_error(err));
+        }
+        Ok(Self { conn })
+    }
+}
+
+impl<'a, T> ErrorType for EmbeddedTlsTransport<'a, T>
+where
+    T: Read + Write + 'a,
+{
+    type Error = TlsError<ErrorKind>;
+}
+
+impl<'a, T> Read for EmbeddedTlsTransport<'a, T>
+where
+    T: Read + Write + 'a,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        match self.conn.read(buf).await {
+            Ok(len) => Ok(len),
+            // Closed connection ends the stream
+            Err(embedded_tls::TlsError::ConnectionClosed) => Ok(0),
+            Err(err) => Err(record_error(err)),
+        }
+    }
+}
+
+impl<'a, T> Write for EmbeddedTlsTransport<'a, T>
+where
+    T: Read + Write + 'a,
+{
+    /// Data is sent right away, embedded-tls would otherwise keep it in the write buffer.
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let len = self.conn.write(buf).await.map_err(record_error)?;
+        self.conn.flush().await.map_err(record_error)?;
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.conn.flush().await.map_err(record_error)
+    }
+}
+
+/// Verifies the server certificate only if the CA is pinned, `connect` checks that the missing
+/// CA was allowed.
+struct Provider<R, C>
+where
+    C: TlsClock,
+{
+    inner: UnsecureProvider<Suite, R>,
+    verifier: Option<CertVerifier<Suite, C, MAX_CERTIFICATE_CHAIN_LEN>>,
+}
+
+impl<R, C> CryptoProvider for Provider<R, C>
+where
+    R: CryptoRngCore,
+    C: TlsClock,
+{
+    type CipherSuite = Suite;
+    type Signature = <UnsecureProvider<Suite, R> as CryptoProvider>::Signature;
+
+    fn rng(&mut self) -> impl CryptoRngCore {
+        self.inner.rng()
+    }
+
+    fn verifier(
+        &mut self,
+    ) -> Result<&mut impl TlsVerifier<Self::CipherSuite>, embedded_tls::TlsError> {
+        self.verifier
+            .as_mut()
+            .ok_or(embedded_tls::TlsError::Unimplemented)
+    }
+
+    fn signer(
+        &mut self,
+        key_der: &[u8],
+    ) -> Result<(impl signature::SignerMut<Self::Signature>, SignatureScheme), embedded_tls::TlsError>
+    {
+        self.inner.signer(key_der)
+    }
+}
+
+fn handshake_error(err: embedded_tls::TlsError) -> TlsError<ErrorKind> {
+    use embedded_tls::TlsError as E;
+    match err {
+        E::InvalidCertificate | E::InvalidCertificateEntry | E::InvalidSignature => {
+            TlsError::CertificateVerification
+        }
+        E::InvalidPrivateKey | E::InsufficientSpace => TlsError::Configuration,
+        E::ConnectionClosed => TlsError::ConnectionClosed,
+        E::Io(kind) => TlsError::Transport(from_kind_07(kind)),
+        _ => TlsError::Handshake,
+    }
+}
+
+fn record_error(err: embedded_tls::TlsError) -> TlsError<ErrorKind> {
+    match err {
+        embedded_tls::TlsError::Io(kind) => TlsError::Transport(from_kind_07(kind)),
+        _ => TlsError::Protocol,
+    }
+}
+
+macro_rules! convert_kind {
+    ($kind:expr, $from:ident => $to:ident) => {
+        match $kind {
+            $from::NotFound => $to::NotFound,
+            $from::PermissionDenied => $to::PermissionDenied,
+            $from::ConnectionRefused => $to::ConnectionRefused,
+            $from::ConnectionReset => $to::ConnectionReset,
+            $from::ConnectionAborted => $to::ConnectionAborted,
+            $from::NotConnected => $to::NotConnected,
+            $from::AddrInUse => $to::AddrInUse,
+            $from::AddrNotAvailable => $to::AddrNotAvailable,
+            $from::BrokenPipe => $to::BrokenPipe,
+            $from::AlreadyExists => $to::AlreadyExists,
+            $from::InvalidInput => $to::InvalidInput,
+            $from::InvalidData => $to::InvalidData,
+            $from::TimedOut => $to::TimedOut,
+            $from::Interrupted => $to::Interrupted,
+            $from::Unsupported => $to::Unsupported,
+            $from::OutOfMemory => $to::OutOfMemory,
+            $from::WriteZero => $to::WriteZero,
+            _ => $to::Other,
+        }
+    };
+}
+
+fn from_kind_07(kind: ErrorKind07) -> ErrorKind {
+    convert_kind!(kind, ErrorKind07 => ErrorKind)
+}
+
+fn to_kind_07(kind: ErrorKind) -> ErrorKind07 {
+    convert_kind!(kind, ErrorKind => ErrorKind07)
+}
+
+/// Exposes embedded-io 0.6 connection through the embedded-io 0.7 traits used by embedded-tls.
+struct Compat<T>(T);
+
+impl<T: ErrorType> embedded_io_07::ErrorType for Compat<T> {
+    type Error = ErrorKind07;
+}
+
+impl<T: Read> embedded_io_async_07::Read for Compat<T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind07> {
+        use embedded_io::Error;
+        self.0.read(buf).await.map_err(|err| to_kind_07(err.kind()))
+    }
+}
+
+impl<T: Write> embedded_io_async_07::Write for Compat<T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind07> {
+        use embedded_io::Error;
+        self.0
+            .write(buf)
+            .await
+            .map_err(|err| to_kind_07(err.kind()))
+    }
+
+    async fn flush(&mut self) -> Result<(), ErrorKind07> {
+        use embedded_io::Error;
+        self.0.flush().await.map_err(|err| to_kind_07(err.kind()))
+    }
+}
@@ -0,0 +1,173 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! TLS transports. `EmbeddedTlsTransport` (`tls` feature) wraps embedded-tls and works without
+//! allocator, `RustlsTransport` (`rustls` feature) wraps rustls and requires `std`. Both encrypt
+//! any `Read + Write` connection and implement `Read + Write` themselves, so they are passed to
+//! `MqttClient::new` in place of the raw connection.
+//!
+//! Both backends are configured by the same `TlsConfig`:
+//! - `server_name` is sent as SNI and the server certificate has to be valid for it,
+//! - `ca` pins the CA certificate, the server certificate has to be issued by it,
+//! - `client_cert` enables client certificate authentication,
+//! - `psk` establishes the connection with the pre-shared key (embedded-tls only),
+//! - `insecure_skip_verify` lets embedded-tls connect without `ca` and `psk`, the server is
+//!   not authenticated then.
+//!
+//! Failed certificate verification is reported as `TlsError::CertificateVerification`, which
+//! converts into `ClientError::Transport`.
+
+use core::fmt::{Debug, Display, Formatter};
+
+use embedded_io::ErrorKind;
+
+use crate::client::client_error::ClientError;
+
+#[cfg(feature = "tls")]
+pub mod embedded;
+#[cfg(feature = "rustls")]
+pub mod rustls;
+
+#[cfg(feature = "rustls")]
+pub use self::rustls::RustlsTransport;
+#[cfg(feature = "tls")]
+pub use embedded::EmbeddedTlsTransport;
+
+/// Client certificate and its private key, both DER encoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClientCertificate<'a> {
+    pub certificate: &'a [u8],
+    pub private_key: &'a [u8],
+}
+
+/// Pre-shared key and its identity.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreSharedKey<'a> {
+    pub identity: &'a [u8],
+    pub key: &'a [u8],
+}
+
+/// Configuration of the TLS connection shared by both backends.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsConfig<'a> {
+    pub server_name: Option<&'a str>,
+    pub ca: Option<&'a [u8]>,
+    pub client_cert: Option<ClientCertificate<'a>>,
+    pub psk: Option<PreSharedKey<'a>>,
+    pub insecure_skip_verify: bool,
+}
+
+impl<'a> TlsConfig<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Method sets the host name sent as SNI. If CA is set, server certificate has to be
+    /// valid for this name.
+    pub fn add_server_name(&mut self, server_name: &'a str) {
+        self.server_name = Some(server_name);
+    }
+
+    /// Method pins DER encoded CA certificate, server certificate has to be issued by it.
+    pub fn add_ca(&mut self, ca: &'a [u8]) {
+        self.ca = Some(ca);
+    }
+
+    /// Method sets DER encoded client certificate and private key used when the server
+    /// requests client authentication. Embedded-tls supports P-256 keys in SEC1 format,
+    /// rustls accepts PKCS#8, SEC1 and PKCS#1 keys.
+    pub fn add_client_cert(&mut self, certificate: &'a [u8], private_key: &'a [u8]) {
+        self.client_cert = Some(ClientCertificate {
+            certificate,
+            private_key,
+        });
+    }
+
+    /// Method sets pre-shared key with its identity. Not supported by rustls.
+    pub fn add_psk(&mut self, identity: &'a [u8], key: &'a [u8]) {
+        self.psk = Some(PreSharedKey { identity, key });
+    }
+
+    /// Method allows embedded-tls to connect without `ca` and `psk`. Server certificate
+    /// is not verified, so anyone on the path can impersonate the broker. Rustls always
+    /// requires `ca`.
+    pub fn insecure_skip_verify(&mut self) {
+        self.insecure_skip_verify = true;
+    }
+}
+
+/// Error of the TLS transports. Generic `E` is the error type of the underlying connection
+/// (its `ErrorKind` for embedded-tls).
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TlsError<E> {
+    /// Underlying connection reported an error.
+    Transport(E),
+    /// Server certificate is not issued by the pinned CA, is not valid for the server name
+    /// or at the current time, or its signature of the handshake is wrong.
+    CertificateVerification,
+    /// Handshake failed for other reason, e.g. the server sent alert or does not support
+    /// the offered parameters.
+    Handshake,
+    /// Certificate, private key or server name in `TlsConfig` is invalid, or the backend
+    /// does not support the requested option.
+    Configuration,
+    /// Server sent invalid TLS record after the handshake.
+    Protocol,
+    /// Connection was closed in the middle of the handshake.
+    ConnectionClosed,
+}
+
+impl<E: Debug> Display for TlsError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TlsError::Transport(err) => write!(f, "Transport error: {:?}!", err),
+            TlsError::CertificateVerification => {
+                write!(f, "Server certificate verification failed!")
+            }
+            TlsError::Handshake => write!(f, "TLS handshake failed!"),
+            TlsError::Configuration => write!(f, "Invalid TLS configuration!"),
+            TlsError::Protocol => write!(f, "TLS protocol violation!"),
+            TlsError::ConnectionClosed => write!(f, "Connection closed unexpectedly!"),
+        }
+    }
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for TlsError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            TlsError::Transport(err) => err.kind(),
+            TlsError::CertificateVerification | TlsError::Handshake => ErrorKind::ConnectionRefused,
+            TlsError::Configuration => ErrorKind::InvalidInput,
+            TlsError::Protocol => ErrorKind::InvalidData,
+            TlsError::ConnectionClosed => ErrorKind::ConnectionAborted,
+        }
+    }
+}
+
+impl<E> From<TlsError<E>> for ClientError<TlsError<E>> {
+    fn from(err: TlsError<E>) -> Self {
+        ClientError::Transport(err)
+    }
+}
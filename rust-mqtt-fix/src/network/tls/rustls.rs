@@ -0,0 +1,223 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! TLS transport on top of rustls with the ring crypto provider, available with the `rustls`
+//! feature. `TlsConfig` has to pin the CA and set the server name, rustls does not connect
+//! without verifying the server. Pre-shared keys are not supported by rustls.
+
+use std::sync::Arc;
+use std::vec::Vec;
+
+use embedded_io_async::{ErrorType, Read, Write};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use rustls::{ClientConfig, ClientConnection, RootCertStore};
+
+use crate::network::tls::{TlsConfig, TlsError};
+
+// Size of the chunks read from the underlying connection
+const READ_CHUNK_LEN: usize = 1024;
+
+/// TLS transport over the `Read + Write` connection `T`.
+pub struct RustlsTransport<T> {
+    io: T,
+    conn: ClientConnection,
+}
+
+impl<T> RustlsTransport<T>
+where
+    T: Read + Write,
+{
+    /// Performs the TLS handshake over the connection `io`. Client certificate rejected by the
+    /// server is reported by the first read, TLS 1.3 server sends the alert after the
+    /// handshake.
+    pub async fn connect(io: T, config: &TlsConfig<'_>) -> Result<Self, TlsError<T::Error>> {
+        let server_name = config
+            .server_name
+            .and_then(|name| ServerName::try_from(name).ok())
+            .ok_or(TlsError::Configuration)?
+            .to_owned();
+        let conn = ClientConnection::new(Arc::new(client_config(config)?), server_name)
+            .map_err(|_| TlsError::Configuration)?;
+
+        let mut transport = Self { io, conn };
+        while transport.conn.is_handshaking() {
+            transport.write_tls().await?;
+            if transport.conn.wants_read() {
+                if let Err(err) = transport.read_tls().await {
+                    error!("TLS handshake failed");
+                    return Err(match err {
+                        TlsError::Protocol => TlsError::Handshake,
+                        err => err,
+                    });
+                }
+            }
+        }
+        transport.write_tls().await?;
+        Ok(transport)
+    }
+
+    /// Returns the underlying connection.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    /// Reads the next chunk of TLS records from the connection and decrypts them.
+    async fn read_tls(&mut self) -> Result<usize, TlsError<T::Error>> {
+        let mut chunk = [0; READ_CHUNK_LEN];
+        let len = self
+            .io
+            .read(&mut chunk)
+            .await
+            .map_err(TlsError::Transport)?;
+        if len == 0 {
+            return if self.conn.is_handshaking() {
+                Err(TlsError::ConnectionClosed)
+            } else {
+                Ok(0)
+            };
+        }
+        let mut data = &chunk[..len];
+        while !data.is_empty() {
+            self.conn
+                .read_tls(&mut data)
+                .map_err(|_| TlsError::Protocol)?;
+        }
+        if let Err(err) = self.conn.process_new_packets() {
+            // Send the alert queued by rustls, the connection fails anyway
+            let _ = self.write_tls().await;
+            return Err(match err {
+                rustls::Error::InvalidCertificate(_) => TlsError::CertificateVerification,
+                _ => TlsError::Protocol,
+            });
+        }
+        Ok(len)
+    }
+
+    /// Sends all TLS records queued in the connection.
+    async fn write_tls(&mut self) -> Result<(), TlsError<T::Error>> {
+        let mut records = Vec::new();
+        while self.conn.wants_write() {
+            self.conn
+                .write_tls(&mut records)
+                .map_err(|_| TlsError::Protocol)?;
+        }
+        let mut written = 0;
+        while written < records.len() {
+            let len = self
+                .io
+                .write(&records[written..])
+                .await
+                .map_err(TlsError::Transport)?;
+            if len == 0 {
+                return Err(TlsError::ConnectionClosed);
+            }
+            written += len;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn client_config<E>(config: &TlsConfig<'_>) -> Result<ClientConfig, TlsError<E>> {
+    if config.psk.is_some() {
+        error!("Pre-shared keys are not supported by rustls");
+        return Err(TlsError::Configuration);
+    }
+    let mut roots = RootCertStore::empty();
+    roots
+        .add(CertificateDer::from(
+            config.ca.ok_or(TlsError::Configuration)?.to_vec(),
+        ))
+        .map_err(|_| TlsError::Configuration)?;
+
+    let builder =
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .map_err(|_| TlsError::Configuration)?
+            .with_root_certificates(roots);
+    match config.client_cert {
+        Some(client_cert) => {
+            let key = PrivateKeyDer::try_from(client_cert.private_key.to_vec())
+                .map_err(|_| TlsError::Configuration)?;
+            builder
+                .with_client_auth_cert(
+                    Vec::from([CertificateDer::from(client_cert.certificate.to_vec())]),
+                    key,
+                )
+                .map_err(|_| TlsError::Configuration)
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+impl<T> ErrorType for RustlsTransport<T>
+where
+    T: Read + Write,
+{
+    type Error = TlsError<T::Error>;
+}
+
+impl<T> Read for RustlsTransport<T>
+where
+    T: Read + Write,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        use std::io::{ErrorKind, Read as _};
+
+        loop {
+            match self.conn.reader().read(buf) {
+                Ok(len) => return Ok(len),
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => return Err(TlsError::ConnectionClosed),
+            }
+            if self.read_tls().await? == 0 {
+                // Closed connection ends the stream
+                return Ok(0);
+            }
+            // Key updates and alerts
+            self.write_tls().await?;
+        }
+    }
+}
+
+impl<T> Write for RustlsTransport<T>
+where
+    T: Read + Write,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        use std::io::Write as _;
+
+        let len = self
+            .conn
+            .writer()
+            .write(buf)
+            .map_err(|_| TlsError::Protocol)?;
+        self.write_tls().await?;
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.write_tls().await?;
+        self.io.flush().await.map_err(TlsError::Transport)
+    }
+}
@@ -0,0 +1,466 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! MQTT over WebSocket (RFC 6455) client transport for networks which allow only HTTP(S)
+//! traffic. `WebSocketTransport` performs the HTTP upgrade handshake with the `mqtt`
+//! subprotocol and then carries the MQTT byte stream in masked binary frames, so it can be
+//! passed to `MqttClient::new` in place of the raw connection. Available with the `websocket`
+//! feature.
+//!
+//! Every `write` is sent as a single binary frame. Received binary and continuation frames are
+//! read as one continuous stream, pings are answered and close frame ends the stream. Reads
+//! keep the frame state in the transport, so they are cancel safe whenever the underlying read
+//! is (except for answering ping or close, which writes to the connection).
+
+use core::fmt::{Debug, Display, Formatter};
+use core::str;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+use rand_core::RngCore;
+use sha1::{Digest, Sha1};
+
+/// Subprotocol of MQTT over WebSocket.
+pub const MQTT_SUBPROTOCOL: &str = "mqtt";
+
+const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const NONCE_LEN: usize = 16;
+const KEY_LEN: usize = 24;
+const ACCEPT_LEN: usize = 28;
+
+const FIN: u8 = 0x80;
+const MASK: u8 = 0x80;
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+const MAX_HEADER_LEN: usize = 14;
+const MAX_CONTROL_LEN: usize = 125;
+// Outgoing payload is masked on the stack in chunks of this size
+const CHUNK_LEN: usize = 64;
+
+/// Error of the WebSocket transport. Generic `E` is the error type of the underlying
+/// connection.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WebSocketError<E> {
+    /// Underlying connection reported an error.
+    Transport(E),
+    /// Server did not upgrade the connection - wrong status, missing or wrong
+    /// `Sec-WebSocket-Accept`, other subprotocol than `mqtt`, or the response did not fit
+    /// into the buffer.
+    Handshake,
+    /// Server sent frame which breaks RFC 6455 (masked or text frame, fragmented or too long
+    /// control frame, unknown opcode).
+    Protocol,
+    /// Connection was closed in the middle of the handshake or frame.
+    ConnectionClosed,
+}
+
+impl<E: Debug> Display for WebSocketError<E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            WebSocketError::Transport(err) => write!(f, "Transport error: {:?}!", err),
+            WebSocketError::Handshake => write!(f, "WebSocket handshake failed!"),
+            WebSocketError::Protocol => write!(f, "WebSocket protocol violation!"),
+            WebSocketError::ConnectionClosed => write!(f, "Connection closed unexpectedly!"),
+        }
+    }
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for WebSocketError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            WebSocketError::Transport(err) => err.kind(),
+            WebSocketError::Handshake => ErrorKind::ConnectionRefused,
+            WebSocketError::Protocol => ErrorKind::InvalidData,
+            WebSocketError::ConnectionClosed => ErrorKind::ConnectionAborted,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReadState {
+    Header,
+    Data { remaining: u64 },
+    Control { opcode: u8, len: usize },
+    Closed,
+}
+
+/// WebSocket client transport over the `Read + Write` connection `T`. Rng provides the frame
+/// masking keys and the handshake nonce, it should not be predictable.
+pub struct WebSocketTransport<T, R> {
+    io: T,
+    rng: R,
+    state: ReadState,
+    header: [u8; MAX_HEADER_LEN],
+    header_len: usize,
+    control: [u8; MAX_CONTROL_LEN],
+    control_len: usize,
+}
+
+impl<T, R> WebSocketTransport<T, R>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    /// Upgrades the connection `io` to WebSocket. `host` is sent in the `Host` header, `path`
+    /// is the resource of the broker WebSocket endpoint (e.g. `/mqtt`). `buffer` holds the
+    /// HTTP response of the server, 256 Bytes are usually enough.
+    pub async fn connect(
+        io: T,
+        rng: R,
+        host: &str,
+        path: &str,
+        buffer: &mut [u8],
+    ) -> Result<Self, WebSocketError<T::Error>> {
+        let mut transport = Self {
+            io,
+            rng,
+            state: ReadState::Header,
+            header: [0; MAX_HEADER_LEN],
+            header_len: 0,
+            control: [0; MAX_CONTROL_LEN],
+            control_len: 0,
+        };
+        transport.handshake(host, path, buffer).await?;
+        Ok(transport)
+    }
+
+    /// Returns the underlying connection.
+    pub fn into_inner(self) -> T {
+        self.io
+    }
+
+    async fn handshake(
+        &mut self,
+        host: &str,
+        path: &str,
+        buffer: &mut [u8],
+    ) -> Result<(), WebSocketError<T::Error>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill_bytes(&mut nonce);
+        let mut key = [0; KEY_LEN];
+        STANDARD
+            .encode_slice(nonce, &mut key)
+            .map_err(|_| WebSocketError::Handshake)?;
+
+        for part in [
+            "GET ",
+            path,
+            " HTTP/1.1\r\nHost: ",
+            host,
+            "\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: ",
+            str::from_utf8(&key).map_err(|_| WebSocketError::Handshake)?,
+            "\r\nSec-WebSocket-Protocol: ",
+            MQTT_SUBPROTOCOL,
+            "\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        ] {
+            self.write_all(part.as_bytes()).await?;
+        }
+        self.io.flush().await.map_err(WebSocketError::Transport)?;
+
+        // Response is read byte by byte, so no frame data is consumed with it
+        let mut len = 0;
+        while !buffer[..len].ends_with(b"\r\n\r\n") {
+            if len == buffer.len() {
+                error!("WebSocket handshake response does not fit into the buffer");
+                return Err(WebSocketError::Handshake);
+            }
+            let read = self
+                .io
+                .read(&mut buffer[len..len + 1])
+                .await
+                .map_err(WebSocketError::Transport)?;
+            if read == 0 {
+                return Err(WebSocketError::ConnectionClosed);
+            }
+            len += read;
+        }
+        let response = str::from_utf8(&buffer[..len]).map_err(|_| WebSocketError::Handshake)?;
+        if verify_response(response, &key) {
+            Ok(())
+        } else {
+            error!("Server refused the WebSocket upgrade");
+            Err(WebSocketError::Handshake)
+        }
+    }
+
+    async fn write_all(&mut self, mut data: &[u8]) -> Result<(), WebSocketError<T::Error>> {
+        while !data.is_empty() {
+            let written = self
+                .io
+                .write(data)
+                .await
+                .map_err(WebSocketError::Transport)?;
+            if written == 0 {
+                return Err(WebSocketError::ConnectionClosed);
+            }
+            data = &data[written..];
+        }
+        Ok(())
+    }
+
+    async fn send_frame(
+        &mut self,
+        opcode: u8,
+        payload: &[u8],
+    ) -> Result<(), WebSocketError<T::Error>> {
+        let mut header = [0; MAX_HEADER_LEN];
+        header[0] = FIN | opcode;
+        let mut header_len = 2;
+        if payload.len() < 126 {
+            header[1] = MASK | payload.len() as u8;
+        } else if payload.len() <= u16::MAX as usize {
+            header[1] = MASK | 126;
+            header[2..4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+            header_len = 4;
+        } else {
+            header[1] = MASK | 127;
+            header[2..10].copy_from_slice(&(payload.len() as u64).to_be_bytes());
+            header_len = 10;
+        }
+        let mask = self.rng.next_u32().to_be_bytes();
+        header[header_len..header_len + 4].copy_from_slice(&mask);
+        header_len += 4;
+        self.write_all(&header[..header_len]).await?;
+
+        let mut masked = [0; CHUNK_LEN];
+        for chunk in payload.chunks(CHUNK_LEN) {
+            // Chunk length is a multiple of 4, so the mask index restarts with every chunk
+            for (i, byte) in chunk.iter().enumerate() {
+                masked[i] = byte ^ mask[i % 4];
+            }
+            self.write_all(&masked[..chunk.len()]).await?;
+        }
+        Ok(())
+    }
+
+    // Parses the frame header once it is complete, returns `None` if more bytes are needed
+    fn parse_header(&self) -> Result<Option<ReadState>, WebSocketError<T::Error>> {
+        let header = &self.header[..self.header_len];
+        if header.len() < 2 {
+            return Ok(None);
+        }
+        if header[1] & MASK != 0 {
+            error!("Server sent masked WebSocket frame");
+            return Err(WebSocketError::Protocol);
+        }
+        let (len, header_len) = match header[1] & 0x7F {
+            126 => (
+                header
+                    .get(2..4)
+                    .map(|b| u16::from_be_bytes([b[0], b[1]]) as u64),
+                4,
+            ),
+            127 => (
+                header
+                    .get(2..10)
+                    .map(|b| u64::from_be_bytes(b.try_into().unwrap())),
+                10,
+            ),
+            len => (Some(len as u64), 2),
+        };
+        let Some(len) = len else {
+            return Ok(None);
+        };
+        debug_assert_eq!(header.len(), header_len);
+
+        let fin = header[0] & FIN != 0;
+        match header[0] & 0x0F {
+            OPCODE_BINARY | OPCODE_CONTINUATION => Ok(Some(ReadState::Data { remaining: len })),
+            opcode @ (OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG)
+                if fin && len <= MAX_CONTROL_LEN as u64 =>
+            {
+                Ok(Some(ReadState::Control {
+                    opcode,
+                    len: len as usize,
+                }))
+            }
+            _ => {
+                error!("Unexpected WebSocket frame {}", header[0]);
+                Err(WebSocketError::Protocol)
+            }
+        }
+    }
+
+    // Number of header Bytes needed to decide the length of the header
+    fn header_needed(&self) -> usize {
+        if self.header_len < 2 {
+            return 2;
+        }
+        match self.header[1] & 0x7F {
+            126 => 4,
+            127 => 10,
+            _ => 2,
+        }
+    }
+
+    async fn handle_control(&mut self, opcode: u8) -> Result<(), WebSocketError<T::Error>> {
+        let mut payload = [0; MAX_CONTROL_LEN];
+        let len = self.control_len;
+        payload[..len].copy_from_slice(&self.control[..len]);
+        match opcode {
+            OPCODE_PING => {
+                trace!("Answering WebSocket ping");
+                self.send_frame(OPCODE_PONG, &payload[..len]).await
+            }
+            OPCODE_CLOSE => {
+                debug!("WebSocket closed by the server");
+                self.state = ReadState::Closed;
+                // Close is answered with the status code of the server
+                self.send_frame(OPCODE_CLOSE, &payload[..len.min(2)]).await
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: ErrorType, R> ErrorType for WebSocketTransport<T, R> {
+    type Error = WebSocketError<T::Error>;
+}
+
+impl<T, R> Read for WebSocketTransport<T, R>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        loop {
+            match self.state {
+                ReadState::Closed => return Ok(0),
+                ReadState::Header => {
+                    let needed = self.header_needed();
+                    if self.header_len == needed {
+                        if let Some(state) = self.parse_header()? {
+                            self.header_len = 0;
+                            self.control_len = 0;
+                            self.state = state;
+                        }
+                        continue;
+                    }
+                    let read = self
+                        .io
+                        .read(&mut self.header[self.header_len..needed])
+                        .await
+                        .map_err(WebSocketError::Transport)?;
+                    match (read, self.header_len) {
+                        // Connection closed between frames without close frame
+                        (0, 0) => return Ok(0),
+                        (0, _) => return Err(WebSocketError::ConnectionClosed),
+                        _ => self.header_len += read,
+                    }
+                }
+                ReadState::Data { remaining: 0 } => self.state = ReadState::Header,
+                ReadState::Data { remaining } => {
+                    if buf.is_empty() {
+                        return Ok(0);
+                    }
+                    let len = (buf.len() as u64).min(remaining) as usize;
+                    let read = self
+                        .io
+                        .read(&mut buf[..len])
+                        .await
+                        .map_err(WebSocketError::Transport)?;
+                    if read == 0 {
+                        return Err(WebSocketError::ConnectionClosed);
+                    }
+                    self.state = ReadState::Data {
+                        remaining: remaining - read as u64,
+                    };
+                    return Ok(read);
+                }
+                ReadState::Control { opcode, len } => {
+                    if self.control_len < len {
+                        let read = self
+                            .io
+                            .read(&mut self.control[self.control_len..len])
+                            .await
+                            .map_err(WebSocketError::Transport)?;
+                        if read == 0 {
+                            return Err(WebSocketError::ConnectionClosed);
+                        }
+                        self.control_len += read;
+                        continue;
+                    }
+                    self.state = ReadState::Header;
+                    self.handle_control(opcode).await?;
+                }
+            }
+        }
+    }
+}
+
+impl<T, R> Write for WebSocketTransport<T, R>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.send_frame(OPCODE_BINARY, buf).await?;
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.io.flush().await.map_err(WebSocketError::Transport)
+    }
+}
+
+// Checks the status line, upgrade headers, accept key and the selected subprotocol
+pub(crate) fn verify_response(response: &str, key: &[u8]) -> bool {
+    let mut lines = response.split("\r\n");
+    let status_ok = lines.next().and_then(|status| status.split(' ').nth(1)) == Some("101");
+
+    let mut expected = [0; ACCEPT_LEN];
+    let digest = Sha1::new().chain_update(key).chain_update(GUID).finalize();
+    if STANDARD.encode_slice(digest, &mut expected).is_err() {
+        return false;
+    }
+
+    let (mut upgrade, mut connection, mut accept, mut protocol) = (false, false, false, true);
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("Upgrade") {
+            upgrade = value.eq_ignore_ascii_case("websocket");
+        } else if name.eq_ignore_ascii_case("Connection") {
+            connection = value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case("upgrade"));
+        } else if name.eq_ignore_ascii_case("Sec-WebSocket-Accept") {
+            accept = value.as_bytes() == expected;
+        } else if name.eq_ignore_ascii_case("Sec-WebSocket-Protocol") {
+            protocol = value == MQTT_SUBPROTOCOL;
+        }
+    }
+    status_ok && upgrade && connection && accept && protocol
+}
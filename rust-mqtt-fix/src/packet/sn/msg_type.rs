@@ -0,0 +1,116 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// MQTT-SN message types, second byte of the message header (after the length).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MsgType {
+    Advertise,
+    SearchGw,
+    GwInfo,
+    Connect,
+    Connack,
+    WillTopicReq,
+    WillTopic,
+    WillMsgReq,
+    WillMsg,
+    Register,
+    Regack,
+    Publish,
+    Puback,
+    Pubcomp,
+    Pubrec,
+    Pubrel,
+    Subscribe,
+    Suback,
+    Unsubscribe,
+    Unsuback,
+    Pingreq,
+    Pingresp,
+    Disconnect,
+    Reserved,
+}
+
+impl From<u8> for MsgType {
+    fn from(orig: u8) -> Self {
+        match orig {
+            0x00 => MsgType::Advertise,
+            0x01 => MsgType::SearchGw,
+            0x02 => MsgType::GwInfo,
+            0x04 => MsgType::Connect,
+            0x05 => MsgType::Connack,
+            0x06 => MsgType::WillTopicReq,
+            0x07 => MsgType::WillTopic,
+            0x08 => MsgType::WillMsgReq,
+            0x09 => MsgType::WillMsg,
+            0x0A => MsgType::Register,
+            0x0B => MsgType::Regack,
+            0x0C => MsgType::Publish,
+            0x0D => MsgType::Puback,
+            0x0E => MsgType::Pubcomp,
+            0x0F => MsgType::Pubrec,
+            0x10 => MsgType::Pubrel,
+            0x12 => MsgType::Subscribe,
+            0x13 => MsgType::Suback,
+            0x14 => MsgType::Unsubscribe,
+            0x15 => MsgType::Unsuback,
+            0x16 => MsgType::Pingreq,
+            0x17 => MsgType::Pingresp,
+            0x18 => MsgType::Disconnect,
+            _ => MsgType::Reserved,
+        }
+    }
+}
+
+impl From<MsgType> for u8 {
+    fn from(value: MsgType) -> Self {
+        match value {
+            MsgType::Advertise => 0x00,
+            MsgType::SearchGw => 0x01,
+            MsgType::GwInfo => 0x02,
+            MsgType::Connect => 0x04,
+            MsgType::Connack => 0x05,
+            MsgType::WillTopicReq => 0x06,
+            MsgType::WillTopic => 0x07,
+            MsgType::WillMsgReq => 0x08,
+            MsgType::WillMsg => 0x09,
+            MsgType::Register => 0x0A,
+            MsgType::Regack => 0x0B,
+            MsgType::Publish => 0x0C,
+            MsgType::Puback => 0x0D,
+            MsgType::Pubcomp => 0x0E,
+            MsgType::Pubrec => 0x0F,
+            MsgType::Pubrel => 0x10,
+            MsgType::Subscribe => 0x12,
+            MsgType::Suback => 0x13,
+            MsgType::Unsubscribe => 0x14,
+            MsgType::Unsuback => 0x15,
+            MsgType::Pingreq => 0x16,
+            MsgType::Pingresp => 0x17,
+            MsgType::Disconnect => 0x18,
+            MsgType::Reserved => 0x03,
+        }
+    }
+}
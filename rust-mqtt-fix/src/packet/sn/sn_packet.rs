@@ -0,0 +1,588 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::str;
+
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::buffer_writer::BuffWriter;
+use crate::utils::types::BufferError;
+
+use super::msg_type::MsgType;
+
+const PROTOCOL_ID: u8 = 0x01;
+
+// Flags field shared by CONNECT, PUBLISH, SUBSCRIBE, SUBACK and UNSUBSCRIBE
+// DUP | QoS QoS | Retain | Will | CleanSession | TopicIdType TopicIdType
+const FLAG_DUP: u8 = 0x80;
+const FLAG_RETAIN: u8 = 0x10;
+const FLAG_WILL: u8 = 0x08;
+const FLAG_CLEAN_SESSION: u8 = 0x04;
+const TOPIC_ID_TYPE_MASK: u8 = 0x03;
+const TOPIC_ID_TYPE_NORMAL: u8 = 0x00;
+const TOPIC_ID_TYPE_PREDEFINED: u8 = 0x01;
+const TOPIC_ID_TYPE_SHORT: u8 = 0x02;
+
+/// Return code of the CONNACK, REGACK, PUBACK and SUBACK messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ReturnCode {
+    Accepted,
+    Congestion,
+    InvalidTopicId,
+    NotSupported,
+}
+
+impl TryFrom<u8> for ReturnCode {
+    type Error = BufferError;
+
+    fn try_from(orig: u8) -> Result<Self, Self::Error> {
+        match orig {
+            0x00 => Ok(ReturnCode::Accepted),
+            0x01 => Ok(ReturnCode::Congestion),
+            0x02 => Ok(ReturnCode::InvalidTopicId),
+            0x03 => Ok(ReturnCode::NotSupported),
+            _ => Err(BufferError::DecodingError),
+        }
+    }
+}
+
+impl From<ReturnCode> for u8 {
+    fn from(value: ReturnCode) -> Self {
+        match value {
+            ReturnCode::Accepted => 0x00,
+            ReturnCode::Congestion => 0x01,
+            ReturnCode::InvalidTopicId => 0x02,
+            ReturnCode::NotSupported => 0x03,
+        }
+    }
+}
+
+/// Quality of service of MQTT-SN. `QoSMinusOne` is used for publishing without connection to
+/// the gateway and only with predefined or short topic ids.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SnQoS {
+    QoS0,
+    QoS1,
+    QoS2,
+    QoSMinusOne,
+}
+
+impl SnQoS {
+    fn from_flags(flags: u8) -> Self {
+        match (flags >> 5) & 0x03 {
+            0 => SnQoS::QoS0,
+            1 => SnQoS::QoS1,
+            2 => SnQoS::QoS2,
+            _ => SnQoS::QoSMinusOne,
+        }
+    }
+
+    fn flags(self) -> u8 {
+        let bits = match self {
+            SnQoS::QoS0 => 0,
+            SnQoS::QoS1 => 1,
+            SnQoS::QoS2 => 2,
+            SnQoS::QoSMinusOne => 3,
+        };
+        bits << 5
+    }
+}
+
+/// Topic of the PUBLISH message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TopicId {
+    /// Id assigned by REGISTER / REGACK exchange or by SUBACK.
+    Normal(u16),
+    /// Id agreed in advance between the client and the gateway.
+    Predefined(u16),
+    /// Topic name with exactly two characters.
+    Short([u8; 2]),
+}
+
+impl TopicId {
+    /// Short topic id for two character topic name.
+    pub fn short(name: &str) -> Option<Self> {
+        name.as_bytes().try_into().ok().map(TopicId::Short)
+    }
+
+    /// Two Byte value transferred in the topic id field.
+    pub fn value(&self) -> u16 {
+        match *self {
+            TopicId::Normal(id) | TopicId::Predefined(id) => id,
+            TopicId::Short(name) => u16::from_be_bytes(name),
+        }
+    }
+
+    fn id_type(&self) -> u8 {
+        match self {
+            TopicId::Normal(_) => TOPIC_ID_TYPE_NORMAL,
+            TopicId::Predefined(_) => TOPIC_ID_TYPE_PREDEFINED,
+            TopicId::Short(_) => TOPIC_ID_TYPE_SHORT,
+        }
+    }
+
+    fn from_parts(id_type: u8, value: u16) -> Result<Self, BufferError> {
+        match id_type {
+            TOPIC_ID_TYPE_NORMAL => Ok(TopicId::Normal(value)),
+            TOPIC_ID_TYPE_PREDEFINED => Ok(TopicId::Predefined(value)),
+            TOPIC_ID_TYPE_SHORT => Ok(TopicId::Short(value.to_be_bytes())),
+            _ => Err(BufferError::DecodingError),
+        }
+    }
+}
+
+/// Topic of the SUBSCRIBE and UNSUBSCRIBE messages, unlike PUBLISH these carry the full
+/// topic filter instead of the normal topic id.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TopicRef<'a> {
+    Name(&'a str),
+    Predefined(u16),
+    Short([u8; 2]),
+}
+
+impl<'a> TopicRef<'a> {
+    fn id_type(&self) -> u8 {
+        match self {
+            TopicRef::Name(_) => TOPIC_ID_TYPE_NORMAL,
+            TopicRef::Predefined(_) => TOPIC_ID_TYPE_PREDEFINED,
+            TopicRef::Short(_) => TOPIC_ID_TYPE_SHORT,
+        }
+    }
+
+    fn encode(&self, writer: &mut BuffWriter<'_>) -> Result<(), BufferError> {
+        match *self {
+            TopicRef::Name(name) => writer.insert_ref(name.len(), name.as_bytes()),
+            TopicRef::Predefined(id) => writer.write_u16(id),
+            TopicRef::Short(name) => writer.insert_ref(2, &name),
+        }
+    }
+
+    fn decode(
+        flags: u8,
+        reader: &mut BuffReader<'a>,
+        total_len: usize,
+    ) -> Result<Self, BufferError> {
+        match flags & TOPIC_ID_TYPE_MASK {
+            TOPIC_ID_TYPE_NORMAL => Ok(TopicRef::Name(read_str(reader, total_len)?)),
+            TOPIC_ID_TYPE_PREDEFINED => Ok(TopicRef::Predefined(reader.read_u16()?)),
+            TOPIC_ID_TYPE_SHORT => Ok(TopicRef::Short(reader.read_u16()?.to_be_bytes())),
+            _ => Err(BufferError::DecodingError),
+        }
+    }
+}
+
+/// Single MQTT-SN message, borrowed from the datagram it was decoded from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SnPacket<'a> {
+    Advertise {
+        gw_id: u8,
+        duration: u16,
+    },
+    SearchGw {
+        radius: u8,
+    },
+    GwInfo {
+        gw_id: u8,
+        gw_address: &'a [u8],
+    },
+    Connect {
+        will: bool,
+        clean_session: bool,
+        duration: u16,
+        client_id: &'a str,
+    },
+    Connack {
+        return_code: ReturnCode,
+    },
+    Register {
+        topic_id: u16,
+        msg_id: u16,
+        topic_name: &'a str,
+    },
+    Regack {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    Publish {
+        dup: bool,
+        qos: SnQoS,
+        retain: bool,
+        topic: TopicId,
+        msg_id: u16,
+        data: &'a [u8],
+    },
+    Puback {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    Subscribe {
+        dup: bool,
+        qos: SnQoS,
+        msg_id: u16,
+        topic: TopicRef<'a>,
+    },
+    Suback {
+        qos: SnQoS,
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    Unsubscribe {
+        msg_id: u16,
+        topic: TopicRef<'a>,
+    },
+    Unsuback {
+        msg_id: u16,
+    },
+    /// Client id is present when a sleeping client checks for buffered messages.
+    Pingreq {
+        client_id: Option<&'a str>,
+    },
+    Pingresp,
+    /// Duration is present when the client goes to sleep.
+    Disconnect {
+        duration: Option<u16>,
+    },
+}
+
+impl<'a> SnPacket<'a> {
+    pub fn msg_type(&self) -> MsgType {
+        match self {
+            SnPacket::Advertise { .. } => MsgType::Advertise,
+            SnPacket::SearchGw { .. } => MsgType::SearchGw,
+            SnPacket::GwInfo { .. } => MsgType::GwInfo,
+            SnPacket::Connect { .. } => MsgType::Connect,
+            SnPacket::Connack { .. } => MsgType::Connack,
+            SnPacket::Register { .. } => MsgType::Register,
+            SnPacket::Regack { .. } => MsgType::Regack,
+            SnPacket::Publish { .. } => MsgType::Publish,
+            SnPacket::Puback { .. } => MsgType::Puback,
+            SnPacket::Subscribe { .. } => MsgType::Subscribe,
+            SnPacket::Suback { .. } => MsgType::Suback,
+            SnPacket::Unsubscribe { .. } => MsgType::Unsubscribe,
+            SnPacket::Unsuback { .. } => MsgType::Unsuback,
+            SnPacket::Pingreq { .. } => MsgType::Pingreq,
+            SnPacket::Pingresp => MsgType::Pingresp,
+            SnPacket::Disconnect { .. } => MsgType::Disconnect,
+        }
+    }
+
+    /// Message id of the messages which carry one.
+    pub fn msg_id(&self) -> Option<u16> {
+        match *self {
+            SnPacket::Register { msg_id, .. }
+            | SnPacket::Regack { msg_id, .. }
+            | SnPacket::Publish { msg_id, .. }
+            | SnPacket::Puback { msg_id, .. }
+            | SnPacket::Subscribe { msg_id, .. }
+            | SnPacket::Suback { msg_id, .. }
+            | SnPacket::Unsubscribe { msg_id, .. }
+            | SnPacket::Unsuback { msg_id } => Some(msg_id),
+            _ => None,
+        }
+    }
+
+    /// Encodes the message into the `buffer` and returns the length of the datagram. Messages
+    /// longer than 255 Bytes use the three Byte length field.
+    pub fn encode(&self, buffer: &mut [u8]) -> Result<usize, BufferError> {
+        if buffer.is_empty() {
+            return Err(BufferError::InsufficientBufferSize);
+        }
+        let len = {
+            let body = &mut buffer[1..];
+            let body_len = body.len();
+            let mut writer = BuffWriter::new(body, body_len);
+            writer.write_u8(self.msg_type().into())?;
+            self.encode_body(&mut writer)?;
+            writer.position
+        };
+        if len + 1 < 256 {
+            buffer[0] = (len + 1) as u8;
+            return Ok(len + 1);
+        }
+        let total_len = len + 3;
+        if total_len > u16::MAX as usize || total_len > buffer.len() {
+            return Err(BufferError::InsufficientBufferSize);
+        }
+        buffer.copy_within(1..len + 1, 3);
+        buffer[0] = 0x01;
+        buffer[1..3].copy_from_slice(&(total_len as u16).to_be_bytes());
+        Ok(total_len)
+    }
+
+    fn encode_body(&self, writer: &mut BuffWriter<'_>) -> Result<(), BufferError> {
+        match *self {
+            SnPacket::Advertise { gw_id, duration } => {
+                writer.write_u8(gw_id)?;
+                writer.write_u16(duration)
+            }
+            SnPacket::SearchGw { radius } => writer.write_u8(radius),
+            SnPacket::GwInfo { gw_id, gw_address } => {
+                writer.write_u8(gw_id)?;
+                writer.insert_ref(gw_address.len(), gw_address)
+            }
+            SnPacket::Connect {
+                will,
+                clean_session,
+                duration,
+                client_id,
+            } => {
+                let mut flags = 0;
+                if will {
+                    flags |= FLAG_WILL;
+                }
+                if clean_session {
+                    flags |= FLAG_CLEAN_SESSION;
+                }
+                writer.write_u8(flags)?;
+                writer.write_u8(PROTOCOL_ID)?;
+                writer.write_u16(duration)?;
+                writer.insert_ref(client_id.len(), client_id.as_bytes())
+            }
+            SnPacket::Connack { return_code } => writer.write_u8(return_code.into()),
+            SnPacket::Register {
+                topic_id,
+                msg_id,
+                topic_name,
+            } => {
+                writer.write_u16(topic_id)?;
+                writer.write_u16(msg_id)?;
+                writer.insert_ref(topic_name.len(), topic_name.as_bytes())
+            }
+            SnPacket::Regack {
+                topic_id,
+                msg_id,
+                return_code,
+            }
+            | SnPacket::Puback {
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                writer.write_u16(topic_id)?;
+                writer.write_u16(msg_id)?;
+                writer.write_u8(return_code.into())
+            }
+            SnPacket::Publish {
+                dup,
+                qos,
+                retain,
+                topic,
+                msg_id,
+                data,
+            } => {
+                let mut flags = qos.flags() | topic.id_type();
+                if dup {
+                    flags |= FLAG_DUP;
+                }
+                if retain {
+                    flags |= FLAG_RETAIN;
+                }
+                writer.write_u8(flags)?;
+                writer.write_u16(topic.value())?;
+                writer.write_u16(msg_id)?;
+                writer.insert_ref(data.len(), data)
+            }
+            SnPacket::Subscribe {
+                dup,
+                qos,
+                msg_id,
+                topic,
+            } => {
+                let mut flags = qos.flags() | topic.id_type();
+                if dup {
+                    flags |= FLAG_DUP;
+                }
+                writer.write_u8(flags)?;
+                writer.write_u16(msg_id)?;
+                topic.encode(writer)
+            }
+            SnPacket::Suback {
+                qos,
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                writer.write_u8(qos.flags())?;
+                writer.write_u16(topic_id)?;
+                writer.write_u16(msg_id)?;
+                writer.write_u8(return_code.into())
+            }
+            SnPacket::Unsubscribe { msg_id, topic } => {
+                writer.write_u8(topic.id_type())?;
+                writer.write_u16(msg_id)?;
+                topic.encode(writer)
+            }
+            SnPacket::Unsuback { msg_id } => writer.write_u16(msg_id),
+            SnPacket::Pingreq { client_id } => match client_id {
+                Some(client_id) => writer.insert_ref(client_id.len(), client_id.as_bytes()),
+                None => Ok(()),
+            },
+            SnPacket::Pingresp => Ok(()),
+            SnPacket::Disconnect { duration } => match duration {
+                Some(duration) => writer.write_u16(duration),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Decodes single message from the received datagram. The length field of the message
+    /// has to match the content, bytes after the message are ignored.
+    pub fn decode(buffer: &'a [u8]) -> Result<Self, BufferError> {
+        let (len, header_len) = match buffer.first() {
+            None => return Err(BufferError::InsufficientBufferSize),
+            Some(0x01) => {
+                if buffer.len() < 3 {
+                    return Err(BufferError::InsufficientBufferSize);
+                }
+                (u16::from_be_bytes([buffer[1], buffer[2]]) as usize, 3)
+            }
+            Some(len) => (*len as usize, 1),
+        };
+        if len > buffer.len() {
+            return Err(BufferError::InsufficientBufferSize);
+        }
+        if len <= header_len {
+            return Err(BufferError::DecodingError);
+        }
+        let mut reader = BuffReader::new(buffer, len);
+        reader.increment_position(header_len);
+
+        let msg_type = MsgType::from(reader.read_u8()?);
+        let packet = match msg_type {
+            MsgType::Advertise => SnPacket::Advertise {
+                gw_id: reader.read_u8()?,
+                duration: reader.read_u16()?,
+            },
+            MsgType::SearchGw => SnPacket::SearchGw {
+                radius: reader.read_u8()?,
+            },
+            MsgType::GwInfo => SnPacket::GwInfo {
+                gw_id: reader.read_u8()?,
+                gw_address: reader.read_message(len)?,
+            },
+            MsgType::Connect => {
+                let flags = reader.read_u8()?;
+                if reader.read_u8()? != PROTOCOL_ID {
+                    error!("Unsupported MQTT-SN protocol id!");
+                    return Err(BufferError::DecodingError);
+                }
+                SnPacket::Connect {
+                    will: flags & FLAG_WILL != 0,
+                    clean_session: flags & FLAG_CLEAN_SESSION != 0,
+                    duration: reader.read_u16()?,
+                    client_id: read_str(&mut reader, len)?,
+                }
+            }
+            MsgType::Connack => SnPacket::Connack {
+                return_code: reader.read_u8()?.try_into()?,
+            },
+            MsgType::Register => SnPacket::Register {
+                topic_id: reader.read_u16()?,
+                msg_id: reader.read_u16()?,
+                topic_name: read_str(&mut reader, len)?,
+            },
+            MsgType::Regack => SnPacket::Regack {
+                topic_id: reader.read_u16()?,
+                msg_id: reader.read_u16()?,
+                return_code: reader.read_u8()?.try_into()?,
+            },
+            MsgType::Publish => {
+                let flags = reader.read_u8()?;
+                SnPacket::Publish {
+                    dup: flags & FLAG_DUP != 0,
+                    qos: SnQoS::from_flags(flags),
+                    retain: flags & FLAG_RETAIN != 0,
+                    topic: TopicId::from_parts(flags & TOPIC_ID_TYPE_MASK, reader.read_u16()?)?,
+                    msg_id: reader.read_u16()?,
+                    data: reader.read_message(len)?,
+                }
+            }
+            MsgType::Puback => SnPacket::Puback {
+                topic_id: reader.read_u16()?,
+                msg_id: reader.read_u16()?,
+                return_code: reader.read_u8()?.try_into()?,
+            },
+            MsgType::Subscribe => {
+                let flags = reader.read_u8()?;
+                SnPacket::Subscribe {
+                    dup: flags & FLAG_DUP != 0,
+                    qos: SnQoS::from_flags(flags),
+                    msg_id: reader.read_u16()?,
+                    topic: TopicRef::decode(flags, &mut reader, len)?,
+                }
+            }
+            MsgType::Suback => SnPacket::Suback {
+                qos: SnQoS::from_flags(reader.read_u8()?),
+                topic_id: reader.read_u16()?,
+                msg_id: reader.read_u16()?,
+                return_code: reader.read_u8()?.try_into()?,
+            },
+            MsgType::Unsubscribe => {
+                let flags = reader.read_u8()?;
+                SnPacket::Unsubscribe {
+                    msg_id: reader.read_u16()?,
+                    topic: TopicRef::decode(flags, &mut reader, len)?,
+                }
+            }
+            MsgType::Unsuback => SnPacket::Unsuback {
+                msg_id: reader.read_u16()?,
+            },
+            MsgType::Pingreq => SnPacket::Pingreq {
+                client_id: if reader.position < len {
+                    Some(read_str(&mut reader, len)?)
+                } else {
+                    None
+                },
+            },
+            MsgType::Pingresp => SnPacket::Pingresp,
+            MsgType::Disconnect => SnPacket::Disconnect {
+                duration: if reader.position < len {
+                    Some(reader.read_u16()?)
+                } else {
+                    None
+                },
+            },
+            _ => {
+                error!("Unsupported MQTT-SN message type {:?}!", msg_type);
+                return Err(BufferError::DecodingError);
+            }
+        };
+        if reader.position != len {
+            error!("MQTT-SN message length does not match its content!");
+            return Err(BufferError::DecodingError);
+        }
+        Ok(packet)
+    }
+}
+
+fn read_str<'a>(reader: &mut BuffReader<'a>, total_len: usize) -> Result<&'a str, BufferError> {
+    str::from_utf8(reader.read_message(total_len)?).map_err(|_| BufferError::Utf8Error)
+}
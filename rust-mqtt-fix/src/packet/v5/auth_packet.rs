@@ -31,10 +31,10 @@ use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::BufferError;
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
-/// Auth packets serves MQTTv5 extended authentication. This packet is not currently supported
-/// by rust-mqtt client but decoding and encoding of packet is prepared for future development.
+/// Auth packets serves MQTTv5 extended authentication. Client uses them to exchange
+/// the authentication data of the `Authenticator` with the broker.
 pub struct AuthPacket<'a, const MAX_PROPERTIES: usize> {
     pub fixed_header: u8,
     pub remain_len: u32,
@@ -62,6 +62,8 @@ impl<'a, const MAX_PROPERTIES: usize> AuthPacket<'a, MAX_PROPERTIES> {
 }
 
 impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for AuthPacket<'a, MAX_PROPERTIES> {
+    const PACKET_TYPE: PacketType = PacketType::Auth;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Auth.into(),
@@ -74,6 +76,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for AuthPacket<'a, MAX_PROPERTI
 
     fn encode(&mut self, buffer: &mut [u8], buff_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buff_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
 
         let mut rm_ln = self.property_len;
         let property_len_enc: [u8; 4] = VariableByteIntegerEncoder::encode(self.property_len)?;
@@ -107,6 +110,10 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for AuthPacket<'a, MAX_PROPERTI
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.auth_property()
     }
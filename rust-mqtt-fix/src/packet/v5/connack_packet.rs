@@ -31,7 +31,7 @@ use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::BufferError;
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 pub struct ConnackPacket<'a, const MAX_PROPERTIES: usize> {
     pub fixed_header: u8,
@@ -45,6 +45,8 @@ pub struct ConnackPacket<'a, const MAX_PROPERTIES: usize> {
 impl<'a, const MAX_PROPERTIES: usize> ConnackPacket<'a, MAX_PROPERTIES> {}
 
 impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for ConnackPacket<'a, MAX_PROPERTIES> {
+    const PACKET_TYPE: PacketType = PacketType::Connack;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Connack.into(),
@@ -58,6 +60,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for ConnackPacket<'a, MAX_PROPE
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
         buff_writer.write_u8(self.fixed_header)?;
         let property_len_enc = VariableByteIntegerEncoder::encode(self.property_len)?;
         let property_len_len = VariableByteIntegerEncoder::len(property_len_enc);
@@ -93,6 +96,10 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for ConnackPacket<'a, MAX_PROPE
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.connack_property()
     }
@@ -26,12 +26,13 @@ use heapless::Vec;
 
 use crate::encoding::variable_byte_integer::VariableByteIntegerEncoder;
 use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::publish_packet::QualityOfService;
 use crate::utils::buffer_reader::BuffReader;
 use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::{BinaryData, BufferError, EncodedString};
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property, PropertyViolation};
 
 pub struct ConnectPacket<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize> {
     pub fixed_header: u8,
@@ -107,14 +108,71 @@ impl<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize>
         }
     }
 
+    /// Sets the will QoS in the connect flags, the will has to be added by `add_will`.
+    pub fn add_will_qos(&mut self, qos: QualityOfService) {
+        self.connect_flags = (self.connect_flags & !0x18) | ((u8::from(qos) << 2) & 0x18);
+    }
+
+    /// Sets or clears the clean start flag, which is set by default.
+    pub fn add_clean_start(&mut self, clean_start: bool) {
+        if clean_start {
+            self.connect_flags |= 0x02;
+        } else {
+            self.connect_flags &= !0x02;
+        }
+    }
+
     pub fn add_client_id(&mut self, id: &EncodedString<'a>) {
         self.client_id = (*id).clone();
     }
+
+    /// Will QoS from the connect flags
+    pub fn will_qos(&self) -> QualityOfService {
+        QualityOfService::from((self.connect_flags >> 2) & 0x06)
+    }
+
+    /// Will retain flag from the connect flags
+    pub fn will_retain(&self) -> bool {
+        self.connect_flags & 0x20 != 0
+    }
+
+    fn decode_will_properties(
+        &mut self,
+        buff_reader: &mut BuffReader<'a>,
+    ) -> Result<(), BufferError> {
+        self.will_property_len = buff_reader.read_variable_byte_int()?;
+        let end = buff_reader.position + self.will_property_len as usize;
+        while buff_reader.position < end {
+            let prop = Property::decode(buff_reader)?;
+            let id: u8 = (&prop).into();
+            if !prop.will_property() {
+                error!("Property 0x{:02X} is not allowed in will properties!", id);
+                return Err(BufferError::InvalidProperty(PropertyViolation::NotAllowed(
+                    id,
+                )));
+            }
+            if !prop.value_valid() {
+                return Err(BufferError::InvalidProperty(
+                    PropertyViolation::InvalidValue(id),
+                ));
+            }
+            if self.will_properties.push(prop).is_err() {
+                return Err(BufferError::InsufficientBufferSize);
+            }
+        }
+        if buff_reader.position != end {
+            error!("Will property overshoots the property length!");
+            return Err(BufferError::DecodingError);
+        }
+        Ok(())
+    }
 }
 
 impl<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize> Packet<'a>
     for ConnectPacket<'a, MAX_PROPERTIES, MAX_WILL_PROPERTIES>
 {
+    const PACKET_TYPE: PacketType = PacketType::Connect;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Connect.into(),
@@ -140,6 +198,7 @@ impl<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize> Packet<'
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
 
         let mut rm_ln = self.property_len;
         let property_len_enc: [u8; 4] = VariableByteIntegerEncoder::encode(self.property_len)?;
@@ -196,9 +255,54 @@ impl<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize> Packet<'
         Ok(buff_writer.position)
     }
 
-    fn decode(&mut self, _buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
-        error!("Decode function is not available for control packet!");
-        Err(BufferError::WrongPacketToDecode)
+    /// Server side decoding of the `CONNECT` packet. Only MQTTv5 is supported, other protocol
+    /// versions end with `DecodingError` right after the protocol version is read.
+    fn decode(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
+        if self.decode_fixed_header(buff_reader)? != PacketType::Connect {
+            error!("Packet you are trying to decode is not CONNECT packet!");
+            return Err(BufferError::PacketTypeMismatch);
+        }
+        self.protocol_name_len = buff_reader.read_u16()?;
+        self.protocol_name = buff_reader.read_u32()?;
+        if self.protocol_name_len != 4 || self.protocol_name != 0x4d515454 {
+            error!("Protocol name of CONNECT packet is not MQTT!");
+            return Err(BufferError::DecodingError);
+        }
+        self.protocol_version = buff_reader.read_u8()?;
+        if self.protocol_version != 5 {
+            error!(
+                "Protocol version {} is not supported!",
+                self.protocol_version
+            );
+            return Err(BufferError::DecodingError);
+        }
+        self.connect_flags = buff_reader.read_u8()?;
+        // Reserved flag has to be zero, will QoS and retain are allowed only with will flag
+        if self.connect_flags & 0x01 != 0
+            || (self.connect_flags & 0x04 == 0 && self.connect_flags & 0x38 != 0)
+            || self.connect_flags & 0x18 == 0x18
+        {
+            error!("Connect flags of CONNECT packet are malformed!");
+            return Err(BufferError::DecodingError);
+        }
+        self.keep_alive = buff_reader.read_u16()?;
+        self.decode_properties(buff_reader)?;
+        self.client_id = buff_reader.read_string()?;
+
+        if self.connect_flags & 0x04 != 0 {
+            self.decode_will_properties(buff_reader)?;
+            self.will_topic = buff_reader.read_string()?;
+            self.will_payload = buff_reader.read_binary()?;
+        }
+
+        if self.connect_flags & 0x80 != 0 {
+            self.username = buff_reader.read_string()?;
+        }
+
+        if self.connect_flags & 0x40 != 0 {
+            self.password = buff_reader.read_binary()?;
+        }
+        Ok(())
     }
 
     fn set_property_len(&mut self, value: u32) {
@@ -213,6 +317,10 @@ impl<'a, const MAX_PROPERTIES: usize, const MAX_WILL_PROPERTIES: usize> Packet<'
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.connect_property()
     }
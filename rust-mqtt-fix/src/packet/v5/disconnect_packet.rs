@@ -31,7 +31,7 @@ use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::BufferError;
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 pub struct DisconnectPacket<'a, const MAX_PROPERTIES: usize> {
     // 7 - 4 mqtt control packet type, 3-0 flagy
@@ -53,6 +53,8 @@ impl<'a, const MAX_PROPERTIES: usize> DisconnectPacket<'a, MAX_PROPERTIES> {
 }
 
 impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for DisconnectPacket<'a, MAX_PROPERTIES> {
+    const PACKET_TYPE: PacketType = PacketType::Disconnect;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Disconnect.into(),
@@ -65,6 +67,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for DisconnectPacket<'a, MAX_PR
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
         buff_writer.write_u8(self.fixed_header)?;
         let property_len_enc = VariableByteIntegerEncoder::encode(self.property_len)?;
         let property_len_len = VariableByteIntegerEncoder::len(property_len_enc);
@@ -102,6 +105,10 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for DisconnectPacket<'a, MAX_PR
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.disconnect_property()
     }
@@ -27,6 +27,7 @@ pub mod connack_packet;
 pub mod mqtt_packet;
 pub mod packet_type;
 pub mod property;
+pub mod property_kind;
 pub mod puback_packet;
 pub mod pubcomp_packet;
 pub mod publish_packet;
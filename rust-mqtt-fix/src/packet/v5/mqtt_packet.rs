@@ -28,11 +28,15 @@ use crate::packet::v5::packet_type::PacketType;
 use crate::utils::buffer_reader::BuffReader;
 use crate::utils::types::BufferError;
 
-use super::property::Property;
+use super::property::{Property, PropertyValidator};
+use super::property_kind::PropertyKind;
 
 /// This trait provide interface for mapping MQTTv5 packets to human readable structures
 /// which can be later modified and used for communication purposes.
 pub trait Packet<'a> {
+    /// Type of the packet, used to select property rules
+    const PACKET_TYPE: PacketType;
+
     fn new() -> Self;
     /// Method encode provide way how to transfer Packet struct into Byte array (buffer)
     fn encode(&mut self, buffer: &mut [u8], buff_len: usize) -> Result<usize, BufferError>;
@@ -45,6 +49,32 @@ pub trait Packet<'a> {
     fn get_property_len(&mut self) -> u32;
     /// Method enables pushing new property into packet properties
     fn push_to_properties(&mut self, property: Property<'a>);
+    /// Properties of the packet - empty for packets without properties
+    fn properties(&self) -> &[Property<'a>];
+    /// Returns value of the first property of kind `K`, e.g. `packet.get::<ReasonString>()`
+    fn get<K: PropertyKind<'a>>(&self) -> Option<K::Value> {
+        self.properties().iter().find_map(K::from_property)
+    }
+    /// Iterator over all user properties as `(name, value)` pairs
+    fn user_properties<'s>(&'s self) -> impl Iterator<Item = (&'a str, &'a str)> + 's
+    where
+        'a: 's,
+    {
+        self.properties().iter().filter_map(|prop| match prop {
+            Property::UserProperty(pair) => Some((pair.name.string, pair.value.string)),
+            _ => None,
+        })
+    }
+    /// Iterator over all subscription identifiers - PUBLISH can carry more of them
+    fn subscription_identifiers<'s>(&'s self) -> impl Iterator<Item = u32> + 's
+    where
+        'a: 's,
+    {
+        self.properties().iter().filter_map(|prop| match prop {
+            Property::SubscriptionIdentifier(id) => Some(*id),
+            _ => None,
+        })
+    }
     /// Returns if property is allowed for packet
     fn property_allowed(&mut self, property: &Property<'a>) -> bool;
     /// Method enables adding properties from client config - each packet decides if property can be used with that or not
@@ -68,22 +98,25 @@ pub trait Packet<'a> {
     fn set_remaining_len(&mut self, remaining_len: u32);
 
     /// Method is decoding Byte array pointing to properties into heapless Vec
-    /// in packet. If decoding goes wrong method is returning Error
+    /// in packet. If decoding goes wrong, the last property overshoots the
+    /// property length or a property breaks the rules for the packet type,
+    /// method is returning Error
     fn decode_properties(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
-        self.set_property_len(buff_reader.read_variable_byte_int()?);
-        let mut x: u32 = 0;
-        let mut prop: Property;
-        if self.get_property_len() != 0 {
-            loop {
-                prop = Property::decode(buff_reader)?;
-                //debug!("Parsed property {:?}", prop);
-                x = x + prop.encoded_len() as u32 + 1;
-                self.push_to_properties(prop);
-
-                if x == self.get_property_len() {
-                    break;
-                }
+        let property_len = buff_reader.read_variable_byte_int()?;
+        self.set_property_len(property_len);
+        let end = buff_reader.position + property_len as usize;
+        let mut validator = PropertyValidator::new(Self::PACKET_TYPE);
+        while buff_reader.position < end {
+            let prop = Property::decode(buff_reader)?;
+            if let Err(violation) = validator.check(&prop) {
+                error!("Invalid property: {}", violation);
+                return Err(BufferError::InvalidProperty(violation));
             }
+            self.push_to_properties(prop);
+        }
+        if buff_reader.position != end {
+            error!("Property overshoots the property length!");
+            return Err(BufferError::DecodingError);
         }
         Ok(())
     }
@@ -24,7 +24,8 @@
 
 // x x x x - - - -
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum PacketType {
     Reserved,
     Connect,
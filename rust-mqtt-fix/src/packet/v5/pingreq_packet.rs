@@ -38,6 +38,8 @@ pub struct PingreqPacket {
 impl PingreqPacket {}
 
 impl<'a> Packet<'a> for PingreqPacket {
+    const PACKET_TYPE: PacketType = PacketType::Pingreq;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Pingreq.into(),
@@ -52,9 +54,16 @@ impl<'a> Packet<'a> for PingreqPacket {
         Ok(buff_writer.position)
     }
 
-    fn decode(&mut self, _buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
-        error!("Pingreq Packet packet does not support decode funtion on client!");
-        Err(BufferError::WrongPacketToDecode)
+    fn decode(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
+        if self.decode_fixed_header(buff_reader)? != PacketType::Pingreq {
+            error!("Packet you are trying to decode is not PINGREQ packet!");
+            return Err(BufferError::PacketTypeMismatch);
+        }
+        if self.remain_len != 0 {
+            error!("PINGREQ packet has to be empty!");
+            return Err(BufferError::DecodingError);
+        }
+        Ok(())
     }
 
     fn set_property_len(&mut self, _value: u32) {
@@ -70,6 +79,10 @@ impl<'a> Packet<'a> for PingreqPacket {
         error!("PINGREQ packet does not contain any properties!");
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &[]
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.pingreq_property()
     }
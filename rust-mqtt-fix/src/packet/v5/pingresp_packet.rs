@@ -38,6 +38,8 @@ pub struct PingrespPacket {
 impl PingrespPacket {}
 
 impl<'a> Packet<'a> for PingrespPacket {
+    const PACKET_TYPE: PacketType = PacketType::Pingresp;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Pingresp.into(),
@@ -78,6 +80,10 @@ impl<'a> Packet<'a> for PingrespPacket {
         error!("PINGRESP packet does not contain any properties!");
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &[]
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.pingresp_property()
     }
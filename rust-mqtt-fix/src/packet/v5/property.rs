@@ -22,7 +22,10 @@
  * SOFTWARE.
  */
 
+use core::fmt::{Display, Formatter};
+
 use crate::encoding::variable_byte_integer::VariableByteIntegerEncoder;
+use crate::packet::v5::packet_type::PacketType;
 use crate::utils::buffer_reader::BuffReader;
 use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::{BinaryData, BufferError, EncodedString, StringPair};
@@ -77,6 +80,22 @@ impl<'a> Property<'a> {
         }
     }
 
+    /// Properties allowed in the will properties of the `CONNECT` packet payload.
+    pub fn will_property(&self) -> bool {
+        // not possible to use with associated values with different types
+        #[allow(clippy::match_like_matches_macro)]
+        match self {
+            Property::WillDelayInterval(_u) => true,
+            Property::PayloadFormat(_u) => true,
+            Property::MessageExpiryInterval(_u) => true,
+            Property::ContentType(_u) => true,
+            Property::ResponseTopic(_u) => true,
+            Property::CorrelationData(_u) => true,
+            Property::UserProperty(_u) => true,
+            _ => false,
+        }
+    }
+
     pub fn connack_property(&self) -> bool {
         // not possible to use with associated values with different types
         #[allow(clippy::match_like_matches_macro)]
@@ -84,6 +103,7 @@ impl<'a> Property<'a> {
             Property::SessionExpiryInterval(_u) => true,
             Property::ReceiveMaximum(_u) => true,
             Property::MaximumQoS(_u) => true,
+            Property::RetainAvailable(_u) => true,
             Property::MaximumPacketSize(_u) => true,
             Property::AssignedClientIdentifier(_u) => true,
             Property::TopicAliasMaximum(_u) => true,
@@ -296,7 +316,7 @@ impl<'a> Property<'a> {
 
     pub fn decode(buff_reader: &mut BuffReader<'a>) -> Result<Property<'a>, BufferError> {
         let property_identifier = buff_reader.read_u8();
-        return match property_identifier {
+        match property_identifier {
             Ok(0x01) => Ok(Property::PayloadFormat(buff_reader.read_u8()?)),
             Ok(0x02) => Ok(Property::MessageExpiryInterval(buff_reader.read_u32()?)),
             Ok(0x03) => Ok(Property::ContentType(buff_reader.read_string()?)),
@@ -336,8 +356,137 @@ impl<'a> Property<'a> {
             )),
             Err(err) => Err(err),
             _ => Err(BufferError::IdNotFound),
-        };
+        }
+    }
+}
+
+/// Property violation of the MQTTv5 rules. Contains identifier of the property.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PropertyViolation {
+    /// Property is not allowed for the packet type.
+    NotAllowed(u8),
+    /// Property which can be included only once is repeated.
+    Duplicate(u8),
+    /// Property value is out of the allowed range.
+    InvalidValue(u8),
+}
+
+impl Display for PropertyViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PropertyViolation::NotAllowed(id) => {
+                write!(f, "Property 0x{:02X} is not allowed for packet!", id)
+            }
+            PropertyViolation::Duplicate(id) => {
+                write!(f, "Property 0x{:02X} is included more than once!", id)
+            }
+            PropertyViolation::InvalidValue(id) => {
+                write!(f, "Property 0x{:02X} has value out of range!", id)
+            }
+        }
+    }
+}
+
+impl<'a> Property<'a> {
+    /// Rule table of allowed properties per packet type.
+    pub fn allowed_in(&self, packet_type: PacketType) -> bool {
+        match packet_type {
+            PacketType::Connect => self.connect_property(),
+            PacketType::Connack => self.connack_property(),
+            PacketType::Publish => self.publish_property(),
+            PacketType::Puback => self.puback_property(),
+            PacketType::Pubrec => self.pubrec_property(),
+            PacketType::Pubrel => self.pubrel_property(),
+            PacketType::Pubcomp => self.pubcomp_property(),
+            PacketType::Subscribe => self.subscribe_property(),
+            PacketType::Suback => self.suback_property(),
+            PacketType::Unsubscribe => self.unsubscribe_property(),
+            PacketType::Unsuback => self.unsuback_property(),
+            PacketType::Disconnect => self.disconnect_property(),
+            PacketType::Auth => self.auth_property(),
+            PacketType::Pingreq | PacketType::Pingresp | PacketType::Reserved => false,
+        }
+    }
+
+    /// Rule table of properties which can be included more than once in the packet.
+    /// User property can be repeated in every packet, subscription identifier only in
+    /// PUBLISH packet.
+    pub fn repeatable_in(&self, packet_type: PacketType) -> bool {
+        match self {
+            Property::UserProperty(_u) => true,
+            Property::SubscriptionIdentifier(_u) => packet_type == PacketType::Publish,
+            _ => false,
+        }
+    }
+
+    /// Returns if the value of the property is in the range allowed by MQTTv5.
+    pub fn value_valid(&self) -> bool {
+        match self {
+            Property::PayloadFormat(u)
+            | Property::MaximumQoS(u)
+            | Property::RetainAvailable(u)
+            | Property::RequestProblemInformation(u)
+            | Property::RequestResponseInformation(u)
+            | Property::WildcardSubscriptionAvailable(u)
+            | Property::SubscriptionIdentifierAvailable(u)
+            | Property::SharedSubscriptionAvailable(u) => *u <= 1,
+            Property::ReceiveMaximum(u) | Property::TopicAlias(u) => *u != 0,
+            Property::MaximumPacketSize(u) => *u != 0,
+            Property::SubscriptionIdentifier(u) => *u != 0 && *u <= 268_435_455,
+            Property::Reserved() => false,
+            _ => true,
+        }
+    }
+}
+
+/// Validates properties of one packet one by one against the rule table, so it can be used
+/// while decoding properties as well as for already collected properties.
+pub struct PropertyValidator {
+    packet_type: PacketType,
+    // Property identifiers are lower than 64, each bit marks one already seen property
+    seen: u64,
+}
+
+impl PropertyValidator {
+    pub fn new(packet_type: PacketType) -> Self {
+        Self {
+            packet_type,
+            seen: 0,
+        }
+    }
+
+    /// Checks next property of the packet.
+    pub fn check(&mut self, property: &Property) -> Result<(), PropertyViolation> {
+        let id: u8 = property.into();
+        if !property.allowed_in(self.packet_type) {
+            return Err(PropertyViolation::NotAllowed(id));
+        }
+        if !property.value_valid() {
+            return Err(PropertyViolation::InvalidValue(id));
+        }
+        let mask = 1u64 << (id & 0x3F);
+        if self.seen & mask != 0 && !property.repeatable_in(self.packet_type) {
+            return Err(PropertyViolation::Duplicate(id));
+        }
+        self.seen |= mask;
+        Ok(())
+    }
+}
+
+/// Validates all `properties` of the packet with `packet_type`.
+pub fn validate_properties(
+    packet_type: PacketType,
+    properties: &[Property],
+) -> Result<(), BufferError> {
+    let mut validator = PropertyValidator::new(packet_type);
+    for property in properties {
+        if let Err(violation) = validator.check(property) {
+            error!("Invalid property: {}", violation);
+            return Err(BufferError::InvalidProperty(violation));
+        }
     }
+    Ok(())
 }
 
 impl<'a> From<&Property<'a>> for u8 {
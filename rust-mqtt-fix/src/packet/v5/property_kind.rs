@@ -0,0 +1,84 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Marker types for typed property lookups on decoded packets.
+//!
+//! Every MQTTv5 property has a marker with the same name as the `Property`
+//! variant, so the value can be read without matching by hand:
+//! `connack.get::<ReceiveMaximum>()` returns `Option<u16>`. Strings are
+//! returned as `&str`, binary data as `&[u8]` and the one byte flags as `bool`.
+//! User properties and subscription identifiers can appear more than once,
+//! use `Packet::user_properties` and `Packet::subscription_identifiers` for them.
+
+use crate::packet::v5::property::Property;
+
+/// Kind of the property which knows how to extract its value
+pub trait PropertyKind<'a> {
+    type Value;
+
+    fn from_property(property: &Property<'a>) -> Option<Self::Value>;
+}
+
+macro_rules! property_kind {
+    ($name:ident, $value:ty, |$v:ident| $map:expr) => {
+        pub struct $name;
+
+        impl<'a> PropertyKind<'a> for $name {
+            type Value = $value;
+
+            fn from_property(property: &Property<'a>) -> Option<Self::Value> {
+                match property {
+                    Property::$name($v) => Some($map),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+property_kind!(PayloadFormat, u8, |v| *v);
+property_kind!(MessageExpiryInterval, u32, |v| *v);
+property_kind!(ContentType, &'a str, |v| v.string);
+property_kind!(ResponseTopic, &'a str, |v| v.string);
+property_kind!(CorrelationData, &'a [u8], |v| v.bin);
+property_kind!(SessionExpiryInterval, u32, |v| *v);
+property_kind!(AssignedClientIdentifier, &'a str, |v| v.string);
+property_kind!(ServerKeepAlive, u16, |v| *v);
+property_kind!(AuthenticationMethod, &'a str, |v| v.string);
+property_kind!(AuthenticationData, &'a [u8], |v| v.bin);
+property_kind!(RequestProblemInformation, bool, |v| *v == 1);
+property_kind!(WillDelayInterval, u32, |v| *v);
+property_kind!(RequestResponseInformation, bool, |v| *v == 1);
+property_kind!(ResponseInformation, &'a str, |v| v.string);
+property_kind!(ServerReference, &'a str, |v| v.string);
+property_kind!(ReasonString, &'a str, |v| v.string);
+property_kind!(ReceiveMaximum, u16, |v| *v);
+property_kind!(TopicAliasMaximum, u16, |v| *v);
+property_kind!(TopicAlias, u16, |v| *v);
+property_kind!(MaximumQoS, u8, |v| *v);
+property_kind!(RetainAvailable, bool, |v| *v == 1);
+property_kind!(MaximumPacketSize, u32, |v| *v);
+property_kind!(WildcardSubscriptionAvailable, bool, |v| *v == 1);
+property_kind!(SubscriptionIdentifierAvailable, bool, |v| *v == 1);
+property_kind!(SharedSubscriptionAvailable, bool, |v| *v == 1);
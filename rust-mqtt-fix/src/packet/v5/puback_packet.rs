@@ -31,7 +31,7 @@ use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::BufferError;
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 pub struct PubackPacket<'a, const MAX_PROPERTIES: usize> {
     pub fixed_header: u8,
@@ -45,6 +45,8 @@ pub struct PubackPacket<'a, const MAX_PROPERTIES: usize> {
 impl<'a, const MAX_PROPERTIES: usize> PubackPacket<'a, MAX_PROPERTIES> {}
 
 impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PubackPacket<'a, MAX_PROPERTIES> {
+    const PACKET_TYPE: PacketType = PacketType::Puback;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Puback.into(),
@@ -58,6 +60,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PubackPacket<'a, MAX_PROPER
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
 
         let mut rm_ln = self.property_len;
         let property_len_enc: [u8; 4] = VariableByteIntegerEncoder::encode(self.property_len)?;
@@ -102,6 +105,10 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PubackPacket<'a, MAX_PROPER
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.puback_property()
     }
@@ -32,7 +32,7 @@ use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::{BufferError, EncodedString};
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum QualityOfService {
@@ -98,6 +98,8 @@ impl<'a, const MAX_PROPERTIES: usize> PublishPacket<'a, MAX_PROPERTIES> {
 }
 
 impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PublishPacket<'a, MAX_PROPERTIES> {
+    const PACKET_TYPE: PacketType = PacketType::Publish;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Publish.into(),
@@ -112,11 +114,13 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PublishPacket<'a, MAX_PROPE
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
 
         let mut rm_ln = self.property_len;
         let property_len_enc: [u8; 4] = VariableByteIntegerEncoder::encode(self.property_len)?;
         let property_len_len = VariableByteIntegerEncoder::len(property_len_enc);
-        let msg_len = self.message.unwrap().len() as u32;
+        let message = self.message.unwrap_or(&[]);
+        let msg_len = message.len() as u32;
         rm_ln = rm_ln + property_len_len as u32 + msg_len + self.topic_name.len as u32 + 2;
 
         buff_writer.write_u8(self.fixed_header)?;
@@ -134,7 +138,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PublishPacket<'a, MAX_PROPE
 
         buff_writer.write_variable_byte_int(self.property_len)?;
         buff_writer.write_properties::<MAX_PROPERTIES>(&self.properties)?;
-        buff_writer.insert_ref(msg_len as usize, self.message.unwrap())?;
+        buff_writer.insert_ref(msg_len as usize, message)?;
         Ok(buff_writer.position)
     }
 
@@ -153,7 +157,7 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PublishPacket<'a, MAX_PROPE
         let mut total_len =
             VariableByteIntegerEncoder::len(VariableByteIntegerEncoder::encode(self.remain_len)?);
         total_len = total_len + 1 + self.remain_len as usize;
-        self.message = Some(buff_reader.read_message(total_len));
+        self.message = Some(buff_reader.read_message(total_len)?);
         Ok(())
     }
 
@@ -169,6 +173,10 @@ impl<'a, const MAX_PROPERTIES: usize> Packet<'a> for PublishPacket<'a, MAX_PROPE
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.publish_property()
     }
@@ -27,10 +27,11 @@ use heapless::Vec;
 use crate::encoding::variable_byte_integer::VariableByteIntegerEncoder;
 use crate::packet::v5::mqtt_packet::Packet;
 use crate::utils::buffer_reader::BuffReader;
+use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::BufferError;
 
 use super::packet_type::PacketType;
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 pub struct SubackPacket<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize> {
     pub fixed_header: u8,
@@ -48,17 +49,13 @@ impl<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize>
         &mut self,
         buff_reader: &mut BuffReader<'a>,
     ) -> Result<(), BufferError> {
-        let rm_ln_ln = VariableByteIntegerEncoder::len(
-            VariableByteIntegerEncoder::encode(self.remain_len).unwrap(),
-        );
+        let rm_ln_ln =
+            VariableByteIntegerEncoder::len(VariableByteIntegerEncoder::encode(self.remain_len)?);
         let max = self.remain_len as usize + rm_ln_ln + 1;
-        if buff_reader.position >= max {
-            return Ok(());
-        }
-        loop {
-            self.reason_codes.push(buff_reader.read_u8()?);
-            if buff_reader.position == max {
-                break;
+        while buff_reader.position < max {
+            if self.reason_codes.push(buff_reader.read_u8()?).is_err() {
+                error!("SUBACK packet contains too many reason codes!");
+                return Err(BufferError::InsufficientBufferSize);
             }
         }
         Ok(())
@@ -68,6 +65,8 @@ impl<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize>
 impl<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize> Packet<'a>
     for SubackPacket<'a, MAX_REASONS, MAX_PROPERTIES>
 {
+    const PACKET_TYPE: PacketType = PacketType::Suback;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Suback.into(),
@@ -79,9 +78,22 @@ impl<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize> Packet<'a>
         }
     }
 
-    fn encode(&mut self, _buffer: &mut [u8], _buffer_len: usize) -> Result<usize, BufferError> {
-        error!("SUBACK packet does not support encoding!");
-        Err(BufferError::WrongPacketToEncode)
+    /// Server side encoding of the `SUBACK` packet.
+    fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
+        let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
+        let property_len_enc = VariableByteIntegerEncoder::encode(self.property_len)?;
+        let property_len_len = VariableByteIntegerEncoder::len(property_len_enc);
+
+        let rm_len: u32 =
+            2 + property_len_len as u32 + self.property_len + self.reason_codes.len() as u32;
+        buff_writer.write_u8(self.fixed_header)?;
+        buff_writer.write_variable_byte_int(rm_len)?;
+        buff_writer.write_u16(self.packet_identifier)?;
+        buff_writer.write_variable_byte_int(self.property_len)?;
+        buff_writer.write_properties(&self.properties)?;
+        buff_writer.insert_ref(self.reason_codes.len(), &self.reason_codes)?;
+        Ok(buff_writer.position)
     }
 
     fn decode(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
@@ -106,6 +118,10 @@ impl<'a, const MAX_REASONS: usize, const MAX_PROPERTIES: usize> Packet<'a>
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.suback_property()
     }
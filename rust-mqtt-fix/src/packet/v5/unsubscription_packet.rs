@@ -31,7 +31,7 @@ use crate::utils::buffer_reader::BuffReader;
 use crate::utils::buffer_writer::BuffWriter;
 use crate::utils::types::{BufferError, TopicFilter};
 
-use super::property::Property;
+use super::property::{validate_properties, Property};
 
 pub struct UnsubscriptionPacket<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize> {
     pub fixed_header: u8,
@@ -60,6 +60,8 @@ impl<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize>
 impl<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize> Packet<'a>
     for UnsubscriptionPacket<'a, MAX_FILTERS, MAX_PROPERTIES>
 {
+    const PACKET_TYPE: PacketType = PacketType::Unsubscribe;
+
     fn new() -> Self {
         Self {
             fixed_header: PacketType::Unsubscribe.into(),
@@ -74,6 +76,7 @@ impl<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize> Packet<'a>
 
     fn encode(&mut self, buffer: &mut [u8], buffer_len: usize) -> Result<usize, BufferError> {
         let mut buff_writer = BuffWriter::new(buffer, buffer_len);
+        validate_properties(Self::PACKET_TYPE, &self.properties)?;
 
         let mut rm_ln = self.property_len;
         let property_len_enc: [u8; 4] = VariableByteIntegerEncoder::encode(self.property_len)?;
@@ -103,9 +106,39 @@ impl<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize> Packet<'a>
         Ok(buff_writer.position)
     }
 
-    fn decode(&mut self, _buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
-        error!("Unsubscribe packet does not support decode funtion on client!");
-        Err(BufferError::WrongPacketToDecode)
+    /// Server side decoding of the `UNSUBSCRIBE` packet. Decoded filters carry no
+    /// subscription options.
+    fn decode(&mut self, buff_reader: &mut BuffReader<'a>) -> Result<(), BufferError> {
+        if self.decode_fixed_header(buff_reader)? != PacketType::Unsubscribe {
+            error!("Packet you are trying to decode is not UNSUBSCRIBE packet!");
+            return Err(BufferError::PacketTypeMismatch);
+        }
+        if self.fixed_header & 0x0F != 0x02 {
+            error!("Reserved flags of UNSUBSCRIBE packet are malformed!");
+            return Err(BufferError::DecodingError);
+        }
+        self.packet_identifier = buff_reader.read_u16()?;
+        self.decode_properties(buff_reader)?;
+
+        let rm_ln_ln =
+            VariableByteIntegerEncoder::len(VariableByteIntegerEncoder::encode(self.remain_len)?);
+        let end = self.remain_len as usize + rm_ln_ln + 1;
+        while buff_reader.position < end {
+            let filter = TopicFilter {
+                filter: buff_reader.read_string()?,
+                sub_options: 0,
+            };
+            if self.topic_filters.push(filter).is_err() {
+                error!("UNSUBSCRIBE packet contains too many topic filters!");
+                return Err(BufferError::InsufficientBufferSize);
+            }
+            self.topic_filter_len += 1;
+        }
+        if self.topic_filter_len == 0 || buff_reader.position != end {
+            error!("UNSUBSCRIBE packet payload is malformed!");
+            return Err(BufferError::DecodingError);
+        }
+        Ok(())
     }
 
     fn set_property_len(&mut self, value: u32) {
@@ -120,6 +153,10 @@ impl<'a, const MAX_FILTERS: usize, const MAX_PROPERTIES: usize> Packet<'a>
         self.properties.push(property);
     }
 
+    fn properties(&self) -> &[Property<'a>] {
+        &self.properties
+    }
+
     fn property_allowed(&mut self, property: &Property<'a>) -> bool {
         property.unsubscribe_property()
     }
@@ -0,0 +1,428 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embedded_storage::nor_flash::NorFlash;
+
+use super::{QueueError, QueueStorage};
+
+const WORD: u32 = 4;
+const PAGE_MAGIC: u32 = 0x4D51_5451;
+const RECORD_MAGIC: u8 = 0xA5;
+/// magic (4) + sequence number (4)
+const PAGE_HEADER_LEN: u32 = 8;
+/// len (2) + crc (1) + magic (1) + consumed marker (4)
+const RECORD_HEADER_LEN: u32 = 8;
+const STAGING_LEN: usize = 32;
+
+/// `QueueStorage` implementation for raw NOR flash (e.g. a dedicated data partition
+/// accessed through `esp-storage`). Storage uses `pages` erase blocks starting at offset
+/// `base` as a ring of append-only logs, so it survives a reboot and every page is
+/// erased only after all of its records were popped.
+///
+/// Every page starts with a header containing a sequence number. Records are written
+/// behind each other, data first and the record header last, so a record interrupted by
+/// a power loss is never visible. Popping a record clears its consumed marker. Flash has
+/// to support writes with granularity of 1, 2 or 4 Bytes.
+pub struct FlashQueueStorage<F: NorFlash> {
+    flash: F,
+    base: u32,
+    pages: u32,
+    write: Option<(u32, u32)>,
+    head: (u32, u32),
+    count: usize,
+    next_seq: u32,
+}
+
+struct RecordHeader {
+    len: u32,
+    crc: u8,
+    live: bool,
+}
+
+struct PageScan {
+    live: usize,
+    first_live: Option<u32>,
+    end: u32,
+    clean: bool,
+}
+
+fn padded(len: u32) -> u32 {
+    len.div_ceil(WORD) * WORD
+}
+
+fn crc8(crc: u8, data: &[u8]) -> u8 {
+    let mut crc = crc;
+    for byte in data {
+        crc ^= *byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+impl<F: NorFlash> FlashQueueStorage<F> {
+    /// Creates the storage over `pages` erase blocks starting at `base` and recovers
+    /// the records which are already stored there.
+    pub fn new(flash: F, base: u32, pages: u32) -> Result<Self, QueueError<F::Error>> {
+        let page_size = F::ERASE_SIZE as u32;
+        if pages < 2
+            || WORD % F::WRITE_SIZE as u32 != 0
+            || WORD % F::READ_SIZE as u32 != 0
+            || page_size % WORD != 0
+            || page_size <= PAGE_HEADER_LEN + RECORD_HEADER_LEN
+            || base % page_size != 0
+            || (base as usize + (pages * page_size) as usize) > flash.capacity()
+        {
+            error!("Flash region is not usable for the queue storage!");
+            return Err(QueueError::InvalidRegion);
+        }
+
+        let mut storage = Self {
+            flash,
+            base,
+            pages,
+            write: None,
+            head: (0, PAGE_HEADER_LEN),
+            count: 0,
+            next_seq: 0,
+        };
+        storage.mount()?;
+        Ok(storage)
+    }
+
+    /// Consumes the storage and returns the flash driver.
+    pub fn release(self) -> F {
+        self.flash
+    }
+
+    /// Removes all records and erases the whole region.
+    pub fn clear(&mut self) -> Result<(), QueueError<F::Error>> {
+        for page in 0..self.pages {
+            self.erase_page(page)?;
+        }
+        self.write = None;
+        self.head = (0, PAGE_HEADER_LEN);
+        self.count = 0;
+        Ok(())
+    }
+
+    fn page_size(&self) -> u32 {
+        F::ERASE_SIZE as u32
+    }
+
+    fn address(&self, page: u32, offset: u32) -> u32 {
+        self.base + page * self.page_size() + offset
+    }
+
+    fn read_word(&mut self, page: u32, offset: u32) -> Result<[u8; 4], QueueError<F::Error>> {
+        let mut word = [0u8; 4];
+        let address = self.address(page, offset);
+        self.flash
+            .read(address, &mut word)
+            .map_err(QueueError::Storage)?;
+        Ok(word)
+    }
+
+    fn write_bytes(
+        &mut self,
+        page: u32,
+        offset: u32,
+        data: &[u8],
+    ) -> Result<(), QueueError<F::Error>> {
+        let address = self.address(page, offset);
+        self.flash.write(address, data).map_err(QueueError::Storage)
+    }
+
+    fn erase_page(&mut self, page: u32) -> Result<(), QueueError<F::Error>> {
+        let from = self.address(page, 0);
+        self.flash
+            .erase(from, from + self.page_size())
+            .map_err(QueueError::Storage)
+    }
+
+    fn page_seq(&mut self, page: u32) -> Result<Option<u32>, QueueError<F::Error>> {
+        let magic = u32::from_be_bytes(self.read_word(page, 0)?);
+        if magic != PAGE_MAGIC {
+            return Ok(None);
+        }
+        Ok(Some(u32::from_be_bytes(self.read_word(page, 4)?)))
+    }
+
+    /// Reads record header at `offset`. Returns `None` if there is no valid record.
+    fn read_record_header(
+        &mut self,
+        page: u32,
+        offset: u32,
+    ) -> Result<Option<RecordHeader>, QueueError<F::Error>> {
+        if offset + RECORD_HEADER_LEN > self.page_size() {
+            return Ok(None);
+        }
+        let header = self.read_word(page, offset)?;
+        if header[3] != RECORD_MAGIC {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([header[0], header[1]]) as u32;
+        if offset + RECORD_HEADER_LEN + padded(len) > self.page_size() {
+            return Ok(None);
+        }
+        let consumed = self.read_word(page, offset + 4)?;
+        Ok(Some(RecordHeader {
+            len,
+            crc: header[2],
+            live: consumed == [0xFF; 4],
+        }))
+    }
+
+    fn scan_page(&mut self, page: u32) -> Result<PageScan, QueueError<F::Error>> {
+        let mut scan = PageScan {
+            live: 0,
+            first_live: None,
+            end: PAGE_HEADER_LEN,
+            clean: true,
+        };
+        while let Some(record) = self.read_record_header(page, scan.end)? {
+            if record.live {
+                scan.live += 1;
+                scan.first_live.get_or_insert(scan.end);
+            }
+            scan.end += RECORD_HEADER_LEN + padded(record.len);
+        }
+        let mut offset = scan.end;
+        while offset < self.page_size() {
+            if self.read_word(page, offset)? != [0xFF; 4] {
+                scan.clean = false;
+                break;
+            }
+            offset += WORD;
+        }
+        Ok(scan)
+    }
+
+    fn mount(&mut self) -> Result<(), QueueError<F::Error>> {
+        let mut oldest: Option<(u32, u32)> = None;
+        let mut newest: Option<(u32, u32)> = None;
+        for page in 0..self.pages {
+            if let Some(seq) = self.page_seq(page)? {
+                if oldest.map_or(true, |(s, _)| seq < s) {
+                    oldest = Some((seq, page));
+                }
+                if newest.map_or(true, |(s, _)| seq > s) {
+                    newest = Some((seq, page));
+                }
+            }
+        }
+        let (Some((_, oldest)), Some((newest_seq, newest))) = (oldest, newest) else {
+            return Ok(());
+        };
+        self.next_seq = newest_seq.wrapping_add(1);
+
+        let mut head = None;
+        for i in 0..self.pages {
+            let page = (oldest + i) % self.pages;
+            if self.page_seq(page)?.is_none() {
+                continue;
+            }
+            let scan = self.scan_page(page)?;
+            if page == newest {
+                self.write = Some((
+                    page,
+                    if scan.clean {
+                        scan.end
+                    } else {
+                        self.page_size()
+                    },
+                ));
+            }
+            if scan.live == 0 && page != newest {
+                self.erase_page(page)?;
+            }
+            if let (None, Some(offset)) = (head, scan.first_live) {
+                head = Some((page, offset));
+            }
+            self.count += scan.live;
+            if page == newest {
+                break;
+            }
+        }
+        self.head = match head {
+            Some(head) => head,
+            None => self.write.unwrap_or((newest, PAGE_HEADER_LEN)),
+        };
+        debug!("Recovered {} queued records from flash", self.count);
+        Ok(())
+    }
+
+    /// Prepares next page in the ring for writing.
+    fn open_page(&mut self, page: u32) -> Result<(), QueueError<F::Error>> {
+        if self.page_seq(page)?.is_some() {
+            return Err(QueueError::Full);
+        }
+        self.erase_page(page)?;
+        let mut header = [0u8; PAGE_HEADER_LEN as usize];
+        header[0..4].copy_from_slice(&PAGE_MAGIC.to_be_bytes());
+        header[4..8].copy_from_slice(&self.next_seq.to_be_bytes());
+        self.write_bytes(page, 0, &header)?;
+        self.next_seq = self.next_seq.wrapping_add(1);
+
+        if let Some((previous, _)) = self.write {
+            if self.count == 0 {
+                self.erase_page(previous)?;
+            }
+        }
+        self.write = Some((page, PAGE_HEADER_LEN));
+        if self.count == 0 {
+            self.head = (page, PAGE_HEADER_LEN);
+        }
+        Ok(())
+    }
+
+    /// Moves head to the next live record, erasing pages which were completely consumed.
+    fn advance_head(&mut self) -> Result<(), QueueError<F::Error>> {
+        loop {
+            let (page, offset) = self.head;
+            if let Some((write_page, write_offset)) = self.write {
+                if page == write_page && offset >= write_offset {
+                    return Ok(());
+                }
+            }
+            match self.read_record_header(page, offset)? {
+                Some(record) if record.live => return Ok(()),
+                Some(record) => {
+                    self.head = (page, offset + RECORD_HEADER_LEN + padded(record.len));
+                }
+                None => {
+                    if self.write.is_some_and(|(write_page, _)| write_page == page) {
+                        return Ok(());
+                    }
+                    self.erase_page(page)?;
+                    self.head = ((page + 1) % self.pages, PAGE_HEADER_LEN);
+                }
+            }
+        }
+    }
+}
+
+impl<F: NorFlash> QueueStorage for FlashQueueStorage<F> {
+    type Error = F::Error;
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn push(&mut self, parts: &[&[u8]]) -> Result<(), QueueError<Self::Error>> {
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+        if len > u16::MAX as usize
+            || RECORD_HEADER_LEN + padded(len as u32) > self.page_size() - PAGE_HEADER_LEN
+        {
+            return Err(QueueError::TooLarge);
+        }
+        let needed = RECORD_HEADER_LEN + padded(len as u32);
+        match self.write {
+            Some((_, offset)) if offset + needed <= self.page_size() => {}
+            Some((page, _)) => self.open_page((page + 1) % self.pages)?,
+            None => self.open_page(0)?,
+        }
+        let (page, offset) = unwrap!(self.write);
+
+        let mut crc = 0;
+        let mut staging = [0xFFu8; STAGING_LEN];
+        let mut staged = 0;
+        let mut position = offset + RECORD_HEADER_LEN;
+        for part in parts {
+            crc = crc8(crc, part);
+            for byte in part.iter() {
+                staging[staged] = *byte;
+                staged += 1;
+                if staged == STAGING_LEN {
+                    self.write_bytes(page, position, &staging)?;
+                    position += STAGING_LEN as u32;
+                    staged = 0;
+                }
+            }
+        }
+        if staged != 0 {
+            let staged_len = padded(staged as u32) as usize;
+            staging[staged..staged_len].fill(0xFF);
+            self.write_bytes(page, position, &staging[..staged_len])?;
+        }
+
+        let len_bytes = (len as u16).to_be_bytes();
+        self.write_bytes(
+            page,
+            offset,
+            &[len_bytes[0], len_bytes[1], crc, RECORD_MAGIC],
+        )?;
+        self.write = Some((page, offset + needed));
+        self.count += 1;
+        Ok(())
+    }
+
+    fn peek(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, QueueError<Self::Error>> {
+        if self.count == 0 {
+            return Ok(None);
+        }
+        let (page, offset) = self.head;
+        let record = match self.read_record_header(page, offset)? {
+            Some(record) if record.live => record,
+            _ => return Err(QueueError::Corrupted),
+        };
+        let len = record.len as usize;
+        if len > buffer.len() {
+            return Err(QueueError::BufferTooSmall);
+        }
+        let aligned = len - len % WORD as usize;
+        let address = self.address(page, offset + RECORD_HEADER_LEN);
+        self.flash
+            .read(address, &mut buffer[..aligned])
+            .map_err(QueueError::Storage)?;
+        if aligned != len {
+            let word = self.read_word(page, offset + RECORD_HEADER_LEN + aligned as u32)?;
+            buffer[aligned..len].copy_from_slice(&word[..len - aligned]);
+        }
+        if crc8(0, &buffer[..len]) != record.crc {
+            error!("Queued record checksum mismatch!");
+            return Err(QueueError::Corrupted);
+        }
+        Ok(Some(len))
+    }
+
+    fn pop(&mut self) -> Result<(), QueueError<Self::Error>> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let (page, offset) = self.head;
+        let Some(record) = self.read_record_header(page, offset)? else {
+            return Err(QueueError::Corrupted);
+        };
+        self.write_bytes(page, offset + 4, &[0; 4])?;
+        self.count -= 1;
+        self.head = (page, offset + RECORD_HEADER_LEN + padded(record.len));
+        self.advance_head()
+    }
+}
@@ -0,0 +1,463 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Store-and-forward queue for outbound PUBLISH messages.
+//!
+//! `OutboundQueue` keeps messages which could not be sent because the client lost the
+//! connection to the broker and sends them in the original order once `drain` is called
+//! with a connected client. Messages are serialized into a `QueueStorage` so the queue can
+//! live either in RAM (`RamQueueStorage`) or on raw NOR flash (`FlashQueueStorage`).
+
+pub mod flash_storage;
+pub mod ram_storage;
+
+use core::convert::Infallible;
+use core::fmt::{Debug, Display, Formatter};
+
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+use rand_core::RngCore;
+
+use crate::client::client::MqttClient;
+use crate::client::client_error::ClientError;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::QualityOfService;
+
+pub use flash_storage::FlashQueueStorage;
+pub use ram_storage::RamQueueStorage;
+
+/// Errors reported by the queue and by the `QueueStorage` implementations. Generic `C` is
+/// the network driver error type of the client used for publishing.
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QueueError<E, C = Infallible> {
+    /// Storage has no space left for the record and the overflow policy rejects it.
+    Full,
+    /// Record is larger than the storage could ever hold.
+    TooLarge,
+    /// Provided buffer is too small for the record at the front of the queue.
+    BufferTooSmall,
+    /// Stored record could not be read back (checksum or header mismatch).
+    Corrupted,
+    /// Storage region is misaligned, too small or not supported by the medium.
+    InvalidRegion,
+    /// Error reported by the underlying storage medium.
+    Storage(E),
+    /// Client failed to publish a queued message.
+    Client(ClientError<C>),
+}
+
+impl<E> QueueError<E> {
+    fn widen<C>(self) -> QueueError<E, C> {
+        match self {
+            QueueError::Full => QueueError::Full,
+            QueueError::TooLarge => QueueError::TooLarge,
+            QueueError::BufferTooSmall => QueueError::BufferTooSmall,
+            QueueError::Corrupted => QueueError::Corrupted,
+            QueueError::InvalidRegion => QueueError::InvalidRegion,
+            QueueError::Storage(err) => QueueError::Storage(err),
+            QueueError::Client(err) => QueueError::Client(err.map_transport(|e| match e {})),
+        }
+    }
+}
+
+impl<E, C: Debug> Display for QueueError<E, C> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            QueueError::Full => write!(f, "Queue is full!"),
+            QueueError::TooLarge => write!(f, "Message is too large for the queue storage!"),
+            QueueError::BufferTooSmall => write!(f, "Buffer is too small for queued message!"),
+            QueueError::Corrupted => write!(f, "Queued message is corrupted!"),
+            QueueError::InvalidRegion => write!(f, "Queue storage region is not valid!"),
+            QueueError::Storage(_) => write!(f, "Queue storage error!"),
+            QueueError::Client(err) => write!(f, "Could not publish queued message: {}", err),
+        }
+    }
+}
+
+/// Storage backend of the `OutboundQueue`. Storage keeps opaque records in FIFO order.
+pub trait QueueStorage {
+    type Error: core::fmt::Debug;
+
+    /// Returns number of records stored.
+    fn len(&self) -> usize;
+
+    /// Returns true if there is no record stored.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends a record at the back of the queue. The record is the concatenation of all
+    /// `parts`. Returns `QueueError::Full` when there is currently not enough space and
+    /// `QueueError::TooLarge` when the record could not fit even into the empty storage.
+    fn push(&mut self, parts: &[&[u8]]) -> Result<(), QueueError<Self::Error>>;
+
+    /// Copies the record from the front of the queue into `buffer` and returns its length.
+    fn peek(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, QueueError<Self::Error>>;
+
+    /// Removes the record from the front of the queue.
+    fn pop(&mut self) -> Result<(), QueueError<Self::Error>>;
+}
+
+/// Decides what happens when the storage is full and new message is enqueued.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OverflowPolicy {
+    /// Oldest messages are dropped until the new one fits.
+    DropOldest,
+    /// New message is rejected with `QueueError::Full`.
+    RejectNewest,
+}
+
+/// Message which should be published through the `OutboundQueue`.
+#[derive(Debug, Clone)]
+pub struct QueuedMessage<'a> {
+    pub topic: &'a str,
+    pub payload: &'a [u8],
+    pub qos: QualityOfService,
+    pub retain: bool,
+    /// `MessageExpiryInterval` in seconds. Message is not sent when it spends more time
+    /// in the queue and the interval sent to the broker is reduced by the time spent waiting.
+    pub message_expiry_interval: Option<u32>,
+}
+
+impl<'a> QueuedMessage<'a> {
+    pub fn new(topic: &'a str, payload: &'a [u8], qos: QualityOfService, retain: bool) -> Self {
+        Self {
+            topic,
+            payload,
+            qos,
+            retain,
+            message_expiry_interval: None,
+        }
+    }
+
+    pub fn add_message_expiry_interval(&mut self, interval: u32) {
+        self.message_expiry_interval = Some(interval);
+    }
+
+    /// Returns remaining expiry interval of the message enqueued at `enqueued_at`
+    /// or `None` if the message already expired.
+    fn remaining_expiry(&self, enqueued_at: u32, now: u32) -> Option<Option<u32>> {
+        match self.message_expiry_interval {
+            None => Some(None),
+            Some(interval) => {
+                let waited = now.wrapping_sub(enqueued_at);
+                if waited >= interval {
+                    None
+                } else {
+                    Some(Some(interval - waited))
+                }
+            }
+        }
+    }
+}
+
+/// Result of the `OutboundQueue::publish`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PublishOutcome {
+    /// Message was handed over to the broker.
+    Sent,
+    /// Message was stored and will be sent by the next `drain`.
+    Queued,
+}
+
+const FLAG_QOS_MASK: u8 = 0x03;
+const FLAG_RETAIN: u8 = 0x04;
+const FLAG_EXPIRY: u8 = 0x08;
+/// enqueued_at (4) + expiry interval (4) + flags (1) + topic len (2)
+const RECORD_HEADER_LEN: usize = 11;
+
+/// Bounded queue of outbound messages. Generic constant `MAX_RECORD` sets the size of the
+/// internal buffer used to read a message back from the storage, so it limits the size
+/// of the topic and payload of a queued message (`topic.len() + payload.len() + 11`).
+///
+/// Time is passed to the queue as `now` in seconds from an arbitrary monotonic epoch.
+/// When the queue is stored in flash and should survive a reboot, the epoch has to survive
+/// the reboot as well (e.g. RTC time).
+pub struct OutboundQueue<S: QueueStorage, const MAX_RECORD: usize> {
+    storage: S,
+    policy: OverflowPolicy,
+    buffer: [u8; MAX_RECORD],
+    dropped: u32,
+    // Message at the head was already sent once, but the send failed
+    resending: bool,
+}
+
+impl<S: QueueStorage, const MAX_RECORD: usize> OutboundQueue<S, MAX_RECORD> {
+    pub fn new(storage: S, policy: OverflowPolicy) -> Self {
+        Self {
+            storage,
+            policy,
+            buffer: [0; MAX_RECORD],
+            dropped: 0,
+            resending: false,
+        }
+    }
+
+    /// Returns number of queued messages.
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty()
+    }
+
+    /// Returns number of messages dropped because of the overflow policy, expiry or corruption.
+    pub fn dropped(&self) -> u32 {
+        self.dropped
+    }
+
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Consumes the queue and returns the storage.
+    pub fn release(self) -> S {
+        self.storage
+    }
+
+    /// Method stores the message at the back of the queue. If the storage is full,
+    /// behaviour depends on the `OverflowPolicy`.
+    pub fn enqueue(
+        &mut self,
+        message: &QueuedMessage<'_>,
+        now: u32,
+    ) -> Result<(), QueueError<S::Error>> {
+        let record_len = RECORD_HEADER_LEN + message.topic.len() + message.payload.len();
+        if record_len > MAX_RECORD || message.topic.len() > u16::MAX as usize {
+            return Err(QueueError::TooLarge);
+        }
+
+        let mut flags = (<QualityOfService as Into<u8>>::into(message.qos) >> 1) & FLAG_QOS_MASK;
+        if message.retain {
+            flags |= FLAG_RETAIN;
+        }
+        let interval = match message.message_expiry_interval {
+            Some(interval) => {
+                flags |= FLAG_EXPIRY;
+                interval
+            }
+            None => 0,
+        };
+
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        header[0..4].copy_from_slice(&now.to_be_bytes());
+        header[4..8].copy_from_slice(&interval.to_be_bytes());
+        header[8] = flags;
+        header[9..11].copy_from_slice(&(message.topic.len() as u16).to_be_bytes());
+        let parts: [&[u8]; 3] = [&header, message.topic.as_bytes(), message.payload];
+
+        loop {
+            match self.storage.push(&parts) {
+                Err(QueueError::Full)
+                    if self.policy == OverflowPolicy::DropOldest && !self.storage.is_empty() =>
+                {
+                    warn!("Outbound queue is full, dropping oldest message");
+                    self.storage.pop()?;
+                    self.dropped += 1;
+                }
+                Err(QueueError::Full) if self.storage.is_empty() => {
+                    return Err(QueueError::TooLarge);
+                }
+                res => return res,
+            }
+        }
+    }
+
+    /// Method publishes the message through the `client`. If there are already queued
+    /// messages, the new message is appended to the queue and the queue is drained so the
+    /// order of messages is kept. If the client is not able to send the message because
+    /// of network error, message is stored in the queue.
+    pub async fn publish<'a, T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+        message: &QueuedMessage<'_>,
+        now: u32,
+    ) -> Result<PublishOutcome, QueueError<S::Error, T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        if !self.storage.is_empty() {
+            self.enqueue(message, now).map_err(QueueError::widen)?;
+            return match self.drain(client, now).await {
+                Ok(_) if self.storage.is_empty() => Ok(PublishOutcome::Sent),
+                Ok(_) => Ok(PublishOutcome::Queued),
+                Err(QueueError::Client(err)) if err.is_connection_error() => {
+                    Ok(PublishOutcome::Queued)
+                }
+                Err(err) => Err(err),
+            };
+        }
+
+        match send(client, message, message.message_expiry_interval).await {
+            Ok(()) => Ok(PublishOutcome::Sent),
+            Err(err) if err.is_connection_error() => {
+                self.enqueue(message, now).map_err(QueueError::widen)?;
+                // Queue was empty, the message is at the head
+                self.resending = was_sent(&err);
+                Ok(PublishOutcome::Queued)
+            }
+            Err(err) => Err(QueueError::Client(err)),
+        }
+    }
+
+    /// Method sends queued messages in order through the `client` until the queue is empty.
+    /// Expired and corrupted messages and messages larger than `MAX_RECORD` are dropped. Method returns the number of sent messages.
+    /// Messages sent again after a failed attempt are counted as retransmissions in the
+    /// `ClientStats` of the client.
+    ///
+    /// If the client reports network error, draining stops and the message stays in the
+    /// queue. Any other error means that the broker refused the message, so the message
+    /// is removed from the queue and the error is returned.
+    pub async fn drain<'a, T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+        now: u32,
+    ) -> Result<usize, QueueError<S::Error, T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        let mut sent = 0;
+        loop {
+            let record = match self.storage.peek(&mut self.buffer) {
+                Ok(Some(len)) => decode_record::<S::Error, T::Error>(&self.buffer[..len]),
+                Ok(None) => break,
+                Err(QueueError::Corrupted) => Err(QueueError::Corrupted),
+                // Written with a larger `MAX_RECORD`, it would block the queue forever
+                Err(QueueError::BufferTooSmall) => {
+                    error!("Queued message is larger than the buffer, dropping it");
+                    self.storage.pop().map_err(QueueError::widen)?;
+                    self.dropped += 1;
+                    self.resending = false;
+                    continue;
+                }
+                Err(err) => return Err(err.widen()),
+            };
+            let (message, enqueued_at) = match record {
+                Ok(record) => record,
+                Err(_) => {
+                    error!("Queued message is corrupted, dropping it");
+                    self.storage.pop().map_err(QueueError::widen)?;
+                    self.dropped += 1;
+                    self.resending = false;
+                    continue;
+                }
+            };
+            let expiry = match message.remaining_expiry(enqueued_at, now) {
+                Some(expiry) => expiry,
+                None => {
+                    debug!("Queued message to {} expired", message.topic);
+                    self.storage.pop().map_err(QueueError::widen)?;
+                    self.dropped += 1;
+                    self.resending = false;
+                    continue;
+                }
+            };
+
+            match send(client, &message, expiry).await {
+                Ok(()) => {
+                    if let (true, Some(stats)) = (self.resending, client.stats()) {
+                        stats.record_retransmission();
+                    }
+                    self.storage.pop().map_err(QueueError::widen)?;
+                    self.resending = false;
+                    sent += 1;
+                }
+                Err(err) if err.is_connection_error() => {
+                    self.resending |= was_sent(&err);
+                    return Err(QueueError::Client(err));
+                }
+                Err(err) => {
+                    error!("Queued message was rejected, dropping it");
+                    self.storage.pop().map_err(QueueError::widen)?;
+                    self.dropped += 1;
+                    self.resending = false;
+                    return Err(QueueError::Client(err));
+                }
+            }
+        }
+        Ok(sent)
+    }
+}
+
+async fn send<'a, T, const MAX_PROPERTIES: usize, R>(
+    client: &mut MqttClient<'a, T, MAX_PROPERTIES, R>,
+    message: &QueuedMessage<'_>,
+    expiry: Option<u32>,
+) -> Result<(), ClientError<T::Error>>
+where
+    T: Read + Write,
+    R: RngCore,
+{
+    let mut properties = Vec::<Property, 1>::new();
+    if let Some(expiry) = expiry {
+        let _ = properties.push(Property::MessageExpiryInterval(expiry));
+    }
+    client
+        .send_message_with_properties(
+            message.topic,
+            message.payload,
+            message.qos,
+            message.retain,
+            &properties,
+        )
+        .await
+}
+
+/// Returns true if the message failed with `err` could have been written to the transport.
+fn was_sent<E>(err: &ClientError<E>) -> bool {
+    matches!(err, ClientError::Transport(_) | ClientError::Timeout)
+}
+
+fn decode_record<E, C>(record: &[u8]) -> Result<(QueuedMessage<'_>, u32), QueueError<E, C>> {
+    if record.len() < RECORD_HEADER_LEN {
+        return Err(QueueError::Corrupted);
+    }
+    let enqueued_at = u32::from_be_bytes([record[0], record[1], record[2], record[3]]);
+    let interval = u32::from_be_bytes([record[4], record[5], record[6], record[7]]);
+    let flags = record[8];
+    let topic_len = u16::from_be_bytes([record[9], record[10]]) as usize;
+    if RECORD_HEADER_LEN + topic_len > record.len() {
+        return Err(QueueError::Corrupted);
+    }
+    let topic = core::str::from_utf8(&record[RECORD_HEADER_LEN..RECORD_HEADER_LEN + topic_len])
+        .map_err(|_| QueueError::Corrupted)?;
+    let message = QueuedMessage {
+        topic,
+        payload: &record[RECORD_HEADER_LEN + topic_len..],
+        qos: QualityOfService::from((flags & FLAG_QOS_MASK) << 1),
+        retain: flags & FLAG_RETAIN != 0,
+        message_expiry_interval: if flags & FLAG_EXPIRY != 0 {
+            Some(interval)
+        } else {
+            None
+        },
+    };
+    Ok((message, enqueued_at))
+}
@@ -0,0 +1,135 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use super::{QueueError, QueueStorage};
+
+/// Length prefix stored in front of every record.
+const LEN_PREFIX: usize = 2;
+
+/// `QueueStorage` implementation keeping the records in a RAM ring buffer of `N` Bytes.
+/// Every record occupies its length plus 2 Bytes of length prefix.
+pub struct RamQueueStorage<const N: usize> {
+    buffer: [u8; N],
+    head: usize,
+    used: usize,
+    count: usize,
+}
+
+impl<const N: usize> RamQueueStorage<N> {
+    pub fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            head: 0,
+            used: 0,
+            count: 0,
+        }
+    }
+
+    /// Returns number of free Bytes in the ring buffer.
+    pub fn free(&self) -> usize {
+        N - self.used
+    }
+
+    fn write_at(&mut self, position: usize, data: &[u8]) {
+        let position = position % N;
+        let first = core::cmp::min(data.len(), N - position);
+        self.buffer[position..position + first].copy_from_slice(&data[..first]);
+        self.buffer[..data.len() - first].copy_from_slice(&data[first..]);
+    }
+
+    fn read_at(&self, position: usize, data: &mut [u8]) {
+        let position = position % N;
+        let first = core::cmp::min(data.len(), N - position);
+        data[..first].copy_from_slice(&self.buffer[position..position + first]);
+        let len = data.len();
+        data[first..].copy_from_slice(&self.buffer[..len - first]);
+    }
+
+    fn front_len(&self) -> usize {
+        let mut prefix = [0u8; LEN_PREFIX];
+        self.read_at(self.head, &mut prefix);
+        u16::from_be_bytes(prefix) as usize
+    }
+}
+
+impl<const N: usize> Default for RamQueueStorage<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> QueueStorage for RamQueueStorage<N> {
+    type Error = core::convert::Infallible;
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn push(&mut self, parts: &[&[u8]]) -> Result<(), QueueError<Self::Error>> {
+        let len: usize = parts.iter().map(|part| part.len()).sum();
+        if len > u16::MAX as usize || len + LEN_PREFIX > N {
+            return Err(QueueError::TooLarge);
+        }
+        if len + LEN_PREFIX > self.free() {
+            return Err(QueueError::Full);
+        }
+
+        let mut position = self.head + self.used;
+        self.write_at(position, &(len as u16).to_be_bytes());
+        position += LEN_PREFIX;
+        for part in parts {
+            self.write_at(position, part);
+            position += part.len();
+        }
+        self.used += len + LEN_PREFIX;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn peek(&mut self, buffer: &mut [u8]) -> Result<Option<usize>, QueueError<Self::Error>> {
+        if self.count == 0 {
+            return Ok(None);
+        }
+        let len = self.front_len();
+        if len > buffer.len() {
+            return Err(QueueError::BufferTooSmall);
+        }
+        self.read_at(self.head + LEN_PREFIX, &mut buffer[..len]);
+        Ok(Some(len))
+    }
+
+    fn pop(&mut self) -> Result<(), QueueError<Self::Error>> {
+        if self.count == 0 {
+            return Ok(());
+        }
+        let len = self.front_len() + LEN_PREFIX;
+        self.head = (self.head + len) % N;
+        self.used -= len;
+        self.count -= 1;
+        if self.count == 0 {
+            self.head = 0;
+        }
+        Ok(())
+    }
+}
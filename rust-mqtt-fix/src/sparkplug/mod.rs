@@ -0,0 +1,413 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Eclipse Sparkplug B edge node on top of `MqttClient`. Available with the `sparkplug`
+//! feature, no allocator is needed.
+//!
+//! `EdgeNode` keeps the session state of the node: the birth/death sequence number `bdSeq`,
+//! incremented for every MQTT connection, and the message sequence number `seq`, which
+//! starts at 0 with NBIRTH and wraps after 255. Every connection starts with
+//! `EdgeNode::death_certificate`, which registers NDEATH with the new `bdSeq` as the will
+//! of a new `ClientConfig`, followed by `publish_birth` and `publish_device_birth` for every
+//! device once the client is connected. Metrics are announced in the birth messages with the
+//! name, the alias and the data type, data messages carry only the alias of aliased metrics.
+//! NCMD and DCMD addressed to the node are returned by `EdgeNode::command`.
+
+pub mod payload;
+pub mod topic;
+
+use core::fmt::{Display, Formatter, Write as _};
+
+use embedded_io_async::{Read, Write};
+use heapless::{String, Vec};
+use rand_core::RngCore;
+
+use crate::client::client::MqttClient;
+use crate::client::client_config::ClientConfig;
+use crate::client::client_error::ClientError;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::clock::Clock;
+use crate::utils::types::BufferError;
+
+use self::payload::{Metric, MetricValue, Payload, PayloadWriter, BD_SEQ_METRIC};
+use self::topic::{is_valid_id, MessageType, Topic, MAX_TOPIC_LEN, NAMESPACE};
+
+/// Size of the NDEATH payload buffer, enough for the `bdSeq` metric.
+pub const DEATH_PAYLOAD_LEN: usize = 24;
+
+/// Error of the Sparkplug topic or payload handling.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SparkplugError {
+    /// Payload or topic does not fit into the buffer.
+    BufferTooSmall,
+    /// Group, edge node or device id is empty or contains `/`, `+` or `#`.
+    InvalidId,
+    /// Topic is not a valid Sparkplug B topic.
+    InvalidTopic,
+    /// Payload is not a valid Sparkplug B protobuf message.
+    Malformed,
+    /// Metric value can not be encoded.
+    Unsupported,
+}
+
+impl Display for SparkplugError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SparkplugError::BufferTooSmall => {
+                write!(f, "Sparkplug payload does not fit into buffer!")
+            }
+            SparkplugError::InvalidId => write!(f, "Sparkplug id is not valid!"),
+            SparkplugError::InvalidTopic => write!(f, "Topic is not a Sparkplug B topic!"),
+            SparkplugError::Malformed => write!(f, "Sparkplug payload is malformed!"),
+            SparkplugError::Unsupported => write!(f, "Metric value is not supported!"),
+        }
+    }
+}
+
+impl From<SparkplugError> for BufferError {
+    fn from(err: SparkplugError) -> Self {
+        match err {
+            SparkplugError::BufferTooSmall => BufferError::InsufficientBufferSize,
+            SparkplugError::InvalidId
+            | SparkplugError::InvalidTopic
+            | SparkplugError::Unsupported => BufferError::EncodingError,
+            SparkplugError::Malformed => BufferError::DecodingError,
+        }
+    }
+}
+
+/// NDEATH message of one connection, registered as the will by `configure_will`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeDeath {
+    topic: String<MAX_TOPIC_LEN>,
+    payload: [u8; DEATH_PAYLOAD_LEN],
+    len: usize,
+    bd_seq: u8,
+}
+
+impl NodeDeath {
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.payload[..self.len]
+    }
+
+    pub fn bd_seq(&self) -> u8 {
+        self.bd_seq
+    }
+
+    /// Sets the NDEATH as the will message of the `config` with QoS 1 required by Sparkplug.
+    pub fn configure_will<'a, const MAX_PROPERTIES: usize, R: RngCore>(
+        &'a self,
+        config: &mut ClientConfig<'a, MAX_PROPERTIES, R>,
+    ) {
+        config.add_will(self.topic.as_str(), self.payload(), false);
+        config.add_will_qos(QualityOfService::QoS1);
+    }
+}
+
+/// Command sent by the host application to the edge node (NCMD) or to its device (DCMD).
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Command<'b> {
+    /// Device of the DCMD, `None` for NCMD.
+    pub device_id: Option<&'b str>,
+    pub payload: Payload<'b>,
+}
+
+/// Session state of the Sparkplug edge node.
+pub struct EdgeNode<'a> {
+    group_id: &'a str,
+    edge_node_id: &'a str,
+    clock: &'a (dyn Clock + Sync),
+    bd_seq: u8,
+    next_bd_seq: u8,
+    seq: u8,
+}
+
+impl<'a> EdgeNode<'a> {
+    /// Creates the edge node, `clock` has to return the UNIX time, it is used for the
+    /// payload timestamps.
+    pub fn new(
+        group_id: &'a str,
+        edge_node_id: &'a str,
+        clock: &'a (dyn Clock + Sync),
+    ) -> Result<Self, SparkplugError> {
+        if !is_valid_id(group_id) || !is_valid_id(edge_node_id) {
+            return Err(SparkplugError::InvalidId);
+        }
+        Ok(Self {
+            group_id,
+            edge_node_id,
+            clock,
+            bd_seq: 0,
+            next_bd_seq: 0,
+            seq: 0,
+        })
+    }
+
+    /// Sets the `bdSeq` of the next connection, e.g. restored from the flash after reboot.
+    pub fn set_bd_seq(&mut self, bd_seq: u8) {
+        self.next_bd_seq = bd_seq;
+    }
+
+    /// `bdSeq` of the current connection.
+    pub fn bd_seq(&self) -> u8 {
+        self.bd_seq
+    }
+
+    /// Sequence number of the next message.
+    pub fn seq(&self) -> u8 {
+        self.seq
+    }
+
+    /// Starts a new connection: takes the next `bdSeq` and returns the NDEATH to be
+    /// registered as the will of the client connecting next. The will of a connected client
+    /// can not be changed, so every connection needs a new `MqttClient` with the new
+    /// `ClientConfig`.
+    pub fn death_certificate(&mut self) -> Result<NodeDeath, SparkplugError> {
+        self.bd_seq = self.next_bd_seq;
+        self.next_bd_seq = self.next_bd_seq.wrapping_add(1);
+        let mut death = NodeDeath {
+            topic: self.topic(MessageType::NDeath, None)?,
+            payload: [0; DEATH_PAYLOAD_LEN],
+            len: 0,
+            bd_seq: self.bd_seq,
+        };
+        let mut writer = PayloadWriter::new(&mut death.payload, None, None)?;
+        writer.add_metric(&self.bd_seq_metric())?;
+        death.len = writer.finish();
+        Ok(death)
+    }
+
+    /// Publishes NBIRTH with the `bdSeq` metric and the `metrics`, resets the sequence
+    /// number. It is also the answer to the rebirth request, followed by the device births.
+    pub async fn publish_birth<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        self.seq = 0;
+        self.publish(client, MessageType::NBirth, None, metrics, buffer)
+            .await
+    }
+
+    /// Publishes NDATA with the `metrics`.
+    pub async fn publish_data<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        self.publish(client, MessageType::NData, None, metrics, buffer)
+            .await
+    }
+
+    /// Publishes DBIRTH of the device with the `metrics`.
+    pub async fn publish_device_birth<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        device_id: &str,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        self.publish(
+            client,
+            MessageType::DBirth,
+            Some(device_id),
+            metrics,
+            buffer,
+        )
+        .await
+    }
+
+    /// Publishes DDATA of the device with the `metrics`.
+    pub async fn publish_device_data<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        device_id: &str,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        self.publish(client, MessageType::DData, Some(device_id), metrics, buffer)
+            .await
+    }
+
+    /// Publishes DDEATH of the device, e.g. when the device stops responding.
+    pub async fn publish_device_death<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        device_id: &str,
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        self.publish(client, MessageType::DDeath, Some(device_id), &[], buffer)
+            .await
+    }
+
+    /// Subscribes the `client` to NCMD of the node and DCMD of all its devices.
+    pub async fn subscribe_commands<T, const MAX_PROPERTIES: usize, R>(
+        &self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        let node_commands = self
+            .topic(MessageType::NCmd, None)
+            .map_err(|err| ClientError::Encode(err.into()))?;
+        let mut device_commands = String::<MAX_TOPIC_LEN>::new();
+        write!(
+            device_commands,
+            "{}/{}/{}/{}/+",
+            NAMESPACE,
+            self.group_id,
+            MessageType::DCmd.name(),
+            self.edge_node_id
+        )
+        .map_err(|_| ClientError::Encode(BufferError::InsufficientBufferSize))?;
+        let mut topics = Vec::<&str, 2>::new();
+        let _ = topics.push(&node_commands);
+        let _ = topics.push(&device_commands);
+        client.subscribe_to_topics(&topics).await
+    }
+
+    /// Returns the command if the message is NCMD or DCMD addressed to this node, other
+    /// messages return `Ok(None)`.
+    pub fn command<'b>(
+        &self,
+        topic: &'b str,
+        payload: &'b [u8],
+    ) -> Result<Option<Command<'b>>, SparkplugError> {
+        let Ok(topic) = Topic::parse(topic) else {
+            return Ok(None);
+        };
+        if topic.group_id != self.group_id
+            || topic.edge_node_id != self.edge_node_id
+            || !matches!(topic.message_type, MessageType::NCmd | MessageType::DCmd)
+        {
+            return Ok(None);
+        }
+        Ok(Some(Command {
+            device_id: topic.device_id,
+            payload: Payload::decode(payload)?,
+        }))
+    }
+
+    fn topic(
+        &self,
+        message_type: MessageType,
+        device_id: Option<&str>,
+    ) -> Result<String<MAX_TOPIC_LEN>, SparkplugError> {
+        Topic {
+            group_id: self.group_id,
+            message_type,
+            edge_node_id: self.edge_node_id,
+            device_id,
+        }
+        .write()
+    }
+
+    fn bd_seq_metric(&self) -> Metric<'static> {
+        Metric::new(BD_SEQ_METRIC, MetricValue::Int64(self.bd_seq.into()))
+    }
+
+    async fn publish<T, const MAX_PROPERTIES: usize, R>(
+        &mut self,
+        client: &mut MqttClient<'_, T, MAX_PROPERTIES, R>,
+        message_type: MessageType,
+        device_id: Option<&str>,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<(), ClientError<T::Error>>
+    where
+        T: Read + Write,
+        R: RngCore,
+    {
+        let topic = self
+            .topic(message_type, device_id)
+            .map_err(|err| ClientError::Encode(err.into()))?;
+        let len = self
+            .write_payload(message_type, metrics, buffer)
+            .map_err(|err| ClientError::Encode(err.into()))?;
+        self.seq = self.seq.wrapping_add(1);
+        debug!("Publishing Sparkplug {}", message_type.name());
+        client
+            .send_message(&topic, &buffer[..len], QualityOfService::QoS0, false)
+            .await
+    }
+
+    fn write_payload(
+        &self,
+        message_type: MessageType,
+        metrics: &[Metric<'_>],
+        buffer: &mut [u8],
+    ) -> Result<usize, SparkplugError> {
+        let timestamp = self.clock.now_micros() / 1000;
+        let mut writer = PayloadWriter::new(buffer, Some(timestamp), Some(self.seq.into()))?;
+        match message_type {
+            MessageType::NBirth => {
+                writer.add_metric(&self.bd_seq_metric())?;
+                for metric in metrics {
+                    writer.add_metric(metric)?;
+                }
+            }
+            MessageType::DBirth => {
+                for metric in metrics {
+                    writer.add_metric(metric)?;
+                }
+            }
+            _ => {
+                for metric in metrics {
+                    writer.add_data_metric(metric)?;
+                }
+            }
+        }
+        Ok(writer.finish())
+    }
+}
@@ -0,0 +1,700 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Sparkplug B payload, protobuf encoding of the `Payload` and `Metric` messages from
+//! `sparkplug_b.proto` without an allocator. `PayloadWriter` writes the payload into a caller
+//! provided buffer and `Payload::decode` reads the payload in place, metrics are iterated
+//! directly from the received bytes. Data sets, templates, metadata and property sets are
+//! not supported, such metrics are decoded as `MetricValue::Unsupported`.
+
+use core::str;
+
+use super::SparkplugError;
+
+/// Name of the birth/death sequence metric in NBIRTH and NDEATH.
+pub const BD_SEQ_METRIC: &str = "bdSeq";
+/// Name of the metric the host application sets to `true` in NCMD to request a rebirth.
+pub const REBIRTH_METRIC: &str = "Node Control/Rebirth";
+
+// Field numbers of the Payload message.
+const PAYLOAD_TIMESTAMP: u32 = 1;
+const PAYLOAD_METRICS: u32 = 2;
+const PAYLOAD_SEQ: u32 = 3;
+const PAYLOAD_UUID: u32 = 4;
+const PAYLOAD_BODY: u32 = 5;
+
+// Field numbers of the Metric message.
+const METRIC_NAME: u32 = 1;
+const METRIC_ALIAS: u32 = 2;
+const METRIC_TIMESTAMP: u32 = 3;
+const METRIC_DATATYPE: u32 = 4;
+const METRIC_IS_NULL: u32 = 7;
+const METRIC_INT_VALUE: u32 = 10;
+const METRIC_LONG_VALUE: u32 = 11;
+const METRIC_FLOAT_VALUE: u32 = 12;
+const METRIC_DOUBLE_VALUE: u32 = 13;
+const METRIC_BOOLEAN_VALUE: u32 = 14;
+const METRIC_STRING_VALUE: u32 = 15;
+const METRIC_BYTES_VALUE: u32 = 16;
+const METRIC_EXTENSION_VALUE: u32 = 19;
+
+const WIRE_VARINT: u8 = 0;
+const WIRE_FIXED64: u8 = 1;
+const WIRE_LEN: u8 = 2;
+const WIRE_FIXED32: u8 = 5;
+
+/// Sparkplug B data type of the metric.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DataType {
+    Unknown = 0,
+    Int8 = 1,
+    Int16 = 2,
+    Int32 = 3,
+    Int64 = 4,
+    UInt8 = 5,
+    UInt16 = 6,
+    UInt32 = 7,
+    UInt64 = 8,
+    Float = 9,
+    Double = 10,
+    Boolean = 11,
+    String = 12,
+    DateTime = 13,
+    Text = 14,
+    Uuid = 15,
+    DataSet = 16,
+    Bytes = 17,
+    File = 18,
+    Template = 19,
+}
+
+impl DataType {
+    const ALL: [DataType; 20] = [
+        DataType::Unknown,
+        DataType::Int8,
+        DataType::Int16,
+        DataType::Int32,
+        DataType::Int64,
+        DataType::UInt8,
+        DataType::UInt16,
+        DataType::UInt32,
+        DataType::UInt64,
+        DataType::Float,
+        DataType::Double,
+        DataType::Boolean,
+        DataType::String,
+        DataType::DateTime,
+        DataType::Text,
+        DataType::Uuid,
+        DataType::DataSet,
+        DataType::Bytes,
+        DataType::File,
+        DataType::Template,
+    ];
+
+    /// Returns the data type with the protobuf value `value`, unknown values map to
+    /// `DataType::Unknown`.
+    pub fn from_u32(value: u32) -> Self {
+        Self::ALL
+            .get(value as usize)
+            .copied()
+            .unwrap_or(DataType::Unknown)
+    }
+}
+
+/// Value of the metric. Signed integers up to 32 bits are sent as the two's complement in
+/// the `int_value` field, 64 bit integers and `DateTime` (milliseconds since the UNIX epoch)
+/// in the `long_value` field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MetricValue<'a> {
+    Int8(i8),
+    Int16(i16),
+    Int32(i32),
+    Int64(i64),
+    UInt8(u8),
+    UInt16(u16),
+    UInt32(u32),
+    UInt64(u64),
+    Float(f32),
+    Double(f64),
+    Boolean(bool),
+    String(&'a str),
+    DateTime(u64),
+    Text(&'a str),
+    Uuid(&'a str),
+    Bytes(&'a [u8]),
+    /// Metric without value (`is_null`) of the data type.
+    Null(DataType),
+    /// Metric of the data type not supported by the decoder.
+    Unsupported(DataType),
+}
+
+impl<'a> MetricValue<'a> {
+    pub fn datatype(&self) -> DataType {
+        match self {
+            MetricValue::Int8(_) => DataType::Int8,
+            MetricValue::Int16(_) => DataType::Int16,
+            MetricValue::Int32(_) => DataType::Int32,
+            MetricValue::Int64(_) => DataType::Int64,
+            MetricValue::UInt8(_) => DataType::UInt8,
+            MetricValue::UInt16(_) => DataType::UInt16,
+            MetricValue::UInt32(_) => DataType::UInt32,
+            MetricValue::UInt64(_) => DataType::UInt64,
+            MetricValue::Float(_) => DataType::Float,
+            MetricValue::Double(_) => DataType::Double,
+            MetricValue::Boolean(_) => DataType::Boolean,
+            MetricValue::String(_) => DataType::String,
+            MetricValue::DateTime(_) => DataType::DateTime,
+            MetricValue::Text(_) => DataType::Text,
+            MetricValue::Uuid(_) => DataType::Uuid,
+            MetricValue::Bytes(_) => DataType::Bytes,
+            MetricValue::Null(datatype) | MetricValue::Unsupported(datatype) => *datatype,
+        }
+    }
+
+    /// Returns the boolean value.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            MetricValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the integer value of any integer type. Values received without the data type
+    /// are decoded as `UInt32` or `UInt64` and have to be cast to the signed type by the
+    /// caller.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            MetricValue::Int8(value) => Some(value.into()),
+            MetricValue::Int16(value) => Some(value.into()),
+            MetricValue::Int32(value) => Some(value.into()),
+            MetricValue::Int64(value) => Some(value),
+            MetricValue::UInt8(value) => Some(value.into()),
+            MetricValue::UInt16(value) => Some(value.into()),
+            MetricValue::UInt32(value) => Some(value.into()),
+            MetricValue::UInt64(value) | MetricValue::DateTime(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    /// Returns the floating point value.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            MetricValue::Float(value) => Some(value.into()),
+            MetricValue::Double(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of the string types.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match *self {
+            MetricValue::String(value) | MetricValue::Text(value) | MetricValue::Uuid(value) => {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Metric of the payload. Metrics are announced in the birth message with the name and the
+/// alias, data messages and commands carry only the alias when it is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Metric<'a> {
+    pub name: Option<&'a str>,
+    pub alias: Option<u64>,
+    /// Milliseconds since the UNIX epoch.
+    pub timestamp: Option<u64>,
+    pub value: MetricValue<'a>,
+}
+
+impl<'a> Metric<'a> {
+    pub const fn new(name: &'a str, value: MetricValue<'a>) -> Self {
+        Self {
+            name: Some(name),
+            alias: None,
+            timestamp: None,
+            value,
+        }
+    }
+
+    pub const fn with_alias(mut self, alias: u64) -> Self {
+        self.alias = Some(alias);
+        self
+    }
+
+    pub const fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Returns the metric with the `value`, name, alias and timestamp are kept.
+    pub const fn with_value(mut self, value: MetricValue<'a>) -> Self {
+        self.value = value;
+        self
+    }
+
+    /// Returns `true` if the metric has the same alias as the `definition`, or the same name
+    /// when the metric is not aliased. Commands are matched against the birth definitions
+    /// this way.
+    pub fn refers_to(&self, definition: &Metric<'_>) -> bool {
+        match (self.alias, definition.alias) {
+            (Some(alias), Some(defined)) => alias == defined,
+            _ => self.name.is_some() && self.name == definition.name,
+        }
+    }
+}
+
+/// Writer of the Sparkplug payload into the buffer.
+pub struct PayloadWriter<'b> {
+    writer: ProtoWriter<'b>,
+}
+
+impl<'b> PayloadWriter<'b> {
+    /// Starts the payload with the `timestamp` (milliseconds since the UNIX epoch) and the
+    /// sequence number, NDEATH is sent without both.
+    pub fn new(
+        buffer: &'b mut [u8],
+        timestamp: Option<u64>,
+        seq: Option<u64>,
+    ) -> Result<Self, SparkplugError> {
+        let mut writer = ProtoWriter { buffer, len: 0 };
+        if let Some(timestamp) = timestamp {
+            writer.varint_field(PAYLOAD_TIMESTAMP, timestamp)?;
+        }
+        if let Some(seq) = seq {
+            writer.varint_field(PAYLOAD_SEQ, seq)?;
+        }
+        Ok(Self { writer })
+    }
+
+    /// Adds the metric with the name, alias and data type, as required in the birth
+    /// messages.
+    pub fn add_metric(&mut self, metric: &Metric<'_>) -> Result<(), SparkplugError> {
+        self.writer.message(PAYLOAD_METRICS, |writer| {
+            write_metric(writer, metric.name, metric, true)
+        })
+    }
+
+    /// Adds the metric as data: aliased metrics are sent without the name and the data
+    /// type is omitted.
+    pub fn add_data_metric(&mut self, metric: &Metric<'_>) -> Result<(), SparkplugError> {
+        let name = match metric.alias {
+            Some(_) => None,
+            None => metric.name,
+        };
+        self.writer.message(PAYLOAD_METRICS, |writer| {
+            write_metric(writer, name, metric, false)
+        })
+    }
+
+    /// Returns the length of the payload.
+    pub fn finish(self) -> usize {
+        self.writer.len
+    }
+}
+
+fn write_metric(
+    writer: &mut ProtoWriter<'_>,
+    name: Option<&str>,
+    metric: &Metric<'_>,
+    datatype: bool,
+) -> Result<(), SparkplugError> {
+    if let Some(name) = name {
+        writer.bytes_field(METRIC_NAME, name.as_bytes())?;
+    }
+    if let Some(alias) = metric.alias {
+        writer.varint_field(METRIC_ALIAS, alias)?;
+    }
+    if let Some(timestamp) = metric.timestamp {
+        writer.varint_field(METRIC_TIMESTAMP, timestamp)?;
+    }
+    if datatype {
+        writer.varint_field(METRIC_DATATYPE, metric.value.datatype() as u64)?;
+    }
+    match metric.value {
+        MetricValue::Int8(value) => writer.varint_field(METRIC_INT_VALUE, value as u32 as u64),
+        MetricValue::Int16(value) => writer.varint_field(METRIC_INT_VALUE, value as u32 as u64),
+        MetricValue::Int32(value) => writer.varint_field(METRIC_INT_VALUE, value as u32 as u64),
+        MetricValue::UInt8(value) => writer.varint_field(METRIC_INT_VALUE, value.into()),
+        MetricValue::UInt16(value) => writer.varint_field(METRIC_INT_VALUE, value.into()),
+        MetricValue::UInt32(value) => writer.varint_field(METRIC_INT_VALUE, value.into()),
+        MetricValue::Int64(value) => writer.varint_field(METRIC_LONG_VALUE, value as u64),
+        MetricValue::UInt64(value) | MetricValue::DateTime(value) => {
+            writer.varint_field(METRIC_LONG_VALUE, value)
+        }
+        MetricValue::Float(value) => {
+            writer.tag(METRIC_FLOAT_VALUE, WIRE_FIXED32)?;
+            writer.raw(&value.to_le_bytes())
+        }
+        MetricValue::Double(value) => {
+            writer.tag(METRIC_DOUBLE_VALUE, WIRE_FIXED64)?;
+            writer.raw(&value.to_le_bytes())
+        }
+        MetricValue::Boolean(value) => writer.varint_field(METRIC_BOOLEAN_VALUE, value.into()),
+        MetricValue::String(value) | MetricValue::Text(value) | MetricValue::Uuid(value) => {
+            writer.bytes_field(METRIC_STRING_VALUE, value.as_bytes())
+        }
+        MetricValue::Bytes(value) => writer.bytes_field(METRIC_BYTES_VALUE, value),
+        MetricValue::Null(_) => writer.varint_field(METRIC_IS_NULL, 1),
+        MetricValue::Unsupported(_) => Err(SparkplugError::Unsupported),
+    }
+}
+
+/// Sparkplug payload decoded in place.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Payload<'a> {
+    /// Milliseconds since the UNIX epoch.
+    pub timestamp: Option<u64>,
+    pub seq: Option<u64>,
+    pub uuid: Option<&'a str>,
+    pub body: Option<&'a [u8]>,
+    data: &'a [u8],
+}
+
+impl<'a> Payload<'a> {
+    /// Decodes the payload and checks all its metrics, so `metrics` can not fail later.
+    /// Unknown fields are skipped.
+    pub fn decode(data: &'a [u8]) -> Result<Self, SparkplugError> {
+        let mut payload = Payload {
+            timestamp: None,
+            seq: None,
+            uuid: None,
+            body: None,
+            data,
+        };
+        let mut reader = ProtoReader::new(data);
+        while let Some((field, value)) = reader.field()? {
+            match (field, value) {
+                (PAYLOAD_TIMESTAMP, Value::Varint(timestamp)) => {
+                    payload.timestamp = Some(timestamp)
+                }
+                (PAYLOAD_SEQ, Value::Varint(seq)) => payload.seq = Some(seq),
+                (PAYLOAD_UUID, Value::Bytes(uuid)) => {
+                    payload.uuid =
+                        Some(str::from_utf8(uuid).map_err(|_| SparkplugError::Malformed)?)
+                }
+                (PAYLOAD_BODY, Value::Bytes(body)) => payload.body = Some(body),
+                (PAYLOAD_METRICS, Value::Bytes(metric)) => {
+                    decode_metric(metric)?;
+                }
+                (
+                    PAYLOAD_TIMESTAMP | PAYLOAD_SEQ | PAYLOAD_UUID | PAYLOAD_BODY | PAYLOAD_METRICS,
+                    _,
+                ) => return Err(SparkplugError::Malformed),
+                _ => {}
+            }
+        }
+        Ok(payload)
+    }
+
+    /// Iterates over the metrics of the payload.
+    pub fn metrics(&self) -> Metrics<'a> {
+        Metrics {
+            reader: ProtoReader::new(self.data),
+        }
+    }
+
+    /// Returns the metric which `refers_to` the `definition`.
+    pub fn metric(&self, definition: &Metric<'_>) -> Option<Metric<'a>> {
+        self.metrics().find(|metric| metric.refers_to(definition))
+    }
+
+    /// Returns `true` if the payload contains the `Node Control/Rebirth` metric set to
+    /// `true`, sent by the host application in NCMD.
+    pub fn is_rebirth_request(&self) -> bool {
+        self.metrics().any(|metric| {
+            metric.name == Some(REBIRTH_METRIC) && metric.value.as_bool() == Some(true)
+        })
+    }
+}
+
+/// Iterator over the metrics of the decoded `Payload`.
+pub struct Metrics<'a> {
+    reader: ProtoReader<'a>,
+}
+
+impl<'a> Iterator for Metrics<'a> {
+    type Item = Metric<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The payload was checked by `Payload::decode`.
+        while let Ok(Some((field, value))) = self.reader.field() {
+            if let (PAYLOAD_METRICS, Value::Bytes(metric)) = (field, value) {
+                return decode_metric(metric).ok();
+            }
+        }
+        None
+    }
+}
+
+fn decode_metric(data: &[u8]) -> Result<Metric<'_>, SparkplugError> {
+    let mut metric = Metric {
+        name: None,
+        alias: None,
+        timestamp: None,
+        value: MetricValue::Null(DataType::Unknown),
+    };
+    let mut datatype = None;
+    let mut is_null = false;
+    let mut raw_value = None;
+    let mut reader = ProtoReader::new(data);
+    while let Some((field, value)) = reader.field()? {
+        match (field, value) {
+            (METRIC_NAME, Value::Bytes(name)) => {
+                metric.name = Some(str::from_utf8(name).map_err(|_| SparkplugError::Malformed)?)
+            }
+            (METRIC_ALIAS, Value::Varint(alias)) => metric.alias = Some(alias),
+            (METRIC_TIMESTAMP, Value::Varint(timestamp)) => metric.timestamp = Some(timestamp),
+            (METRIC_DATATYPE, Value::Varint(value)) => {
+                datatype = Some(DataType::from_u32(value as u32))
+            }
+            (METRIC_IS_NULL, Value::Varint(value)) => is_null = value != 0,
+            (
+                METRIC_NAME | METRIC_ALIAS | METRIC_TIMESTAMP | METRIC_DATATYPE | METRIC_IS_NULL,
+                _,
+            ) => return Err(SparkplugError::Malformed),
+            (METRIC_INT_VALUE..=METRIC_EXTENSION_VALUE, value) => raw_value = Some((field, value)),
+            _ => {}
+        }
+    }
+    metric.value = match (is_null, raw_value) {
+        (true, _) | (false, None) => MetricValue::Null(datatype.unwrap_or(DataType::Unknown)),
+        (false, Some((field, value))) => decode_value(datatype, field, value)?,
+    };
+    Ok(metric)
+}
+
+fn decode_value(
+    datatype: Option<DataType>,
+    field: u32,
+    value: Value<'_>,
+) -> Result<MetricValue<'_>, SparkplugError> {
+    let string = |bytes| str::from_utf8(bytes).map_err(|_| SparkplugError::Malformed);
+    let value = match (datatype, field, value) {
+        (Some(DataType::Int8), METRIC_INT_VALUE, Value::Varint(v)) => MetricValue::Int8(v as i8),
+        (Some(DataType::Int16), METRIC_INT_VALUE, Value::Varint(v)) => MetricValue::Int16(v as i16),
+        (Some(DataType::Int32), METRIC_INT_VALUE, Value::Varint(v)) => MetricValue::Int32(v as i32),
+        (Some(DataType::UInt8), METRIC_INT_VALUE, Value::Varint(v)) => MetricValue::UInt8(v as u8),
+        (Some(DataType::UInt16), METRIC_INT_VALUE, Value::Varint(v)) => {
+            MetricValue::UInt16(v as u16)
+        }
+        (Some(DataType::UInt32) | None, METRIC_INT_VALUE, Value::Varint(v)) => {
+            MetricValue::UInt32(v as u32)
+        }
+        (Some(DataType::Int64), METRIC_LONG_VALUE, Value::Varint(v)) => {
+            MetricValue::Int64(v as i64)
+        }
+        (Some(DataType::UInt64) | None, METRIC_LONG_VALUE, Value::Varint(v)) => {
+            MetricValue::UInt64(v)
+        }
+        (Some(DataType::DateTime), METRIC_LONG_VALUE, Value::Varint(v)) => MetricValue::DateTime(v),
+        (Some(DataType::Float) | None, METRIC_FLOAT_VALUE, Value::Fixed32(v)) => {
+            MetricValue::Float(f32::from_bits(v))
+        }
+        (Some(DataType::Double) | None, METRIC_DOUBLE_VALUE, Value::Fixed64(v)) => {
+            MetricValue::Double(f64::from_bits(v))
+        }
+        (Some(DataType::Boolean) | None, METRIC_BOOLEAN_VALUE, Value::Varint(v)) => {
+            MetricValue::Boolean(v != 0)
+        }
+        (Some(DataType::String) | None, METRIC_STRING_VALUE, Value::Bytes(v)) => {
+            MetricValue::String(string(v)?)
+        }
+        (Some(DataType::Text), METRIC_STRING_VALUE, Value::Bytes(v)) => {
+            MetricValue::Text(string(v)?)
+        }
+        (Some(DataType::Uuid), METRIC_STRING_VALUE, Value::Bytes(v)) => {
+            MetricValue::Uuid(string(v)?)
+        }
+        (Some(DataType::Bytes) | None, METRIC_BYTES_VALUE, Value::Bytes(v)) => {
+            MetricValue::Bytes(v)
+        }
+        (
+            Some(
+                datatype @ (DataType::Unknown
+                | DataType::DataSet
+                | DataType::File
+                | DataType::Template),
+            ),
+            ..,
+        ) => MetricValue::Unsupported(datatype),
+        _ => return Err(SparkplugError::Malformed),
+    };
+    Ok(value)
+}
+
+/// Protobuf field value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+struct ProtoReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, SparkplugError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self
+                .data
+                .get(self.position)
+                .ok_or(SparkplugError::Malformed)?;
+            self.position += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SparkplugError::Malformed)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SparkplugError> {
+        let end = self
+            .position
+            .checked_add(len)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(SparkplugError::Malformed)?;
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    /// Reads the next field, returns `None` at the end of the data.
+    fn field(&mut self) -> Result<Option<(u32, Value<'a>)>, SparkplugError> {
+        if self.position == self.data.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = u32::try_from(key >> 3).map_err(|_| SparkplugError::Malformed)?;
+        let value = match (key & 0x07) as u8 {
+            WIRE_VARINT => Value::Varint(self.varint()?),
+            WIRE_FIXED64 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                Value::Fixed64(u64::from_le_bytes(bytes))
+            }
+            WIRE_LEN => {
+                let len = usize::try_from(self.varint()?).map_err(|_| SparkplugError::Malformed)?;
+                Value::Bytes(self.take(len)?)
+            }
+            WIRE_FIXED32 => {
+                let mut bytes = [0; 4];
+                bytes.copy_from_slice(self.take(4)?);
+                Value::Fixed32(u32::from_le_bytes(bytes))
+            }
+            _ => return Err(SparkplugError::Malformed),
+        };
+        Ok(Some((field, value)))
+    }
+}
+
+struct ProtoWriter<'b> {
+    buffer: &'b mut [u8],
+    len: usize,
+}
+
+impl<'b> ProtoWriter<'b> {
+    fn raw(&mut self, bytes: &[u8]) -> Result<(), SparkplugError> {
+        let end = self.len + bytes.len();
+        if end > self.buffer.len() {
+            return Err(SparkplugError::BufferTooSmall);
+        }
+        self.buffer[self.len..end].copy_from_slice(bytes);
+        self.len = end;
+        Ok(())
+    }
+
+    fn varint(&mut self, value: u64) -> Result<(), SparkplugError> {
+        let mut encoded = [0; 10];
+        let len = encode_varint(value, &mut encoded);
+        self.raw(&encoded[..len])
+    }
+
+    fn tag(&mut self, field: u32, wire_type: u8) -> Result<(), SparkplugError> {
+        self.varint(u64::from(field) << 3 | u64::from(wire_type))
+    }
+
+    fn varint_field(&mut self, field: u32, value: u64) -> Result<(), SparkplugError> {
+        self.tag(field, WIRE_VARINT)?;
+        self.varint(value)
+    }
+
+    fn bytes_field(&mut self, field: u32, bytes: &[u8]) -> Result<(), SparkplugError> {
+        self.tag(field, WIRE_LEN)?;
+        self.varint(bytes.len() as u64)?;
+        self.raw(bytes)
+    }
+
+    /// Writes the embedded message produced by `write`. One byte is reserved for the length,
+    /// the message is moved when the length needs more bytes.
+    fn message(
+        &mut self,
+        field: u32,
+        write: impl FnOnce(&mut Self) -> Result<(), SparkplugError>,
+    ) -> Result<(), SparkplugError> {
+        self.tag(field, WIRE_LEN)?;
+        let start = self.len;
+        self.raw(&[0])?;
+        write(self)?;
+        let body = start + 1..self.len;
+        let mut encoded = [0; 10];
+        let len_bytes = encode_varint(body.len() as u64, &mut encoded);
+        if self.len + len_bytes - 1 > self.buffer.len() {
+            return Err(SparkplugError::BufferTooSmall);
+        }
+        self.buffer.copy_within(body, start + len_bytes);
+        self.buffer[start..start + len_bytes].copy_from_slice(&encoded[..len_bytes]);
+        self.len += len_bytes - 1;
+        Ok(())
+    }
+}
+
+fn encode_varint(mut value: u64, encoded: &mut [u8; 10]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            encoded[len] = byte;
+            return len + 1;
+        }
+        encoded[len] = byte | 0x80;
+        len += 1;
+    }
+}
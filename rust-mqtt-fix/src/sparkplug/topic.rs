@@ -0,0 +1,187 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Sparkplug B topic namespace `spBv1.0/<group_id>/<message_type>/<edge_node_id>[/<device_id>]`.
+
+use core::fmt::Write as _;
+
+use heapless::String;
+
+use super::SparkplugError;
+
+/// Namespace element of the Sparkplug B topics.
+pub const NAMESPACE: &str = "spBv1.0";
+/// Maximal length of the Sparkplug topic.
+pub const MAX_TOPIC_LEN: usize = 128;
+
+/// Sparkplug message type, the third element of the topic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MessageType {
+    NBirth,
+    NDeath,
+    DBirth,
+    DDeath,
+    NData,
+    DData,
+    NCmd,
+    DCmd,
+}
+
+impl MessageType {
+    const ALL: [MessageType; 8] = [
+        MessageType::NBirth,
+        MessageType::NDeath,
+        MessageType::DBirth,
+        MessageType::DDeath,
+        MessageType::NData,
+        MessageType::DData,
+        MessageType::NCmd,
+        MessageType::DCmd,
+    ];
+
+    /// Name of the message type used in the topic.
+    pub fn name(&self) -> &'static str {
+        match self {
+            MessageType::NBirth => "NBIRTH",
+            MessageType::NDeath => "NDEATH",
+            MessageType::DBirth => "DBIRTH",
+            MessageType::DDeath => "DDEATH",
+            MessageType::NData => "NDATA",
+            MessageType::DData => "DDATA",
+            MessageType::NCmd => "NCMD",
+            MessageType::DCmd => "DCMD",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// Returns `true` for the message types addressed to a device behind the edge node.
+    pub fn is_device_message(&self) -> bool {
+        matches!(
+            self,
+            MessageType::DBirth | MessageType::DDeath | MessageType::DData | MessageType::DCmd
+        )
+    }
+}
+
+/// Parsed or generated Sparkplug topic.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Topic<'a> {
+    pub group_id: &'a str,
+    pub message_type: MessageType,
+    pub edge_node_id: &'a str,
+    pub device_id: Option<&'a str>,
+}
+
+impl<'a> Topic<'a> {
+    /// Topic of the edge node message.
+    pub fn node(group_id: &'a str, message_type: MessageType, edge_node_id: &'a str) -> Self {
+        Self {
+            group_id,
+            message_type,
+            edge_node_id,
+            device_id: None,
+        }
+    }
+
+    /// Topic of the device message.
+    pub fn device(
+        group_id: &'a str,
+        message_type: MessageType,
+        edge_node_id: &'a str,
+        device_id: &'a str,
+    ) -> Self {
+        Self {
+            group_id,
+            message_type,
+            edge_node_id,
+            device_id: Some(device_id),
+        }
+    }
+
+    /// Parses the `topic`. Topics outside the `spBv1.0` namespace, with unknown message type
+    /// or with the device element not matching the message type are rejected.
+    pub fn parse(topic: &'a str) -> Result<Self, SparkplugError> {
+        let mut levels = topic.split('/');
+        if levels.next() != Some(NAMESPACE) {
+            return Err(SparkplugError::InvalidTopic);
+        }
+        let (Some(group_id), Some(message_type), Some(edge_node_id)) =
+            (levels.next(), levels.next(), levels.next())
+        else {
+            return Err(SparkplugError::InvalidTopic);
+        };
+        let message_type =
+            MessageType::from_name(message_type).ok_or(SparkplugError::InvalidTopic)?;
+        let parsed = Self {
+            group_id,
+            message_type,
+            edge_node_id,
+            device_id: levels.next(),
+        };
+        if levels.next().is_some() || parsed.validate().is_err() {
+            return Err(SparkplugError::InvalidTopic);
+        }
+        Ok(parsed)
+    }
+
+    /// Writes the topic, ids which are empty or contain `/`, `+` or `#` are reported as
+    /// `SparkplugError::InvalidId`.
+    pub fn write(&self) -> Result<String<MAX_TOPIC_LEN>, SparkplugError> {
+        self.validate()?;
+        let mut topic = String::new();
+        write!(
+            topic,
+            "{}/{}/{}/{}",
+            NAMESPACE,
+            self.group_id,
+            self.message_type.name(),
+            self.edge_node_id
+        )
+        .map_err(|_| SparkplugError::BufferTooSmall)?;
+        if let Some(device_id) = self.device_id {
+            write!(topic, "/{}", device_id).map_err(|_| SparkplugError::BufferTooSmall)?;
+        }
+        Ok(topic)
+    }
+
+    fn validate(&self) -> Result<(), SparkplugError> {
+        if !is_valid_id(self.group_id)
+            || !is_valid_id(self.edge_node_id)
+            || self.device_id.is_some_and(|id| !is_valid_id(id))
+            || self.message_type.is_device_message() != self.device_id.is_some()
+        {
+            return Err(SparkplugError::InvalidId);
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn is_valid_id(id: &str) -> bool {
+    !id.is_empty() && !id.contains(['/', '+', '#'])
+}
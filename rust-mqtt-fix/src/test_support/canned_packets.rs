@@ -0,0 +1,92 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+//! Packets sent by the `ScriptedBroker`. They are encoded by hand so the broker
+//! does not share the encoders with the client under test.
+
+use std::vec::Vec;
+
+use crate::encoding::variable_byte_integer::VariableByteIntegerEncoder;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::packet::v5::reason_codes::ReasonCode;
+
+/// Prefixes `body` with the fixed header.
+pub fn packet(first_byte: u8, body: &[u8]) -> Vec<u8> {
+    let remaining_len = VariableByteIntegerEncoder::encode(body.len() as u32).unwrap();
+    let mut packet = vec![first_byte];
+    packet.extend_from_slice(&remaining_len[..VariableByteIntegerEncoder::len(remaining_len)]);
+    packet.extend_from_slice(body);
+    packet
+}
+
+/// CONNACK without session present flag and properties.
+pub fn connack(reason: ReasonCode) -> Vec<u8> {
+    packet(0x20, &[0x00, reason.into(), 0x00])
+}
+
+/// CONNACK accepting the connection with the session present flag.
+pub fn connack_session_present() -> Vec<u8> {
+    packet(0x20, &[0x01, ReasonCode::Success.into(), 0x00])
+}
+
+/// SUBACK with one reason code (granted QoS) for every topic filter.
+pub fn suback(identifier: u16, reason_codes: &[u8]) -> Vec<u8> {
+    let mut body = identifier.to_be_bytes().to_vec();
+    body.push(0x00);
+    body.extend_from_slice(reason_codes);
+    packet(0x90, &body)
+}
+
+/// UNSUBACK with one reason code for every topic filter.
+pub fn unsuback(identifier: u16, reason_codes: &[u8]) -> Vec<u8> {
+    let mut body = identifier.to_be_bytes().to_vec();
+    body.push(0x00);
+    body.extend_from_slice(reason_codes);
+    packet(0xB0, &body)
+}
+
+pub fn puback(identifier: u16, reason: ReasonCode) -> Vec<u8> {
+    let [msb, lsb] = identifier.to_be_bytes();
+    packet(0x40, &[msb, lsb, reason.into(), 0x00])
+}
+
+pub fn pingresp() -> Vec<u8> {
+    packet(0xD0, &[])
+}
+
+/// PUBLISH without properties, `identifier` is used only for QoS 1 and QoS 2.
+pub fn publish(topic: &str, payload: &[u8], qos: QualityOfService, identifier: u16) -> Vec<u8> {
+    let mut body = (topic.len() as u16).to_be_bytes().to_vec();
+    body.extend_from_slice(topic.as_bytes());
+    if qos != QualityOfService::QoS0 {
+        body.extend_from_slice(&identifier.to_be_bytes());
+    }
+    body.push(0x00);
+    body.extend_from_slice(payload);
+    packet(0x30 | u8::from(qos), &body)
+}
+
+pub fn disconnect(reason: ReasonCode) -> Vec<u8> {
+    packet(0xE0, &[reason.into(), 0x00])
+}
@@ -0,0 +1,159 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::future::poll_fn;
+use core::task::{Poll, Waker};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+
+/// One direction of the in-memory connection.
+struct Pipe {
+    state: Mutex<PipeState>,
+}
+
+struct PipeState {
+    buffer: VecDeque<u8>,
+    capacity: usize,
+    closed: bool,
+    read_waker: Option<Waker>,
+    write_waker: Option<Waker>,
+}
+
+impl Pipe {
+    fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(PipeState {
+                buffer: VecDeque::with_capacity(capacity),
+                capacity,
+                closed: false,
+                read_waker: None,
+                write_waker: None,
+            }),
+        })
+    }
+
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        if let Some(waker) = state.read_waker.take() {
+            waker.wake();
+        }
+        if let Some(waker) = state.write_waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Creates connected pair of in-memory transports. Bytes written to one end can be
+/// read from the other one, every direction buffers at most `capacity` bytes.
+/// Closing or dropping one end is seen as end of stream (read of 0 bytes) by the other.
+pub fn duplex(capacity: usize) -> (MemoryTransport, MemoryTransport) {
+    let first = Pipe::new(capacity);
+    let second = Pipe::new(capacity);
+    (
+        MemoryTransport {
+            incoming: first.clone(),
+            outgoing: second.clone(),
+        },
+        MemoryTransport {
+            incoming: second,
+            outgoing: first,
+        },
+    )
+}
+
+/// End of the in-memory connection created by `duplex`. Implements the
+/// `embedded_io_async` traits so it can be passed to the client as network driver.
+pub struct MemoryTransport {
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+}
+
+impl MemoryTransport {
+    /// Closes the connection in both directions.
+    pub fn close(&self) {
+        self.incoming.close();
+        self.outgoing.close();
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+impl ErrorType for MemoryTransport {
+    type Error = ErrorKind;
+}
+
+impl Read for MemoryTransport {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.incoming.state.lock().unwrap();
+            if !state.buffer.is_empty() {
+                let len = buf.len().min(state.buffer.len());
+                for (dst, src) in buf.iter_mut().zip(state.buffer.drain(..len)) {
+                    *dst = src;
+                }
+                if let Some(waker) = state.write_waker.take() {
+                    waker.wake();
+                }
+                Poll::Ready(Ok(len))
+            } else if state.closed || buf.is_empty() {
+                Poll::Ready(Ok(0))
+            } else {
+                state.read_waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl Write for MemoryTransport {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        poll_fn(|cx| {
+            let mut state = self.outgoing.state.lock().unwrap();
+            if state.closed {
+                return Poll::Ready(Err(ErrorKind::BrokenPipe));
+            }
+            let free = state.capacity - state.buffer.len();
+            if free == 0 && !buf.is_empty() {
+                state.write_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+            let len = buf.len().min(free);
+            state.buffer.extend(&buf[..len]);
+            if let Some(waker) = state.read_waker.take() {
+                waker.wake();
+            }
+            Poll::Ready(Ok(len))
+        })
+        .await
+    }
+}
@@ -0,0 +1,410 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::{Display, Formatter};
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use std::vec::Vec;
+
+use embedded_io_async::{Read, Write};
+
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::reason_codes::ReasonCode;
+
+use super::canned_packets;
+use super::memory_transport::MemoryTransport;
+
+/// Creates reply for the packet received from the client.
+pub type Reply = Box<dyn FnOnce(&[u8]) -> Vec<u8> + Send>;
+
+/// Step of the broker script.
+pub enum Step {
+    /// Reads one packet from the client and checks its type.
+    Expect(PacketType),
+    /// Same as `Expect` but also replies with the acknowledgement: CONNACK for CONNECT,
+    /// SUBACK granting the requested QoS for SUBSCRIBE, UNSUBACK for UNSUBSCRIBE,
+    /// PUBACK for QoS 1 PUBLISH and PINGRESP for PINGREQ.
+    ExpectAndAck(PacketType),
+    /// Same as `Expect` but replies with the bytes created from the received packet,
+    /// e.g. acknowledgement with a failure reason code.
+    ExpectAndReply(PacketType, Reply),
+    /// Writes the bytes to the client at once. Several packets can be coalesced
+    /// into one write, malformed bytes can be injected as well.
+    Send(Vec<u8>),
+    /// Writes the bytes in chunks of `chunk` bytes with `pause` after every chunk,
+    /// so the client receives the packet in partial reads.
+    SendChunked {
+        bytes: Vec<u8>,
+        chunk: usize,
+        pause: Duration,
+    },
+    /// Waits before the next step.
+    Delay(Duration),
+    /// Closes the connection.
+    Disconnect,
+}
+
+/// Reason why the broker script failed. `step` is the index of the failed step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptError {
+    UnexpectedPacket {
+        step: usize,
+        expected: PacketType,
+        received: PacketType,
+    },
+    ConnectionClosed {
+        step: usize,
+    },
+    MalformedPacket {
+        step: usize,
+    },
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ScriptError::UnexpectedPacket {
+                step,
+                expected,
+                received,
+            } => write!(
+                f,
+                "Step {}: expected {:?} packet but received {:?}!",
+                step, expected, received
+            ),
+            ScriptError::ConnectionClosed { step } => {
+                write!(f, "Step {}: client closed the connection!", step)
+            }
+            ScriptError::MalformedPacket { step } => {
+                write!(f, "Step {}: client sent malformed packet!", step)
+            }
+        }
+    }
+}
+
+/// Broker peer of the in-memory connection which follows the script. Steps are
+/// added in the builder style and executed by `run`, which returns all packets
+/// received from the client so the test can check their content:
+///
+/// ```ignore
+/// let (client_end, broker_end) = duplex(1024);
+/// let broker = ScriptedBroker::new(broker_end)
+///     .expect_and_ack(PacketType::Connect)
+///     .expect(PacketType::Disconnect);
+/// ```
+pub struct ScriptedBroker {
+    transport: MemoryTransport,
+    steps: Vec<Step>,
+    pending: Vec<u8>,
+}
+
+impl ScriptedBroker {
+    pub fn new(transport: MemoryTransport) -> Self {
+        Self {
+            transport,
+            steps: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn expect(self, packet_type: PacketType) -> Self {
+        self.step(Step::Expect(packet_type))
+    }
+
+    pub fn expect_and_ack(self, packet_type: PacketType) -> Self {
+        self.step(Step::ExpectAndAck(packet_type))
+    }
+
+    pub fn expect_and_reply(
+        self,
+        packet_type: PacketType,
+        reply: impl FnOnce(&[u8]) -> Vec<u8> + Send + 'static,
+    ) -> Self {
+        self.step(Step::ExpectAndReply(packet_type, Box::new(reply)))
+    }
+
+    pub fn send(self, bytes: Vec<u8>) -> Self {
+        self.step(Step::Send(bytes))
+    }
+
+    pub fn send_chunked(self, bytes: Vec<u8>, chunk: usize, pause: Duration) -> Self {
+        self.step(Step::SendChunked {
+            bytes,
+            chunk,
+            pause,
+        })
+    }
+
+    pub fn delay(self, duration: Duration) -> Self {
+        self.step(Step::Delay(duration))
+    }
+
+    pub fn disconnect(self) -> Self {
+        self.step(Step::Disconnect)
+    }
+
+    /// Executes the script and returns packets received from the client. Connection
+    /// is closed once the script finishes.
+    pub async fn run(mut self) -> Result<Vec<Vec<u8>>, ScriptError> {
+        let steps = core::mem::take(&mut self.steps);
+        let mut received = Vec::new();
+        for (index, step) in steps.into_iter().enumerate() {
+            match step {
+                Step::Expect(expected) => {
+                    received.push(self.expect_packet(index, expected).await?);
+                }
+                Step::ExpectAndAck(expected) => {
+                    let packet = self.expect_packet(index, expected).await?;
+                    if let Some(ack) = acknowledgement(index, &packet)? {
+                        self.write(index, &ack).await?;
+                    }
+                    received.push(packet);
+                }
+                Step::ExpectAndReply(expected, reply) => {
+                    let packet = self.expect_packet(index, expected).await?;
+                    self.write(index, &reply(&packet)).await?;
+                    received.push(packet);
+                }
+                Step::Send(bytes) => self.write(index, &bytes).await?,
+                Step::SendChunked {
+                    bytes,
+                    chunk,
+                    pause,
+                } => {
+                    for part in bytes.chunks(chunk.max(1)) {
+                        self.write(index, part).await?;
+                        sleep(pause).await;
+                    }
+                }
+                Step::Delay(duration) => sleep(duration).await,
+                Step::Disconnect => self.transport.close(),
+            }
+        }
+        Ok(received)
+    }
+
+    async fn expect_packet(
+        &mut self,
+        step: usize,
+        expected: PacketType,
+    ) -> Result<Vec<u8>, ScriptError> {
+        let packet = self.read_packet(step).await?;
+        let received = PacketType::from(packet[0]);
+        if received != expected {
+            return Err(ScriptError::UnexpectedPacket {
+                step,
+                expected,
+                received,
+            });
+        }
+        Ok(packet)
+    }
+
+    async fn read_packet(&mut self, step: usize) -> Result<Vec<u8>, ScriptError> {
+        loop {
+            match packet_len(&self.pending) {
+                Some(Ok(len)) if self.pending.len() >= len => {
+                    let rest = self.pending.split_off(len);
+                    return Ok(core::mem::replace(&mut self.pending, rest));
+                }
+                Some(Err(())) => return Err(ScriptError::MalformedPacket { step }),
+                _ => {}
+            }
+            let mut buffer = [0u8; 256];
+            match self.transport.read(&mut buffer).await {
+                Ok(0) | Err(_) => return Err(ScriptError::ConnectionClosed { step }),
+                Ok(len) => self.pending.extend_from_slice(&buffer[..len]),
+            }
+        }
+    }
+
+    async fn write(&mut self, step: usize, bytes: &[u8]) -> Result<(), ScriptError> {
+        self.transport
+            .write_all(bytes)
+            .await
+            .map_err(|_| ScriptError::ConnectionClosed { step })
+    }
+}
+
+/// Returns the full length of the first packet in `bytes`, `None` if the fixed
+/// header is not complete yet.
+fn packet_len(bytes: &[u8]) -> Option<Result<usize, ()>> {
+    let (remaining_len, header_len) = remaining_len(bytes)?;
+    Some(remaining_len.map(|len| header_len + len))
+}
+
+/// Decodes the remaining length, returns it together with the fixed header length.
+fn remaining_len(bytes: &[u8]) -> Option<(Result<usize, ()>, usize)> {
+    let mut len = 0usize;
+    for i in 0..4 {
+        let byte = *bytes.get(1 + i)?;
+        len += ((byte & 0x7F) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((Ok(len), 2 + i));
+        }
+    }
+    Some((Err(()), 5))
+}
+
+/// Acknowledgement the broker sends for the client `packet`, `MalformedPacket` if
+/// the packet is too short to be acknowledged.
+fn acknowledgement(step: usize, packet: &[u8]) -> Result<Option<Vec<u8>>, ScriptError> {
+    let malformed = ScriptError::MalformedPacket { step };
+    let (_, header_len) = remaining_len(packet).ok_or(malformed.clone())?;
+    let body = packet.get(header_len..).ok_or(malformed.clone())?;
+    let ack = match PacketType::from(packet[0]) {
+        PacketType::Connect => canned_packets::connack(ReasonCode::Success),
+        PacketType::Subscribe => {
+            let identifier = packet_identifier(packet).ok_or(malformed.clone())?;
+            let mut granted = Vec::new();
+            let filters = body.get(2..).ok_or(malformed.clone())?;
+            for_each_filter(filters, true, |options| granted.push(options & 0x03))
+                .ok_or(malformed)?;
+            canned_packets::suback(identifier, &granted)
+        }
+        PacketType::Unsubscribe => {
+            let identifier = packet_identifier(packet).ok_or(malformed.clone())?;
+            let mut codes = Vec::new();
+            let filters = body.get(2..).ok_or(malformed.clone())?;
+            for_each_filter(filters, false, |_| codes.push(0x00)).ok_or(malformed)?;
+            canned_packets::unsuback(identifier, &codes)
+        }
+        PacketType::Publish if (packet[0] & 0x06) == 0x02 => canned_packets::puback(
+            packet_identifier(packet).ok_or(malformed)?,
+            ReasonCode::Success,
+        ),
+        PacketType::Pingreq => canned_packets::pingresp(),
+        _ => return Ok(None),
+    };
+    Ok(Some(ack))
+}
+
+/// Returns packet identifier of the client `packet` (PUBLISH with QoS 1 or QoS 2,
+/// SUBSCRIBE, UNSUBSCRIBE or PUBACK).
+pub fn packet_identifier(packet: &[u8]) -> Option<u16> {
+    let (_, header_len) = remaining_len(packet)?;
+    let body = &packet[header_len..];
+    let position = match PacketType::from(packet[0]) {
+        PacketType::Publish if packet[0] & 0x06 != 0 => {
+            2 + u16::from_be_bytes([*body.first()?, *body.get(1)?]) as usize
+        }
+        PacketType::Subscribe | PacketType::Unsubscribe | PacketType::Puback => 0,
+        _ => return None,
+    };
+    Some(u16::from_be_bytes([
+        *body.get(position)?,
+        *body.get(position + 1)?,
+    ]))
+}
+
+/// Calls `f` for every topic filter of SUBSCRIBE (`options` is `true`, `f` gets the
+/// subscription options byte) or UNSUBSCRIBE (`f` gets 0). `bytes` starts with the
+/// property length. Returns `None` if the filters are truncated.
+fn for_each_filter(bytes: &[u8], options: bool, mut f: impl FnMut(u8)) -> Option<()> {
+    let mut position = 0;
+    let mut property_len = 0usize;
+    loop {
+        let byte = *bytes.get(position)?;
+        position += 1;
+        property_len += ((byte & 0x7F) as usize) << (7 * (position - 1));
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if position == 4 {
+            return None;
+        }
+    }
+    position += property_len;
+    if position > bytes.len() {
+        return None;
+    }
+    while position < bytes.len() {
+        let len = u16::from_be_bytes([*bytes.get(position)?, *bytes.get(position + 1)?]);
+        position += 2 + len as usize;
+        if options {
+            f(*bytes.get(position)?);
+            position += 1;
+        } else if position > bytes.len() {
+            return None;
+        } else {
+            f(0);
+        }
+    }
+    Some(())
+}
+
+/// Runtime independent sleep, the waker is woken from a helper thread. Zero
+/// duration yields once so other tasks can run.
+fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        waker: None,
+        yielded: false,
+    }
+}
+
+struct Sleep {
+    deadline: Instant,
+    waker: Option<Arc<Mutex<Waker>>>,
+    yielded: bool,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let elapsed = Instant::now() >= self.deadline;
+        if self.yielded && elapsed {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        if elapsed {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        match &self.waker {
+            Some(waker) => *waker.lock().unwrap() = cx.waker().clone(),
+            None => {
+                let waker = Arc::new(Mutex::new(cx.waker().clone()));
+                let deadline = self.deadline;
+                let thread_waker = waker.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    thread_waker.lock().unwrap().wake_by_ref();
+                });
+                self.waker = Some(waker);
+            }
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::task::{Poll, Waker};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::Wake;
+
+use crate::broker::state::{BrokerState, Message};
+use crate::packet::v5::reason_codes::ReasonCode;
+
+type State = BrokerState<2, 4, 2, 2>;
+
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn message(topic: &str, payload: &[u8], qos: u8, retain: bool) -> Message {
+    Message::new(topic, payload, qos, retain).unwrap()
+}
+
+fn counting_waker() -> (Arc<CountingWaker>, Waker) {
+    let waker = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    (waker.clone(), Waker::from(waker))
+}
+
+fn next(state: &mut State, session: u32) -> Poll<Option<Message>> {
+    state.poll_message(session, &counting_waker().1)
+}
+
+fn next_message(state: &mut State, session: u32) -> Message {
+    match next(state, session) {
+        Poll::Ready(Some(message)) => message,
+        _ => panic!("No message queued"),
+    }
+}
+
+#[test]
+fn test_publish_to_matching_subscriptions() {
+    let mut state = State::new();
+    let publisher = state.connect("publisher", None).unwrap();
+    let subscriber = state.connect("subscriber", None).unwrap();
+    assert_eq!(state.subscribe(subscriber, "sensor/+/temperature", 0x01), 1);
+    assert_eq!(state.subscribe(subscriber, "sensor/#", 0x00), 0);
+
+    let matched = state.publish(
+        Some(publisher),
+        &message("sensor/1/temperature", b"21", 1, false),
+    );
+    assert_eq!(matched, 1);
+    assert_eq!(
+        state.publish(Some(publisher), &message("other", b"x", 0, false)),
+        0
+    );
+
+    // Overlapping subscriptions deliver the message once with the highest QoS
+    let received = next_message(&mut state, subscriber);
+    assert_eq!(received.topic.as_str(), "sensor/1/temperature");
+    assert_eq!(received.payload.as_slice(), b"21");
+    assert_eq!(received.qos, 1);
+    assert!(next(&mut state, subscriber).is_pending());
+    assert!(next(&mut state, publisher).is_pending());
+}
+
+#[test]
+fn test_qos_is_downgraded() {
+    let mut state = State::new();
+    let subscriber = state.connect("subscriber", None).unwrap();
+    // QoS 2 subscription is granted as QoS 1
+    assert_eq!(state.subscribe(subscriber, "a", 0x02), 1);
+    assert_eq!(state.subscribe(subscriber, "b", 0x00), 0);
+    state.publish(None, &message("b", b"x", 1, false));
+    assert_eq!(next_message(&mut state, subscriber).qos, 0);
+}
+
+#[test]
+fn test_no_local() {
+    let mut state = State::new();
+    let client = state.connect("client", None).unwrap();
+    state.subscribe(client, "echo", 0x04);
+    assert_eq!(
+        state.publish(Some(client), &message("echo", b"x", 0, false)),
+        0
+    );
+    assert_eq!(state.publish(None, &message("echo", b"x", 0, false)), 1);
+}
+
+#[test]
+fn test_retained_messages() {
+    let mut state = State::new();
+    state.publish(None, &message("sensor/1", b"first", 1, true));
+    state.publish(None, &message("sensor/1", b"second", 1, true));
+    state.publish(None, &message("sensor/2", b"other", 0, true));
+    state.publish(None, &message("sensor/2", b"", 0, true));
+
+    let subscriber = state.connect("subscriber", None).unwrap();
+    assert_eq!(state.subscribe(subscriber, "sensor/+", 0x00), 0);
+    let retained = next_message(&mut state, subscriber);
+    assert_eq!(retained.payload.as_slice(), b"second");
+    assert!(retained.retain);
+    assert_eq!(retained.qos, 0);
+    assert!(next(&mut state, subscriber).is_pending());
+
+    // Retain handling 1 sends retained messages only for new subscription
+    state.subscribe(subscriber, "sensor/+", 0x10);
+    assert!(next(&mut state, subscriber).is_pending());
+    // Retain handling 2 never sends retained messages
+    state.subscribe(subscriber, "sensor/1", 0x20);
+    assert!(next(&mut state, subscriber).is_pending());
+}
+
+#[test]
+fn test_retain_flag_of_forwarded_messages() {
+    let mut state = State::new();
+    let subscriber = state.connect("subscriber", None).unwrap();
+    state.subscribe(subscriber, "plain", 0x00);
+    state.subscribe(subscriber, "published", 0x08);
+    state.publish(None, &message("plain", b"x", 0, true));
+    state.publish(None, &message("published", b"x", 0, true));
+    assert!(!next_message(&mut state, subscriber).retain);
+    assert!(next_message(&mut state, subscriber).retain);
+}
+
+#[test]
+fn test_invalid_subscriptions() {
+    let mut state = State::new();
+    let client = state.connect("client", None).unwrap();
+    assert_eq!(
+        state.subscribe(client, "a/#/b", 0),
+        u8::from(ReasonCode::TopicFilterInvalid)
+    );
+    assert_eq!(
+        state.subscribe(client, "$share/group/a", 0),
+        u8::from(ReasonCode::SharedSubscriptionNotSupported)
+    );
+    for filter in ["a", "b", "c", "d"] {
+        assert_eq!(state.subscribe(client, filter, 0), 0);
+    }
+    assert_eq!(
+        state.subscribe(client, "e", 0),
+        u8::from(ReasonCode::QuotaExceeded)
+    );
+    // Replacing existing subscription does not need more space
+    assert_eq!(state.subscribe(client, "a", 1), 1);
+}
+
+#[test]
+fn test_unsubscribe() {
+    let mut state = State::new();
+    let client = state.connect("client", None).unwrap();
+    state.subscribe(client, "a", 0);
+    assert_eq!(state.unsubscribe(client, "a"), 0x00);
+    assert_eq!(
+        state.unsubscribe(client, "a"),
+        u8::from(ReasonCode::NoSubscriptionExisted)
+    );
+    assert_eq!(state.publish(None, &message("a", b"x", 0, false)), 0);
+}
+
+#[test]
+fn test_capacity_and_client_ids() {
+    let mut state = State::new();
+    let first = state.connect("", None).unwrap();
+    assert_eq!(state.client_id(first).unwrap().as_str(), "auto-1");
+    state.connect("second", None).unwrap();
+    assert_eq!(state.connect("third", None), Err(ReasonCode::ServerBusy));
+    assert_eq!(
+        state.connect("client-identifier-longer-than-23", None),
+        Err(ReasonCode::ClientIdNotValid)
+    );
+    assert_eq!(state.session_count(), 2);
+}
+
+#[test]
+fn test_full_queue_drops_messages() {
+    let mut state = State::new();
+    let subscriber = state.connect("subscriber", None).unwrap();
+    state.subscribe(subscriber, "a", 0);
+    for payload in [b"1", b"2", b"3"] {
+        state.publish(None, &message("a", payload, 0, false));
+    }
+    assert_eq!(
+        next_message(&mut state, subscriber).payload.as_slice(),
+        b"1"
+    );
+    assert_eq!(
+        next_message(&mut state, subscriber).payload.as_slice(),
+        b"2"
+    );
+    assert!(next(&mut state, subscriber).is_pending());
+}
+
+#[test]
+fn test_will() {
+    let mut state = State::new();
+    let watcher = state.connect("watcher", None).unwrap();
+    state.subscribe(watcher, "status/#", 0x01);
+    let will = message("status/sensor", b"offline", 1, false);
+    let sensor = state.connect("sensor", Some(will.clone())).unwrap();
+    state.disconnect(sensor, false);
+    assert!(next(&mut state, watcher).is_pending());
+
+    let sensor = state.connect("sensor", Some(will)).unwrap();
+    state.disconnect(sensor, true);
+    assert_eq!(
+        next_message(&mut state, watcher).payload.as_slice(),
+        b"offline"
+    );
+    assert!(!state.is_connected(sensor));
+    // Disconnecting removed session does nothing
+    state.disconnect(sensor, true);
+    assert!(next(&mut state, watcher).is_pending());
+}
+
+#[test]
+fn test_session_takeover() {
+    let mut state = State::new();
+    let (waker, waker_ref) = counting_waker();
+    let watcher = state.connect("watcher", None).unwrap();
+    state.subscribe(watcher, "status", 0);
+
+    let old = state
+        .connect("sensor", Some(message("status", b"lost", 0, false)))
+        .unwrap();
+    state.subscribe(old, "cmd", 0);
+    assert!(state.poll_message(old, &waker_ref).is_pending());
+
+    let new = state.connect("sensor", None).unwrap();
+    assert_ne!(old, new);
+    // Old connection is woken up and finds its session gone
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+    assert!(matches!(
+        state.poll_message(old, &waker_ref),
+        Poll::Ready(None)
+    ));
+    assert_eq!(
+        next_message(&mut state, watcher).payload.as_slice(),
+        b"lost"
+    );
+    // Subscriptions of the old session are gone
+    assert_eq!(state.publish(None, &message("cmd", b"x", 0, false)), 0);
+}
+
+#[test]
+fn test_waker_is_woken_by_publish() {
+    let mut state = State::new();
+    let (waker, waker_ref) = counting_waker();
+    let subscriber = state.connect("subscriber", None).unwrap();
+    state.subscribe(subscriber, "a", 0);
+    assert!(state.poll_message(subscriber, &waker_ref).is_pending());
+    state.publish(None, &message("a", b"x", 0, false));
+    assert_eq!(waker.0.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,88 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::Channel;
+use heapless::Vec;
+use std::vec::Vec as StdVec;
+
+use crate::client::channel::{forward_message, ForwardError};
+use crate::client::owned_message::{OwnedMessage, OwnedMessageError};
+use crate::client::raw_client::ReceivedMessage;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::queue::OverflowPolicy;
+
+type Message = OwnedMessage<8, 8>;
+
+fn message(payload: &[u8]) -> ReceivedMessage<'_> {
+    ReceivedMessage {
+        topic: "t",
+        payload,
+        qos: QualityOfService::QoS0,
+        retain: false,
+        properties: Vec::new(),
+        duplicate: false,
+    }
+}
+
+fn payloads(channel: &Channel<NoopRawMutex, Message, 2>) -> StdVec<u8> {
+    let mut payloads = StdVec::new();
+    while let Ok(message) = channel.try_receive() {
+        payloads.push(message.payload[0]);
+    }
+    payloads
+}
+
+#[test]
+fn test_drop_oldest() {
+    let channel = Channel::<NoopRawMutex, Message, 2>::new();
+    for payload in 1..=3 {
+        let result = forward_message(&channel, &message(&[payload]), OverflowPolicy::DropOldest);
+        assert_eq!(result, Ok(()));
+    }
+    assert_eq!(payloads(&channel), [2, 3]);
+}
+
+#[test]
+fn test_reject_newest() {
+    let channel = Channel::<NoopRawMutex, Message, 2>::new();
+    let policy = OverflowPolicy::RejectNewest;
+    assert_eq!(forward_message(&channel, &message(&[1]), policy), Ok(()));
+    assert_eq!(forward_message(&channel, &message(&[2]), policy), Ok(()));
+    assert_eq!(
+        forward_message(&channel, &message(&[3]), policy),
+        Err(ForwardError::Full)
+    );
+    assert_eq!(payloads(&channel), [1, 2]);
+}
+
+#[test]
+fn test_message_too_long() {
+    let channel = Channel::<NoopRawMutex, Message, 2>::new();
+    assert_eq!(
+        forward_message(&channel, &message(&[0; 9]), OverflowPolicy::DropOldest),
+        Err(ForwardError::Message(OwnedMessageError::PayloadTooLong))
+    );
+    assert!(channel.is_empty());
+}
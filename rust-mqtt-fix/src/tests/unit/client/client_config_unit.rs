@@ -0,0 +1,242 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::fmt::Write;
+
+use heapless::String;
+
+use crate::client::client_config::{
+    ClientConfig, ClientConfigBuilder, ConfigError, ConfigField, ConfigIssue, ConfigString,
+    MqttVersion, MAX_OWNED_STRING_LEN,
+};
+use crate::packet::v5::property::{Property, PropertyViolation};
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::rng_generator::CountingRng;
+
+#[test]
+fn test_max_packet_size_not_duplicated() {
+    let mut config = ClientConfig::<3, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    config.max_packet_size = 100;
+    config.add_max_packet_size_as_prop();
+    config.max_packet_size = 200;
+    config.add_max_packet_size_as_prop();
+    assert_eq!(config.properties.len(), 1);
+    assert!(matches!(
+        config.properties.first(),
+        Some(Property::MaximumPacketSize(200))
+    ));
+}
+
+#[test]
+fn test_property_setters_replace() {
+    let mut config = ClientConfig::<5, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    assert_eq!(config.add_receive_maximum(10), Ok(()));
+    assert_eq!(config.add_topic_alias_maximum(5), Ok(()));
+    assert_eq!(config.add_receive_maximum(20), Ok(()));
+    assert_eq!(config.add_user_property("a", "b"), Ok(()));
+    assert_eq!(config.add_user_property("a", "c"), Ok(()));
+    assert_eq!(config.properties.len(), 4);
+    assert!(matches!(config.properties[0], Property::ReceiveMaximum(20)));
+    assert!(matches!(
+        config.properties[1],
+        Property::TopicAliasMaximum(5)
+    ));
+}
+
+#[test]
+fn test_connect_properties() {
+    let mut config = ClientConfig::<3, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    assert!(config.clean_start);
+    config.max_packet_size = 100;
+    config.add_max_packet_size_as_prop();
+    config
+        .add_property(Property::MessageExpiryInterval(5))
+        .unwrap();
+    config.add_session_expiry_interval(10);
+    config.add_session_expiry_interval(30);
+    let properties = config.connect_properties().unwrap();
+    assert_eq!(properties.len(), 2);
+    assert!(matches!(properties[0], Property::MaximumPacketSize(100)));
+    assert!(matches!(properties[1], Property::SessionExpiryInterval(30)));
+    // Config is not changed
+    assert_eq!(config.properties.len(), 2);
+    assert_eq!(config.connect_properties().unwrap().len(), 2);
+
+    config.add_session_expiry_interval(0);
+    assert_eq!(config.connect_properties().unwrap().len(), 1);
+    config.properties.clear();
+    config.add_receive_maximum(20).unwrap();
+    config.add_topic_alias_maximum(5).unwrap();
+    config.add_user_property("a", "b").unwrap();
+    // Implicit MaximumPacketSize is left out when the Vec is full
+    let properties = config.connect_properties().unwrap();
+    assert_eq!(properties.len(), 3);
+    assert!(!properties
+        .iter()
+        .any(|p| matches!(p, Property::MaximumPacketSize(_))));
+    assert_eq!(config.validate(), Ok(()));
+    config.add_session_expiry_interval(30);
+    assert!(config.connect_properties().is_none());
+
+    let config = ClientConfig::<0, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    assert_eq!(config.connect_properties().unwrap().len(), 0);
+}
+
+#[test]
+fn test_property_setters_full() {
+    let mut config = ClientConfig::<1, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let full = Err(ConfigError::from(ConfigIssue::TooManyProperties));
+    assert_eq!(config.add_receive_maximum(20), Ok(()));
+    assert_eq!(config.add_topic_alias_maximum(5), full);
+    assert_eq!(config.add_user_property("a", "b"), full);
+    assert_eq!(
+        config.add_property(Property::MessageExpiryInterval(5)),
+        full
+    );
+    // Replacing the present property still works
+    assert_eq!(config.add_receive_maximum(10), Ok(()));
+    assert_eq!(config.properties.len(), 1);
+    assert_eq!(config.add_max_packet_size_as_prop(), 0);
+}
+
+#[test]
+fn test_builder() {
+    let config = ClientConfigBuilder::<4, _>::new(MqttVersion::MQTTv5, CountingRng(0))
+        .client_id("sensor-1")
+        .username("user")
+        .password(b"secret")
+        .will("sensor/1/status", b"offline", true)
+        .will_qos(QualityOfService::QoS1)
+        .keep_alive(30)
+        .max_packet_size(1024)
+        .max_subscribe_qos(QualityOfService::QoS1)
+        .clean_start(false)
+        .session_expiry_interval(3600)
+        .receive_maximum(10)
+        .receive_maximum(20)
+        .user_property("fw", "1.2")
+        .build()
+        .unwrap();
+    assert_eq!(config.client_id, ConfigString::Borrowed("sensor-1"));
+    assert_eq!(config.encoded_client_id().len, 8);
+    assert!(config.username_flag && config.password_flag && config.will_flag);
+    assert_eq!(config.password.bin, b"secret");
+    assert!(config.will_retain);
+    assert_eq!(config.will_qos, QualityOfService::QoS1);
+    assert_eq!(config.keep_alive, 30);
+    assert!(!config.clean_start);
+    assert_eq!(config.session_expiry_interval, 3600);
+    assert_eq!(config.properties.len(), 2);
+    assert!(matches!(config.properties[0], Property::ReceiveMaximum(20)));
+    assert_eq!(config.connect_properties().unwrap().len(), 4);
+}
+
+fn runtime_config(serial: u32) -> ClientConfig<'static, 2, CountingRng> {
+    let mut client_id = String::<MAX_OWNED_STRING_LEN>::new();
+    write!(client_id, "sensor-{}", serial).unwrap();
+    let mut will_topic = String::<MAX_OWNED_STRING_LEN>::new();
+    write!(will_topic, "{}/status", client_id).unwrap();
+    ClientConfigBuilder::new(MqttVersion::MQTTv5, CountingRng(0))
+        .client_id(client_id)
+        .username("user")
+        .will(will_topic, b"offline", true)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_builder_owned_strings() {
+    let config = runtime_config(42);
+    assert!(matches!(config.client_id, ConfigString::Owned(_)));
+    assert_eq!(config.client_id.as_str(), "sensor-42");
+    assert_eq!(config.encoded_client_id().len, 9);
+    assert_eq!(config.encoded_will_topic().string, "sensor-42/status");
+    assert_eq!(config.encoded_username().string, "user");
+
+    let mut config = config;
+    config.add_will(String::try_from("sensor-42/+").unwrap(), b"offline", true);
+    assert_eq!(
+        config.validate().unwrap_err().issues.as_slice(),
+        &[ConfigIssue::InvalidWillTopic]
+    );
+}
+
+#[test]
+fn test_builder_lists_issues() {
+    let err = ClientConfigBuilder::<5, _>::new(MqttVersion::MQTTv5, CountingRng(0))
+        .client_id("")
+        .clean_start(false)
+        .will("sensor/+/status", b"offline", false)
+        .max_subscribe_qos(QualityOfService::QoS2)
+        .receive_maximum(0)
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.issues.as_slice(),
+        &[
+            ConfigIssue::EmptyClientIdWithoutCleanStart,
+            ConfigIssue::InvalidWillTopic,
+            ConfigIssue::UnsupportedQoS,
+            ConfigIssue::InvalidProperty(PropertyViolation::InvalidValue(0x21)),
+        ]
+    );
+
+    let err = ClientConfigBuilder::<1, _>::new(MqttVersion::MQTTv3, CountingRng(0))
+        .client_id("id\n")
+        .user_property("a", "b")
+        .user_property("c", "d")
+        .build()
+        .err()
+        .unwrap();
+    assert_eq!(
+        err.issues.as_slice(),
+        &[
+            ConfigIssue::UnsupportedProtocolVersion,
+            ConfigIssue::InvalidClientId,
+            ConfigIssue::TooManyProperties,
+        ]
+    );
+}
+
+#[test]
+fn test_validate_string_len() {
+    let long = [b'a'; 65536];
+    let long = core::str::from_utf8(&long).unwrap();
+    let mut config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    config.add_username(long);
+    config.add_user_property("name", long);
+    let err = config.validate().unwrap_err();
+    assert_eq!(
+        err.issues.as_slice(),
+        &[
+            ConfigIssue::StringTooLong(ConfigField::Username),
+            ConfigIssue::StringTooLong(ConfigField::UserProperty),
+        ]
+    );
+    assert_eq!(
+        std::format!("{}", err),
+        "Invalid client config: Username is too long! UserProperty is too long!"
+    );
+}
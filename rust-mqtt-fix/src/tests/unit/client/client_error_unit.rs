@@ -0,0 +1,186 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::client::client::MqttClient;
+use crate::client::client_config::{ClientConfig, MqttVersion};
+use crate::client::client_error::{ClientError, ProtocolViolation, MAX_REASON_STRING_LEN};
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::rng_generator::CountingRng;
+use crate::utils::types::BufferError;
+
+struct CannedTransport<'a> {
+    incoming: &'a [u8],
+}
+
+impl<'a> ErrorType for CannedTransport<'a> {
+    type Error = ErrorKind;
+}
+
+impl<'a> Read for CannedTransport<'a> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.incoming.len());
+        buf[..len].copy_from_slice(&self.incoming[..len]);
+        self.incoming = &self.incoming[len..];
+        Ok(len)
+    }
+}
+
+impl<'a> Write for CannedTransport<'a> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(buf.len())
+    }
+}
+
+#[test]
+fn test_rejected_truncates_reason_string() {
+    let long = "x".repeat(MAX_REASON_STRING_LEN + 10);
+    let err = ClientError::<()>::rejected(ReasonCode::NotAuthorized, Some(&long));
+    match err {
+        ClientError::BrokerRejected {
+            reason,
+            reason_string: Some(reason_string),
+        } => {
+            assert_eq!(reason, ReasonCode::NotAuthorized);
+            assert_eq!(reason_string.len(), MAX_REASON_STRING_LEN);
+        }
+        _ => panic!("Unexpected error variant"),
+    }
+}
+
+#[test]
+fn test_connection_error() {
+    assert!(ClientError::Transport(ErrorKind::ConnectionReset).is_connection_error());
+    assert!(ClientError::<ErrorKind>::NotConnected.is_connection_error());
+    assert!(ClientError::<ErrorKind>::Timeout.is_connection_error());
+    assert!(!ClientError::<ErrorKind>::Decode(BufferError::Utf8Error).is_connection_error());
+    assert!(
+        !ClientError::<ErrorKind>::rejected(ReasonCode::QuotaExceeded, None).is_connection_error()
+    );
+}
+
+#[test]
+fn test_display() {
+    let err = ClientError::<ErrorKind>::rejected(ReasonCode::NotAuthorized, Some("bad creds"));
+    assert_eq!(
+        format!("{}", err),
+        format!(
+            "Broker rejected request: {} (bad creds)",
+            ReasonCode::NotAuthorized
+        )
+    );
+    let err = ClientError::<ErrorKind>::Protocol(ProtocolViolation::PacketIdentifierMismatch {
+        expected: 1,
+        received: 2,
+    });
+    assert_eq!(
+        format!("{}", err),
+        "Protocol violation: Packet identifier mismatch, expected 1 but received 2!"
+    );
+    assert_eq!(
+        format!("{}", ClientError::Transport(ErrorKind::TimedOut)),
+        "Transport error: TimedOut!"
+    );
+}
+
+#[test]
+fn test_map_transport() {
+    let err = ClientError::Transport(5u8).map_transport(u16::from);
+    assert_eq!(err, ClientError::Transport(5u16));
+    let err = ClientError::<u8>::NotConnected.map_transport(u16::from);
+    assert_eq!(err, ClientError::NotConnected);
+}
+
+#[tokio::test]
+async fn test_connack_rejection_carries_reason_string() {
+    let connack = [
+        0x20, 0x0F, 0x00, 0x87, 0x0C, 0x1F, 0x00, 0x09, b'b', b'a', b'd', b' ', b'c', b'r', b'e',
+        b'd', b's',
+    ];
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        CannedTransport { incoming: &connack },
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::rejected(
+            ReasonCode::NotAuthorized,
+            Some("bad creds")
+        ))
+    );
+}
+
+#[tokio::test]
+async fn test_closed_connection() {
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        CannedTransport { incoming: &[] },
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::NotConnected)
+    );
+}
+
+#[tokio::test]
+async fn test_unexpected_packet() {
+    // PINGRESP received as the answer to CONNECT
+    let pingresp = [0xD0, 0x00];
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        CannedTransport {
+            incoming: &pingresp,
+        },
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::Protocol(ProtocolViolation::UnexpectedPacket(
+            PacketType::Pingresp
+        )))
+    );
+}
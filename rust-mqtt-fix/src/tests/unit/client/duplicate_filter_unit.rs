@@ -0,0 +1,89 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::client::duplicate_filter::{DuplicateFilter, MessageKey, RecentMessages};
+
+fn publish(first_byte: u8, identifier: u16, payload: &[u8]) -> std::vec::Vec<u8> {
+    let [msb, lsb] = identifier.to_be_bytes();
+    let mut packet = std::vec![first_byte, 6 + payload.len() as u8, 0, 1, b't', msb, lsb, 0];
+    packet.extend_from_slice(payload);
+    packet
+}
+
+#[test]
+fn test_key_from_publish() {
+    let key = MessageKey::new(7, "t", b"hi");
+    assert_eq!(
+        MessageKey::from_publish(&publish(0x32, 7, b"hi")),
+        Some((key, false))
+    );
+    assert_eq!(
+        MessageKey::from_publish(&publish(0x3A, 7, b"hi")),
+        Some((key, true))
+    );
+}
+
+#[test]
+fn test_key_from_publish_ignores_other_packets() {
+    assert_eq!(
+        MessageKey::from_publish(&[0x30, 5, 0, 1, b't', b'h', b'i']),
+        None
+    );
+    assert_eq!(MessageKey::from_publish(&publish(0x34, 7, b"hi")), None);
+    assert_eq!(MessageKey::from_publish(&[0x40, 2, 0, 7]), None);
+    // Remaining length past the end of the packet
+    assert_eq!(
+        MessageKey::from_publish(&[0x32, 20, 0, 1, b't', 0, 7, 0]),
+        None
+    );
+}
+
+#[test]
+fn test_key_depends_on_content() {
+    let key = MessageKey::new(1, "t", b"on");
+    assert_ne!(key, MessageKey::new(2, "t", b"on"));
+    assert_ne!(key, MessageKey::new(1, "t", b"off"));
+    assert_ne!(key, MessageKey::new(1, "to", b"n"));
+}
+
+#[test]
+fn test_recent_messages() {
+    let mut filter = RecentMessages::<2>::new();
+    let first = MessageKey::new(1, "t", b"a");
+    let second = MessageKey::new(2, "t", b"b");
+    let third = MessageKey::new(3, "t", b"c");
+
+    assert!(!filter.insert(first));
+    assert!(filter.insert(first));
+    assert!(!filter.insert(second));
+    assert_eq!(filter.len(), 2);
+    // Oldest key is forgotten
+    assert!(!filter.insert(third));
+    assert!(!filter.insert(first));
+    assert!(filter.insert(third));
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert!(!filter.insert(third));
+}
@@ -0,0 +1,156 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, SigningKey, VerifyingKey};
+
+use crate::client::credentials::{CredentialsError, CredentialsProvider};
+use crate::client::jwt::{JwtKey, JwtProvider};
+use crate::utils::clock::Clock;
+
+struct FixedClock(u64);
+
+impl Clock for FixedClock {
+    fn now_micros(&self) -> u64 {
+        self.0 * 1_000_000
+    }
+}
+
+fn decode(part: &str) -> std::vec::Vec<u8> {
+    URL_SAFE_NO_PAD.decode(part).unwrap()
+}
+
+#[test]
+fn test_hs256() {
+    let clock = FixedClock(1_700_000_000);
+    let key = JwtKey::hs256(b"secret").unwrap();
+    let mut provider = JwtProvider::new("my-project", key, 3600, &clock).unwrap();
+    provider.refresh().unwrap();
+    assert_eq!(
+        provider.token(),
+        "eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9\
+         .eyJhdWQiOiJteS1wcm9qZWN0IiwiaWF0IjoxNzAwMDAwMDAwLCJleHAiOjE3MDAwMDM2MDB9\
+         .HPmc7KnWMHS4p1ZKp0sgaSzQqanY7xo7Z55ILDrrLkA"
+    );
+    assert_eq!(provider.password(), Some(provider.token().as_bytes()));
+    // Config values are used
+    assert_eq!(provider.client_id(), None);
+    assert_eq!(provider.username(), None);
+}
+
+#[test]
+fn test_es256() {
+    let private_key = [0x11; 32];
+    let clock = FixedClock(1_700_000_000);
+    let key = JwtKey::es256(&private_key).unwrap();
+    let mut provider = JwtProvider::new("my-project", key, 600, &clock).unwrap();
+    provider.refresh().unwrap();
+
+    let token = provider.token();
+    let mut parts = token.split('.');
+    let (header, claims, signature) = (
+        parts.next().unwrap(),
+        parts.next().unwrap(),
+        parts.next().unwrap(),
+    );
+    assert_eq!(decode(header), br#"{"alg":"ES256","typ":"JWT"}"#);
+    assert_eq!(
+        decode(claims),
+        br#"{"aud":"my-project","iat":1700000000,"exp":1700000600}"#
+    );
+    let signature = Signature::from_slice(&decode(signature)).unwrap();
+    let verifying_key = VerifyingKey::from(&SigningKey::from_bytes((&private_key).into()).unwrap());
+    let signed = &token[..header.len() + claims.len() + 1];
+    assert!(verifying_key.verify(signed.as_bytes(), &signature).is_ok());
+}
+
+#[test]
+fn test_google_cloud_iot() {
+    let clock = FixedClock(1_700_000_000);
+    let key = JwtKey::hs256(b"secret").unwrap();
+    let mut provider = JwtProvider::google_cloud_iot(
+        "my-project",
+        "europe-west1",
+        "sensors",
+        "dht11",
+        key,
+        3600,
+        &clock,
+    )
+    .unwrap();
+    provider.refresh().unwrap();
+    assert_eq!(
+        provider.client_id(),
+        Some("projects/my-project/locations/europe-west1/registries/sensors/devices/dht11")
+    );
+    assert_eq!(provider.username(), Some("unused"));
+    assert!(provider
+        .token()
+        .starts_with("eyJhbGciOiJIUzI1NiIsInR5cCI6IkpXVCJ9."));
+}
+
+#[test]
+fn test_client_id_and_username() {
+    let clock = FixedClock(0);
+    let key = JwtKey::hs256(b"secret").unwrap();
+    let mut provider = JwtProvider::new("broker", key, 60, &clock).unwrap();
+    provider.set_client_id("device-1").unwrap();
+    provider.set_username("jwt");
+    assert_eq!(provider.client_id(), Some("device-1"));
+    assert_eq!(provider.username(), Some("jwt"));
+    assert_eq!(
+        provider.set_client_id(&"x".repeat(200)),
+        Err(CredentialsError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn test_errors() {
+    let clock = FixedClock(0);
+    assert_eq!(
+        JwtKey::hs256(&[0; 65]).err(),
+        Some(CredentialsError::InvalidKey)
+    );
+    // Zero is not a valid private scalar
+    assert_eq!(
+        JwtKey::es256(&[0; 32]).err(),
+        Some(CredentialsError::InvalidKey)
+    );
+    let key = JwtKey::hs256(b"secret").unwrap();
+    assert_eq!(
+        JwtProvider::new("quo\"te", key.clone(), 60, &clock).err(),
+        Some(CredentialsError::InvalidClaim)
+    );
+    let long_audience = "a".repeat(400);
+    let mut provider = JwtProvider::new(&long_audience, key, 60, &clock).unwrap();
+    assert_eq!(provider.refresh(), Err(CredentialsError::BufferTooSmall));
+}
+
+#[test]
+fn test_key_debug_hides_key() {
+    let key = JwtKey::hs256(b"secret").unwrap();
+    assert_eq!(std::format!("{:?}", key), "JwtKey(HS256)");
+}
@@ -0,0 +1,126 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use heapless::Vec;
+
+use crate::client::owned_message::{OwnedMessage, OwnedMessageError};
+use crate::client::raw_client::{Event, ReceivedMessage};
+use crate::packet::v5::property::Property;
+use crate::packet::v5::property_kind::{ContentType, CorrelationData, ResponseTopic};
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::types::{BinaryData, EncodedString, StringPair};
+
+fn encoded(string: &str) -> EncodedString<'_> {
+    EncodedString {
+        string,
+        len: string.len() as u16,
+    }
+}
+
+fn message<'a>(
+    topic: &'a str,
+    payload: &'a [u8],
+    properties: &[Property<'a>],
+) -> ReceivedMessage<'a> {
+    ReceivedMessage {
+        topic,
+        payload,
+        qos: QualityOfService::QoS1,
+        retain: true,
+        properties: Vec::from_slice(properties).unwrap(),
+        duplicate: false,
+    }
+}
+
+#[test]
+fn test_from_received() {
+    let received = message(
+        "devices/1/cmd",
+        b"reboot",
+        &[
+            Property::PayloadFormat(1),
+            Property::ContentType(encoded("text/plain")),
+            Property::ResponseTopic(encoded("devices/1/reply")),
+            Property::CorrelationData(BinaryData {
+                bin: &[1, 2],
+                len: 2,
+            }),
+            Property::UserProperty(StringPair {
+                name: encoded("a"),
+                value: encoded("b"),
+            }),
+        ],
+    );
+    let owned = OwnedMessage::<20, 10>::try_from(&received).unwrap();
+    assert_eq!(owned.topic.as_str(), "devices/1/cmd");
+    assert_eq!(owned.payload.as_slice(), b"reboot");
+    assert_eq!(owned.qos, QualityOfService::QoS1);
+    assert!(owned.retain);
+    assert_eq!(owned.payload_format, Some(1));
+    assert_eq!(owned.message_expiry_interval, None);
+    assert_eq!(owned.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(owned.response_topic.as_deref(), Some("devices/1/reply"));
+    assert_eq!(owned.correlation_data.as_deref(), Some(&[1u8, 2][..]));
+
+    let view = owned.as_received();
+    assert_eq!(view.topic, received.topic);
+    assert_eq!(view.payload, received.payload);
+    assert_eq!(view.get::<ContentType>(), Some("text/plain"));
+    assert_eq!(view.get::<ResponseTopic>(), Some("devices/1/reply"));
+    assert_eq!(view.get::<CorrelationData>(), Some(&[1u8, 2][..]));
+}
+
+#[test]
+fn test_capacity() {
+    let received = message("devices/1/cmd", b"reboot", &[]);
+    assert_eq!(
+        OwnedMessage::<4, 10>::try_from(&received),
+        Err(OwnedMessageError::TopicTooLong)
+    );
+    assert_eq!(
+        OwnedMessage::<20, 4>::try_from(&received),
+        Err(OwnedMessageError::PayloadTooLong)
+    );
+    let received = message(
+        "cmd",
+        b"reboot",
+        &[Property::ResponseTopic(encoded("devices/1/reply"))],
+    );
+    assert_eq!(
+        OwnedMessage::<4, 10>::try_from(&received),
+        Err(OwnedMessageError::PropertyTooLong)
+    );
+}
+
+#[test]
+fn test_from_event() {
+    let event = Event::Message(message("cmd", b"on", &[]));
+    let owned = OwnedMessage::<8, 8>::try_from(&event).unwrap();
+    drop(event);
+    assert_eq!(owned.payload.as_slice(), b"on");
+    assert_eq!(
+        OwnedMessage::<8, 8>::try_from(&Event::Pingresp),
+        Err(OwnedMessageError::NotMessage)
+    );
+}
@@ -0,0 +1,106 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use heapless::Vec;
+
+use crate::client::packet_observer::PacketSummary;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use crate::packet::v5::subscription_packet::SubscriptionPacket;
+use crate::utils::types::{BufferError, EncodedString};
+
+#[test]
+fn test_summary_publish() {
+    let mut properties = Vec::<Property, 2>::new();
+    properties.push(Property::TopicAlias(3)).unwrap();
+    properties
+        .push(Property::ContentType(EncodedString {
+            string: "text",
+            len: 4,
+        }))
+        .unwrap();
+    let mut packet = PublishPacket::<2>::new();
+    packet.add_topic_name("sensor/1");
+    packet.add_message(b"21.5");
+    packet.add_qos(QualityOfService::QoS1);
+    packet.add_identifier(7);
+    let property_len = packet.add_properties(&properties);
+    packet.set_property_len(property_len);
+    let mut buffer = [0; 64];
+    let len = packet.encode(&mut buffer, 64).unwrap();
+
+    let summary = PacketSummary::decode(&buffer[..len]).unwrap();
+    assert_eq!(summary.packet_type, PacketType::Publish);
+    assert_eq!(summary.packet_identifier, Some(7));
+    assert_eq!(summary.qos, Some(QualityOfService::QoS1));
+    assert_eq!(summary.topic, Some("sensor/1"));
+    assert_eq!(summary.properties.len(), 2);
+    assert!(matches!(summary.properties[0], Property::TopicAlias(3)));
+}
+
+#[test]
+fn test_summary_subscribe() {
+    let mut packet = SubscriptionPacket::<2, 1>::new();
+    packet.packet_identifier = 12;
+    packet.add_new_filter("room/+/light", QualityOfService::QoS1);
+    packet.add_new_filter("alarm", QualityOfService::QoS0);
+    let mut buffer = [0; 64];
+    let len = packet.encode(&mut buffer, 64).unwrap();
+
+    let summary = PacketSummary::decode(&buffer[..len]).unwrap();
+    assert_eq!(summary.packet_type, PacketType::Subscribe);
+    assert_eq!(summary.packet_identifier, Some(12));
+    assert_eq!(summary.qos, None);
+    assert_eq!(summary.topic, Some("room/+/light"));
+    assert!(summary.properties.is_empty());
+}
+
+#[test]
+fn test_summary_short_packets() {
+    // PUBACK without reason code, PINGRESP, DISCONNECT with reason and reason string
+    let summary = PacketSummary::decode(&[0x40, 0x02, 0x00, 0x05]).unwrap();
+    assert_eq!(summary.packet_type, PacketType::Puback);
+    assert_eq!(summary.packet_identifier, Some(5));
+
+    let summary = PacketSummary::decode(&[0xD0, 0x00]).unwrap();
+    assert_eq!(summary.packet_type, PacketType::Pingresp);
+    assert_eq!(summary.packet_identifier, None);
+
+    let summary =
+        PacketSummary::decode(&[0xE0, 0x07, 0x97, 0x05, 0x1F, 0x00, 0x02, b'o', b'k']).unwrap();
+    assert_eq!(summary.packet_type, PacketType::Disconnect);
+    assert!(matches!(summary.properties[0], Property::ReasonString(ref s) if s.string == "ok"));
+}
+
+#[test]
+fn test_summary_malformed() {
+    // Remaining length longer than the packet, property length overshooting the packet
+    assert_eq!(
+        PacketSummary::decode(&[0x40, 0x04, 0x00, 0x05]).err(),
+        Some(BufferError::InsufficientBufferSize)
+    );
+    assert!(PacketSummary::decode(&[0x90, 0x04, 0x00, 0x01, 0x05, 0x00]).is_err());
+}
@@ -0,0 +1,111 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::vec::Vec;
+
+use crate::client::packet_observer::{Direction, ObservedPacket, PacketObserver, PacketSummary};
+use crate::client::pcap::PcapWriter;
+
+fn observe(writer: &mut PcapWriter<Vec<u8>>, direction: Direction, micros: u64, raw: &[u8]) {
+    writer.on_packet(&ObservedPacket {
+        direction,
+        timestamp_micros: micros,
+        raw,
+        summary: PacketSummary::decode(raw),
+    });
+}
+
+/// One's complement sum of the header has to be zero.
+fn checksum_valid(data: &[u8]) -> bool {
+    let mut sum = data.chunks(2).fold(0_u32, |sum, word| {
+        sum + u32::from(u16::from_be_bytes([
+            word[0],
+            word.get(1).copied().unwrap_or(0),
+        ]))
+    });
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum == 0xffff
+}
+
+/// Splits the capture into (timestamp, packet data) records.
+fn records(capture: &[u8]) -> Vec<(u32, u32, &[u8])> {
+    let mut records = Vec::new();
+    let mut rest = &capture[24..];
+    while !rest.is_empty() {
+        let len = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+        records.push((
+            u32::from_le_bytes(rest[0..4].try_into().unwrap()),
+            u32::from_le_bytes(rest[4..8].try_into().unwrap()),
+            &rest[16..16 + len],
+        ));
+        rest = &rest[16 + len..];
+    }
+    records
+}
+
+#[test]
+fn test_pcap_capture() {
+    let mut writer = PcapWriter::new(Vec::new()).unwrap();
+    observe(&mut writer, Direction::Outbound, 1_500_000, &[0xC0, 0x00]);
+    observe(&mut writer, Direction::Inbound, 2_000_001, &[0xD0, 0x00]);
+    let capture = writer.finish().unwrap();
+
+    // Microsecond pcap, version 2.4, raw IP
+    assert_eq!(&capture[0..4], &[0xD4, 0xC3, 0xB2, 0xA1]);
+    assert_eq!(&capture[4..8], &[2, 0, 4, 0]);
+    assert_eq!(&capture[20..24], &[101, 0, 0, 0]);
+
+    let records = records(&capture);
+    // SYN, SYN ACK, ACK, PINGREQ, PINGRESP
+    assert_eq!(records.len(), 5);
+    let flags: Vec<u8> = records.iter().map(|(_, _, data)| data[33]).collect();
+    assert_eq!(flags, [0x02, 0x12, 0x10, 0x18, 0x18]);
+    assert_eq!((records[3].0, records[3].1), (1, 500_000));
+    assert_eq!((records[4].0, records[4].1), (2, 1));
+
+    for (_, _, data) in &records {
+        assert!(checksum_valid(&data[..20]));
+        // TCP checksum over the pseudo header and segment
+        let mut pseudo = Vec::from(&data[12..20]);
+        pseudo.extend_from_slice(&[0, 6]);
+        pseudo.extend_from_slice(&((data.len() - 20) as u16).to_be_bytes());
+        pseudo.extend_from_slice(&data[20..]);
+        assert!(checksum_valid(&pseudo));
+    }
+
+    let pingreq = records[3].2;
+    assert_eq!(&pingreq[40..], &[0xC0, 0x00]);
+    assert_eq!(&pingreq[22..24], &1883_u16.to_be_bytes());
+    let pingresp = records[4].2;
+    assert_eq!(&pingresp[40..], &[0xD0, 0x00]);
+    assert_eq!(&pingresp[20..22], &1883_u16.to_be_bytes());
+    // Sequence continues after SYN, acknowledgement matches the other side
+    let seq = |data: &[u8]| u32::from_be_bytes(data[24..28].try_into().unwrap());
+    let ack = |data: &[u8]| u32::from_be_bytes(data[28..32].try_into().unwrap());
+    assert_eq!(seq(pingreq), seq(records[0].2) + 1);
+    assert_eq!(ack(pingresp), seq(pingreq) + 2);
+    assert_eq!(ack(pingreq), seq(pingresp));
+}
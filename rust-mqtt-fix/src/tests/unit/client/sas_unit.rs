@@ -0,0 +1,86 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::client::credentials::{CredentialsError, CredentialsProvider};
+use crate::client::sas::AzureSasProvider;
+use crate::utils::clock::Clock;
+
+const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+struct FixedClock(u64);
+
+impl Clock for FixedClock {
+    fn now_micros(&self) -> u64 {
+        self.0 * 1_000_000
+    }
+}
+
+#[test]
+fn test_sas_token() {
+    let clock = FixedClock(1_700_000_000);
+    let mut provider =
+        AzureSasProvider::new("my-hub.azure-devices.net", "sensor-1", KEY, 3600, &clock).unwrap();
+    provider.refresh().unwrap();
+    assert_eq!(
+        provider.token(),
+        "SharedAccessSignature sr=my-hub.azure-devices.net%2Fdevices%2Fsensor-1\
+         &sig=hSDz%2F9ahuEPh3OmD0muVLP4YXSKQ0VlmzPuWqFadm1k%3D&se=1700003600"
+    );
+    assert_eq!(provider.expiry(), 1_700_003_600);
+    assert_eq!(provider.password(), Some(provider.token().as_bytes()));
+    assert_eq!(provider.client_id(), Some("sensor-1"));
+    assert_eq!(
+        provider.username(),
+        Some("my-hub.azure-devices.net/sensor-1/?api-version=2021-04-12")
+    );
+}
+
+#[test]
+fn test_sas_token_changes_with_time() {
+    let clock = FixedClock(1_700_000_000);
+    let mut provider =
+        AzureSasProvider::new("my-hub.azure-devices.net", "sensor-1", KEY, 60, &clock).unwrap();
+    provider.refresh().unwrap();
+    let first = provider.expiry();
+    let later = FixedClock(1_700_000_100);
+    let mut provider =
+        AzureSasProvider::new("my-hub.azure-devices.net", "sensor-1", KEY, 60, &later).unwrap();
+    provider.refresh().unwrap();
+    assert_eq!(provider.expiry(), first + 100);
+    assert!(provider.token().ends_with("&se=1700000160"));
+}
+
+#[test]
+fn test_sas_errors() {
+    let clock = FixedClock(0);
+    assert_eq!(
+        AzureSasProvider::new("hub", "dev", "not base64!", 60, &clock).err(),
+        Some(CredentialsError::InvalidKey)
+    );
+    let long_host = "h".repeat(200);
+    assert_eq!(
+        AzureSasProvider::new(&long_host, "dev", KEY, 60, &clock).err(),
+        Some(CredentialsError::BufferTooSmall)
+    );
+}
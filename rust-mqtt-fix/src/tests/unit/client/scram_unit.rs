@@ -0,0 +1,307 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::vec::Vec;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+use rand_core::{CryptoRng, Error, RngCore};
+
+use crate::client::authenticator::{AuthError, Authenticator};
+use crate::client::client::MqttClient;
+use crate::client::client_config::{ClientConfig, MqttVersion};
+use crate::client::client_error::ClientError;
+use crate::client::scram::{ScramSha256, MAX_ITERATIONS, SCRAM_SHA_256};
+use crate::encoding::variable_byte_integer::VariableByteIntegerEncoder;
+use crate::utils::rng_generator::CountingRng;
+
+// Test vector from RFC 7677
+const CLIENT_NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+const CLIENT_FIRST: &[u8] = b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+const SERVER_FIRST: &[u8] =
+    b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+const CLIENT_FINAL: &[u8] = b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+const SERVER_FINAL: &[u8] = b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+/// Rng returning bytes of the RFC 7677 client nonce.
+struct NonceRng;
+
+impl RngCore for NonceRng {
+    fn next_u32(&mut self) -> u32 {
+        0
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        0
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let len = STANDARD.decode_slice(CLIENT_NONCE, dest).unwrap();
+        assert_eq!(len, dest.len());
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl CryptoRng for NonceRng {}
+
+/// Broker side of the connection replaying scripted packets and recording
+/// everything written by the client.
+struct ScriptedBroker<'a> {
+    incoming: &'a [u8],
+    written: &'a mut Vec<u8>,
+}
+
+impl<'a> ErrorType for ScriptedBroker<'a> {
+    type Error = ErrorKind;
+}
+
+impl<'a> Read for ScriptedBroker<'a> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        let len = buf.len().min(self.incoming.len());
+        buf[..len].copy_from_slice(&self.incoming[..len]);
+        self.incoming = &self.incoming[len..];
+        Ok(len)
+    }
+}
+
+impl<'a> Write for ScriptedBroker<'a> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+/// Encodes packet with the `header` bytes after the fixed header followed
+/// by the SCRAM authentication method and `data` properties.
+fn packet(first_byte: u8, header: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut properties = vec![0x15, 0x00, SCRAM_SHA_256.len() as u8];
+    properties.extend_from_slice(SCRAM_SHA_256.as_bytes());
+    properties.extend_from_slice(&[0x16, 0x00, data.len() as u8]);
+    properties.extend_from_slice(data);
+
+    let mut body = header.to_vec();
+    let property_len = VariableByteIntegerEncoder::encode(properties.len() as u32).unwrap();
+    body.extend_from_slice(&property_len[..VariableByteIntegerEncoder::len(property_len)]);
+    body.extend_from_slice(&properties);
+
+    let mut packet = vec![first_byte];
+    let remaining_len = VariableByteIntegerEncoder::encode(body.len() as u32).unwrap();
+    packet.extend_from_slice(&remaining_len[..VariableByteIntegerEncoder::len(remaining_len)]);
+    packet.extend_from_slice(&body);
+    packet
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+#[test]
+fn test_rfc7677_exchange() {
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    assert_eq!(scram.method(), "SCRAM-SHA-256");
+    assert_eq!(scram.start(), Ok(()));
+    assert_eq!(scram.response(), CLIENT_FIRST);
+    assert_eq!(scram.challenge(SERVER_FIRST), Ok(()));
+    assert_eq!(scram.response(), CLIENT_FINAL);
+    assert_eq!(scram.finish(SERVER_FINAL), Ok(()));
+    assert!(scram.is_done());
+}
+
+#[test]
+fn test_wrong_server_signature() {
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    scram.start().unwrap();
+    scram.challenge(SERVER_FIRST).unwrap();
+    assert_eq!(
+        scram.finish(b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA="),
+        Err(AuthError::ServerVerificationFailed)
+    );
+    assert_eq!(
+        scram.finish(b"e=invalid-proof"),
+        Err(AuthError::ServerVerificationFailed)
+    );
+    assert!(!scram.is_done());
+}
+
+#[test]
+fn test_invalid_challenge() {
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    assert_eq!(
+        scram.challenge(SERVER_FIRST),
+        Err(AuthError::UnexpectedStep)
+    );
+    scram.start().unwrap();
+    // server nonce has to extend the client nonce
+    assert_eq!(
+        scram.challenge(b"r=differentnonce,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"),
+        Err(AuthError::ServerVerificationFailed)
+    );
+    assert_eq!(
+        scram.challenge(b"s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096"),
+        Err(AuthError::MalformedChallenge)
+    );
+    assert_eq!(
+        scram.challenge(b"r=rOprNGfwEbeRWgbNEkqO%hvY,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=x"),
+        Err(AuthError::MalformedChallenge)
+    );
+}
+
+#[test]
+fn test_iteration_limit() {
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    scram.start().unwrap();
+    let challenge = std::format!(
+        "r=rOprNGfwEbeRWgbNEkqO%hvY,s=W22ZaJ0SNY7soEsUEjb6gQ==,i={}",
+        MAX_ITERATIONS + 1
+    );
+    assert_eq!(
+        scram.challenge(challenge.as_bytes()),
+        Err(AuthError::MalformedChallenge)
+    );
+
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng).with_max_iterations(1000);
+    scram.start().unwrap();
+    assert_eq!(
+        scram.challenge(SERVER_FIRST),
+        Err(AuthError::MalformedChallenge)
+    );
+}
+
+#[test]
+fn test_username_escaping() {
+    let mut scram = ScramSha256::new("a,b=c", "pencil", NonceRng);
+    scram.start().unwrap();
+    assert_eq!(scram.response(), b"n,,n=a=2Cb=3Dc,r=rOprNGfwEbeRWgbNEkqO");
+}
+
+#[tokio::test]
+async fn test_connect_and_reauthenticate() {
+    let mut incoming = packet(0xF0, &[0x18], SERVER_FIRST);
+    incoming.extend(packet(0x20, &[0x00, 0x00], SERVER_FINAL));
+    // re-authentication
+    incoming.extend(packet(0xF0, &[0x18], SERVER_FIRST));
+    incoming.extend(packet(0xF0, &[0x00], SERVER_FINAL));
+
+    let mut written = Vec::new();
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    {
+        let mut write_buffer = [0; 200];
+        let mut recv_buffer = [0; 200];
+        let config = ClientConfig::<3, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+        let mut client = MqttClient::new(
+            ScriptedBroker {
+                incoming: &incoming,
+                written: &mut written,
+            },
+            &mut write_buffer,
+            200,
+            &mut recv_buffer,
+            200,
+            config,
+        );
+        client.set_authenticator(&mut scram);
+        assert_eq!(client.connect_to_broker().await, Ok(false));
+        assert_eq!(client.reauthenticate().await, Ok(()));
+    }
+    assert!(scram.is_done());
+
+    // CONNECT with the client-first message
+    assert_eq!(written[0], 0x10);
+    assert!(contains(&written, SCRAM_SHA_256.as_bytes()));
+    assert!(contains(&written, CLIENT_FIRST));
+    // AUTH continue with the client-final message
+    let auth = packet(0xF0, &[0x18], CLIENT_FINAL);
+    assert!(contains(&written, &auth));
+    // AUTH re-authenticate with the new client-first message
+    let reauth = packet(0xF0, &[0x19], CLIENT_FIRST);
+    assert!(contains(&written, &reauth));
+}
+
+#[tokio::test]
+async fn test_wrong_server_final_fails_connect() {
+    let mut incoming = packet(0xF0, &[0x18], SERVER_FIRST);
+    incoming.extend(packet(
+        0x20,
+        &[0x00, 0x00],
+        b"v=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+    ));
+
+    let mut written = Vec::new();
+    let mut scram = ScramSha256::new("user", "pencil", NonceRng);
+    let mut write_buffer = [0; 200];
+    let mut recv_buffer = [0; 200];
+    let config = ClientConfig::<3, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        ScriptedBroker {
+            incoming: &incoming,
+            written: &mut written,
+        },
+        &mut write_buffer,
+        200,
+        &mut recv_buffer,
+        200,
+        config,
+    );
+    client.set_authenticator(&mut scram);
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::Auth(AuthError::ServerVerificationFailed))
+    );
+}
+
+#[tokio::test]
+async fn test_auth_without_authenticator() {
+    let incoming = packet(0xF0, &[0x18], SERVER_FIRST);
+    let mut written = Vec::new();
+    let mut write_buffer = [0; 200];
+    let mut recv_buffer = [0; 200];
+    let config = ClientConfig::<3, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        ScriptedBroker {
+            incoming: &incoming,
+            written: &mut written,
+        },
+        &mut write_buffer,
+        200,
+        &mut recv_buffer,
+        200,
+        config,
+    );
+    assert!(matches!(
+        client.connect_to_broker().await,
+        Err(ClientError::Protocol(_))
+    ));
+    assert_eq!(
+        client.reauthenticate().await,
+        Err(ClientError::Auth(AuthError::NotConfigured))
+    );
+}
@@ -0,0 +1,194 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::client::client_error::{ClientError, ProtocolViolation};
+use crate::client::packet_observer::Direction;
+use crate::client::stats::{ClientStats, Latency, StatsRecorder, MAX_PENDING_ACKS};
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::reason_codes::ReasonCode;
+use crate::utils::clock::Clock;
+use crate::utils::types::BufferError;
+
+struct TestClock(AtomicU64);
+
+impl Clock for TestClock {
+    fn now_micros(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl TestClock {
+    fn advance(&self, micros: u64) {
+        self.0.fetch_add(micros, Ordering::Relaxed);
+    }
+}
+
+fn publish(identifier: u16) -> [u8; 10] {
+    let [msb, lsb] = identifier.to_be_bytes();
+    [0x32, 8, 0, 1, b't', msb, lsb, 0, b'h', b'i']
+}
+
+fn puback(identifier: u16) -> [u8; 4] {
+    let [msb, lsb] = identifier.to_be_bytes();
+    [0x40, 2, msb, lsb]
+}
+
+#[test]
+fn test_packet_counters() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(0));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    recorder.on_send(&publish(1));
+    recorder.on_send(&publish(2));
+    recorder.on_receive(&puback(1));
+    recorder.on_receive(&[0xD0, 0]);
+
+    assert_eq!(stats.packets(Direction::Outbound, PacketType::Publish), 2);
+    assert_eq!(stats.bytes(Direction::Outbound, PacketType::Publish), 20);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Puback), 1);
+    assert_eq!(stats.bytes(Direction::Inbound, PacketType::Puback), 4);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Pingresp), 1);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Publish), 0);
+}
+
+#[test]
+fn test_retransmissions() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(0));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    let mut redelivered = publish(1);
+    redelivered[0] |= 0x08;
+    recorder.on_send(&publish(1));
+    recorder.on_send(&redelivered);
+    recorder.on_receive(&publish(2));
+    recorder.on_receive(&redelivered);
+    recorder.on_receive(&puback(1));
+
+    assert_eq!(stats.retransmissions(), 2);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Publish), 2);
+}
+
+#[test]
+fn test_ack_latency() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(1_000_000));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    assert_eq!(stats.ack_latency(), Latency::default());
+
+    recorder.on_send(&publish(1));
+    recorder.on_send(&publish(2));
+    clock.advance(1000);
+    recorder.on_receive(&puback(2));
+    clock.advance(2000);
+    recorder.on_receive(&puback(1));
+    // Unknown and repeated acknowledgements are not measured
+    recorder.on_receive(&puback(1));
+    recorder.on_receive(&puback(9));
+    recorder.on_send(&publish(3));
+    clock.advance(4000);
+    recorder.on_receive(&puback(3));
+
+    assert_eq!(
+        stats.ack_latency(),
+        Latency {
+            samples: 3,
+            min_micros: 1000,
+            avg_micros: 2666,
+            max_micros: 4000,
+        }
+    );
+}
+
+#[test]
+fn test_pending_acks_overflow() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(0));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    for identifier in 0..=MAX_PENDING_ACKS as u16 {
+        recorder.on_send(&publish(identifier));
+    }
+    clock.advance(500);
+    recorder.on_receive(&puback(0));
+    assert_eq!(stats.ack_latency().samples, 0);
+    recorder.on_receive(&puback(MAX_PENDING_ACKS as u16));
+    assert_eq!(stats.ack_latency().samples, 1);
+}
+
+#[test]
+fn test_ping_rtt() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(0));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    // PINGRESP without PINGREQ
+    recorder.on_receive(&[0xD0, 0]);
+    recorder.on_send(&[0xC0, 0]);
+    clock.advance(250);
+    recorder.on_receive(&[0xD0, 0]);
+    assert_eq!(
+        stats.ping_rtt(),
+        Latency {
+            samples: 1,
+            min_micros: 250,
+            avg_micros: 250,
+            max_micros: 250,
+        }
+    );
+}
+
+#[test]
+fn test_errors_and_disconnects() {
+    let stats = ClientStats::new();
+    let clock = TestClock(AtomicU64::new(0));
+    let mut recorder = StatsRecorder::new(&stats, &clock);
+    recorder.on_send_error(&publish(1), &ClientError::Transport(()));
+    recorder.on_send_error(&[0xC0, 0], &ClientError::Transport(()));
+    stats.record_error(&ClientError::<()>::Decode(BufferError::DecodingError));
+    stats.record_error(&ClientError::<()>::Protocol(
+        ProtocolViolation::MalformedRemainingLength,
+    ));
+    stats.record_error(&ClientError::<()>::NotConnected);
+    assert_eq!(stats.transport_errors(), 2);
+    assert_eq!(stats.publish_failures(), 1);
+    assert_eq!(stats.decode_errors(), 1);
+    assert_eq!(stats.protocol_errors(), 1);
+    assert_eq!(stats.packets(Direction::Outbound, PacketType::Publish), 0);
+
+    assert_eq!(stats.last_disconnect_reason(), None);
+    stats.record_disconnect(ReasonCode::ServerShuttingDown.into());
+    stats.record_disconnect(ReasonCode::Success.into());
+    stats.record_disconnect(ReasonCode::ServerShuttingDown.into());
+    stats.record_disconnect(ReasonCode::TopicAliasInvalid.into());
+    assert_eq!(stats.disconnects(), 4);
+    assert_eq!(
+        stats.last_disconnect_reason(),
+        Some(ReasonCode::TopicAliasInvalid)
+    );
+    assert_eq!(stats.disconnects_with(ReasonCode::ServerShuttingDown), 2);
+    assert_eq!(stats.disconnects_with(ReasonCode::Success), 1);
+    assert_eq!(stats.disconnects_with(ReasonCode::KeepAliveTimeout), 0);
+    assert_eq!(stats.disconnects_with(ReasonCode::TopicAliasInvalid), 0);
+    assert_eq!(stats.other_disconnects(), 1);
+}
@@ -0,0 +1,137 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::client::raw_client::ReceivedMessage;
+use crate::codec::{Codec, CodecError};
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::types::EncodedString;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading<'a> {
+    sensor: &'a str,
+    temperature: i16,
+    ok: bool,
+}
+
+const READING: Reading<'static> = Reading {
+    sensor: "kitchen",
+    temperature: -12,
+    ok: true,
+};
+
+fn codecs() -> [Codec; 3] {
+    [Codec::Json, Codec::Postcard, Codec::Cbor]
+}
+
+fn message<'a>(payload: &'a [u8], content_type: &'a str) -> ReceivedMessage<'a> {
+    let mut properties = Vec::new();
+    properties
+        .push(Property::ContentType(EncodedString {
+            string: content_type,
+            len: content_type.len() as u16,
+        }))
+        .unwrap();
+    ReceivedMessage {
+        topic: "sensors",
+        payload,
+        qos: QualityOfService::QoS0,
+        retain: false,
+        properties,
+        duplicate: false,
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    for codec in codecs() {
+        let mut buffer = [0; 64];
+        let len = codec.encode(&READING, &mut buffer).unwrap();
+        let decoded: Reading = codec.decode(&buffer[..len]).unwrap();
+        assert_eq!(decoded, READING);
+    }
+}
+
+#[test]
+fn test_json_payload() {
+    let mut buffer = [0; 64];
+    let len = Codec::Json.encode(&READING, &mut buffer).unwrap();
+    assert_eq!(
+        &buffer[..len],
+        br#"{"sensor":"kitchen","temperature":-12,"ok":true}"#
+    );
+}
+
+#[test]
+fn test_buffer_too_small() {
+    for codec in codecs() {
+        let mut buffer = [0; 4];
+        assert_eq!(
+            codec.encode(&READING, &mut buffer),
+            Err(CodecError::BufferTooSmall)
+        );
+    }
+}
+
+#[test]
+fn test_decode_invalid() {
+    let result: Result<Reading, _> = Codec::Json.decode(b"{\"sensor\":");
+    assert_eq!(result, Err(CodecError::Deserialize));
+}
+
+#[test]
+fn test_content_type() {
+    for codec in codecs() {
+        assert_eq!(Codec::from_content_type(codec.content_type()), Some(codec));
+        let properties = codec.properties();
+        assert_eq!(properties.len(), 2);
+        assert!(matches!(
+            properties[0],
+            Property::PayloadFormat(format) if format == codec.payload_format()
+        ));
+    }
+    assert_eq!(
+        Codec::from_content_type("Application/JSON; charset=utf-8"),
+        Some(Codec::Json)
+    );
+    assert_eq!(Codec::from_content_type("text/plain"), None);
+}
+
+#[test]
+fn test_received_message_decode() {
+    let mut buffer = [0; 64];
+    let len = Codec::Postcard.encode(&READING, &mut buffer).unwrap();
+    let received = message(&buffer[..len], "application/x-postcard");
+    assert_eq!(received.codec(), Some(Codec::Postcard));
+    assert_eq!(received.decode::<Reading>(), Ok(READING));
+
+    let received = message(b"hello", "text/plain");
+    assert_eq!(
+        received.decode::<Reading>(),
+        Err(CodecError::UnknownContentType)
+    );
+}
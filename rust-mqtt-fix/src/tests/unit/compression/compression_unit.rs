@@ -0,0 +1,164 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use heapless::Vec;
+
+use crate::client::raw_client::ReceivedMessage;
+use crate::compression::{
+    compress, decompress, decompress_with, CompressionError, ContentEncoding,
+};
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::types::{EncodedString, StringPair};
+
+const READINGS: &[u8] = br#"[{"sensor":"dht11","temperature":21.5,"humidity":40},{"sensor":"dht11","temperature":21.6,"humidity":41},{"sensor":"dht11","temperature":21.6,"humidity":40}]"#;
+
+fn message<'a>(payload: &'a [u8], encoding: Option<&'a str>) -> ReceivedMessage<'a> {
+    let mut properties = Vec::new();
+    if let Some(encoding) = encoding {
+        let _ = properties.push(Property::UserProperty(StringPair {
+            name: EncodedString {
+                string: "content-encoding",
+                len: 16,
+            },
+            value: EncodedString {
+                string: encoding,
+                len: encoding.len() as u16,
+            },
+        }));
+    }
+    ReceivedMessage {
+        topic: "t",
+        payload,
+        qos: QualityOfService::QoS0,
+        retain: false,
+        properties,
+        duplicate: false,
+    }
+}
+
+#[test]
+fn test_bit_stream() {
+    // Literal 'a' and back reference of 3 bytes at distance 1
+    let mut buffer = [0; 8];
+    let len = compress(b"aaaa", &mut buffer).unwrap();
+    assert_eq!(&buffer[..len], &[0xB0, 0x80, 0x08]);
+    let mut output = [0; 8];
+    assert_eq!(decompress(&buffer[..len], &mut output), Ok(4));
+    assert_eq!(&output[..4], b"aaaa");
+}
+
+#[test]
+fn test_round_trip() {
+    let mut compressed = [0; 256];
+    let len = compress(READINGS, &mut compressed).unwrap();
+    assert!(len * 2 < READINGS.len());
+    let mut output = [0; 256];
+    let out_len = decompress(&compressed[..len], &mut output).unwrap();
+    assert_eq!(&output[..out_len], READINGS);
+}
+
+#[test]
+fn test_incompressible_and_empty() {
+    let input: std::vec::Vec<u8> = (0..=255).collect();
+    let mut compressed = [0; 300];
+    let len = compress(&input, &mut compressed).unwrap();
+    // 9 bits per literal
+    assert_eq!(len, 288);
+    let mut output = [0; 256];
+    assert_eq!(decompress(&compressed[..len], &mut output), Ok(256));
+    assert_eq!(&output[..], &input[..]);
+
+    assert_eq!(compress(b"", &mut compressed), Ok(0));
+    assert_eq!(decompress(b"", &mut output), Ok(0));
+}
+
+#[test]
+fn test_buffer_too_small() {
+    let mut compressed = [0; 4];
+    assert_eq!(
+        compress(READINGS, &mut compressed),
+        Err(CompressionError::BufferTooSmall)
+    );
+    let mut compressed = [0; 256];
+    let len = compress(READINGS, &mut compressed).unwrap();
+    let mut output = [0; 16];
+    assert_eq!(
+        decompress(&compressed[..len], &mut output),
+        Err(CompressionError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn test_streaming_output_larger_than_input() {
+    let input = [b'x'; 1000];
+    let mut compressed = [0; 128];
+    let len = compress(&input, &mut compressed).unwrap();
+    assert!(len < 128);
+    let mut chunks = 0;
+    let mut output = std::vec::Vec::new();
+    let total = decompress_with(&compressed[..len], |chunk| {
+        assert!(chunk.len() <= 64);
+        chunks += 1;
+        output.extend_from_slice(chunk);
+        Ok::<(), ()>(())
+    });
+    assert_eq!(total, Ok(1000));
+    assert_eq!(chunks, 16);
+    assert_eq!(output, input);
+}
+
+#[test]
+fn test_back_reference_before_start() {
+    // Reference to the zeroed window, as the reference decoder does
+    let mut output = [0xff; 4];
+    assert_eq!(decompress(&[0x00, 0x08], &mut output), Ok(2));
+    assert_eq!(&output[..2], &[0, 0]);
+}
+
+#[test]
+fn test_content_encoding() {
+    let encoding = ContentEncoding::Heatshrink;
+    assert_eq!(ContentEncoding::from_name(encoding.name()), Some(encoding));
+    assert_eq!(ContentEncoding::from_name("gzip"), None);
+
+    let mut compressed = [0; 256];
+    let len = compress(READINGS, &mut compressed).unwrap();
+    let received = message(&compressed[..len], Some(encoding.name()));
+    assert_eq!(received.content_encoding(), Some("heatshrink;w=8;l=4"));
+    let mut output = [0; 256];
+    let out_len = received.decompress(&mut output).unwrap();
+    assert_eq!(&output[..out_len], READINGS);
+
+    let plain = message(b"plain", None);
+    assert_eq!(plain.content_encoding(), None);
+    assert_eq!(plain.decompress(&mut output), Ok(5));
+    assert_eq!(&output[..5], b"plain");
+
+    let unknown = message(b"data", Some("gzip"));
+    assert_eq!(
+        unknown.decompress(&mut output),
+        Err(CompressionError::UnknownEncoding)
+    );
+}
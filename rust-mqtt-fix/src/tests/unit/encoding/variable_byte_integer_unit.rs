@@ -47,7 +47,7 @@ fn test_decode_small() {
 
 #[test]
 fn test_encode() {
-    let encoded = VariableByteIntegerEncoder::encode(211_366_5);
+    let encoded = VariableByteIntegerEncoder::encode(2_113_665);
     assert!(encoded.is_ok());
     let res = encoded.unwrap();
     assert_eq!(res, [0x81, 0x81, 0x81, 0x01]);
@@ -78,3 +78,13 @@ fn test_encode_max() {
     assert!(encoded.is_err());
     assert_eq!(encoded.unwrap_err(), BufferError::EncodingError);
 }
+
+#[test]
+fn test_decode_too_long() {
+    static BUFFER: VariableByteInteger = [0x80, 0x80, 0x80, 0x80];
+
+    let decoded = VariableByteIntegerDecoder::decode(BUFFER);
+    assert!(decoded.is_err());
+    assert_eq!(decoded.unwrap_err(), BufferError::DecodingError);
+    assert_eq!(VariableByteIntegerEncoder::len(BUFFER), 4);
+}
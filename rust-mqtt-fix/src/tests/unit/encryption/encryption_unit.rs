@@ -0,0 +1,363 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::encryption::{
+    EncryptionError, PayloadCipher, PayloadKey, PayloadProtection, ReplayWindow, HEADER_LEN,
+    OVERHEAD,
+};
+
+const AES_KEY: [u8; 16] = [7; 16];
+const CHACHA_KEY: [u8; 32] = [9; 32];
+
+const DEVICE: u8 = 1;
+const BACKEND: u8 = 2;
+
+fn seal<const KEYS: usize, const SENDERS: usize>(
+    cipher: &mut PayloadCipher<'_, KEYS, SENDERS>,
+    topic: &str,
+    payload: &[u8],
+) -> std::vec::Vec<u8> {
+    cipher.seal(topic, payload).unwrap().to_vec()
+}
+
+#[test]
+fn test_round_trip() {
+    for key in [
+        PayloadKey::aes128_gcm(1, &AES_KEY),
+        PayloadKey::chacha20_poly1305(1, &CHACHA_KEY),
+    ] {
+        let mut send_buffer = [0; 64];
+        let mut recv_buffer = [0; 64];
+        let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 0);
+        let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+        sender.add_key(key.clone()).unwrap();
+        receiver.add_key(key).unwrap();
+
+        let sealed = seal(&mut sender, "testtopic/dht11", b"21.5");
+        assert_eq!(sealed.len(), 4 + OVERHEAD);
+        assert_ne!(&sealed[HEADER_LEN..HEADER_LEN + 4], b"21.5");
+        assert_eq!(receiver.open("testtopic/dht11", &sealed), Ok(&b"21.5"[..]));
+        assert_eq!(sender.counter(), 1);
+    }
+}
+
+#[test]
+fn test_header() {
+    let mut buffer = [0; 64];
+    let mut cipher = PayloadCipher::<1, 2>::new(&mut buffer, DEVICE, 0x0102);
+    cipher
+        .add_key(PayloadKey::chacha20_poly1305(5, &CHACHA_KEY))
+        .unwrap();
+    let sealed = seal(&mut cipher, "t", b"x");
+    assert_eq!(
+        &sealed[..HEADER_LEN],
+        &[1, 2, 5, DEVICE, 0, 0, 0, 0, 0, 0, 1, 2]
+    );
+}
+
+#[test]
+fn test_tampered_payload_and_topic() {
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 0);
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+
+    let mut sealed = seal(&mut sender, "t", b"on");
+    assert_eq!(
+        receiver.open("other", &sealed),
+        Err(EncryptionError::Authentication)
+    );
+    sealed[HEADER_LEN] ^= 1;
+    assert_eq!(
+        receiver.open("t", &sealed),
+        Err(EncryptionError::Authentication)
+    );
+    // Counter is part of the nonce
+    sealed[HEADER_LEN] ^= 1;
+    sealed[HEADER_LEN - 1] ^= 1;
+    assert_eq!(
+        receiver.open("t", &sealed),
+        Err(EncryptionError::Authentication)
+    );
+}
+
+#[test]
+fn test_wrong_key_and_malformed() {
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 0);
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &[8; 16]))
+        .unwrap();
+
+    let mut sealed = seal(&mut sender, "t", b"on");
+    assert_eq!(
+        receiver.open("t", &sealed),
+        Err(EncryptionError::Authentication)
+    );
+    assert_eq!(
+        receiver.open("t", b"plaintext"),
+        Err(EncryptionError::Malformed)
+    );
+    // Key 1 is AES-128-GCM
+    sealed[1] = 2;
+    assert_eq!(receiver.open("t", &sealed), Err(EncryptionError::Malformed));
+    sealed[2] = 3;
+    assert_eq!(
+        receiver.open("t", &sealed),
+        Err(EncryptionError::UnknownKey(3))
+    );
+}
+
+#[test]
+fn test_replay_rejected() {
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 0);
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+
+    let first = seal(&mut sender, "t", b"1");
+    let second = seal(&mut sender, "t", b"2");
+    // Out of order delivery within the window is accepted once
+    assert_eq!(receiver.open("t", &second), Ok(&b"2"[..]));
+    assert_eq!(receiver.open("t", &first), Ok(&b"1"[..]));
+    assert_eq!(receiver.open("t", &first), Err(EncryptionError::Replay));
+    assert_eq!(receiver.open("t", &second), Err(EncryptionError::Replay));
+}
+
+#[test]
+fn test_senders_use_distinct_nonces() {
+    let mut device_buffer = [0; 64];
+    let mut backend_buffer = [0; 64];
+    let mut device = PayloadCipher::<1, 2>::new(&mut device_buffer, DEVICE, 0);
+    let mut backend = PayloadCipher::<1, 2>::new(&mut backend_buffer, BACKEND, 0);
+    device.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    backend
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+
+    // Same key, counter and plaintext in both directions
+    let up = seal(&mut device, "t", b"same");
+    let down = seal(&mut backend, "t", b"same");
+    assert_eq!(up[3], DEVICE);
+    assert_eq!(down[3], BACKEND);
+    assert_ne!(&up[HEADER_LEN..], &down[HEADER_LEN..]);
+    assert_eq!(backend.open("t", &up), Ok(&b"same"[..]));
+    assert_eq!(device.open("t", &down), Ok(&b"same"[..]));
+    // Sender id is part of the nonce
+    let mut spoofed = up.clone();
+    spoofed[3] = 3;
+    assert_eq!(
+        backend.open("t", &spoofed),
+        Err(EncryptionError::Authentication)
+    );
+}
+
+#[test]
+fn test_replay_window_per_sender() {
+    let mut first_buffer = [0; 64];
+    let mut second_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut first = PayloadCipher::<1, 1>::new(&mut first_buffer, 10, 5);
+    let mut second = PayloadCipher::<1, 1>::new(&mut second_buffer, 11, 0);
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    first.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    second.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+
+    let from_first = seal(&mut first, "t", b"1");
+    let from_second = seal(&mut second, "t", b"2");
+    assert_eq!(receiver.open("t", &from_first), Ok(&b"1"[..]));
+    // Lower counter of another sender is not a replay
+    assert_eq!(receiver.open("t", &from_second), Ok(&b"2"[..]));
+    assert_eq!(
+        receiver.open("t", &from_second),
+        Err(EncryptionError::Replay)
+    );
+    assert_eq!(
+        receiver
+            .replay_window(10)
+            .and_then(|window| window.highest()),
+        Some(5)
+    );
+    assert_eq!(receiver.replay_window(12), None);
+
+    let mut third_buffer = [0; 64];
+    let mut third = PayloadCipher::<1, 1>::new(&mut third_buffer, 12, 0);
+    third.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    assert_eq!(
+        receiver.open("t", &seal(&mut third, "t", b"3")),
+        Err(EncryptionError::TooManySenders)
+    );
+}
+
+#[test]
+fn test_restored_replay_window() {
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 0);
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    let old = seal(&mut sender, "t", b"old");
+    let new = seal(&mut sender, "t", b"new");
+
+    // Receiver restarted with the counter persisted after the first message
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+    receiver
+        .set_replay_window(DEVICE, ReplayWindow::after(0))
+        .unwrap();
+    assert_eq!(receiver.open("t", &old), Err(EncryptionError::Replay));
+    assert_eq!(receiver.open("t", &new), Ok(&b"new"[..]));
+}
+
+#[test]
+fn test_replay_window() {
+    let mut window = ReplayWindow::new();
+    assert!(window.check(100));
+    window.accept(100);
+    assert!(!window.check(100));
+    assert!(window.check(99));
+    assert!(window.check(37));
+    assert!(!window.check(36));
+    window.accept(99);
+    assert!(!window.check(99));
+
+    window.accept(300);
+    assert!(!window.check(100));
+    assert!(window.check(299));
+    assert!(window.check(301));
+
+    let window = ReplayWindow::after(100);
+    assert_eq!(window.highest(), Some(100));
+    assert!(!window.check(100));
+    assert!(!window.check(99));
+    assert!(window.check(101));
+}
+
+#[test]
+fn test_failed_message_does_not_move_window() {
+    let mut forge_buffer = [0; 64];
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut forger = PayloadCipher::<1, 2>::new(&mut forge_buffer, DEVICE, 1000);
+    let mut sender = PayloadCipher::<1, 2>::new(&mut send_buffer, DEVICE, 1);
+    let mut receiver = PayloadCipher::<1, 2>::new(&mut recv_buffer, BACKEND, 0);
+    forger.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+
+    let mut forged = seal(&mut forger, "t", b"x");
+    forged[HEADER_LEN] ^= 1;
+    assert_eq!(
+        receiver.open("t", &forged),
+        Err(EncryptionError::Authentication)
+    );
+    let genuine = seal(&mut sender, "t", b"y");
+    assert_eq!(receiver.open("t", &genuine), Ok(&b"y"[..]));
+}
+
+#[test]
+fn test_key_rotation() {
+    let mut send_buffer = [0; 64];
+    let mut recv_buffer = [0; 64];
+    let mut sender = PayloadCipher::<2, 2>::new(&mut send_buffer, DEVICE, 0);
+    let mut receiver = PayloadCipher::<2, 2>::new(&mut recv_buffer, BACKEND, 0);
+    sender.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    receiver
+        .add_key(PayloadKey::aes128_gcm(1, &AES_KEY))
+        .unwrap();
+    receiver
+        .add_key(PayloadKey::chacha20_poly1305(2, &CHACHA_KEY))
+        .unwrap();
+
+    let old = seal(&mut sender, "t", b"old");
+    sender
+        .rotate(PayloadKey::chacha20_poly1305(2, &CHACHA_KEY))
+        .unwrap();
+    assert_eq!(sender.active_key(), Some(2));
+    let new = seal(&mut sender, "t", b"new");
+    assert_eq!(new[2], 2);
+    assert_eq!(receiver.open("t", &new), Ok(&b"new"[..]));
+    assert_eq!(receiver.open("t", &old), Ok(&b"old"[..]));
+
+    receiver.remove_key(1);
+    assert_eq!(
+        receiver.open("t", &old),
+        Err(EncryptionError::UnknownKey(1))
+    );
+    assert_eq!(
+        receiver.add_key(PayloadKey::aes128_gcm(3, &AES_KEY)),
+        Ok(())
+    );
+    assert_eq!(
+        receiver.add_key(PayloadKey::aes128_gcm(4, &AES_KEY)),
+        Err(EncryptionError::TooManyKeys)
+    );
+}
+
+#[test]
+fn test_errors() {
+    let mut buffer = [0; 8];
+    let mut cipher = PayloadCipher::<1, 2>::new(&mut buffer, DEVICE, 0);
+    assert_eq!(cipher.seal("t", b"x"), Err(EncryptionError::NoActiveKey));
+    assert_eq!(
+        cipher.set_active_key(1),
+        Err(EncryptionError::UnknownKey(1))
+    );
+    cipher.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    assert_eq!(cipher.seal("t", b"x"), Err(EncryptionError::BufferTooSmall));
+
+    let mut buffer = [0; 64];
+    let mut cipher = PayloadCipher::<1, 2>::new(&mut buffer, DEVICE, u64::MAX);
+    cipher.add_key(PayloadKey::aes128_gcm(1, &AES_KEY)).unwrap();
+    assert_eq!(
+        cipher.seal("t", b"x"),
+        Err(EncryptionError::CounterExhausted)
+    );
+}
+
+#[test]
+fn test_key_debug_hides_key() {
+    let key = PayloadKey::aes128_gcm(1, &AES_KEY);
+    assert_eq!(
+        std::format!("{:?}", key),
+        "PayloadKey { id: 1, algorithm: Aes128Gcm }"
+    );
+}
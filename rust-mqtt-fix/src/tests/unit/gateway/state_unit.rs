@@ -0,0 +1,195 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::gateway::state::{GatewayState, Route};
+use crate::packet::sn::sn_packet::{ReturnCode, SnQoS, TopicId, TopicRef};
+
+type State = GatewayState<'static, u8, 2, 4, 2>;
+
+const PREDEFINED: &[(u16, &str)] = &[(1, "config/node")];
+
+fn state() -> State {
+    let mut state = State::new();
+    state.set_predefined(PREDEFINED);
+    state
+}
+
+#[test]
+fn test_register_shares_topic_ids() {
+    let mut state = state();
+    let (first, _) = state.connect(1, "first", true).unwrap();
+    let (second, _) = state.connect(2, "second", true).unwrap();
+
+    assert_eq!(state.register(first, "sensor/1"), Ok(1));
+    assert_eq!(state.register(second, "sensor/2"), Ok(2));
+    assert_eq!(state.register(second, "sensor/1"), Ok(1));
+    assert_eq!(
+        state.register(first, "sensor/#"),
+        Err(ReturnCode::NotSupported)
+    );
+
+    assert_eq!(state.topic_name(TopicId::Normal(2)).unwrap(), "sensor/2");
+    assert_eq!(
+        state.topic_name(TopicId::Predefined(1)).unwrap(),
+        "config/node"
+    );
+    assert_eq!(state.topic_name(TopicId::Short(*b"ab")).unwrap(), "ab");
+    assert_eq!(
+        state.topic_name(TopicId::Normal(0)),
+        Err(ReturnCode::InvalidTopicId)
+    );
+    assert_eq!(
+        state.topic_name(TopicId::Predefined(2)),
+        Err(ReturnCode::InvalidTopicId)
+    );
+}
+
+#[test]
+fn test_connect_limits_and_clean_session() {
+    let mut state = state();
+    let (first, _) = state.connect(1, "first", true).unwrap();
+    state.connect(2, "second", true).unwrap();
+    assert_eq!(state.connect(3, "third", true), Err(ReturnCode::Congestion));
+    assert_eq!(state.connect(3, "", true), Err(ReturnCode::NotSupported));
+
+    let subscribed = state
+        .subscribe(first, TopicRef::Name("a/+"), SnQoS::QoS2)
+        .unwrap();
+    assert_eq!(subscribed.qos, SnQoS::QoS1);
+    assert_eq!(subscribed.topic_id, 0);
+    assert!(subscribed.new_filter);
+
+    // Reconnect from other address keeps the session
+    let (resumed, unused) = state.connect(4, "first", false).unwrap();
+    assert_eq!(resumed, first);
+    assert!(unused.is_empty());
+    assert_eq!(state.find(4), Some(first));
+    assert!(state.route(first, "a/b").is_some());
+
+    // Clean session drops the subscriptions
+    let (_, unused) = state.connect(4, "first", true).unwrap();
+    assert_eq!(unused.len(), 1);
+    assert_eq!(unused[0].as_str(), "a/+");
+    assert!(state.route(first, "a/b").is_none());
+}
+
+#[test]
+fn test_shared_filter_reference_counting() {
+    let mut state = state();
+    let (first, _) = state.connect(1, "first", true).unwrap();
+    let (second, _) = state.connect(2, "second", true).unwrap();
+
+    let subscribed = state
+        .subscribe(first, TopicRef::Name("sensor/1"), SnQoS::QoS0)
+        .unwrap();
+    assert!(subscribed.new_filter);
+    assert_eq!(subscribed.topic_id, 1);
+    let subscribed = state
+        .subscribe(second, TopicRef::Name("sensor/1"), SnQoS::QoS1)
+        .unwrap();
+    assert!(!subscribed.new_filter);
+    assert_eq!(subscribed.topic_id, 1);
+
+    assert_eq!(state.unsubscribe(first, TopicRef::Name("sensor/1")), None);
+    assert_eq!(state.unsubscribe(first, TopicRef::Name("sensor/1")), None);
+    let unused = state.remove(state.find(2).unwrap());
+    assert_eq!(unused.len(), 1);
+    assert_eq!(state.session_count(), 1);
+}
+
+#[test]
+fn test_route_registers_wildcard_topics() {
+    let mut state = state();
+    let (session, _) = state.connect(1, "node", true).unwrap();
+    state
+        .subscribe(session, TopicRef::Name("sensor/+"), SnQoS::QoS0)
+        .unwrap();
+    state
+        .subscribe(session, TopicRef::Name("sensor/#"), SnQoS::QoS1)
+        .unwrap();
+
+    let route = state.route(session, "sensor/1").unwrap();
+    assert_eq!(
+        route,
+        Route {
+            qos: SnQoS::QoS1,
+            topic: TopicId::Normal(1),
+            register: true,
+        }
+    );
+    state.mark_registered(session, 1);
+    assert!(!state.route(session, "sensor/1").unwrap().register);
+    assert_eq!(state.route(session, "other"), None);
+}
+
+#[test]
+fn test_route_predefined_and_short_topics() {
+    let mut state = state();
+    let (session, _) = state.connect(1, "node", true).unwrap();
+    let subscribed = state
+        .subscribe(session, TopicRef::Predefined(1), SnQoS::QoS0)
+        .unwrap();
+    assert_eq!(subscribed.filter.as_str(), "config/node");
+    assert_eq!(subscribed.topic_id, 1);
+    state
+        .subscribe(session, TopicRef::Short(*b"ab"), SnQoS::QoS0)
+        .unwrap();
+    assert_eq!(
+        state.subscribe(session, TopicRef::Predefined(9), SnQoS::QoS0),
+        Err(ReturnCode::InvalidTopicId)
+    );
+    assert_eq!(
+        state.subscribe(session, TopicRef::Short(*b"a#"), SnQoS::QoS0),
+        Err(ReturnCode::NotSupported)
+    );
+
+    let route = state.route(session, "config/node").unwrap();
+    assert_eq!(route.topic, TopicId::Predefined(1));
+    assert!(!route.register);
+    let route = state.route(session, "ab").unwrap();
+    assert_eq!(route.topic, TopicId::Short(*b"ab"));
+}
+
+#[test]
+fn test_sleeping_client_queue() {
+    let mut state = state();
+    let (session, _) = state.connect(1, "node", true).unwrap();
+    state.sleep(session);
+    assert!(state.is_asleep(session));
+
+    state.enqueue(session, "a", b"1");
+    state.enqueue(session, "b", b"2");
+    state.enqueue(session, "c", b"3");
+    assert_eq!(state.find_client("node"), Some(session));
+
+    let first = state.dequeue(session).unwrap();
+    assert_eq!(first.topic.as_str(), "a");
+    assert_eq!(first.payload.as_slice(), b"1");
+    assert_eq!(state.dequeue(session).unwrap().topic.as_str(), "b");
+    // Queue holds two messages, the third one is dropped
+    assert!(state.dequeue(session).is_none());
+
+    state.connect(1, "node", false).unwrap();
+    assert!(!state.is_asleep(session));
+}
@@ -0,0 +1,178 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+use crate::client::client_config::{ClientConfig, MqttVersion};
+use crate::homeassistant::{
+    Availability, DeviceInfo, Discovery, DiscoveryError, Entity, StateClass,
+};
+use crate::utils::rng_generator::CountingRng;
+
+const ENTITIES: [Entity<'static>; 5] = [
+    Entity::sensor("temperature", "Temperature", "node1/temperature")
+        .with_unit("°C")
+        .with_device_class("temperature")
+        .with_state_class(StateClass::Measurement),
+    Entity::binary_sensor("door", "Door", "node1/door").with_device_class("door"),
+    Entity::switch("relay", "Relay", "node1/relay", "node1/relay/set"),
+    Entity::light("led", "LED", "node1/led", "node1/led/set"),
+    Entity::button("restart", "Restart", "node1/restart"),
+];
+
+const DEVICE: DeviceInfo<'static> = DeviceInfo::new("aabbccddeeff", "Node 1")
+    .with_manufacturer("ACME")
+    .with_model("ESP32")
+    .with_sw_version("1.0.0");
+
+fn discovery() -> Discovery<'static> {
+    Discovery::new(
+        "node1",
+        DEVICE,
+        Availability::new("node1/availability"),
+        &ENTITIES,
+    )
+}
+
+fn config(discovery: &Discovery<'_>, entity: &Entity<'_>) -> std::string::String {
+    let mut buffer = [0; 512];
+    let len = discovery.write_config(entity, &mut buffer).unwrap();
+    std::string::String::from_utf8(buffer[..len].to_vec()).unwrap()
+}
+
+#[test]
+fn test_config_topics() {
+    let discovery = discovery();
+    let topics: std::vec::Vec<_> = ENTITIES
+        .iter()
+        .map(|entity| discovery.config_topic(entity).unwrap())
+        .collect();
+    assert_eq!(topics[0], "homeassistant/sensor/node1/temperature/config");
+    assert_eq!(topics[1], "homeassistant/binary_sensor/node1/door/config");
+    assert_eq!(topics[2], "homeassistant/switch/node1/relay/config");
+    assert_eq!(topics[3], "homeassistant/light/node1/led/config");
+    assert_eq!(topics[4], "homeassistant/button/node1/restart/config");
+    let custom = discovery.with_prefix("ha");
+    assert_eq!(
+        custom.config_topic(&ENTITIES[0]).unwrap(),
+        "ha/sensor/node1/temperature/config"
+    );
+}
+
+#[test]
+fn test_sensor_config() {
+    assert_eq!(
+        config(&discovery(), &ENTITIES[0]),
+        concat!(
+            r#"{"name":"Temperature","unique_id":"node1_temperature","#,
+            r#""state_topic":"node1/temperature","unit_of_measurement":"°C","#,
+            r#""device_class":"temperature","state_class":"measurement","#,
+            r#""availability_topic":"node1/availability","payload_available":"online","#,
+            r#""payload_not_available":"offline","device":{"identifiers":["aabbccddeeff"],"#,
+            r#""name":"Node 1","manufacturer":"ACME","model":"ESP32","sw_version":"1.0.0"}}"#
+        )
+    );
+}
+
+#[test]
+fn test_switch_and_button_config() {
+    let discovery = Discovery::new(
+        "node1",
+        DeviceInfo::new("id", "Node"),
+        Availability::new("node1/availability"),
+        &ENTITIES,
+    );
+    assert_eq!(
+        config(&discovery, &ENTITIES[2]),
+        concat!(
+            r#"{"name":"Relay","unique_id":"node1_relay","state_topic":"node1/relay","#,
+            r#""command_topic":"node1/relay/set","availability_topic":"node1/availability","#,
+            r#""payload_available":"online","payload_not_available":"offline","#,
+            r#""device":{"identifiers":["id"],"name":"Node"}}"#
+        )
+    );
+    let button = config(&discovery, &ENTITIES[4]);
+    assert!(button.contains(r#""command_topic":"node1/restart""#));
+    assert!(!button.contains("state_topic"));
+}
+
+#[test]
+fn test_config_escapes_strings() {
+    let entity = Entity::sensor("t", "Say \"hi\"\\\n\u{1}", "node1/t")
+        .with_value_template("{{ value_json.temp }}");
+    let config = config(&discovery(), &entity);
+    assert!(config.starts_with(r#"{"name":"Say \"hi\"\\\n\u0001","#));
+    assert!(config.contains(r#""value_template":"{{ value_json.temp }}""#));
+}
+
+#[test]
+fn test_config_buffer_too_small() {
+    let discovery = discovery();
+    let mut buffer = [0; 64];
+    assert_eq!(
+        discovery.write_config(&ENTITIES[0], &mut buffer),
+        Err(DiscoveryError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn test_invalid_ids() {
+    let discovery = discovery();
+    let entity = Entity::sensor("temp/1", "Temperature", "node1/temperature");
+    assert_eq!(
+        discovery.config_topic(&entity),
+        Err(DiscoveryError::InvalidId)
+    );
+    let mut buffer = [0; 512];
+    assert_eq!(
+        discovery.write_config(&entity, &mut buffer),
+        Err(DiscoveryError::InvalidId)
+    );
+    let node = Discovery::new("", DEVICE, Availability::new("a"), &ENTITIES);
+    assert_eq!(
+        node.config_topic(&ENTITIES[0]),
+        Err(DiscoveryError::InvalidId)
+    );
+}
+
+#[test]
+fn test_birth_message() {
+    let discovery = discovery();
+    assert!(discovery.is_birth_message("homeassistant/status", b"online"));
+    assert!(!discovery.is_birth_message("homeassistant/status", b"offline"));
+    assert!(!discovery.is_birth_message("homeassistant/statusx", b"online"));
+    assert!(!discovery.is_birth_message("node1/status", b"online"));
+    assert_eq!(discovery.status_topic().unwrap(), "homeassistant/status");
+    assert!(discovery
+        .with_prefix("ha")
+        .is_birth_message("ha/status", b"online"));
+}
+
+#[test]
+fn test_configure_will() {
+    let availability = Availability::new("node1/availability");
+    let mut config = ClientConfig::<1, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    availability.configure_will(&mut config);
+    assert!(config.will_flag);
+    assert!(config.will_retain);
+    assert_eq!(config.will_topic.as_str(), "node1/availability");
+    assert_eq!(config.will_payload.bin, b"offline");
+}
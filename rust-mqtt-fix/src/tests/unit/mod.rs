@@ -22,6 +22,22 @@
  * SOFTWARE.
  */
 
+#[cfg(feature = "broker")]
+pub mod broker;
+pub mod client;
+#[cfg(feature = "codec-cbor")]
+pub mod codec;
+#[cfg(feature = "compression")]
+pub mod compression;
 pub mod encoding;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod gateway;
+#[cfg(feature = "homeassistant")]
+pub mod homeassistant;
+pub mod network;
 pub mod packet;
+pub mod queue;
+#[cfg(feature = "sparkplug")]
+pub mod sparkplug;
 pub mod utils;
@@ -0,0 +1,52 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embassy_time::Duration;
+
+use crate::network::embassy::{socket_timeouts, ClientBuffers};
+
+#[test]
+fn test_socket_timeouts() {
+    assert_eq!(
+        socket_timeouts(60),
+        (Some(Duration::from_secs(90)), Some(Duration::from_secs(30)))
+    );
+    assert_eq!(
+        socket_timeouts(1),
+        (
+            Some(Duration::from_millis(1500)),
+            Some(Duration::from_millis(500))
+        )
+    );
+    assert_eq!(socket_timeouts(0), (None, None));
+}
+
+#[test]
+fn test_client_buffers() {
+    let buffers = ClientBuffers::<1024, 128>::new();
+    assert_eq!(buffers.socket_rx.len(), 1024);
+    assert_eq!(buffers.socket_tx.len(), 1024);
+    assert_eq!(buffers.write.len(), 128);
+    assert_eq!(buffers.recv.len(), 128);
+}
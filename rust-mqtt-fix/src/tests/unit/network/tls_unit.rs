@@ -0,0 +1,104 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embedded_io::{Error, ErrorKind};
+
+use crate::client::client_error::ClientError;
+use crate::network::tls::{ClientCertificate, PreSharedKey, TlsConfig, TlsError};
+
+#[test]
+fn config_setters() {
+    let mut config = TlsConfig::new();
+    assert_eq!(config, TlsConfig::default());
+    config.add_server_name("broker.local");
+    config.add_ca(b"ca");
+    config.add_client_cert(b"cert", b"key");
+    config.add_psk(b"client", b"secret");
+    config.insecure_skip_verify();
+    assert!(config.insecure_skip_verify);
+    assert_eq!(config.server_name, Some("broker.local"));
+    assert_eq!(config.ca, Some(&b"ca"[..]));
+    assert_eq!(
+        config.client_cert,
+        Some(ClientCertificate {
+            certificate: b"cert",
+            private_key: b"key",
+        })
+    );
+    assert_eq!(
+        config.psk,
+        Some(PreSharedKey {
+            identity: b"client",
+            key: b"secret",
+        })
+    );
+}
+
+#[test]
+fn error_kinds_and_conversion() {
+    assert_eq!(
+        TlsError::Transport(ErrorKind::TimedOut).kind(),
+        ErrorKind::TimedOut
+    );
+    assert_eq!(
+        TlsError::<ErrorKind>::CertificateVerification.kind(),
+        ErrorKind::ConnectionRefused
+    );
+    assert_eq!(
+        TlsError::<ErrorKind>::Configuration.kind(),
+        ErrorKind::InvalidInput
+    );
+    let err: ClientError<TlsError<ErrorKind>> = TlsError::CertificateVerification.into();
+    assert_eq!(
+        err,
+        ClientError::Transport(TlsError::CertificateVerification)
+    );
+    assert!(err.is_connection_error());
+}
+
+#[cfg(feature = "rustls")]
+#[test]
+fn rustls_rejects_unsupported_config() {
+    use crate::network::tls::rustls::client_config;
+
+    // CA is required
+    let mut config = TlsConfig::new();
+    config.add_server_name("localhost");
+    assert_eq!(
+        client_config::<ErrorKind>(&config).err(),
+        Some(TlsError::Configuration)
+    );
+    config.add_ca(b"not a certificate");
+    assert_eq!(
+        client_config::<ErrorKind>(&config).err(),
+        Some(TlsError::Configuration)
+    );
+
+    let mut config = TlsConfig::new();
+    config.add_psk(b"client", b"secret");
+    assert_eq!(
+        client_config::<ErrorKind>(&config).err(),
+        Some(TlsError::Configuration)
+    );
+}
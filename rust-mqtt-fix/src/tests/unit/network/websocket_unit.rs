@@ -0,0 +1,290 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+use rand_core::RngCore;
+use sha1::{Digest, Sha1};
+
+use crate::network::websocket::{verify_response, WebSocketError, WebSocketTransport};
+use crate::utils::rng_generator::CountingRng;
+
+/// Stream with prepared incoming bytes returned at most `chunk` Bytes per read, records
+/// everything written.
+struct MockStream {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    chunk: usize,
+}
+
+impl MockStream {
+    fn new(input: &[u8], chunk: usize) -> Self {
+        Self {
+            input: input.iter().copied().collect(),
+            output: Vec::new(),
+            chunk,
+        }
+    }
+}
+
+impl ErrorType for MockStream {
+    type Error = ErrorKind;
+}
+
+impl Read for MockStream {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ErrorKind> {
+        let len = buf.len().min(self.chunk).min(self.input.len());
+        for (dst, src) in buf.iter_mut().zip(self.input.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+}
+
+impl Write for MockStream {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ErrorKind> {
+        self.output.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+}
+
+const RFC_KEY: &[u8] = b"dGhlIHNhbXBsZSBub25jZQ==";
+
+fn response(accept: &str, protocol: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\nSec-WebSocket-Protocol: {}\r\n\r\n",
+        accept, protocol
+    )
+    .into_bytes()
+}
+
+// Accept key for the handshake of the transport created with `CountingRng(0)`
+fn counting_accept() -> String {
+    let mut nonce = [0; 16];
+    CountingRng(0).fill_bytes(&mut nonce);
+    let mut key = [0; 24];
+    STANDARD.encode_slice(nonce, &mut key).unwrap();
+    let digest = Sha1::new()
+        .chain_update(key)
+        .chain_update(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11")
+        .finalize();
+    let mut accept = [0; 28];
+    STANDARD.encode_slice(digest, &mut accept).unwrap();
+    String::from_utf8(accept.to_vec()).unwrap()
+}
+
+async fn connected(input: &[u8], chunk: usize) -> WebSocketTransport<MockStream, CountingRng> {
+    let mut stream = response(&counting_accept(), "mqtt");
+    stream.extend_from_slice(input);
+    let mut buffer = [0; 256];
+    WebSocketTransport::connect(
+        MockStream::new(&stream, chunk),
+        CountingRng(0),
+        "broker.local",
+        "/mqtt",
+        &mut buffer,
+    )
+    .await
+    .unwrap()
+}
+
+// Decodes masked client frames from the written bytes, returns (opcode, payload) pairs
+fn client_frames(mut output: &[u8]) -> Vec<(u8, Vec<u8>)> {
+    let mut frames = Vec::new();
+    while !output.is_empty() {
+        assert_eq!(output[0] & 0x80, 0x80);
+        assert_eq!(output[1] & 0x80, 0x80);
+        let (len, mut pos) = match output[1] & 0x7F {
+            126 => (u16::from_be_bytes([output[2], output[3]]) as usize, 4),
+            len => (len as usize, 2),
+        };
+        let mask = [
+            output[pos],
+            output[pos + 1],
+            output[pos + 2],
+            output[pos + 3],
+        ];
+        pos += 4;
+        let payload = output[pos..pos + len]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        frames.push((output[0] & 0x0F, payload));
+        output = &output[pos + len..];
+    }
+    frames
+}
+
+#[test]
+fn test_verify_response() {
+    let valid = response("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", "mqtt");
+    assert!(verify_response(
+        core::str::from_utf8(&valid).unwrap(),
+        RFC_KEY
+    ));
+    let wrong_accept = response("AAAAAAAAAAAAAAAAAAAAAAAAAAA=", "mqtt");
+    assert!(!verify_response(
+        core::str::from_utf8(&wrong_accept).unwrap(),
+        RFC_KEY
+    ));
+    let wrong_protocol = response("s3pPLMBiTxaQ9kYGzzhZRbK+xOo=", "mqttv3.1");
+    assert!(!verify_response(
+        core::str::from_utf8(&wrong_protocol).unwrap(),
+        RFC_KEY
+    ));
+    let refused =
+        "HTTP/1.1 403 Forbidden\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n";
+    assert!(!verify_response(refused, RFC_KEY));
+}
+
+#[tokio::test]
+async fn test_handshake_request() {
+    let transport = connected(&[], 7).await;
+    let output = transport.into_inner().output;
+    let request = core::str::from_utf8(&output).unwrap();
+    assert!(request.starts_with("GET /mqtt HTTP/1.1\r\nHost: broker.local\r\n"));
+    assert!(request.contains("\r\nSec-WebSocket-Protocol: mqtt\r\n"));
+    assert!(request.contains("\r\nSec-WebSocket-Version: 13\r\n"));
+    assert!(request.ends_with("\r\n\r\n"));
+}
+
+#[tokio::test]
+async fn test_handshake_refused() {
+    let mut buffer = [0; 256];
+    let res = WebSocketTransport::connect(
+        MockStream::new(b"HTTP/1.1 404 Not Found\r\n\r\n", 64),
+        CountingRng(0),
+        "broker.local",
+        "/mqtt",
+        &mut buffer,
+    )
+    .await;
+    assert!(matches!(res, Err(WebSocketError::Handshake)));
+
+    let mut small = [0; 16];
+    let res = WebSocketTransport::connect(
+        MockStream::new(&response(&counting_accept(), "mqtt"), 64),
+        CountingRng(0),
+        "broker.local",
+        "/mqtt",
+        &mut small,
+    )
+    .await;
+    assert!(matches!(res, Err(WebSocketError::Handshake)));
+
+    let res = WebSocketTransport::connect(
+        MockStream::new(b"HTTP/1.1 101", 64),
+        CountingRng(0),
+        "broker.local",
+        "/mqtt",
+        &mut buffer,
+    )
+    .await;
+    assert!(matches!(res, Err(WebSocketError::ConnectionClosed)));
+}
+
+#[tokio::test]
+async fn test_write_masks_binary_frames() {
+    let mut transport = connected(&[], 64).await;
+    let long = [0x5A; 300];
+    assert_eq!(transport.write(&[0x10, 0x02, 0x00, 0x00]).await, Ok(4));
+    assert_eq!(transport.write(&long).await, Ok(300));
+
+    let output = transport.into_inner().output;
+    let handshake_len = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    let frames = client_frames(&output[handshake_len..]);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0], (0x2, vec![0x10, 0x02, 0x00, 0x00]));
+    assert_eq!(frames[1], (0x2, long.to_vec()));
+}
+
+#[tokio::test]
+async fn test_read_reassembles_frames_and_answers_ping() {
+    let input = [
+        // Binary frame without FIN, ping, continuation with FIN, binary with 16 bit length
+        &[0x02, 0x02, 0x20, 0x03][..],
+        &[0x89, 0x02, b'h', b'i'],
+        &[0x80, 0x02, 0x00, 0x00],
+        &[0x82, 0x7E, 0x00, 0x02, 0xD0, 0x00],
+    ]
+    .concat();
+    // One Byte per read exercises resuming of every frame part
+    let mut transport = connected(&input, 1).await;
+    let mut received = Vec::new();
+    let mut buffer = [0; 8];
+    while received.len() < 6 {
+        let len = transport.read(&mut buffer).await.unwrap();
+        received.extend_from_slice(&buffer[..len]);
+    }
+    assert_eq!(received, [0x20, 0x03, 0x00, 0x00, 0xD0, 0x00]);
+    assert_eq!(transport.read(&mut buffer).await, Ok(0));
+
+    let output = transport.into_inner().output;
+    let handshake_len = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert_eq!(
+        client_frames(&output[handshake_len..]),
+        [(0xA, b"hi".to_vec())]
+    );
+}
+
+#[tokio::test]
+async fn test_read_close_and_protocol_errors() {
+    let mut buffer = [0; 8];
+    let mut transport = connected(&[0x88, 0x02, 0x03, 0xE8, 0x82, 0x01, 0xFF], 64).await;
+    assert_eq!(transport.read(&mut buffer).await, Ok(0));
+    assert_eq!(transport.read(&mut buffer).await, Ok(0));
+    let output = transport.into_inner().output;
+    let handshake_len = output.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+    assert_eq!(
+        client_frames(&output[handshake_len..]),
+        [(0x8, vec![0x03, 0xE8])]
+    );
+
+    // Masked frame from the server
+    let mut transport = connected(&[0x82, 0x81, 0, 0, 0, 0, 0xFF], 64).await;
+    assert_eq!(
+        transport.read(&mut buffer).await,
+        Err(WebSocketError::Protocol)
+    );
+    // Text frame
+    let mut transport = connected(&[0x81, 0x01, b'a'], 64).await;
+    assert_eq!(
+        transport.read(&mut buffer).await,
+        Err(WebSocketError::Protocol)
+    );
+    // Connection closed in the middle of the frame
+    let mut transport = connected(&[0x82, 0x04, 0x01], 64).await;
+    assert_eq!(transport.read(&mut buffer).await, Ok(1));
+    assert_eq!(
+        transport.read(&mut buffer).await,
+        Err(WebSocketError::ConnectionClosed)
+    );
+}
@@ -22,4 +22,5 @@
  * SOFTWARE.
  */
 
+pub mod sn;
 pub mod v5;
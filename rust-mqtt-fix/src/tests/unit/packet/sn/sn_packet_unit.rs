@@ -0,0 +1,224 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::packet::sn::msg_type::MsgType;
+use crate::packet::sn::sn_packet::{ReturnCode, SnPacket, SnQoS, TopicId, TopicRef};
+use crate::utils::types::BufferError;
+
+fn round_trip(packet: SnPacket<'_>, expected: &[u8]) {
+    let mut buffer = [0u8; 64];
+    let len = packet.encode(&mut buffer).unwrap();
+    assert_eq!(&buffer[..len], expected);
+    assert_eq!(SnPacket::decode(&buffer[..len]), Ok(packet));
+}
+
+#[test]
+fn test_connect() {
+    round_trip(
+        SnPacket::Connect {
+            will: false,
+            clean_session: true,
+            duration: 60,
+            client_id: "node",
+        },
+        &[0x0A, 0x04, 0x04, 0x01, 0x00, 0x3C, b'n', b'o', b'd', b'e'],
+    );
+}
+
+#[test]
+fn test_register_and_regack() {
+    round_trip(
+        SnPacket::Register {
+            topic_id: 0,
+            msg_id: 1,
+            topic_name: "a/b",
+        },
+        &[0x09, 0x0A, 0x00, 0x00, 0x00, 0x01, b'a', b'/', b'b'],
+    );
+    round_trip(
+        SnPacket::Regack {
+            topic_id: 5,
+            msg_id: 1,
+            return_code: ReturnCode::Accepted,
+        },
+        &[0x07, 0x0B, 0x00, 0x05, 0x00, 0x01, 0x00],
+    );
+}
+
+#[test]
+fn test_publish_topic_id_types() {
+    round_trip(
+        SnPacket::Publish {
+            dup: false,
+            qos: SnQoS::QoS1,
+            retain: true,
+            topic: TopicId::Normal(5),
+            msg_id: 2,
+            data: b"21",
+        },
+        &[0x09, 0x0C, 0x30, 0x00, 0x05, 0x00, 0x02, b'2', b'1'],
+    );
+    round_trip(
+        SnPacket::Publish {
+            dup: true,
+            qos: SnQoS::QoSMinusOne,
+            retain: false,
+            topic: TopicId::Predefined(1),
+            msg_id: 0,
+            data: b"x",
+        },
+        &[0x08, 0x0C, 0xE1, 0x00, 0x01, 0x00, 0x00, b'x'],
+    );
+    round_trip(
+        SnPacket::Publish {
+            dup: false,
+            qos: SnQoS::QoS0,
+            retain: false,
+            topic: TopicId::short("ab").unwrap(),
+            msg_id: 0,
+            data: b"",
+        },
+        &[0x07, 0x0C, 0x02, b'a', b'b', 0x00, 0x00],
+    );
+    assert_eq!(TopicId::short("abc"), None);
+}
+
+#[test]
+fn test_subscribe_topic_ref_types() {
+    round_trip(
+        SnPacket::Subscribe {
+            dup: false,
+            qos: SnQoS::QoS1,
+            msg_id: 3,
+            topic: TopicRef::Name("a/#"),
+        },
+        &[0x08, 0x12, 0x20, 0x00, 0x03, b'a', b'/', b'#'],
+    );
+    round_trip(
+        SnPacket::Subscribe {
+            dup: false,
+            qos: SnQoS::QoS0,
+            msg_id: 4,
+            topic: TopicRef::Predefined(7),
+        },
+        &[0x07, 0x12, 0x01, 0x00, 0x04, 0x00, 0x07],
+    );
+    round_trip(
+        SnPacket::Unsubscribe {
+            msg_id: 5,
+            topic: TopicRef::Short(*b"ab"),
+        },
+        &[0x07, 0x14, 0x02, 0x00, 0x05, b'a', b'b'],
+    );
+    round_trip(
+        SnPacket::Suback {
+            qos: SnQoS::QoS1,
+            topic_id: 9,
+            msg_id: 3,
+            return_code: ReturnCode::Accepted,
+        },
+        &[0x08, 0x13, 0x20, 0x00, 0x09, 0x00, 0x03, 0x00],
+    );
+}
+
+#[test]
+fn test_sleeping_client_messages() {
+    round_trip(
+        SnPacket::Disconnect {
+            duration: Some(300),
+        },
+        &[0x04, 0x18, 0x01, 0x2C],
+    );
+    round_trip(SnPacket::Disconnect { duration: None }, &[0x02, 0x18]);
+    round_trip(
+        SnPacket::Pingreq {
+            client_id: Some("node"),
+        },
+        &[0x06, 0x16, b'n', b'o', b'd', b'e'],
+    );
+    round_trip(SnPacket::Pingreq { client_id: None }, &[0x02, 0x16]);
+    round_trip(SnPacket::Pingresp, &[0x02, 0x17]);
+}
+
+#[test]
+fn test_long_message_length_field() {
+    let data = [0xAA; 300];
+    let packet = SnPacket::Publish {
+        dup: false,
+        qos: SnQoS::QoS0,
+        retain: false,
+        topic: TopicId::Normal(1),
+        msg_id: 0,
+        data: &data,
+    };
+    let mut buffer = [0u8; 320];
+    let len = packet.encode(&mut buffer).unwrap();
+    assert_eq!(len, 309);
+    assert_eq!(&buffer[..4], &[0x01, 0x01, 0x35, 0x0C]);
+    assert_eq!(SnPacket::decode(&buffer[..len]), Ok(packet));
+
+    let mut short = [0u8; 306];
+    assert_eq!(
+        packet.encode(&mut short),
+        Err(BufferError::InsufficientBufferSize)
+    );
+}
+
+#[test]
+fn test_decode_malformed() {
+    // Length larger than the datagram
+    assert_eq!(
+        SnPacket::decode(&[0x05, 0x17]),
+        Err(BufferError::InsufficientBufferSize)
+    );
+    // Length does not match the content
+    assert_eq!(
+        SnPacket::decode(&[0x03, 0x17, 0x00]),
+        Err(BufferError::DecodingError)
+    );
+    // Unknown return code
+    assert_eq!(
+        SnPacket::decode(&[0x03, 0x05, 0x07]),
+        Err(BufferError::DecodingError)
+    );
+    // Wrong protocol id
+    assert_eq!(
+        SnPacket::decode(&[0x07, 0x04, 0x00, 0x02, 0x00, 0x3C, b'n']),
+        Err(BufferError::DecodingError)
+    );
+    // Reserved topic id type
+    assert_eq!(
+        SnPacket::decode(&[0x07, 0x0C, 0x03, 0x00, 0x01, 0x00, 0x00]),
+        Err(BufferError::DecodingError)
+    );
+    // Will messages are not supported
+    assert_eq!(
+        SnPacket::decode(&[0x02, 0x06]),
+        Err(BufferError::DecodingError)
+    );
+    assert_eq!(
+        SnPacket::decode(&[0x02, 0x16]).map(|p| p.msg_type()),
+        Ok(MsgType::Pingreq)
+    );
+}
@@ -24,6 +24,10 @@
 
 use crate::packet::v5::connect_packet::ConnectPacket;
 use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::QualityOfService;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::{BinaryData, BufferError, EncodedString};
 
 #[test]
 fn test_encode() {
@@ -40,3 +44,87 @@ fn test_encode() {
         ]
     )
 }
+
+#[test]
+fn test_clean_start() {
+    let mut connect = ConnectPacket::<1, 0>::clean();
+    connect.add_username(&EncodedString {
+        string: "user",
+        len: 4,
+    });
+    connect.add_clean_start(false);
+    assert_eq!(connect.connect_flags, 0x80);
+    connect.add_clean_start(true);
+    assert_eq!(connect.connect_flags, 0x82);
+}
+
+#[test]
+fn test_decode_roundtrip() {
+    let mut buffer: [u8; 100] = [0; 100];
+    let mut connect = ConnectPacket::<1, 1>::clean();
+    connect.add_client_id(&EncodedString {
+        string: "sensor",
+        len: 6,
+    });
+    connect.add_username(&EncodedString {
+        string: "user",
+        len: 4,
+    });
+    connect.add_password(&BinaryData {
+        bin: &[0x01, 0x02],
+        len: 2,
+    });
+    connect.add_will(
+        &EncodedString {
+            string: "will",
+            len: 4,
+        },
+        &BinaryData {
+            bin: b"gone",
+            len: 4,
+        },
+        true,
+    );
+    connect.add_will_qos(QualityOfService::QoS1);
+    connect
+        .will_properties
+        .push(Property::WillDelayInterval(5))
+        .unwrap();
+    connect.will_property_len = 5;
+    let len = connect.encode(&mut buffer, 100).unwrap();
+
+    let mut decoded = ConnectPacket::<2, 2>::new();
+    assert!(decoded.decode(&mut BuffReader::new(&buffer, len)).is_ok());
+    assert_eq!(decoded.keep_alive, 60);
+    assert_eq!(decoded.client_id.string, "sensor");
+    assert_eq!(decoded.username.string, "user");
+    assert_eq!(decoded.password.bin, &[0x01, 0x02]);
+    assert_eq!(decoded.will_topic.string, "will");
+    assert_eq!(decoded.will_payload.bin, b"gone");
+    assert_eq!(decoded.will_qos(), QualityOfService::QoS1);
+    assert!(decoded.will_retain());
+    assert_eq!(decoded.will_properties.len(), 1);
+    assert_eq!(decoded.properties.len(), 1);
+}
+
+#[test]
+fn test_decode_rejects_old_protocol() {
+    // MQTT 3.1.1 CONNECT with empty client id
+    let buffer: [u8; 14] = [
+        0x10, 0x0C, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x04, 0x02, 0x00, 0x3c, 0x00, 0x00,
+    ];
+    let mut decoded = ConnectPacket::<2, 2>::new();
+    let res = decoded.decode(&mut BuffReader::new(&buffer, 14));
+    assert_eq!(res, Err(BufferError::DecodingError));
+    assert_eq!(decoded.protocol_version, 4);
+}
+
+#[test]
+fn test_decode_rejects_will_flags_without_will() {
+    let buffer: [u8; 15] = [
+        0x10, 0x0D, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x05, 0x22, 0x00, 0x3c, 0x00, 0x00, 0x00,
+    ];
+    let mut decoded = ConnectPacket::<2, 2>::new();
+    let res = decoded.decode(&mut BuffReader::new(&buffer, 15));
+    assert_eq!(res, Err(BufferError::DecodingError));
+}
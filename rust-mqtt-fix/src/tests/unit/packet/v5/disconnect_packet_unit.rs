@@ -56,7 +56,7 @@ fn test_decode() {
     assert_eq!(packet.remain_len, 7);
     assert_eq!(packet.disconnect_reason, 0x00);
     assert_eq!(packet.property_len, 5);
-    let prop = packet.properties.get(0);
+    let prop = packet.properties.first();
     assert!(prop.is_some());
     assert_eq!(<&Property as Into<u8>>::into(prop.unwrap()), 0x11);
     if let Property::SessionExpiryInterval(u) = *prop.unwrap() {
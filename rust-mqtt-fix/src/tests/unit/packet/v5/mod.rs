@@ -25,8 +25,11 @@
 pub mod connack_packet_unit;
 pub mod connect_packet_unit;
 pub mod disconnect_packet_unit;
+pub mod packet_proptest_unit;
 pub mod pingreq_packet_unit;
 pub mod pingresp_packet_unit;
+pub mod property_kind_unit;
+pub mod property_unit;
 pub mod puback_packet_unit;
 pub mod pubcomp_packet_unit;
 pub mod publish_packet_unit;
@@ -0,0 +1,464 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+// Property based tests of the packet codecs. Packets which can be both encoded and decoded
+// are checked by round trip (encode -> decode -> encode gives the same Bytes). Packets
+// supported only in one direction are checked against hand built Bytes. Arbitrary input
+// must never panic any decoder.
+
+use heapless::Vec;
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+use crate::packet::v5::auth_packet::AuthPacket;
+use crate::packet::v5::connack_packet::ConnackPacket;
+use crate::packet::v5::connect_packet::ConnectPacket;
+use crate::packet::v5::disconnect_packet::DisconnectPacket;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::pingreq_packet::PingreqPacket;
+use crate::packet::v5::pingresp_packet::PingrespPacket;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::puback_packet::PubackPacket;
+use crate::packet::v5::pubcomp_packet::PubcompPacket;
+use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use crate::packet::v5::pubrec_packet::PubrecPacket;
+use crate::packet::v5::pubrel_packet::PubrelPacket;
+use crate::packet::v5::suback_packet::SubackPacket;
+use crate::packet::v5::subscription_packet::SubscriptionPacket;
+use crate::packet::v5::unsuback_packet::UnsubackPacket;
+use crate::packet::v5::unsubscription_packet::UnsubscriptionPacket;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::{BinaryData, EncodedString, StringPair};
+
+const BUFFER_LEN: usize = 512;
+
+fn encoded(string: &str) -> EncodedString<'_> {
+    EncodedString {
+        string,
+        len: string.len() as u16,
+    }
+}
+
+fn binary(bin: &[u8]) -> BinaryData<'_> {
+    BinaryData {
+        bin,
+        len: bin.len() as u16,
+    }
+}
+
+fn text() -> impl Strategy<Value = String> {
+    "[a-zA-Z0-9/ ]{0,16}"
+}
+
+fn user_properties() -> impl Strategy<Value = std::vec::Vec<(String, String)>> {
+    vec((text(), text()), 0..3)
+}
+
+fn qos() -> impl Strategy<Value = QualityOfService> {
+    prop_oneof![
+        Just(QualityOfService::QoS0),
+        Just(QualityOfService::QoS1),
+        Just(QualityOfService::QoS2),
+    ]
+}
+
+/// Properties with reason string and user properties, allowed for most of acknowledgements.
+fn ack_properties<'a>(reason: &'a str, user: &'a [(String, String)]) -> Vec<Property<'a>, 4> {
+    let mut properties = Vec::new();
+    properties
+        .push(Property::ReasonString(encoded(reason)))
+        .unwrap();
+    for (name, value) in user {
+        properties
+            .push(Property::UserProperty(StringPair {
+                name: encoded(name),
+                value: encoded(value),
+            }))
+            .unwrap();
+    }
+    properties
+}
+
+fn reencode<'a, P: Packet<'a>>(packet: &mut P) -> std::vec::Vec<u8> {
+    let mut buffer = [0u8; BUFFER_LEN];
+    let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+    buffer[..len].to_vec()
+}
+
+/// Checks that the remaining length in the fixed header matches the encoded packet.
+fn assert_remaining_len(buffer: &[u8], len: usize) {
+    let mut reader = BuffReader::new(buffer, len);
+    reader.read_u8().unwrap();
+    let remaining = reader.read_variable_byte_int().unwrap() as usize;
+    assert_eq!(reader.position + remaining, len);
+}
+
+proptest! {
+    #[test]
+    fn test_puback_round_trip(
+        identifier in any::<u16>(),
+        reason_code in any::<u8>(),
+        reason in text(),
+        user in user_properties(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = PubackPacket::<4>::new();
+        packet.packet_identifier = identifier;
+        packet.reason_code = reason_code;
+        packet.property_len = packet.add_properties(&ack_properties(&reason, &user));
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = PubackPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.packet_identifier, identifier);
+        prop_assert_eq!(decoded.reason_code, reason_code);
+        prop_assert_eq!(decoded.properties.len(), 1 + user.len());
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_pubrec_round_trip(
+        identifier in any::<u16>(),
+        reason_code in any::<u8>(),
+        reason in text(),
+        user in user_properties(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = PubrecPacket::<4>::new();
+        packet.packet_identifier = identifier;
+        packet.reason_code = reason_code;
+        packet.property_len = packet.add_properties(&ack_properties(&reason, &user));
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = PubrecPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.packet_identifier, identifier);
+        prop_assert_eq!(decoded.reason_code, reason_code);
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_pubrel_round_trip(
+        identifier in any::<u16>(),
+        reason_code in any::<u8>(),
+        reason in text(),
+        user in user_properties(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = PubrelPacket::<4>::new();
+        packet.packet_identifier = identifier;
+        packet.reason_code = reason_code;
+        packet.property_len = packet.add_properties(&ack_properties(&reason, &user));
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = PubrelPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.packet_identifier, identifier);
+        prop_assert_eq!(decoded.reason_code, reason_code);
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_pubcomp_round_trip(
+        identifier in any::<u16>(),
+        reason_code in any::<u8>(),
+        reason in text(),
+        user in user_properties(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = PubcompPacket::<4>::new();
+        packet.packet_identifier = identifier;
+        packet.reason_code = reason_code;
+        packet.property_len = packet.add_properties(&ack_properties(&reason, &user));
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = PubcompPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.packet_identifier, identifier);
+        prop_assert_eq!(decoded.reason_code, reason_code);
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_disconnect_round_trip(
+        reason_code in any::<u8>(),
+        session_expiry in any::<u32>(),
+        reason in text(),
+        user in user_properties(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut properties = ack_properties(&reason, &user);
+        properties.push(Property::SessionExpiryInterval(session_expiry)).unwrap();
+        let mut packet = DisconnectPacket::<4>::new();
+        packet.disconnect_reason = reason_code;
+        packet.property_len = packet.add_properties(&properties);
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = DisconnectPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.disconnect_reason, reason_code);
+        prop_assert_eq!(decoded.properties.len(), properties.len());
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_connack_round_trip(
+        session_present in 0u8..=1,
+        reason_code in any::<u8>(),
+        receive_maximum in any::<u16>(),
+        client_id in text(),
+        reason in text(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut properties = Vec::<Property, 3>::new();
+        properties.push(Property::ReceiveMaximum(receive_maximum)).unwrap();
+        properties.push(Property::AssignedClientIdentifier(encoded(&client_id))).unwrap();
+        properties.push(Property::ReasonString(encoded(&reason))).unwrap();
+        let mut packet = ConnackPacket::<3>::new();
+        packet.ack_flags = session_present;
+        packet.connect_reason_code = reason_code;
+        packet.property_len = packet.add_properties(&properties);
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = ConnackPacket::<3>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.ack_flags, session_present);
+        prop_assert_eq!(decoded.connect_reason_code, reason_code);
+        prop_assert_eq!(decoded.properties.len(), 3);
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_auth_round_trip(
+        reason_code in any::<u8>(),
+        method in text(),
+        data in vec(any::<u8>(), 0..32),
+        reason in text(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut properties = Vec::<Property, 3>::new();
+        properties.push(Property::AuthenticationMethod(encoded(&method))).unwrap();
+        properties.push(Property::AuthenticationData(binary(&data))).unwrap();
+        properties.push(Property::ReasonString(encoded(&reason))).unwrap();
+        let mut packet = AuthPacket::<3>::new();
+        packet.auth_reason = reason_code;
+        packet.property_len = packet.add_properties(&properties);
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = AuthPacket::<3>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.auth_reason, reason_code);
+        prop_assert_eq!(decoded.properties.len(), 3);
+        if let Some(Property::AuthenticationData(decoded_data)) = decoded.properties.get(1) {
+            prop_assert_eq!(decoded_data.bin, &data[..]);
+        } else {
+            prop_assert!(false, "Authentication data not decoded");
+        }
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_publish_round_trip(
+        topic in text(),
+        qos in qos(),
+        retain in any::<bool>(),
+        identifier in any::<u16>(),
+        message in vec(any::<u8>(), 0..128),
+        expiry in any::<u32>(),
+        subscription_identifier in 1u32..268_435_455,
+        correlation in vec(any::<u8>(), 0..16),
+        content_type in text(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut properties = Vec::<Property, 4>::new();
+        properties.push(Property::MessageExpiryInterval(expiry)).unwrap();
+        properties.push(Property::SubscriptionIdentifier(subscription_identifier)).unwrap();
+        properties.push(Property::CorrelationData(binary(&correlation))).unwrap();
+        properties.push(Property::ContentType(encoded(&content_type))).unwrap();
+        let mut packet = PublishPacket::<4>::new();
+        packet.add_topic_name(&topic);
+        packet.add_qos(qos);
+        packet.add_retain(retain);
+        packet.add_identifier(identifier);
+        packet.add_message(&message);
+        packet.property_len = packet.add_properties(&properties);
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+
+        let mut decoded = PublishPacket::<4>::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(decoded.topic_name.string, topic.as_str());
+        prop_assert_eq!(decoded.message, Some(&message[..]));
+        prop_assert_eq!(decoded.properties.len(), 4);
+        if qos != QualityOfService::QoS0 {
+            prop_assert_eq!(decoded.packet_identifier, identifier);
+        }
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_pingresp_round_trip(_x in 0..1) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = PingrespPacket::new();
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+        let mut decoded = PingrespPacket::new();
+        decoded.decode(&mut BuffReader::new(&buffer, len)).unwrap();
+        prop_assert_eq!(reencode(&mut decoded), &buffer[..len]);
+    }
+
+    #[test]
+    fn test_suback_decode(
+        identifier in any::<u16>(),
+        reason in proptest::option::of(text()),
+        codes in vec(any::<u8>(), 1..4),
+    ) {
+        let mut properties = std::vec::Vec::new();
+        if let Some(reason) = &reason {
+            properties.push(0x1F);
+            properties.extend_from_slice(&(reason.len() as u16).to_be_bytes());
+            properties.extend_from_slice(reason.as_bytes());
+        }
+        let mut buffer = vec![0x90, (3 + properties.len() + codes.len()) as u8];
+        buffer.extend_from_slice(&identifier.to_be_bytes());
+        buffer.push(properties.len() as u8);
+        buffer.extend_from_slice(&properties);
+        buffer.extend_from_slice(&codes);
+
+        let mut packet = SubackPacket::<4, 1>::new();
+        packet.decode(&mut BuffReader::new(&buffer, buffer.len())).unwrap();
+        prop_assert_eq!(packet.packet_identifier, identifier);
+        prop_assert_eq!(&packet.reason_codes[..], &codes[..]);
+        prop_assert_eq!(packet.properties.len(), reason.is_some() as usize);
+    }
+
+    #[test]
+    fn test_unsuback_decode(
+        identifier in any::<u16>(),
+        reason in proptest::option::of(text()),
+        codes in vec(any::<u8>(), 1..4),
+    ) {
+        let mut properties = std::vec::Vec::new();
+        if let Some(reason) = &reason {
+            properties.push(0x1F);
+            properties.extend_from_slice(&(reason.len() as u16).to_be_bytes());
+            properties.extend_from_slice(reason.as_bytes());
+        }
+        let mut buffer = vec![0xB0, (3 + properties.len() + codes.len()) as u8];
+        buffer.extend_from_slice(&identifier.to_be_bytes());
+        buffer.push(properties.len() as u8);
+        buffer.extend_from_slice(&properties);
+        buffer.extend_from_slice(&codes);
+
+        let mut packet = UnsubackPacket::<4, 1>::new();
+        packet.decode(&mut BuffReader::new(&buffer, buffer.len())).unwrap();
+        prop_assert_eq!(packet.packet_identifier, identifier);
+        prop_assert_eq!(&packet.reason_codes[..], &codes[..]);
+    }
+
+    #[test]
+    fn test_connect_encode(
+        client_id in text(),
+        username in text(),
+        password in vec(any::<u8>(), 0..16),
+        keep_alive in any::<u16>(),
+        will in proptest::option::of((text(), vec(any::<u8>(), 0..16), any::<bool>())),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = ConnectPacket::<2, 0>::new();
+        packet.keep_alive = keep_alive;
+        packet.add_client_id(&encoded(&client_id));
+        packet.add_username(&encoded(&username));
+        packet.add_password(&binary(&password));
+        if let Some((topic, payload, retain)) = &will {
+            packet.add_will(&encoded(topic), &binary(payload), *retain);
+        }
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+        assert_remaining_len(&buffer, len);
+    }
+
+    #[test]
+    fn test_subscribe_encode(
+        identifier in any::<u16>(),
+        filters in vec(text(), 1..4),
+        qos in qos(),
+    ) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = SubscriptionPacket::<4, 1>::new();
+        packet.packet_identifier = identifier;
+        for filter in &filters {
+            packet.add_new_filter(filter, qos);
+        }
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+        assert_remaining_len(&buffer, len);
+    }
+
+    #[test]
+    fn test_unsubscribe_encode(identifier in any::<u16>(), filters in vec(text(), 1..4)) {
+        let mut buffer = [0u8; BUFFER_LEN];
+        let mut packet = UnsubscriptionPacket::<4, 1>::new();
+        packet.packet_identifier = identifier;
+        for filter in &filters {
+            packet.add_new_filter(filter);
+        }
+        let len = packet.encode(&mut buffer, BUFFER_LEN).unwrap();
+        assert_remaining_len(&buffer, len);
+    }
+
+    #[test]
+    fn test_encode_into_small_buffer(len in 0usize..16, message in vec(any::<u8>(), 0..32)) {
+        let mut buffer = [0u8; 16];
+        let mut packet = PublishPacket::<1>::new();
+        packet.add_topic_name("topic");
+        packet.add_message(&message);
+        let _ = packet.encode(&mut buffer, len);
+        let mut packet = PingreqPacket::new();
+        let _ = packet.encode(&mut buffer, len);
+    }
+
+    #[test]
+    fn test_decode_arbitrary_bytes(
+        header in prop_oneof![
+            Just(0x20u8), Just(0x30), Just(0x32), Just(0x40), Just(0x50), Just(0x62),
+            Just(0x70), Just(0x90), Just(0xB0), Just(0xD0), Just(0xE0), Just(0xF0), any::<u8>(),
+        ],
+        bytes in vec(any::<u8>(), 0..64),
+        buff_len in 0usize..80,
+    ) {
+        let mut buffer = std::vec::Vec::with_capacity(bytes.len() + 1);
+        buffer.push(header);
+        buffer.extend_from_slice(&bytes);
+
+        let _ = ConnackPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PublishPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PubackPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PubrecPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PubrelPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PubcompPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = SubackPacket::<2, 2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = UnsubackPacket::<2, 2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = PingrespPacket::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = DisconnectPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = AuthPacket::<2>::new().decode(&mut BuffReader::new(&buffer, buff_len));
+        let _ = Property::decode(&mut BuffReader::new(&buffer, buff_len));
+    }
+}
@@ -25,6 +25,7 @@
 use crate::packet::v5::mqtt_packet::Packet;
 use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::pingreq_packet::PingreqPacket;
+use crate::utils::buffer_reader::BuffReader;
 
 #[test]
 fn test_encode() {
@@ -36,3 +37,12 @@ fn test_encode() {
     assert!(res.is_ok());
     assert_eq!(buffer, [0xC0, 0x00, 0x45])
 }
+
+#[test]
+fn test_decode() {
+    let buffer: [u8; 2] = [0xC0, 0x00];
+    let mut packet = PingreqPacket::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 2));
+    assert!(res.is_ok());
+    assert_eq!(packet.fixed_header, PacketType::Pingreq.into());
+}
@@ -0,0 +1,89 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::packet::v5::connack_packet::ConnackPacket;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::pingresp_packet::PingrespPacket;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::property_kind::{
+    AssignedClientIdentifier, ReasonString, ReceiveMaximum, RetainAvailable, ServerKeepAlive,
+    SubscriptionIdentifierAvailable, TopicAlias,
+};
+use crate::packet::v5::publish_packet::PublishPacket;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::{EncodedString, StringPair};
+
+#[test]
+fn test_connack_typed_properties() {
+    let buffer: [u8; 27] = [
+        0x20, 0x19, 0x00, 0x00, 0x16, 0x21, 0x00, 0x10, 0x1F, 0x00, 0x02, 0x6F, 0x6B, 0x25, 0x01,
+        0x26, 0x00, 0x01, 0x61, 0x00, 0x01, 0x62, 0x12, 0x00, 0x02, 0x69, 0x64,
+    ];
+    let mut packet = ConnackPacket::<5>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 27));
+    assert_eq!(res, Ok(()));
+    assert_eq!(packet.get::<ReceiveMaximum>(), Some(16));
+    assert_eq!(packet.get::<ReasonString>(), Some("ok"));
+    assert_eq!(packet.get::<RetainAvailable>(), Some(true));
+    assert_eq!(packet.get::<AssignedClientIdentifier>(), Some("id"));
+    assert_eq!(packet.get::<ServerKeepAlive>(), None);
+    assert_eq!(packet.get::<SubscriptionIdentifierAvailable>(), None);
+    let mut user_properties = packet.user_properties();
+    assert_eq!(user_properties.next(), Some(("a", "b")));
+    assert_eq!(user_properties.next(), None);
+}
+
+#[test]
+fn test_publish_repeated_properties() {
+    let mut packet = PublishPacket::<5>::new();
+    packet.push_to_properties(Property::SubscriptionIdentifier(1));
+    packet.push_to_properties(Property::TopicAlias(3));
+    packet.push_to_properties(Property::SubscriptionIdentifier(7));
+    for (name, value) in [("k1", "v1"), ("k2", "v2")] {
+        packet.push_to_properties(Property::UserProperty(StringPair {
+            name: EncodedString {
+                string: name,
+                len: 2,
+            },
+            value: EncodedString {
+                string: value,
+                len: 2,
+            },
+        }));
+    }
+    assert_eq!(packet.get::<TopicAlias>(), Some(3));
+    let mut ids = packet.subscription_identifiers();
+    assert_eq!(ids.next(), Some(1));
+    assert_eq!(ids.next(), Some(7));
+    assert_eq!(ids.next(), None);
+    assert_eq!(packet.user_properties().count(), 2);
+    assert_eq!(packet.user_properties().last(), Some(("k2", "v2")));
+}
+
+#[test]
+fn test_packet_without_properties() {
+    let packet = PingrespPacket::new();
+    assert_eq!(packet.get::<ReasonString>(), None);
+    assert_eq!(packet.user_properties().count(), 0);
+}
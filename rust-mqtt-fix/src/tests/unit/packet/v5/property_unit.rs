@@ -0,0 +1,184 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use heapless::Vec;
+
+use crate::packet::v5::connack_packet::ConnackPacket;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::packet_type::PacketType;
+use crate::packet::v5::property::{validate_properties, Property, PropertyViolation};
+use crate::packet::v5::puback_packet::PubackPacket;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::{BufferError, EncodedString, StringPair};
+
+fn user_property<'a>() -> Property<'a> {
+    Property::UserProperty(StringPair {
+        name: EncodedString {
+            string: "a",
+            len: 1,
+        },
+        value: EncodedString {
+            string: "b",
+            len: 1,
+        },
+    })
+}
+
+#[test]
+fn test_validate_duplicates() {
+    let reason = Property::ReasonString(EncodedString {
+        string: "x",
+        len: 1,
+    });
+    assert_eq!(
+        validate_properties(PacketType::Puback, &[reason.clone(), reason]),
+        Err(BufferError::InvalidProperty(PropertyViolation::Duplicate(
+            0x1F
+        )))
+    );
+    assert!(validate_properties(PacketType::Puback, &[user_property(), user_property()]).is_ok());
+}
+
+#[test]
+fn test_validate_subscription_identifier() {
+    let properties = [
+        Property::SubscriptionIdentifier(1),
+        Property::SubscriptionIdentifier(2),
+    ];
+    assert!(validate_properties(PacketType::Publish, &properties).is_ok());
+    assert_eq!(
+        validate_properties(PacketType::Subscribe, &properties),
+        Err(BufferError::InvalidProperty(PropertyViolation::Duplicate(
+            0x0B
+        )))
+    );
+    assert_eq!(
+        validate_properties(
+            PacketType::Subscribe,
+            &[Property::SubscriptionIdentifier(0)]
+        ),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x0B)
+        ))
+    );
+}
+
+#[test]
+fn test_validate_values() {
+    assert_eq!(
+        validate_properties(PacketType::Connect, &[Property::ReceiveMaximum(0)]),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x21)
+        ))
+    );
+    assert_eq!(
+        validate_properties(PacketType::Publish, &[Property::TopicAlias(0)]),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x23)
+        ))
+    );
+    assert_eq!(
+        validate_properties(PacketType::Publish, &[Property::PayloadFormat(2)]),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x01)
+        ))
+    );
+    assert_eq!(
+        validate_properties(PacketType::Connack, &[Property::MaximumQoS(2)]),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x24)
+        ))
+    );
+    assert!(validate_properties(
+        PacketType::Publish,
+        &[Property::PayloadFormat(1), Property::TopicAlias(5)]
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_validate_not_allowed() {
+    assert_eq!(
+        validate_properties(PacketType::Publish, &[Property::ReceiveMaximum(10)]),
+        Err(BufferError::InvalidProperty(PropertyViolation::NotAllowed(
+            0x21
+        )))
+    );
+}
+
+#[test]
+fn test_decode_duplicate_property() {
+    // CONNACK with ReceiveMaximum twice
+    let buffer: [u8; 11] = [
+        0x20, 0x09, 0x00, 0x00, 0x06, 0x21, 0x00, 0x10, 0x21, 0x00, 0x20,
+    ];
+    let mut packet = ConnackPacket::<2>::new();
+    assert_eq!(
+        packet.decode(&mut BuffReader::new(&buffer, 11)),
+        Err(BufferError::InvalidProperty(PropertyViolation::Duplicate(
+            0x21
+        )))
+    );
+}
+
+#[test]
+fn test_decode_invalid_value() {
+    // CONNACK with MaximumQoS 2
+    let buffer: [u8; 7] = [0x20, 0x05, 0x00, 0x00, 0x02, 0x24, 0x02];
+    let mut packet = ConnackPacket::<2>::new();
+    assert_eq!(
+        packet.decode(&mut BuffReader::new(&buffer, 7)),
+        Err(BufferError::InvalidProperty(
+            PropertyViolation::InvalidValue(0x24)
+        ))
+    );
+}
+
+#[test]
+fn test_encode_rejects_duplicate() {
+    let mut buffer = [0u8; 32];
+    let reason = Property::ReasonString(EncodedString {
+        string: "x",
+        len: 1,
+    });
+    let mut props = Vec::<Property, 2>::new();
+    props.push(reason.clone()).unwrap();
+    props.push(reason).unwrap();
+    let mut packet = PubackPacket::<2>::new();
+    packet.property_len = packet.add_properties(&props);
+    assert_eq!(
+        packet.encode(&mut buffer, 32),
+        Err(BufferError::InvalidProperty(PropertyViolation::Duplicate(
+            0x1F
+        )))
+    );
+}
+
+#[test]
+fn test_decode_retain_available() {
+    // CONNACK with RetainAvailable 0
+    let buffer: [u8; 7] = [0x20, 0x05, 0x00, 0x00, 0x02, 0x25, 0x00];
+    let mut packet = ConnackPacket::<2>::new();
+    assert_eq!(packet.decode(&mut BuffReader::new(&buffer, 7)), Ok(()));
+}
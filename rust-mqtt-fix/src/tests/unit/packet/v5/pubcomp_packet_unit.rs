@@ -65,7 +65,7 @@ fn test_decode() {
     assert_eq!(packet.packet_identifier, 35420);
     assert_eq!(packet.reason_code, 0x00);
     assert_eq!(packet.property_len, 8);
-    let prop = packet.properties.get(0);
+    let prop = packet.properties.first();
     assert!(prop.is_some());
     assert_eq!(<&Property as Into<u8>>::into(prop.unwrap()), 0x1F);
     if let Property::ReasonString(u) = (*prop.unwrap()).clone() {
@@ -29,7 +29,7 @@ use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::property::Property;
 use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
 use crate::utils::buffer_reader::BuffReader;
-use crate::utils::types::EncodedString;
+use crate::utils::types::{BufferError, EncodedString};
 
 #[test]
 fn test_encode() {
@@ -77,7 +77,7 @@ fn test_decode() {
     assert_eq!(packet.topic_name.string, "test");
     assert_eq!(packet.packet_identifier, 23432);
     assert_eq!(packet.property_len, 7);
-    let prop = packet.properties.get(0);
+    let prop = packet.properties.first();
     assert!(prop.is_some());
     assert_eq!(<&Property as Into<u8>>::into(prop.unwrap()), 0x01);
     if let Property::PayloadFormat(u) = (*prop.unwrap()).clone() {
@@ -96,3 +96,23 @@ fn test_decode() {
         );
     }
 }
+
+#[test]
+fn test_decode_property_overshoot() {
+    // Property length is 3 but the MessageExpiryInterval property takes 5 Bytes
+    let buffer: [u8; 14] = [
+        0x30, 0x0C, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74, 0x03, 0x02, 0x00, 0x00, 0x00, 0x01,
+    ];
+    let mut packet = PublishPacket::<1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 14));
+    assert_eq!(res, Err(BufferError::DecodingError));
+}
+
+#[test]
+fn test_decode_truncated_message() {
+    // Remaining length announces more Bytes than the buffer holds
+    let buffer: [u8; 10] = [0x30, 0x10, 0x00, 0x04, 0x74, 0x65, 0x73, 0x74, 0x00, 0x61];
+    let mut packet = PublishPacket::<1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 10));
+    assert_eq!(res, Err(BufferError::InsufficientBufferSize));
+}
@@ -27,6 +27,7 @@ use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::property::Property;
 use crate::packet::v5::suback_packet::SubackPacket;
 use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::{BufferError, EncodedString};
 
 #[test]
 fn test_decode() {
@@ -41,7 +42,7 @@ fn test_decode() {
     assert_eq!(packet.remain_len, 21);
     assert_eq!(packet.packet_identifier, 52232);
     assert_eq!(packet.property_len, 15);
-    let prop = packet.properties.get(0);
+    let prop = packet.properties.first();
     assert!(prop.is_some());
     assert_eq!(<&Property as Into<u8>>::into(prop.unwrap()), 0x1F);
     if let Property::ReasonString(u) = (*prop.unwrap()).clone() {
@@ -49,7 +50,7 @@ fn test_decode() {
         assert_eq!(u.string, "reasonString");
     }
     assert_eq!(packet.reason_codes.len(), 3);
-    let res1 = packet.reason_codes.get(0);
+    let res1 = packet.reason_codes.first();
     assert!(res1.is_some());
     if let Some(r) = res1 {
         assert_eq!(*r, 0x12);
@@ -65,3 +66,38 @@ fn test_decode() {
         assert_eq!(*r, 0x56);
     }
 }
+
+#[test]
+fn test_encode() {
+    let mut buffer: [u8; 23] = [0; 23];
+    let mut packet = SubackPacket::<3, 1>::new();
+    packet.packet_identifier = 52232;
+    packet
+        .properties
+        .push(Property::ReasonString(EncodedString {
+            string: "reasonString",
+            len: 12,
+        }));
+    packet.property_len = 15;
+    packet
+        .reason_codes
+        .extend_from_slice(&[0x12, 0x34, 0x56])
+        .unwrap();
+    let res = packet.encode(&mut buffer, 23);
+    assert_eq!(res, Ok(23));
+    assert_eq!(
+        buffer,
+        [
+            0x90, 0x15, 0xCC, 0x08, 0x0F, 0x1F, 0x00, 0x0C, 0x72, 0x65, 0x61, 0x73, 0x6f, 0x6e,
+            0x53, 0x74, 0x72, 0x69, 0x6e, 0x67, 0x12, 0x34, 0x56,
+        ]
+    );
+}
+
+#[test]
+fn test_decode_too_many_reason_codes() {
+    let buffer: [u8; 8] = [0x90, 0x06, 0x00, 0x01, 0x00, 0x00, 0x01, 0x02];
+    let mut packet = SubackPacket::<2, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 8));
+    assert_eq!(res, Err(BufferError::InsufficientBufferSize));
+}
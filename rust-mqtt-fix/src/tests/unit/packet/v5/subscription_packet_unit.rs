@@ -29,6 +29,8 @@ use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::property::Property;
 use crate::packet::v5::publish_packet::QualityOfService::{QoS0, QoS1};
 use crate::packet::v5::subscription_packet::SubscriptionPacket;
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::BufferError;
 
 #[test]
 fn test_encode() {
@@ -53,3 +55,40 @@ fn test_encode() {
         ]
     );
 }
+
+#[test]
+fn test_decode() {
+    let buffer: [u8; 30] = [
+        0x82, 0x1C, 0x15, 0x38, 0x03, 0x0B, 0x80, 0x13, 0x00, 0x0A, 0x74, 0x65, 0x73, 0x74, 0x2f,
+        0x74, 0x6f, 0x70, 0x69, 0x63, 0x00, 0x00, 0x06, 0x68, 0x65, 0x68, 0x65, 0x2F, 0x23, 0x01,
+    ];
+    let mut packet = SubscriptionPacket::<2, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 30));
+    assert!(res.is_ok());
+    assert_eq!(packet.packet_identifier, 5432);
+    assert_eq!(packet.subscription_identifiers().next(), Some(2432));
+    assert_eq!(packet.topic_filter_len, 2);
+    assert_eq!(packet.topic_filters[0].filter.string, "test/topic");
+    assert_eq!(packet.topic_filters[0].sub_options, 0x00);
+    assert_eq!(packet.topic_filters[1].filter.string, "hehe/#");
+    assert_eq!(packet.topic_filters[1].sub_options, 0x01);
+}
+
+#[test]
+fn test_decode_too_many_filters() {
+    let buffer: [u8; 30] = [
+        0x82, 0x1C, 0x15, 0x38, 0x03, 0x0B, 0x80, 0x13, 0x00, 0x0A, 0x74, 0x65, 0x73, 0x74, 0x2f,
+        0x74, 0x6f, 0x70, 0x69, 0x63, 0x00, 0x00, 0x06, 0x68, 0x65, 0x68, 0x65, 0x2F, 0x23, 0x01,
+    ];
+    let mut packet = SubscriptionPacket::<1, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 30));
+    assert_eq!(res, Err(BufferError::InsufficientBufferSize));
+}
+
+#[test]
+fn test_decode_without_filters() {
+    let buffer: [u8; 5] = [0x82, 0x03, 0x00, 0x01, 0x00];
+    let mut packet = SubscriptionPacket::<1, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 5));
+    assert_eq!(res, Err(BufferError::DecodingError));
+}
@@ -27,6 +27,7 @@ use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::property::Property;
 use crate::packet::v5::unsuback_packet::UnsubackPacket;
 use crate::utils::buffer_reader::BuffReader;
+use crate::utils::types::BufferError;
 
 #[test]
 fn test_decode() {
@@ -41,7 +42,7 @@ fn test_decode() {
     assert_eq!(packet.remain_len, 20);
     assert_eq!(packet.packet_identifier, 52232);
     assert_eq!(packet.property_len, 15);
-    let prop = packet.properties.get(0);
+    let prop = packet.properties.first();
     assert!(prop.is_some());
     assert_eq!(<&Property as Into<u8>>::into(prop.unwrap()), 0x1F);
     if let Property::ReasonString(u) = (*prop.unwrap()).clone() {
@@ -49,7 +50,7 @@ fn test_decode() {
         assert_eq!(u.string, "reasonString");
     }
     assert_eq!(packet.reason_codes.len(), 2);
-    let res1 = packet.reason_codes.get(0);
+    let res1 = packet.reason_codes.first();
     assert!(res1.is_some());
     if let Some(r) = res1 {
         assert_eq!(*r, 0x77);
@@ -60,3 +61,25 @@ fn test_decode() {
         assert_eq!(*r, 0x55);
     }
 }
+
+#[test]
+fn test_encode() {
+    let mut buffer: [u8; 7] = [0; 7];
+    let mut packet = UnsubackPacket::<2, 1>::new();
+    packet.packet_identifier = 7;
+    packet
+        .reason_codes
+        .extend_from_slice(&[0x00, 0x11])
+        .unwrap();
+    let res = packet.encode(&mut buffer, 7);
+    assert_eq!(res, Ok(7));
+    assert_eq!(buffer, [0xB0, 0x05, 0x00, 0x07, 0x00, 0x00, 0x11]);
+}
+
+#[test]
+fn test_decode_too_many_reason_codes() {
+    let buffer: [u8; 8] = [0xB0, 0x06, 0x00, 0x01, 0x00, 0x00, 0x11, 0x80];
+    let mut packet = UnsubackPacket::<2, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 8));
+    assert_eq!(res, Err(BufferError::InsufficientBufferSize));
+}
@@ -28,6 +28,7 @@ use crate::packet::v5::mqtt_packet::Packet;
 use crate::packet::v5::packet_type::PacketType;
 use crate::packet::v5::property::Property;
 use crate::packet::v5::unsubscription_packet::UnsubscriptionPacket;
+use crate::utils::buffer_reader::BuffReader;
 use crate::utils::types::{EncodedString, StringPair};
 
 #[test]
@@ -62,3 +63,20 @@ fn test_encode() {
         ]
     );
 }
+
+#[test]
+fn test_decode() {
+    let buffer: [u8; 40] = [
+        0xA2, 0x26, 0x15, 0x38, 0x0F, 0x26, 0x00, 0x04, 0x68, 0x61, 0x68, 0x61, 0x00, 0x06, 0x68,
+        0x65, 0x68, 0x65, 0x38, 0x39, 0x00, 0x0A, 0x74, 0x65, 0x73, 0x74, 0x2F, 0x74, 0x6F, 0x70,
+        0x69, 0x63, 0x00, 0x06, 0x68, 0x65, 0x68, 0x65, 0x2F, 0x23,
+    ];
+    let mut packet = UnsubscriptionPacket::<2, 1>::new();
+    let res = packet.decode(&mut BuffReader::new(&buffer, 40));
+    assert!(res.is_ok());
+    assert_eq!(packet.packet_identifier, 5432);
+    assert_eq!(packet.user_properties().next(), Some(("haha", "hehe89")));
+    assert_eq!(packet.topic_filter_len, 2);
+    assert_eq!(packet.topic_filters[0].filter.string, "test/topic");
+    assert_eq!(packet.topic_filters[1].filter.string, "hehe/#");
+}
@@ -0,0 +1,204 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use embedded_storage::nor_flash::{
+    ErrorType, NorFlash, NorFlashError, NorFlashErrorKind, ReadNorFlash,
+};
+
+use crate::queue::{FlashQueueStorage, QueueError, QueueStorage};
+
+const PAGE: usize = 64;
+
+#[derive(Debug, PartialEq)]
+struct MockFlashError(NorFlashErrorKind);
+
+impl NorFlashError for MockFlashError {
+    fn kind(&self) -> NorFlashErrorKind {
+        self.0
+    }
+}
+
+/// NOR flash simulation, writes are only able to clear bits.
+struct MockFlash {
+    data: [u8; PAGE * 4],
+}
+
+impl MockFlash {
+    fn new() -> Self {
+        Self {
+            data: [0xFF; PAGE * 4],
+        }
+    }
+}
+
+impl ErrorType for MockFlash {
+    type Error = MockFlashError;
+}
+
+impl ReadNorFlash for MockFlash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let offset = offset as usize;
+        bytes.copy_from_slice(&self.data[offset..offset + bytes.len()]);
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl NorFlash for MockFlash {
+    const WRITE_SIZE: usize = 4;
+    const ERASE_SIZE: usize = PAGE;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from as usize % PAGE != 0 || to as usize % PAGE != 0 {
+            return Err(MockFlashError(NorFlashErrorKind::NotAligned));
+        }
+        self.data[from as usize..to as usize].fill(0xFF);
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        if offset as usize % Self::WRITE_SIZE != 0 || bytes.len() % Self::WRITE_SIZE != 0 {
+            return Err(MockFlashError(NorFlashErrorKind::NotAligned));
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            let cell = &mut self.data[offset as usize + i];
+            if *byte & !*cell != 0 {
+                return Err(MockFlashError(NorFlashErrorKind::Other));
+            }
+            *cell &= *byte;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_push_peek_pop() {
+    let mut storage = FlashQueueStorage::new(MockFlash::new(), 0, 4).unwrap();
+    assert!(storage.push(&[b"hello", b" world"]).is_ok());
+    assert!(storage.push(&[b"abc"]).is_ok());
+    assert_eq!(storage.len(), 2);
+
+    let mut buffer = [0u8; 16];
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(11)));
+    assert_eq!(&buffer[..11], b"hello world");
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(3)));
+    assert_eq!(&buffer[..3], b"abc");
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(None));
+}
+
+#[test]
+fn test_recover_after_reboot() {
+    let mut storage = FlashQueueStorage::new(MockFlash::new(), 0, 4).unwrap();
+    for i in 0..6u8 {
+        assert!(storage.push(&[&[i; 20]]).is_ok());
+    }
+    assert!(storage.pop().is_ok());
+    assert!(storage.pop().is_ok());
+
+    let flash = storage.release();
+    let mut storage = FlashQueueStorage::new(flash, 0, 4).unwrap();
+    assert_eq!(storage.len(), 4);
+    let mut buffer = [0u8; 32];
+    for i in 2..6u8 {
+        assert_eq!(storage.peek(&mut buffer), Ok(Some(20)));
+        assert_eq!(buffer[..20], [i; 20]);
+        assert!(storage.pop().is_ok());
+    }
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn test_ring_reuses_consumed_pages() {
+    let mut storage = FlashQueueStorage::new(MockFlash::new(), 0, 4).unwrap();
+    let mut buffer = [0u8; 32];
+    // Every page fits two records, so this goes multiple times around the ring
+    for i in 0..40u8 {
+        assert!(storage.push(&[&[i; 20]]).is_ok());
+        assert_eq!(storage.peek(&mut buffer), Ok(Some(20)));
+        assert_eq!(buffer[..20], [i; 20]);
+        assert!(storage.pop().is_ok());
+    }
+    assert!(storage.is_empty());
+}
+
+#[test]
+fn test_full() {
+    let mut storage = FlashQueueStorage::new(MockFlash::new(), 0, 4).unwrap();
+    let mut pushed = 0;
+    loop {
+        match storage.push(&[&[0xAB; 20]]) {
+            Ok(()) => pushed += 1,
+            Err(err) => {
+                assert_eq!(err, QueueError::Full);
+                break;
+            }
+        }
+    }
+    assert_eq!(pushed, 8);
+    assert_eq!(storage.push(&[&[0; 60]]), Err(QueueError::TooLarge));
+    assert!(storage.pop().is_ok());
+    assert!(storage.pop().is_ok());
+    assert!(storage.push(&[&[0xAB; 20]]).is_ok());
+}
+
+#[test]
+fn test_interrupted_write_is_ignored() {
+    let mut storage = FlashQueueStorage::new(MockFlash::new(), 0, 4).unwrap();
+    assert!(storage.push(&[b"committed"]).is_ok());
+    let mut flash = storage.release();
+    // Data of the next record were written, but the header was not
+    flash.data[36..40].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+
+    let mut storage = FlashQueueStorage::new(flash, 0, 4).unwrap();
+    assert_eq!(storage.len(), 1);
+    assert!(storage.push(&[b"next"]).is_ok());
+    let mut buffer = [0u8; 16];
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(9)));
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(4)));
+    assert_eq!(&buffer[..4], b"next");
+}
+
+#[test]
+fn test_invalid_region() {
+    assert!(matches!(
+        FlashQueueStorage::new(MockFlash::new(), 0, 1),
+        Err(QueueError::InvalidRegion)
+    ));
+    assert!(matches!(
+        FlashQueueStorage::new(MockFlash::new(), 32, 2),
+        Err(QueueError::InvalidRegion)
+    ));
+    assert!(matches!(
+        FlashQueueStorage::new(MockFlash::new(), 0, 5),
+        Err(QueueError::InvalidRegion)
+    ));
+}
@@ -0,0 +1,330 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use std::vec::Vec as StdVec;
+
+use embedded_io::ErrorKind;
+use embedded_io_async::{ErrorType, Read, Write};
+
+use crate::client::client::MqttClient;
+use crate::client::client_config::{ClientConfig, MqttVersion};
+use crate::client::client_error::ClientError;
+use crate::client::connection_state::{ConnectionState, DisconnectReason};
+use crate::client::stats::ClientStats;
+use crate::packet::v5::mqtt_packet::Packet;
+use crate::packet::v5::property::Property;
+use crate::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use crate::queue::{
+    OutboundQueue, OverflowPolicy, PublishOutcome, QueueError, QueueStorage, QueuedMessage,
+    RamQueueStorage,
+};
+use crate::utils::buffer_reader::BuffReader;
+use crate::utils::clock::SystemClock;
+use crate::utils::rng_generator::CountingRng;
+
+/// Transport which only records written packets or fails every write. The only packet
+/// read from it is CONNACK.
+struct RecordingTransport {
+    written: StdVec<StdVec<u8>>,
+    offline: bool,
+    // Writes fail once this number of packets was written
+    max_writes: usize,
+    incoming: StdVec<u8>,
+}
+
+impl RecordingTransport {
+    fn new(offline: bool) -> Self {
+        Self {
+            written: StdVec::new(),
+            offline,
+            max_writes: usize::MAX,
+            incoming: StdVec::from([0x20, 0x03, 0x00, 0x00, 0x00]),
+        }
+    }
+
+    fn failing_after(max_writes: usize) -> Self {
+        Self {
+            max_writes,
+            ..Self::new(false)
+        }
+    }
+}
+
+impl ErrorType for RecordingTransport {
+    type Error = ErrorKind;
+}
+
+impl Read for RecordingTransport {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.offline || self.incoming.is_empty() {
+            return Err(ErrorKind::NotConnected);
+        }
+        let len = buf.len().min(self.incoming.len());
+        buf[..len].copy_from_slice(&self.incoming[..len]);
+        self.incoming.drain(..len);
+        Ok(len)
+    }
+}
+
+impl Write for RecordingTransport {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.offline || self.written.len() >= self.max_writes {
+            return Err(ErrorKind::NotConnected);
+        }
+        self.written.push(buf.to_vec());
+        Ok(buf.len())
+    }
+}
+
+fn decode_publish(bytes: &[u8]) -> (&str, &[u8], Option<u32>) {
+    let mut packet = PublishPacket::<2>::new();
+    assert!(packet
+        .decode(&mut BuffReader::new(bytes, bytes.len()))
+        .is_ok());
+    let expiry = packet.properties.iter().find_map(|p| match p {
+        Property::MessageExpiryInterval(u) => Some(*u),
+        _ => None,
+    });
+    (packet.topic_name.string, packet.message.unwrap(), expiry)
+}
+
+#[test]
+fn test_enqueue_overflow_policy() {
+    let message = QueuedMessage::new("t", b"0123456789", QualityOfService::QoS0, false);
+
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<60>::new(), OverflowPolicy::RejectNewest);
+    for _ in 0..2 {
+        assert!(queue.enqueue(&message, 0).is_ok());
+    }
+    assert_eq!(queue.enqueue(&message, 0), Err(QueueError::Full));
+    assert_eq!(queue.len(), 2);
+
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<60>::new(), OverflowPolicy::DropOldest);
+    for _ in 0..5 {
+        assert!(queue.enqueue(&message, 0).is_ok());
+    }
+    assert_eq!(queue.len(), 2);
+    assert_eq!(queue.dropped(), 3);
+}
+
+#[test]
+fn test_enqueue_too_large() {
+    let message = QueuedMessage::new("topic", &[0; 64], QualityOfService::QoS0, false);
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<256>::new(), OverflowPolicy::DropOldest);
+    assert_eq!(queue.enqueue(&message, 0), Err(QueueError::TooLarge));
+    assert!(queue.is_empty());
+}
+
+#[tokio::test]
+async fn test_queue_while_offline_and_drain() {
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<256>::new(), OverflowPolicy::DropOldest);
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        RecordingTransport::new(true),
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::Transport(ErrorKind::NotConnected))
+    );
+
+    let first = QueuedMessage::new("sensor/1", b"first", QualityOfService::QoS0, false);
+    let mut stale = QueuedMessage::new("sensor/2", b"stale", QualityOfService::QoS0, false);
+    stale.add_message_expiry_interval(5);
+    let mut fresh = QueuedMessage::new("sensor/3", b"fresh", QualityOfService::QoS0, true);
+    fresh.add_message_expiry_interval(60);
+
+    assert_eq!(
+        queue.publish(&mut client, &first, 100).await,
+        Ok(PublishOutcome::Queued)
+    );
+    assert_eq!(
+        queue.publish(&mut client, &stale, 100).await,
+        Ok(PublishOutcome::Queued)
+    );
+    assert_eq!(
+        queue.publish(&mut client, &fresh, 110).await,
+        Ok(PublishOutcome::Queued)
+    );
+    assert_eq!(queue.len(), 3);
+    assert_eq!(
+        queue.drain(&mut client, 120).await,
+        Err(QueueError::Client(ClientError::InvalidState(
+            ConnectionState::Disconnected(DisconnectReason::Transport)
+        )))
+    );
+    assert_eq!(queue.len(), 3);
+
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        RecordingTransport::new(false),
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    assert_eq!(client.connect_to_broker().await, Ok(false));
+    assert_eq!(queue.drain(&mut client, 120).await, Ok(2));
+    assert!(queue.storage().is_empty());
+    assert_eq!(queue.dropped(), 1);
+}
+
+#[tokio::test]
+async fn test_resent_message_counted_as_retransmission() {
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<256>::new(), OverflowPolicy::DropOldest);
+    let (stats, clock) = (ClientStats::new(), SystemClock);
+    let mut write_buffer = [0; 100];
+    let mut recv_buffer = [0; 100];
+    let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+    let mut client = MqttClient::new(
+        RecordingTransport::failing_after(1),
+        &mut write_buffer,
+        100,
+        &mut recv_buffer,
+        100,
+        config,
+    );
+    client.set_stats(&stats, &clock);
+    assert_eq!(client.connect_to_broker().await, Ok(false));
+
+    let first = QueuedMessage::new("sensor/1", b"first", QualityOfService::QoS0, false);
+    let second = QueuedMessage::new("sensor/2", b"second", QualityOfService::QoS0, false);
+    assert_eq!(
+        queue.publish(&mut client, &first, 0).await,
+        Ok(PublishOutcome::Queued)
+    );
+    // Not sent at all, the client is disconnected
+    assert_eq!(
+        queue.publish(&mut client, &second, 0).await,
+        Ok(PublishOutcome::Queued)
+    );
+
+    assert_eq!(
+        client.set_network_driver(RecordingTransport::new(false)),
+        Ok(())
+    );
+    assert_eq!(client.connect_to_broker().await, Ok(false));
+    assert_eq!(queue.drain(&mut client, 0).await, Ok(2));
+    assert_eq!(stats.retransmissions(), 1);
+}
+
+#[tokio::test]
+async fn test_drain_keeps_order_and_reduces_expiry() {
+    let mut queue =
+        OutboundQueue::<_, 64>::new(RamQueueStorage::<256>::new(), OverflowPolicy::DropOldest);
+    let first = QueuedMessage::new("a", b"1", QualityOfService::QoS0, false);
+    let mut second = QueuedMessage::new("b", b"2", QualityOfService::QoS0, false);
+    second.add_message_expiry_interval(30);
+    assert!(queue.enqueue(&first, 0).is_ok());
+    assert!(queue.enqueue(&second, 10).is_ok());
+
+    let mut transport = RecordingTransport::new(false);
+    {
+        let mut write_buffer = [0; 100];
+        let mut recv_buffer = [0; 100];
+        let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+        let mut client = MqttClient::new(
+            &mut transport,
+            &mut write_buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config,
+        );
+        assert_eq!(client.connect_to_broker().await, Ok(false));
+        let third = QueuedMessage::new("c", b"3", QualityOfService::QoS0, false);
+        assert_eq!(
+            queue.publish(&mut client, &third, 25).await,
+            Ok(PublishOutcome::Sent)
+        );
+    }
+
+    // CONNECT is followed by the queued messages and the new one
+    assert_eq!(transport.written.len(), 4);
+    assert_eq!(
+        decode_publish(&transport.written[1]),
+        ("a", &b"1"[..], None)
+    );
+    assert_eq!(
+        decode_publish(&transport.written[2]),
+        ("b", &b"2"[..], Some(15))
+    );
+    assert_eq!(
+        decode_publish(&transport.written[3]),
+        ("c", &b"3"[..], None)
+    );
+}
+
+#[tokio::test]
+async fn test_drain_drops_unreadable_records() {
+    let mut storage = RamQueueStorage::<256>::new();
+    // shorter than the record header
+    assert!(storage.push(&[b"garbage"]).is_ok());
+    // topic is not valid UTF-8
+    let header = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+    assert!(storage.push(&[&header, &[0xFF, 0xFE], b"x"]).is_ok());
+    // written with a larger `MAX_RECORD`
+    assert!(storage.push(&[&header, b"ok", &[0; 100]]).is_ok());
+    let mut queue = OutboundQueue::<_, 64>::new(storage, OverflowPolicy::DropOldest);
+    let message = QueuedMessage::new("a", b"1", QualityOfService::QoS0, false);
+    assert!(queue.enqueue(&message, 0).is_ok());
+
+    let mut transport = RecordingTransport::new(false);
+    {
+        let mut write_buffer = [0; 100];
+        let mut recv_buffer = [0; 100];
+        let config = ClientConfig::<2, _>::new(MqttVersion::MQTTv5, CountingRng(0));
+        let mut client = MqttClient::new(
+            &mut transport,
+            &mut write_buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config,
+        );
+        assert_eq!(client.connect_to_broker().await, Ok(false));
+        assert_eq!(queue.drain(&mut client, 0).await, Ok(1));
+    }
+    assert!(queue.is_empty());
+    assert_eq!(queue.dropped(), 3);
+    assert_eq!(transport.written.len(), 2);
+    assert_eq!(
+        decode_publish(&transport.written[1]),
+        ("a", &b"1"[..], None)
+    );
+}
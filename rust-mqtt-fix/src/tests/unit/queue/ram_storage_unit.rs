@@ -0,0 +1,78 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::queue::{QueueError, QueueStorage, RamQueueStorage};
+
+#[test]
+fn test_push_peek_pop() {
+    let mut storage = RamQueueStorage::<32>::new();
+    assert!(storage.is_empty());
+    assert!(storage.push(&[b"abc", b"de"]).is_ok());
+    assert!(storage.push(&[b"fgh"]).is_ok());
+    assert_eq!(storage.len(), 2);
+    assert_eq!(storage.free(), 32 - 7 - 5);
+
+    let mut buffer = [0u8; 8];
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(5)));
+    assert_eq!(&buffer[..5], b"abcde");
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(3)));
+    assert_eq!(&buffer[..3], b"fgh");
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(None));
+    assert_eq!(storage.free(), 32);
+}
+
+#[test]
+fn test_wrap_around() {
+    let mut storage = RamQueueStorage::<16>::new();
+    let mut buffer = [0u8; 16];
+    assert!(storage.push(&[b"0123456"]).is_ok());
+    assert!(storage.push(&[b"abc"]).is_ok());
+    assert!(storage.pop().is_ok());
+    // Record crosses the end of the ring buffer
+    assert!(storage.push(&[b"wxyz", b"12"]).is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(3)));
+    assert_eq!(&buffer[..3], b"abc");
+    assert!(storage.pop().is_ok());
+    assert_eq!(storage.peek(&mut buffer), Ok(Some(6)));
+    assert_eq!(&buffer[..6], b"wxyz12");
+}
+
+#[test]
+fn test_full_and_too_large() {
+    let mut storage = RamQueueStorage::<10>::new();
+    assert_eq!(storage.push(&[b"0123456789"]), Err(QueueError::TooLarge));
+    assert!(storage.push(&[b"0123"]).is_ok());
+    assert_eq!(storage.push(&[b"0123"]), Err(QueueError::Full));
+    assert_eq!(storage.len(), 1);
+}
+
+#[test]
+fn test_peek_small_buffer() {
+    let mut storage = RamQueueStorage::<16>::new();
+    assert!(storage.push(&[b"0123456"]).is_ok());
+    let mut buffer = [0u8; 4];
+    assert_eq!(storage.peek(&mut buffer), Err(QueueError::BufferTooSmall));
+}
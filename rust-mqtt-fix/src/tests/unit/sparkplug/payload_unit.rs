@@ -0,0 +1,210 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+use std::vec::Vec;
+
+use crate::sparkplug::payload::{DataType, Metric, MetricValue, Payload, PayloadWriter};
+use crate::sparkplug::SparkplugError;
+
+fn encode(metrics: &[Metric<'_>], data: bool, buffer: &mut [u8]) -> usize {
+    let mut writer = PayloadWriter::new(buffer, Some(1), Some(0)).unwrap();
+    for metric in metrics {
+        if data {
+            writer.add_data_metric(metric).unwrap();
+        } else {
+            writer.add_metric(metric).unwrap();
+        }
+    }
+    writer.finish()
+}
+
+#[test]
+fn test_encode_birth_metric() {
+    let mut buffer = [0; 64];
+    let len = encode(
+        &[Metric::new("a", MetricValue::Float(1.0)).with_alias(1)],
+        false,
+        &mut buffer,
+    );
+    assert_eq!(
+        buffer[..len],
+        [
+            0x08, 0x01, 0x18, 0x00, 0x12, 0x0c, 0x0a, 0x01, b'a', 0x10, 0x01, 0x20, 0x09, 0x65,
+            0x00, 0x00, 0x80, 0x3f
+        ]
+    );
+}
+
+#[test]
+fn test_encode_data_metric_uses_alias() {
+    let mut buffer = [0; 64];
+    let len = encode(
+        &[Metric::new("a", MetricValue::Boolean(true)).with_alias(300)],
+        true,
+        &mut buffer,
+    );
+    assert_eq!(
+        buffer[..len],
+        [0x08, 0x01, 0x18, 0x00, 0x12, 0x05, 0x10, 0xac, 0x02, 0x70, 0x01]
+    );
+    let payload = Payload::decode(&buffer[..len]).unwrap();
+    let metric = payload.metrics().next().unwrap();
+    assert_eq!(metric.name, None);
+    assert_eq!(metric.alias, Some(300));
+    assert_eq!(metric.value, MetricValue::Boolean(true));
+}
+
+#[test]
+fn test_round_trip_all_types() {
+    let long_name = "x".repeat(200);
+    let metrics = [
+        Metric::new("i8", MetricValue::Int8(-1)),
+        Metric::new("i16", MetricValue::Int16(-300)),
+        Metric::new("i32", MetricValue::Int32(i32::MIN)),
+        Metric::new("i64", MetricValue::Int64(-5)),
+        Metric::new("u8", MetricValue::UInt8(255)),
+        Metric::new("u16", MetricValue::UInt16(65535)),
+        Metric::new("u32", MetricValue::UInt32(u32::MAX)),
+        Metric::new("u64", MetricValue::UInt64(u64::MAX)),
+        Metric::new("f", MetricValue::Float(21.5)),
+        Metric::new("d", MetricValue::Double(-0.125)),
+        Metric::new("s", MetricValue::String("running")),
+        Metric::new("t", MetricValue::Text("text")),
+        Metric::new(
+            "uuid",
+            MetricValue::Uuid("123e4567-e89b-12d3-a456-426614174000"),
+        ),
+        Metric::new("dt", MetricValue::DateTime(1_700_000_000_000)).with_timestamp(7),
+        Metric::new("b", MetricValue::Bytes(&[1, 2, 3])),
+        Metric::new("null", MetricValue::Null(DataType::Double)),
+        Metric::new(&long_name, MetricValue::Boolean(false)).with_alias(9),
+    ];
+    let mut buffer = [0; 1024];
+    let len = encode(&metrics, false, &mut buffer);
+    let payload = Payload::decode(&buffer[..len]).unwrap();
+    assert_eq!(payload.timestamp, Some(1));
+    assert_eq!(payload.seq, Some(0));
+    assert_eq!(payload.metrics().collect::<Vec<_>>(), metrics);
+}
+
+#[test]
+fn test_data_metrics_without_datatype() {
+    let mut buffer = [0; 64];
+    let len = encode(
+        &[Metric::new("i8", MetricValue::Int8(-1))],
+        true,
+        &mut buffer,
+    );
+    let payload = Payload::decode(&buffer[..len]).unwrap();
+    let metric = payload.metrics().next().unwrap();
+    assert_eq!(metric.value, MetricValue::UInt32(u32::MAX));
+    assert_eq!(metric.value.as_i64().map(|v| v as i8), Some(-1));
+}
+
+#[test]
+fn test_buffer_too_small() {
+    let mut buffer = [0; 20];
+    let mut writer = PayloadWriter::new(&mut buffer, Some(1), Some(0)).unwrap();
+    assert_eq!(
+        writer.add_metric(&Metric::new("temperature", MetricValue::Double(1.0))),
+        Err(SparkplugError::BufferTooSmall)
+    );
+    let mut buffer = [0; 131];
+    let mut writer = PayloadWriter::new(&mut buffer, None, None).unwrap();
+    // Metric of 128 bytes needs two bytes for the length, 132 bytes in total.
+    let name = "x".repeat(126);
+    assert_eq!(
+        writer.add_metric(&Metric::new(&name, MetricValue::Null(DataType::Unknown))),
+        Err(SparkplugError::BufferTooSmall)
+    );
+}
+
+#[test]
+fn test_unsupported_and_unknown_fields() {
+    // Metric with the DataSet type and value, payload with an unknown field 9.
+    let data = [
+        0x48, 0x05, 0x12, 0x08, 0x0a, 0x01, b'd', 0x20, 0x10, 0x8a, 0x01, 0x00,
+    ];
+    let payload = Payload::decode(&data).unwrap();
+    let metric = payload.metrics().next().unwrap();
+    assert_eq!(metric.value, MetricValue::Unsupported(DataType::DataSet));
+}
+
+#[test]
+fn test_malformed_payloads() {
+    for data in [
+        &[0x08][..],
+        &[0x12, 0x05, 0x0a][..],
+        &[0x12, 0x04, 0x20, 0x09, 0x50, 0x01][..],
+        &[0x12, 0x03, 0x0a, 0x01, 0xff][..],
+        &[0x0b][..],
+    ] {
+        assert_eq!(
+            Payload::decode(data),
+            Err(SparkplugError::Malformed),
+            "{:?}",
+            data
+        );
+    }
+}
+
+#[test]
+fn test_rebirth_request() {
+    let mut buffer = [0; 64];
+    let len = encode(
+        &[Metric::new(
+            "Node Control/Rebirth",
+            MetricValue::Boolean(true),
+        )],
+        true,
+        &mut buffer,
+    );
+    assert!(Payload::decode(&buffer[..len])
+        .unwrap()
+        .is_rebirth_request());
+    let len = encode(
+        &[Metric::new(
+            "Node Control/Rebirth",
+            MetricValue::Boolean(false),
+        )],
+        true,
+        &mut buffer,
+    );
+    assert!(!Payload::decode(&buffer[..len])
+        .unwrap()
+        .is_rebirth_request());
+}
+
+#[test]
+fn test_metric_refers_to_definition() {
+    let definition = Metric::new("speed", MetricValue::UInt32(0)).with_alias(4);
+    let by_alias = Metric {
+        name: None,
+        alias: Some(4),
+        timestamp: None,
+        value: MetricValue::UInt32(5),
+    };
+    assert!(by_alias.refers_to(&definition));
+    assert!(Metric::new("speed", MetricValue::UInt32(5)).refers_to(&definition));
+    assert!(!Metric::new("other", MetricValue::UInt32(5)).refers_to(&definition));
+}
@@ -0,0 +1,98 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+use crate::sparkplug::topic::{MessageType, Topic};
+use crate::sparkplug::SparkplugError;
+
+#[test]
+fn test_write_topics() {
+    assert_eq!(
+        Topic::node("plant", MessageType::NBirth, "gateway1")
+            .write()
+            .unwrap(),
+        "spBv1.0/plant/NBIRTH/gateway1"
+    );
+    assert_eq!(
+        Topic::device("plant", MessageType::DData, "gateway1", "pump")
+            .write()
+            .unwrap(),
+        "spBv1.0/plant/DDATA/gateway1/pump"
+    );
+}
+
+#[test]
+fn test_write_invalid_ids() {
+    assert_eq!(
+        Topic::node("plant/a", MessageType::NData, "gateway1").write(),
+        Err(SparkplugError::InvalidId)
+    );
+    assert_eq!(
+        Topic::device("plant", MessageType::DCmd, "gateway1", "#").write(),
+        Err(SparkplugError::InvalidId)
+    );
+    assert_eq!(
+        Topic::node("plant", MessageType::DData, "gateway1").write(),
+        Err(SparkplugError::InvalidId)
+    );
+    assert_eq!(
+        Topic::device("plant", MessageType::NData, "gateway1", "pump").write(),
+        Err(SparkplugError::InvalidId)
+    );
+}
+
+#[test]
+fn test_parse_topics() {
+    assert_eq!(
+        Topic::parse("spBv1.0/plant/NCMD/gateway1"),
+        Ok(Topic::node("plant", MessageType::NCmd, "gateway1"))
+    );
+    assert_eq!(
+        Topic::parse("spBv1.0/plant/DCMD/gateway1/pump"),
+        Ok(Topic::device(
+            "plant",
+            MessageType::DCmd,
+            "gateway1",
+            "pump"
+        ))
+    );
+}
+
+#[test]
+fn test_parse_invalid_topics() {
+    for topic in [
+        "spAv1.0/plant/NCMD/gateway1",
+        "spBv1.0/plant/NCMD",
+        "spBv1.0/plant/STATUS/gateway1",
+        "spBv1.0/plant/NCMD/gateway1/pump",
+        "spBv1.0/plant/DCMD/gateway1",
+        "spBv1.0/plant/DCMD/gateway1/pump/extra",
+        "spBv1.0//NDATA/gateway1",
+    ] {
+        assert_eq!(
+            Topic::parse(topic),
+            Err(SparkplugError::InvalidTopic),
+            "{}",
+            topic
+        );
+    }
+}
@@ -233,3 +233,32 @@ fn test_read_string_pair_oob() {
         BufferError::InsufficientBufferSize
     );
 }
+
+#[test]
+fn test_read_binary_moves_position() {
+    static BUFFER: [u8; 5] = [0x00, 0x02, 0xFF, 0xEE, 0x07];
+    let mut reader: BuffReader = BuffReader::new(&BUFFER, 5);
+    assert!(reader.read_binary().is_ok());
+    assert_eq!(reader.read_u8(), Ok(0x07));
+}
+
+#[test]
+fn test_read_message() {
+    static BUFFER: [u8; 5] = [0x01, 0x02, 0x03, 0x04, 0x05];
+    let mut reader: BuffReader = BuffReader::new(&BUFFER, 5);
+    reader.increment_position(2);
+    assert_eq!(reader.read_message(4), Ok(&BUFFER[2..4]));
+    assert_eq!(
+        reader.read_message(6),
+        Err(BufferError::InsufficientBufferSize)
+    );
+    assert_eq!(reader.read_message(3), Err(BufferError::DecodingError));
+}
+
+#[test]
+fn test_len_limited_by_buffer() {
+    static BUFFER: [u8; 2] = [0x00, 0x01];
+    let mut reader: BuffReader = BuffReader::new(&BUFFER, 10);
+    assert_eq!(reader.read_u16(), Ok(1));
+    assert_eq!(reader.read_u8(), Err(BufferError::InsufficientBufferSize));
+}
@@ -24,3 +24,4 @@
 
 pub mod buffer_reader_unit;
 pub mod buffer_writer_unit;
+pub mod topic_unit;
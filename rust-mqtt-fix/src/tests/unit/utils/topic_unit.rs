@@ -0,0 +1,71 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+
+use crate::utils::topic::{topic_matches, valid_topic_filter, valid_topic_name};
+
+#[test]
+fn test_topic_name() {
+    assert!(valid_topic_name("sensor/1/temperature"));
+    assert!(valid_topic_name("/"));
+    assert!(!valid_topic_name(""));
+    assert!(!valid_topic_name("sensor/+"));
+    assert!(!valid_topic_name("sensor/#"));
+}
+
+#[test]
+fn test_topic_filter() {
+    assert!(valid_topic_filter("sensor/+/temperature"));
+    assert!(valid_topic_filter("sensor/#"));
+    assert!(valid_topic_filter("#"));
+    assert!(valid_topic_filter("+"));
+    assert!(!valid_topic_filter(""));
+    assert!(!valid_topic_filter("sensor/#/temperature"));
+    assert!(!valid_topic_filter("sensor#"));
+    assert!(!valid_topic_filter("sensor/te+"));
+}
+
+#[test]
+fn test_topic_matches() {
+    assert!(topic_matches("sensor/1", "sensor/1"));
+    assert!(!topic_matches("sensor/1", "sensor/2"));
+    assert!(topic_matches("sensor/+", "sensor/2"));
+    assert!(!topic_matches("sensor/+", "sensor/2/temperature"));
+    assert!(topic_matches(
+        "sensor/+/temperature",
+        "sensor/2/temperature"
+    ));
+    assert!(topic_matches("sensor/#", "sensor/2/temperature"));
+    // Multi-level wildcard includes the parent level
+    assert!(topic_matches("sensor/#", "sensor"));
+    assert!(topic_matches("#", "sensor/2"));
+    assert!(!topic_matches("sensor", "sensor/2"));
+    assert!(topic_matches("+/+", "/sensor"));
+}
+
+#[test]
+fn test_system_topics() {
+    assert!(!topic_matches("#", "$SYS/clients"));
+    assert!(!topic_matches("+/clients", "$SYS/clients"));
+    assert!(topic_matches("$SYS/#", "$SYS/clients"));
+}
@@ -41,11 +41,13 @@ impl<'a> BuffReader<'a> {
         self.position += increment;
     }
 
+    /// Creates reader over first `buff_len` Bytes of the `buffer`. If `buff_len` is larger than
+    /// the `buffer`, reader is limited to the `buffer` length.
     pub fn new(buffer: &'a [u8], buff_len: usize) -> Self {
         Self {
             buffer,
             position: 0,
-            len: buff_len,
+            len: buff_len.min(buffer.len()),
         }
     }
 
@@ -148,6 +150,7 @@ impl<'a> BuffReader<'a> {
         }
 
         let res_bin = &(self.buffer[self.position..(self.position + len as usize)]);
+        self.increment_position(len as usize);
         Ok(BinaryData { bin: res_bin, len })
     }
 
@@ -158,12 +161,18 @@ impl<'a> BuffReader<'a> {
         Ok(StringPair { name, value })
     }
 
-    /// Read payload message from buffer
-    pub fn read_message(&mut self, total_len: usize) -> &'a [u8] {
+    /// Read payload message from buffer. Message spans from the current position up to
+    /// `total_len` which is the length of the whole packet.
+    pub fn read_message(&mut self, total_len: usize) -> Result<&'a [u8], BufferError> {
         if total_len > self.len {
-            return &self.buffer[self.position..self.len];
+            return Err(BufferError::InsufficientBufferSize);
+        }
+        if self.position > total_len {
+            return Err(BufferError::DecodingError);
         }
-        &self.buffer[self.position..total_len]
+        let message = &self.buffer[self.position..total_len];
+        self.position = total_len;
+        Ok(message)
     }
 
     /// Peeking (without incremental internal pointer) one byte from buffer as `Big endian`
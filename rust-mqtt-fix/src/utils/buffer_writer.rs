@@ -41,10 +41,11 @@ pub struct BuffWriter<'a> {
 
 impl<'a> BuffWriter<'a> {
     pub fn new(buffer: &'a mut [u8], buff_len: usize) -> Self {
+        let len = buff_len.min(buffer.len());
         Self {
             buffer,
             position: 0,
-            len: buff_len,
+            len,
         }
     }
 
@@ -87,10 +88,10 @@ impl<'a> BuffWriter<'a> {
 
     /// Writes (part of) an array to the buffer.
     pub fn insert_ref(&mut self, len: usize, array: &[u8]) -> Result<(), BufferError> {
-        if self.position + len > self.len {
+        if self.position + len > self.len || len > array.len() {
             return Err(BufferError::InsufficientBufferSize);
         }
-        self.buffer[self.position..self.position+len].copy_from_slice(&array[0..len]);
+        self.buffer[self.position..self.position + len].copy_from_slice(&array[0..len]);
         self.increment_position(len);
         Ok(())
     }
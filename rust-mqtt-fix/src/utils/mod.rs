@@ -24,5 +24,8 @@
 
 pub mod buffer_reader;
 pub mod buffer_writer;
+pub mod clock;
 pub mod rng_generator;
+pub(crate) mod select;
+pub mod topic;
 pub mod types;
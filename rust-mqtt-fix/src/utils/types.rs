@@ -24,6 +24,8 @@
 
 use core::fmt::{Display, Formatter};
 
+use crate::packet::v5::property::PropertyViolation;
+
 #[derive(core::fmt::Debug, Clone, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 pub enum BufferError {
@@ -37,6 +39,7 @@ pub enum BufferError {
     WrongPacketToDecode,
     WrongPacketToEncode,
     PropertyNotFound,
+    InvalidProperty(PropertyViolation),
 }
 
 impl Display for BufferError {
@@ -51,7 +54,8 @@ impl Display for BufferError {
             BufferError::PacketTypeMismatch => write!(f, "Packet type not matched during decoding (Received different packet type than encode type)!"),
             BufferError::WrongPacketToDecode => write!(f, "Not able to decode packet, this packet is used just for sending to broker, not receiving by client!"),
             BufferError::WrongPacketToEncode => write!(f, "Not able to encode packet, this packet is used only from server to client not the opposite way!"),
-            BufferError::PropertyNotFound => write!(f, "Property with ID not found!")
+            BufferError::PropertyNotFound => write!(f, "Property with ID not found!"),
+            BufferError::InvalidProperty(violation) => write!(f, "Invalid property: {}", violation),
         }
     }
 }
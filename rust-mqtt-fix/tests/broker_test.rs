@@ -0,0 +1,365 @@
+//! Clients of this crate talking to the embedded `Broker` over the in-memory transport.
+use embedded_io_async::{Read, Write};
+use tokio::sync::Notify;
+use tokio_test::{assert_err, assert_ok};
+
+use rust_mqtt::broker::{Broker, BrokerError};
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::test_support::memory_transport::{duplex, MemoryTransport};
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+type TestBroker = Broker<3, 8, 4, 4>;
+type TestClient<'a> = MqttClient<'a, MemoryTransport, 5, CountingRng>;
+
+fn config(client_id: &str) -> ClientConfig<'_, 5, CountingRng> {
+    let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
+    config.add_client_id(client_id);
+    config.add_max_subscribe_qos(QualityOfService::QoS1);
+    config.max_packet_size = 100;
+    config
+}
+
+async fn serve(
+    broker: &TestBroker,
+    transport: MemoryTransport,
+) -> Result<(), BrokerError<embedded_io::ErrorKind>> {
+    let mut buffer = [0; 128];
+    let mut recv_buffer = [0; 128];
+    broker.serve(transport, &mut buffer, &mut recv_buffer).await
+}
+
+#[tokio::test]
+async fn broker_forwards_messages() {
+    let broker = TestBroker::new();
+    let (sub_end, sub_broker_end) = duplex(256);
+    let (pub_end, pub_broker_end) = duplex(256);
+    let subscribed = Notify::new();
+
+    let subscriber = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            sub_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("sub"),
+        );
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("sensor/+/temperature").await?;
+        subscribed.notify_one();
+        for expected in ["21.5", "22.0"] {
+            let (topic, payload) = client.receive_message().await?;
+            assert_eq!(topic, "sensor/1/temperature");
+            assert_eq!(payload, expected.as_bytes());
+        }
+        client.disconnect().await
+    };
+    let publisher = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            pub_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("pub"),
+        );
+        client.connect_to_broker().await?;
+        subscribed.notified().await;
+        client
+            .send_message("sensor/1/humidity", b"40", QualityOfService::QoS0, false)
+            .await?;
+        client
+            .send_message(
+                "sensor/1/temperature",
+                b"21.5",
+                QualityOfService::QoS1,
+                false,
+            )
+            .await?;
+        client
+            .send_message(
+                "sensor/1/temperature",
+                b"22.0",
+                QualityOfService::QoS0,
+                false,
+            )
+            .await?;
+        client.disconnect().await
+    };
+
+    let (sub_res, pub_res, sub_served, pub_served) = tokio::join!(
+        subscriber,
+        publisher,
+        serve(&broker, sub_broker_end),
+        serve(&broker, pub_broker_end)
+    );
+    assert_ok!(sub_res);
+    assert_ok!(pub_res);
+    assert_ok!(sub_served);
+    assert_ok!(pub_served);
+    assert_eq!(broker.connected_clients(), 0);
+}
+
+#[tokio::test]
+async fn broker_delivers_retained_and_local_messages() {
+    let broker = TestBroker::new();
+    assert_eq!(
+        broker.publish("hub/status", b"online", QualityOfService::QoS1, true),
+        Ok(0)
+    );
+    assert_eq!(
+        broker.publish("hub/#", b"x", QualityOfService::QoS0, false),
+        Err(ReasonCode::TopicNameInvalid)
+    );
+    let (client_end, broker_end) = duplex(256);
+    let subscribed = Notify::new();
+
+    let client = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            client_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("node"),
+        );
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("hub/#").await?;
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!((topic, payload), ("hub/status", &b"online"[..]));
+        subscribed.notify_one();
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!((topic, payload), ("hub/time", &b"12:00"[..]));
+        client.send_ping().await?;
+        client.disconnect().await
+    };
+    let hub = async {
+        subscribed.notified().await;
+        assert_eq!(
+            broker.publish("hub/time", b"12:00", QualityOfService::QoS0, false),
+            Ok(1)
+        );
+    };
+
+    let (client_res, _, served) = tokio::join!(client, hub, serve(&broker, broker_end));
+    assert_ok!(client_res);
+    assert_ok!(served);
+}
+
+#[tokio::test]
+async fn broker_publishes_will_of_lost_client() {
+    let broker = TestBroker::new();
+    let (watcher_end, watcher_broker_end) = duplex(256);
+    let (sensor_end, sensor_broker_end) = duplex(256);
+    let subscribed = Notify::new();
+
+    let watcher = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            watcher_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("watcher"),
+        );
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("status/#").await?;
+        subscribed.notify_one();
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!((topic, payload), ("status/sensor", &b"offline"[..]));
+        client.disconnect().await
+    };
+    let sensor = async {
+        subscribed.notified().await;
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut sensor_config = config("sensor");
+        sensor_config.add_will("status/sensor", b"offline", false);
+        let mut client = TestClient::new(
+            sensor_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            sensor_config,
+        );
+        client.connect_to_broker().await?;
+        // Connection is lost without DISCONNECT
+        drop(client);
+        Ok::<(), ClientError<embedded_io::ErrorKind>>(())
+    };
+
+    let (watcher_res, sensor_res, watcher_served, sensor_served) = tokio::join!(
+        watcher,
+        sensor,
+        serve(&broker, watcher_broker_end),
+        serve(&broker, sensor_broker_end)
+    );
+    assert_ok!(watcher_res);
+    assert_ok!(sensor_res);
+    assert_ok!(watcher_served);
+    assert_eq!(sensor_served, Err(BrokerError::ConnectionClosed));
+}
+
+#[tokio::test]
+async fn broker_refuses_clients_over_capacity() {
+    let broker = Broker::<1, 1, 1, 1>::new();
+    let (first_end, first_broker_end) = duplex(256);
+    let (second_end, second_broker_end) = duplex(256);
+    let connected = Notify::new();
+    let refused = Notify::new();
+
+    let first = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            first_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("first"),
+        );
+        client.connect_to_broker().await?;
+        connected.notify_one();
+        refused.notified().await;
+        client.disconnect().await
+    };
+    let second = async {
+        connected.notified().await;
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            second_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("second"),
+        );
+        let res = client.connect_to_broker().await;
+        refused.notify_one();
+        res
+    };
+    let mut buffers = ([0; 128], [0; 128], [0; 128], [0; 128]);
+    let (first_res, second_res, first_served, second_served) = tokio::join!(
+        first,
+        second,
+        broker.serve(first_broker_end, &mut buffers.0, &mut buffers.1),
+        broker.serve(second_broker_end, &mut buffers.2, &mut buffers.3)
+    );
+    assert_ok!(first_res);
+    assert_err!(second_res);
+    assert_ok!(first_served);
+    assert_eq!(
+        second_served,
+        Err(BrokerError::Refused(ReasonCode::ServerBusy))
+    );
+}
+
+#[tokio::test]
+async fn broker_takes_over_session() {
+    let broker = TestBroker::new();
+    let (old_end, old_broker_end) = duplex(256);
+    let (new_end, new_broker_end) = duplex(256);
+    let connected = Notify::new();
+
+    let old = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            old_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("sensor"),
+        );
+        client.connect_to_broker().await?;
+        connected.notify_one();
+        client.receive_message().await.map(|_| ())
+    };
+    let new = async {
+        connected.notified().await;
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            new_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("sensor"),
+        );
+        client.connect_to_broker().await?;
+        client.disconnect().await
+    };
+
+    let (old_res, new_res, old_served, new_served) = tokio::join!(
+        old,
+        new,
+        serve(&broker, old_broker_end),
+        serve(&broker, new_broker_end)
+    );
+    assert_err!(old_res);
+    assert_ok!(new_res);
+    assert_eq!(old_served, Err(BrokerError::SessionTakenOver));
+    assert_ok!(new_served);
+}
+
+#[tokio::test]
+async fn broker_answers_old_protocol_version() {
+    let broker = TestBroker::new();
+    let (mut client_end, broker_end) = duplex(256);
+    let client = async {
+        // MQTT 3.1.1 CONNECT with client identifier "a"
+        let connect = [
+            0x10, 0x0D, 0x00, 0x04, 0x4d, 0x51, 0x54, 0x54, 0x04, 0x02, 0x00, 0x3c, 0x00, 0x01,
+            0x61,
+        ];
+        assert_ok!(client_end.write(&connect).await);
+        let mut connack = [0; 8];
+        let len = assert_ok!(client_end.read(&mut connack).await);
+        connack[..len].to_vec()
+    };
+    let (connack, served) = tokio::join!(client, serve(&broker, broker_end));
+    assert_eq!(connack, [0x20, 0x02, 0x00, 0x01]);
+    assert_eq!(
+        served,
+        Err(BrokerError::Refused(ReasonCode::UnsupportedProtocolVersion))
+    );
+}
+
+#[tokio::test]
+async fn broker_disconnects_client_sending_qos2() {
+    let broker = TestBroker::new();
+    let (client_end, broker_end) = duplex(256);
+    let client = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            client_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("qos2"),
+        );
+        client.connect_to_broker().await?;
+        // Client does not wait for PUBREC, the DISCONNECT is seen by the next receive
+        client
+            .send_message("topic", b"x", QualityOfService::QoS2, false)
+            .await?;
+        client.receive_message().await.map(|_| ())
+    };
+    let (client_res, served) = tokio::join!(client, serve(&broker, broker_end));
+    assert_err!(client_res);
+    assert_eq!(
+        served,
+        Err(BrokerError::Protocol(ReasonCode::QoSNotSupported))
+    );
+    assert_eq!(broker.connected_clients(), 0);
+}
@@ -0,0 +1,76 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Forwarding of received messages into an embassy-sync channel.
+mod common;
+
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use embassy_sync::channel::Channel;
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::channel::forward_messages;
+use rust_mqtt::client::owned_message::OwnedMessage;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::queue::OverflowPolicy;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+
+use common::{config, receiver_broker, TestBuffers, TestError};
+
+#[tokio::test]
+async fn offline_forward_messages() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = receiver_broker(
+        broker_end,
+        QualityOfService::QoS1,
+        &[
+            ("sensors/1", "first"),
+            ("sensors/2", "too long payload"),
+            ("sensors/3", "third"),
+        ],
+    )
+    .send(canned_packets::disconnect(ReasonCode::ServerShuttingDown));
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    let channel = Channel::<NoopRawMutex, OwnedMessage<16, 8>, 4>::new();
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("sensors/#").await?;
+        Err::<(), TestError>(
+            forward_messages(&mut client, &channel, OverflowPolicy::DropOldest).await,
+        )
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_eq!(
+        result.unwrap_err().reason_code(),
+        Some(&ReasonCode::ServerShuttingDown)
+    );
+    let first = channel.try_receive().unwrap();
+    assert_eq!(first.topic.as_str(), "sensors/1");
+    assert_eq!(first.payload.as_slice(), b"first");
+    assert_eq!(first.qos, QualityOfService::QoS1);
+    assert_eq!(channel.try_receive().unwrap().payload.as_slice(), b"third");
+    assert!(channel.is_empty());
+}
@@ -0,0 +1,125 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Typed publish and content type based decoding of received messages.
+mod common;
+
+use serde::{Deserialize, Serialize};
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::codec::Codec;
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::property_kind::{ContentType, PayloadFormat};
+use rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+use rust_mqtt::utils::types::BufferError;
+
+use common::{config, decode, TestBuffers, TestError};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Reading<'a> {
+    sensor: &'a str,
+    temperature: i16,
+}
+
+#[tokio::test]
+async fn offline_publish_typed() {
+    let (client_end, broker_end) = duplex(256);
+    // The broker echoes the PUBLISH back, so the client receives its own typed message.
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_reply(PacketType::Publish, |packet| packet.to_vec());
+    let mut buffers = TestBuffers::<100>::new();
+    let mut payload_buffer = [0; 64];
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let reading = Reading {
+        sensor: "kitchen",
+        temperature: 21,
+    };
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client
+            .publish_typed(
+                "sensors",
+                &reading,
+                Codec::Json,
+                QualityOfService::QoS0,
+                false,
+                &mut payload_buffer,
+            )
+            .await?;
+        let message = client.receive_message_with_properties().await?;
+        assert_eq!(message.codec(), Some(Codec::Json));
+        assert_eq!(message.decode::<Reading>(), Ok(reading));
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let received = received.unwrap();
+    let publish: PublishPacket<2> = decode(&received[1]);
+    assert_eq!(publish.get::<ContentType>(), Some("application/json"));
+    assert_eq!(publish.get::<PayloadFormat>(), Some(1));
+    assert_eq!(
+        publish.message,
+        Some(&br#"{"sensor":"kitchen","temperature":21}"#[..])
+    );
+}
+
+#[tokio::test]
+async fn offline_publish_typed_buffer_too_small() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect(PacketType::Disconnect);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut payload_buffer = [0; 8];
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let reading = Reading {
+        sensor: "kitchen",
+        temperature: 21,
+    };
+    let client_part = async {
+        client.connect_to_broker().await?;
+        let result = client
+            .publish_typed(
+                "sensors",
+                &reading,
+                Codec::Postcard,
+                QualityOfService::QoS0,
+                false,
+                &mut payload_buffer,
+            )
+            .await;
+        assert_eq!(
+            result,
+            Err(ClientError::Encode(BufferError::InsufficientBufferSize))
+        );
+        client.disconnect().await
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    assert_eq!(received.unwrap().len(), 2);
+}
@@ -0,0 +1,168 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Fixture shared by the offline integration tests running the client against the
+//! scripted broker over the in-memory transport.
+#![allow(dead_code)]
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use embedded_io::ErrorKind;
+use heapless::Vec;
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::client_config::{ClientConfig, ClientConfigBuilder};
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::MemoryTransport;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+use rust_mqtt::utils::buffer_reader::BuffReader;
+use rust_mqtt::utils::clock::Clock;
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+pub type TestClient<'a> = MqttClient<'a, MemoryTransport, 5, CountingRng>;
+pub type TestError = ClientError<ErrorKind>;
+
+pub static USERNAME: &str = "test";
+pub static PASSWORD: &str = "testPass";
+pub static MSG: &str = "testMessage";
+
+pub fn config(qos: QualityOfService) -> ClientConfig<'static, 5, CountingRng> {
+    ClientConfigBuilder::new(MQTTv5, CountingRng(20000))
+        .max_subscribe_qos(qos)
+        .username(USERNAME)
+        .password(PASSWORD.as_bytes())
+        .max_packet_size(100)
+        .receive_maximum(20)
+        .build()
+        .unwrap()
+}
+
+/// Write and receive buffers of `LEN` bytes for the client.
+pub struct TestBuffers<const LEN: usize> {
+    write: [u8; LEN],
+    recv: [u8; LEN],
+}
+
+impl<const LEN: usize> TestBuffers<LEN> {
+    pub fn new() -> Self {
+        Self {
+            write: [0; LEN],
+            recv: [0; LEN],
+        }
+    }
+
+    /// Returns the client over the `transport` using the whole buffers.
+    pub fn client<'a>(
+        &'a mut self,
+        transport: MemoryTransport,
+        config: ClientConfig<'a, 5, CountingRng>,
+    ) -> TestClient<'a> {
+        MqttClient::new(transport, &mut self.write, LEN, &mut self.recv, LEN, config)
+    }
+}
+
+/// Decodes the packet `P` sent by the client.
+pub fn decode<'a, P: Packet<'a>>(packet: &'a [u8]) -> P {
+    let mut decoded = P::new();
+    assert_ok!(decoded.decode(&mut BuffReader::new(packet, packet.len())));
+    decoded
+}
+
+/// Returns topic and payload of the PUBLISH packet sent by the client.
+pub fn published(packet: &[u8]) -> (String, String) {
+    let publish: PublishPacket<2> = decode(packet);
+    (
+        publish.topic_name.string.to_string(),
+        String::from_utf8_lossy(publish.message.unwrap()).to_string(),
+    )
+}
+
+/// Broker script for the receiver: CONNECT, SUBSCRIBE and the PUBLISH packets delivered
+/// to the client (acknowledged by the client for QoS 1).
+pub fn receiver_broker(
+    transport: MemoryTransport,
+    qos: QualityOfService,
+    messages: &[(&str, &str)],
+) -> ScriptedBroker {
+    let mut broker = ScriptedBroker::new(transport)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Subscribe);
+    for (i, (topic, message)) in messages.iter().enumerate() {
+        broker = broker.send(canned_packets::publish(
+            topic,
+            message.as_bytes(),
+            qos,
+            i as u16 + 1,
+        ));
+        if qos == QualityOfService::QoS1 {
+            broker = broker.expect(PacketType::Puback);
+        }
+    }
+    broker
+}
+
+pub async fn receive_core<const TOPICS: usize>(
+    client: &mut TestClient<'_>,
+    topic_names: &Vec<&str, TOPICS>,
+    messages: &[&str],
+) -> Result<(), TestError> {
+    client.connect_to_broker().await?;
+    client.subscribe_to_topics(topic_names).await?;
+    for expected in messages {
+        let (_, payload) = client.receive_message().await?;
+        assert_eq!(String::from_utf8_lossy(payload), *expected);
+    }
+    client.disconnect().await
+}
+
+pub fn topics<const TOPICS: usize>(names: &[&'static str]) -> Vec<&'static str, TOPICS> {
+    let mut topic_names = Vec::new();
+    for name in names {
+        assert_ok!(topic_names.push(*name));
+    }
+    topic_names
+}
+
+/// Clock advancing by one millisecond on every reading.
+pub struct StepClock(pub AtomicU64);
+
+impl Clock for StepClock {
+    fn now_micros(&self) -> u64 {
+        self.0.fetch_add(1000, Ordering::Relaxed)
+    }
+}
+
+/// UNIX time clock advancing by one minute on every reading.
+pub struct MinuteClock(pub AtomicU64);
+
+impl Clock for MinuteClock {
+    fn now_micros(&self) -> u64 {
+        self.0.fetch_add(60_000_000, Ordering::Relaxed)
+    }
+}
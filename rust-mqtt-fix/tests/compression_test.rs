@@ -0,0 +1,95 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Payload compression signalled by the content-encoding user property.
+mod common;
+
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, TestBuffers, TestError};
+
+#[tokio::test]
+async fn offline_publish_compressed() {
+    let readings = br#"[{"sensor":"dht11","temperature":21.5,"humidity":40},{"sensor":"dht11","temperature":21.6,"humidity":41},{"sensor":"dht11","temperature":21.6,"humidity":40},{"sensor":"dht11","temperature":21.7,"humidity":40}]"#;
+    let (client_end, broker_end) = duplex(512);
+    // The broker echoes both PUBLISH packets back.
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_reply(PacketType::Publish, |packet| packet.to_vec())
+        .expect_and_reply(PacketType::Publish, |packet| packet.to_vec());
+    let mut decompression_buffer = [0; 256];
+    let mut payload_buffer = [0; 256];
+    let mut buffers = TestBuffers::<160>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    client.set_decompression_buffer(&mut decompression_buffer);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client
+            .publish_compressed(
+                "testtopic/dht11",
+                readings,
+                QualityOfService::QoS0,
+                false,
+                &mut payload_buffer,
+            )
+            .await?;
+        {
+            // Decompressed payload is larger than the receive buffer
+            let message = client.receive_message_with_properties().await?;
+            assert_eq!(message.payload, &readings[..]);
+            assert_eq!(message.content_encoding(), None);
+        }
+        client
+            .publish_compressed(
+                "testtopic/dht11",
+                b"21.5",
+                QualityOfService::QoS0,
+                false,
+                &mut payload_buffer,
+            )
+            .await?;
+        let (_, payload) = client.receive_message().await?;
+        assert_eq!(payload, b"21.5");
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let received = received.unwrap();
+    let publish: PublishPacket<2> = decode(&received[1]);
+    assert_eq!(
+        publish.user_properties().collect::<StdVec<_>>(),
+        [("content-encoding", "heatshrink;w=8;l=4")]
+    );
+    assert!(publish.message.unwrap().len() < readings.len() / 2);
+    let publish: PublishPacket<2> = decode(&received[2]);
+    assert_eq!(publish.user_properties().count(), 0);
+    assert_eq!(publish.message, Some(&b"21.5"[..]));
+}
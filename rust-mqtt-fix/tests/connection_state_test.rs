@@ -0,0 +1,118 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Connection state reported by the client through the connection lifetime.
+mod common;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::client::connection_state::{ConnectionState, DisconnectReason};
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, TestBuffers};
+
+#[tokio::test]
+async fn offline_connection_state() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .send(canned_packets::disconnect(ReasonCode::ServerShuttingDown));
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    assert_eq!(client.state(), ConnectionState::TcpConnected);
+    assert_eq!(
+        client
+            .send_message("test", b"early", QualityOfService::QoS0, false)
+            .await,
+        Err(ClientError::InvalidState(ConnectionState::TcpConnected))
+    );
+    let client_part = async {
+        client.connect_to_broker().await?;
+        assert!(client.is_connected());
+        client.receive_message().await.map(|_| ())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_eq!(
+        result.unwrap_err().reason_code(),
+        Some(&ReasonCode::ServerShuttingDown)
+    );
+    let state =
+        ConnectionState::Disconnected(DisconnectReason::Broker(ReasonCode::ServerShuttingDown));
+    assert_eq!(client.state(), state);
+    let err = client.subscribe_to_topic("test").await.unwrap_err();
+    assert_eq!(err, ClientError::InvalidState(state));
+    assert!(err.is_connection_error());
+    assert_eq!(
+        client.disconnect().await,
+        Err(ClientError::InvalidState(state))
+    );
+}
+
+#[tokio::test]
+async fn offline_rejected_connect_closes_connection() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end).expect_and_reply(PacketType::Connect, |_| {
+        vec![0x20, 0x03, 0x00, ReasonCode::NotAuthorized.into(), 0x00]
+    });
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let (received, result) = tokio::join!(broker.run(), client.connect_to_broker());
+    assert_ok!(received);
+    assert_eq!(
+        result.unwrap_err().reason_code(),
+        Some(&ReasonCode::NotAuthorized)
+    );
+    assert_eq!(
+        client.state(),
+        ConnectionState::Disconnected(DisconnectReason::Rejected(ReasonCode::NotAuthorized))
+    );
+}
+
+#[tokio::test]
+async fn offline_disconnect_state() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect(PacketType::Disconnect);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.disconnect().await
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_ok!(result);
+    assert_eq!(client.state(), ConnectionState::Idle);
+    assert_eq!(
+        client.connect_to_broker().await,
+        Err(ClientError::InvalidState(ConnectionState::Idle))
+    );
+}
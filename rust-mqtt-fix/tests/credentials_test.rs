@@ -0,0 +1,85 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Credentials provider consulted on every connect.
+mod common;
+
+use std::sync::atomic::AtomicU64;
+use std::vec::Vec as StdVec;
+
+use tokio_test::{assert_err, assert_ok};
+
+use rust_mqtt::client::sas::AzureSasProvider;
+use rust_mqtt::packet::v5::connect_packet::ConnectPacket;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, MinuteClock, TestBuffers};
+
+#[tokio::test]
+async fn offline_credentials_refreshed_on_reconnect() {
+    let (client_end, broker_end) = duplex(512);
+    let (second_client_end, second_broker_end) = duplex(512);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .disconnect();
+    let second_broker = ScriptedBroker::new(second_broker_end).expect_and_ack(PacketType::Connect);
+    let clock = MinuteClock(AtomicU64::new(1_700_000_000_000_000));
+    let mut provider = AzureSasProvider::new(
+        "my-hub.azure-devices.net",
+        "sensor-1",
+        "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=",
+        3600,
+        &clock,
+    )
+    .unwrap();
+    let mut buffers = TestBuffers::<400>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    client.set_credentials_provider(&mut provider);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        assert_err!(client.receive_message().await);
+        client.set_network_driver(second_client_end)?;
+        client.connect_to_broker().await
+    };
+    let (received, second_received, result) =
+        tokio::join!(broker.run(), second_broker.run(), client_part);
+    assert_ok!(result);
+    let mut expiries = StdVec::new();
+    for packets in [received.unwrap(), second_received.unwrap()] {
+        let connect: ConnectPacket<5, 0> = decode(&packets[0]);
+        assert_eq!(connect.client_id.string, "sensor-1");
+        assert_eq!(
+            connect.username.string,
+            "my-hub.azure-devices.net/sensor-1/?api-version=2021-04-12"
+        );
+        let password = std::str::from_utf8(connect.password.bin).unwrap();
+        assert!(password.starts_with(
+            "SharedAccessSignature sr=my-hub.azure-devices.net%2Fdevices%2Fsensor-1&sig="
+        ));
+        expiries.push(password.rsplit("&se=").next().unwrap().to_string());
+    }
+    assert_eq!(expiries, ["1700003600", "1700003660"]);
+}
@@ -0,0 +1,115 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Duplicate filter for QoS 1 messages redelivered by the broker.
+mod common;
+
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::duplicate_filter::{DuplicateMode, RecentMessages};
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::{duplex, MemoryTransport};
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, TestBuffers, TestError};
+
+fn redelivering_broker(transport: MemoryTransport) -> ScriptedBroker {
+    let mut redelivered = canned_packets::publish("cmd", b"on", QualityOfService::QoS1, 1);
+    redelivered[0] |= 0x08;
+    let mut first_delivery = canned_packets::publish("cmd", b"off", QualityOfService::QoS1, 2);
+    first_delivery[0] |= 0x08;
+    ScriptedBroker::new(transport)
+        .expect_and_ack(PacketType::Connect)
+        .send(canned_packets::publish(
+            "cmd",
+            b"on",
+            QualityOfService::QoS1,
+            1,
+        ))
+        .expect(PacketType::Puback)
+        .send(redelivered)
+        .expect(PacketType::Puback)
+        .send(first_delivery)
+        .expect(PacketType::Puback)
+}
+
+#[tokio::test]
+async fn offline_duplicate_flagged() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = redelivering_broker(broker_end);
+    let mut filter = RecentMessages::<4>::new();
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    client.set_duplicate_filter(&mut filter, DuplicateMode::Flag);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        let mut received = StdVec::new();
+        for _ in 0..3 {
+            let message = client.receive_message_with_properties().await?;
+            received.push((message.payload.to_vec(), message.is_duplicate()));
+        }
+        Ok::<_, TestError>(received)
+    };
+    let (packets, received) = tokio::join!(broker.run(), client_part);
+    assert_ok!(packets);
+    assert_eq!(
+        received.unwrap(),
+        [
+            (b"on".to_vec(), false),
+            (b"on".to_vec(), true),
+            // DUP flag alone does not make a duplicate
+            (b"off".to_vec(), false),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn offline_duplicate_suppressed() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = redelivering_broker(broker_end);
+    let mut filter = RecentMessages::<4>::new();
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    client.set_duplicate_filter(&mut filter, DuplicateMode::Suppress);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        let (_, first) = client.receive_message().await?;
+        assert_eq!(first, b"on");
+        let (_, second) = client.receive_message().await?;
+        assert_eq!(second, b"off");
+        Ok::<_, TestError>(())
+    };
+    let (packets, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let pubacks: StdVec<u16> = packets
+        .unwrap()
+        .iter()
+        .filter(|packet| PacketType::from(packet[0]) == PacketType::Puback)
+        .map(|packet| u16::from_be_bytes([packet[2], packet[3]]))
+        .collect();
+    assert_eq!(pubacks, [1, 1, 2]);
+}
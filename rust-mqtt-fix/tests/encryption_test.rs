@@ -0,0 +1,90 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! End-to-end payload protection of sent and received messages.
+mod common;
+
+use heapless::Vec;
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::encryption::{EncryptionError, PayloadCipher, PayloadKey, OVERHEAD};
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::property::Property;
+use rust_mqtt::packet::v5::property_kind::PayloadFormat;
+use rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, TestBuffers, TestError};
+
+#[tokio::test]
+async fn offline_payload_protection() {
+    let (client_end, broker_end) = duplex(256);
+    // The broker echoes the PUBLISH back and then sends a spoofed plaintext message.
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_reply(PacketType::Publish, |packet| packet.to_vec())
+        .send(canned_packets::publish(
+            "testtopic/dht11",
+            b"99.9",
+            QualityOfService::QoS0,
+            0,
+        ));
+    let mut cipher_buffer = [0; 64];
+    let mut cipher = PayloadCipher::<1, 1>::new(&mut cipher_buffer, 1, 0);
+    cipher.add_key(PayloadKey::aes128_gcm(1, &[7; 16])).unwrap();
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    client.set_payload_protection(&mut cipher);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        let mut properties = Vec::<Property, 1>::new();
+        properties.push(Property::PayloadFormat(1)).unwrap();
+        client
+            .send_message_with_properties(
+                "testtopic/dht11",
+                b"21.5",
+                QualityOfService::QoS0,
+                false,
+                &properties,
+            )
+            .await?;
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!(topic, "testtopic/dht11");
+        assert_eq!(payload, b"21.5");
+        assert_eq!(
+            client.receive_message().await.map(|_| ()),
+            Err(ClientError::Encryption(EncryptionError::Malformed))
+        );
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let received = received.unwrap();
+    let publish: PublishPacket<2> = decode(&received[1]);
+    assert_eq!(publish.get::<PayloadFormat>(), None);
+    assert_eq!(publish.message.unwrap().len(), 4 + OVERHEAD);
+}
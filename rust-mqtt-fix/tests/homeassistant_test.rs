@@ -0,0 +1,118 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Home Assistant MQTT discovery announced to the scripted broker.
+mod common;
+
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::homeassistant::{Availability, DeviceInfo, Discovery, Entity, StateClass};
+use rust_mqtt::packet::v5::connect_packet::ConnectPacket;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, published, TestBuffers, TestError};
+
+#[tokio::test]
+async fn offline_homeassistant_discovery() {
+    const ENTITIES: [Entity<'static>; 2] = [
+        Entity::sensor("temperature", "Temperature", "node1/temperature")
+            .with_unit("°C")
+            .with_state_class(StateClass::Measurement),
+        Entity::switch("relay", "Relay", "node1/relay", "node1/relay/set"),
+    ];
+    let discovery = Discovery::new(
+        "node1",
+        DeviceInfo::new("aabbccddeeff", "Node 1"),
+        Availability::new("node1/availability"),
+        &ENTITIES,
+    );
+    let (client_end, broker_end) = duplex(2048);
+    // Home Assistant restarts after the first announcement and sends the birth message.
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Subscribe)
+        .expect_and_ack(PacketType::Publish)
+        .expect_and_ack(PacketType::Publish)
+        .expect_and_ack(PacketType::Publish)
+        .send(canned_packets::publish(
+            "homeassistant/status",
+            b"online",
+            QualityOfService::QoS0,
+            0,
+        ))
+        .expect_and_ack(PacketType::Publish)
+        .expect_and_ack(PacketType::Publish)
+        .expect_and_ack(PacketType::Publish);
+    let mut payload_buffer = [0; 400];
+    let mut buffers = TestBuffers::<512>::new();
+    let mut client_config = config(QualityOfService::QoS1);
+    discovery.availability.configure_will(&mut client_config);
+    let mut client = buffers.client(client_end, client_config);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        discovery.subscribe_status(&mut client).await?;
+        discovery.announce(&mut client, &mut payload_buffer).await?;
+        let (topic, payload) = client.receive_message().await?;
+        assert!(discovery.is_birth_message(topic, payload));
+        discovery.announce(&mut client, &mut payload_buffer).await?;
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let received = received.unwrap();
+    let connect: ConnectPacket<5, 0> = decode(&received[0]);
+    assert_eq!(connect.will_topic.string, "node1/availability");
+    assert_eq!(connect.will_payload.bin, b"offline");
+    // Every announcement is retained
+    assert!(received[2..].iter().all(|packet| packet[0] & 0x01 == 0x01));
+    let announcements: StdVec<_> = received[2..]
+        .iter()
+        .map(|packet| published(packet))
+        .collect();
+    assert_eq!(announcements.len(), 6);
+    assert_eq!(announcements[..3], announcements[3..]);
+    assert_eq!(
+        announcements[0].0,
+        "homeassistant/sensor/node1/temperature/config"
+    );
+    assert!(announcements[0]
+        .1
+        .contains(r#""unit_of_measurement":"°C","state_class":"measurement""#));
+    assert_eq!(
+        announcements[1].0,
+        "homeassistant/switch/node1/relay/config"
+    );
+    assert!(announcements[1]
+        .1
+        .contains(r#""availability_topic":"node1/availability""#));
+    assert_eq!(
+        announcements[2],
+        ("node1/availability".to_string(), "online".to_string())
+    );
+}
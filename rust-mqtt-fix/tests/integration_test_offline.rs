@@ -0,0 +1,449 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Integration scenarios of `integration_test_single` running against the
+//! scripted broker over the in-memory transport, plus transport fault injection.
+mod common;
+
+use core::time::Duration;
+use std::vec::Vec as StdVec;
+
+use embedded_io_async::Write;
+use heapless::Vec;
+use tokio_test::{assert_err, assert_ok};
+
+use rust_mqtt::client::client_error::{ClientError, ProtocolViolation};
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::{duplex, MemoryTransport};
+use rust_mqtt::test_support::scripted_broker::{packet_identifier, ScriptedBroker};
+
+use common::{
+    config, published, receive_core, receiver_broker, topics, TestBuffers, TestClient, TestError,
+    MSG,
+};
+
+/// Broker script for the publisher: CONNECT, PUBLISH (acknowledged for QoS 1) and DISCONNECT.
+fn publisher_broker(transport: MemoryTransport) -> ScriptedBroker {
+    ScriptedBroker::new(transport)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Publish)
+        .expect(PacketType::Disconnect)
+}
+
+async fn publish_core(
+    client: &mut TestClient<'_>,
+    qos: QualityOfService,
+    topic: &str,
+    message: &str,
+    should_err: bool,
+) -> Result<(), TestError> {
+    client.connect_to_broker().await?;
+    let result = client
+        .send_message(topic, message.as_bytes(), qos, false)
+        .await;
+    if should_err {
+        assert_err!(result);
+    } else {
+        assert_ok!(result);
+    }
+    client.disconnect().await
+}
+
+async fn publish(
+    broker: ScriptedBroker,
+    client_end: MemoryTransport,
+    qos: QualityOfService,
+    topic: &str,
+    message: &str,
+    should_err: bool,
+) -> StdVec<StdVec<u8>> {
+    let mut buffers = TestBuffers::<80>::new();
+    let mut client = buffers.client(client_end, config(qos));
+    let (received, result) = tokio::join!(
+        broker.run(),
+        publish_core(&mut client, qos, topic, message, should_err)
+    );
+    assert_ok!(result);
+    received.unwrap()
+}
+
+async fn receive<const TOPICS: usize>(
+    qos: QualityOfService,
+    topic_names: Vec<&str, TOPICS>,
+    messages: &[(&str, &str)],
+) {
+    let (client_end, broker_end) = duplex(256);
+    let broker = receiver_broker(broker_end, qos, messages).expect(PacketType::Disconnect);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(qos));
+    let payloads: StdVec<&str> = messages.iter().map(|(_, message)| *message).collect();
+    let (received, result) = tokio::join!(
+        broker.run(),
+        receive_core(&mut client, &topic_names, &payloads)
+    );
+    assert_ok!(result);
+    assert_ok!(received);
+}
+
+#[tokio::test]
+async fn offline_publish_recv() {
+    let (client_end, broker_end) = duplex(256);
+    let received = publish(
+        publisher_broker(broker_end),
+        client_end,
+        QualityOfService::QoS0,
+        "test/recv/simple",
+        MSG,
+        false,
+    )
+    .await;
+    assert_eq!(
+        published(&received[1]),
+        ("test/recv/simple".to_string(), MSG.to_string())
+    );
+
+    receive(
+        QualityOfService::QoS0,
+        topics::<1>(&["test/recv/simple"]),
+        &[("test/recv/simple", MSG)],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn offline_publish_recv_qos() {
+    let (client_end, broker_end) = duplex(256);
+    let received = publish(
+        publisher_broker(broker_end),
+        client_end,
+        QualityOfService::QoS1,
+        "test/recv/qos",
+        MSG,
+        false,
+    )
+    .await;
+    assert_eq!(received[1][0] & 0x06, 0x02);
+
+    receive(
+        QualityOfService::QoS1,
+        topics::<1>(&["test/recv/qos"]),
+        &[("test/recv/qos", MSG)],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn offline_publish_recv_multiple() {
+    receive(
+        QualityOfService::QoS0,
+        topics::<2>(&["test/topic1", "test/topic2"]),
+        &[("test/topic1", MSG), ("test/topic2", MSG)],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn offline_publish_recv_multiple_qos() {
+    receive(
+        QualityOfService::QoS1,
+        topics::<2>(&["test/topic3", "test/topic4"]),
+        &[("test/topic3", MSG), ("test/topic4", MSG)],
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn offline_publish_recv_wrong_cred() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect(PacketType::Connect)
+        .send(canned_packets::connack(ReasonCode::NotAuthorized));
+    let mut buffers = TestBuffers::<100>::new();
+    let mut config = config(QualityOfService::QoS1);
+    config.add_username("xyz");
+    let mut client = buffers.client(client_end, config);
+    let (received, result) = tokio::join!(broker.run(), client.connect_to_broker());
+    assert_ok!(received);
+    assert_eq!(
+        result.unwrap_err().reason_code(),
+        Some(&ReasonCode::NotAuthorized)
+    );
+}
+
+#[tokio::test]
+async fn offline_sub_unsub() {
+    let msg_t1 = "First topic message";
+    let msg_t2 = "Second topic message";
+    let topic_names = topics::<2>(&["unsub/topic1", "unsub/topic2"]);
+
+    let (client_end, broker_end) = duplex(256);
+    let broker = receiver_broker(
+        broker_end,
+        QualityOfService::QoS1,
+        &[("unsub/topic1", msg_t1), ("unsub/topic2", msg_t2)],
+    )
+    .expect_and_ack(PacketType::Unsubscribe)
+    .send(canned_packets::publish(
+        "unsub/topic1",
+        msg_t1.as_bytes(),
+        QualityOfService::QoS1,
+        3,
+    ))
+    .expect(PacketType::Puback)
+    .expect(PacketType::Disconnect);
+
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.subscribe_to_topics(&topic_names).await?;
+        assert_eq!(client.receive_message().await?.1, msg_t1.as_bytes());
+        assert_eq!(client.receive_message().await?.1, msg_t2.as_bytes());
+        client.unsubscribe_from_topic("unsub/topic2").await?;
+        assert_eq!(client.receive_message().await?.1, msg_t1.as_bytes());
+        let res = tokio::time::timeout(Duration::from_millis(100), client.receive_message()).await;
+        assert_err!(res);
+        client.disconnect().await
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    assert_ok!(received);
+
+    // Broker without subscribers for the topic refuses the QoS 1 message
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_reply(PacketType::Publish, |packet| {
+            canned_packets::puback(
+                packet_identifier(packet).unwrap(),
+                ReasonCode::NoMatchingSubscribers,
+            )
+        })
+        .expect(PacketType::Disconnect);
+    publish(
+        broker,
+        client_end,
+        QualityOfService::QoS1,
+        "unsub/topic2",
+        msg_t2,
+        true,
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn offline_partial_reads() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect(PacketType::Connect)
+        .send_chunked(
+            canned_packets::connack(ReasonCode::Success),
+            1,
+            Duration::from_millis(1),
+        )
+        .expect_and_reply(PacketType::Subscribe, |packet| {
+            canned_packets::suback(packet_identifier(packet).unwrap(), &[0x00])
+        })
+        .send_chunked(
+            canned_packets::publish("test/partial", MSG.as_bytes(), QualityOfService::QoS0, 0),
+            3,
+            Duration::from_millis(1),
+        );
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("test/partial").await?;
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!(topic, "test/partial");
+        assert_eq!(payload, MSG.as_bytes());
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    assert_ok!(received);
+}
+
+#[tokio::test]
+async fn offline_coalesced_packets() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_reply(PacketType::Subscribe, |packet| {
+            // SUBACK and two messages in one write
+            let mut bytes = canned_packets::suback(packet_identifier(packet).unwrap(), &[0x00]);
+            bytes.extend(canned_packets::publish(
+                "test/first",
+                b"first",
+                QualityOfService::QoS0,
+                0,
+            ));
+            bytes.extend(canned_packets::publish(
+                "test/second",
+                b"second",
+                QualityOfService::QoS0,
+                0,
+            ));
+            bytes
+        });
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("test/#").await?;
+        assert_eq!(
+            client.receive_message().await?,
+            ("test/first", &b"first"[..])
+        );
+        assert_eq!(
+            client.receive_message().await?,
+            ("test/second", &b"second"[..])
+        );
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    assert_ok!(received);
+}
+
+#[tokio::test]
+async fn offline_delayed_ack() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect(PacketType::Connect)
+        .delay(Duration::from_millis(50))
+        .send(canned_packets::connack(ReasonCode::Success))
+        .expect(PacketType::Pingreq)
+        .delay(Duration::from_millis(50))
+        .send(canned_packets::pingresp());
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.send_ping().await
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    assert_ok!(received);
+}
+
+#[tokio::test]
+async fn offline_broker_disconnects() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Subscribe)
+        .disconnect();
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("test/closed").await?;
+        client.receive_message().await.map(|_| ())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_eq!(result, Err(ClientError::NotConnected));
+    assert!(result.unwrap_err().is_connection_error());
+}
+
+#[tokio::test]
+async fn offline_broker_sends_disconnect() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect(PacketType::Publish)
+        .send(canned_packets::disconnect(ReasonCode::QuotaExceeded));
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client
+            .send_message("test/quota", MSG.as_bytes(), QualityOfService::QoS1, false)
+            .await
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_eq!(
+        result.unwrap_err().reason_code(),
+        Some(&ReasonCode::QuotaExceeded)
+    );
+}
+
+#[tokio::test]
+async fn offline_malformed_bytes() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect(PacketType::Connect)
+        // remaining length with continuation bit on all four bytes
+        .send(vec![0x20, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let (received, result) = tokio::join!(broker.run(), client.connect_to_broker());
+    assert_ok!(received);
+    assert_eq!(
+        result,
+        Err(ClientError::Protocol(
+            ProtocolViolation::MalformedRemainingLength
+        ))
+    );
+}
+
+#[tokio::test]
+async fn offline_script_reports_unexpected_packet() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end).expect(PacketType::Subscribe);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS0));
+    let (received, result) = tokio::join!(broker.run(), client.connect_to_broker());
+    assert_eq!(
+        received,
+        Err(
+            rust_mqtt::test_support::scripted_broker::ScriptError::UnexpectedPacket {
+                step: 0,
+                expected: PacketType::Subscribe,
+                received: PacketType::Connect,
+            }
+        )
+    );
+    assert_eq!(result, Err(ClientError::NotConnected));
+}
+
+#[tokio::test]
+async fn offline_script_reports_truncated_subscribe() {
+    let (mut client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end).expect_and_ack(PacketType::Subscribe);
+    // packet identifier without the property length and topic filters
+    let subscribe = async {
+        assert_ok!(client_end.write_all(&[0x82, 0x02, 0x00, 0x01]).await);
+        client_end
+    };
+    let (received, _client_end) = tokio::join!(broker.run(), subscribe);
+    assert_eq!(
+        received,
+        Err(rust_mqtt::test_support::scripted_broker::ScriptError::MalformedPacket { step: 0 })
+    );
+}
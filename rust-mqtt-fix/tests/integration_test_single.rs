@@ -39,12 +39,12 @@ use embedded_io_adapters::tokio_1::FromTokio;
 use rust_mqtt::client::client::MqttClient;
 use rust_mqtt::client::client_config::ClientConfig;
 use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
-use rust_mqtt::packet::v5::property::Property;
+use rust_mqtt::client::client_error::ClientError;
 use rust_mqtt::packet::v5::publish_packet::QualityOfService;
-use rust_mqtt::packet::v5::reason_codes::ReasonCode;
 use rust_mqtt::packet::v5::reason_codes::ReasonCode::NotAuthorized;
 use rust_mqtt::utils::rng_generator::CountingRng;
 pub type TokioNetwork = FromTokio<TcpStream>;
+type TestError = ClientError<std::io::Error>;
 
 static IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
 static PORT: u16 = 1883;
@@ -67,12 +67,12 @@ async fn publish_core<'b>(
     topic: &str,
     message: &str,
     should_err: bool,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     info!(
         "[Publisher] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = { client.connect_to_broker().await };
+    let mut result = { client.connect_to_broker().await.map(|_| ()) };
     assert_ok!(result);
     info!("[Publisher] Waiting {} seconds before sending", wait);
     sleep(Duration::from_secs(wait)).await;
@@ -103,11 +103,11 @@ async fn publish(
     wait: u64,
     qos: QualityOfService,
     topic: &str,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(ip.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
@@ -135,11 +135,11 @@ async fn publish_spec(
     topic: &str,
     message: &str,
     err: bool,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(ip.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
@@ -163,12 +163,12 @@ async fn publish_spec(
 async fn receive_core<'b>(
     client: &mut MqttClient<'b, TokioNetwork, 5, CountingRng>,
     topic: &str,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     info!(
         "[Receiver] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = client.connect_to_broker().await;
+    let mut result = client.connect_to_broker().await.map(|_| ());
     assert_ok!(result);
 
     info!("[Receiver] Subscribing to topic {}", topic);
@@ -191,17 +191,17 @@ async fn receive_core<'b>(
 async fn receive_core_multiple<'b, const TOPICS: usize>(
     client: &mut MqttClient<'b, TokioNetwork, 5, CountingRng>,
     topic_names: &'b Vec<&'b str, TOPICS>,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     info!(
         "[Receiver] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = client.connect_to_broker().await;
+    let mut result = client.connect_to_broker().await.map(|_| ());
     assert_ok!(result);
 
     info!(
         "[Receiver] Subscribing to topics {}, {}",
-        topic_names.get(0).unwrap(),
+        topic_names.first().unwrap(),
         topic_names.get(1).unwrap()
     );
     result = client.subscribe_to_topics(topic_names).await;
@@ -232,18 +232,18 @@ async fn receive_core_multiple<'b, const TOPICS: usize>(
 async fn receive_multiple<const TOPICS: usize>(
     qos: QualityOfService,
     topic_names: &Vec<&str, TOPICS>,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(IP.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
     config.add_username(USERNAME);
     config.add_password(PASSWORD);
     config.max_packet_size = 60;
-    assert_ok!(config.properties.push(Property::ReceiveMaximum(20)));
+    assert_ok!(config.add_receive_maximum(20));
     let mut recv_buffer = [0; 100];
     let mut write_buffer = [0; 100];
 
@@ -259,18 +259,18 @@ async fn receive_multiple<const TOPICS: usize>(
     receive_core_multiple(&mut client, topic_names).await
 }
 
-async fn receive(ip: Ipv4Addr, qos: QualityOfService, topic: &str) -> Result<(), ReasonCode> {
+async fn receive(ip: Ipv4Addr, qos: QualityOfService, topic: &str) -> Result<(), TestError> {
     let addr = SocketAddr::new(ip.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
     config.add_username(USERNAME);
     config.add_password(PASSWORD);
     config.max_packet_size = 6000;
-    assert_ok!(config.properties.push(Property::ReceiveMaximum(20)));
+    assert_ok!(config.add_receive_maximum(20));
     let mut recv_buffer = [0; 100];
     let mut write_buffer = [0; 100];
 
@@ -286,18 +286,18 @@ async fn receive(ip: Ipv4Addr, qos: QualityOfService, topic: &str) -> Result<(),
     receive_core(&mut client, topic).await
 }
 
-async fn receive_with_wrong_cred(qos: QualityOfService) -> Result<(), ReasonCode> {
+async fn receive_with_wrong_cred(qos: QualityOfService) -> Result<(), TestError> {
     let addr = SocketAddr::new(IP.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
     config.add_username("xyz");
     config.add_password(PASSWORD);
     config.max_packet_size = 60;
-    assert_ok!(config.properties.push(Property::ReceiveMaximum(20)));
+    assert_ok!(config.add_receive_maximum(20));
     let mut recv_buffer = [0; 100];
     let mut write_buffer = [0; 100];
 
@@ -316,7 +316,7 @@ async fn receive_with_wrong_cred(qos: QualityOfService) -> Result<(), ReasonCode
     );
     let result = client.connect_to_broker().await;
     assert!(result.is_err());
-    assert_eq!(result.unwrap_err(), NotAuthorized);
+    assert_eq!(result.unwrap_err().reason_code(), Some(&NotAuthorized));
     Ok(())
 }
 
@@ -325,18 +325,18 @@ async fn receive_multiple_second_unsub<const TOPICS: usize>(
     topic_names: &Vec<&str, TOPICS>,
     msg_t1: &str,
     msg_t2: &str,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(IP.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
     config.add_max_subscribe_qos(qos);
     config.add_username(USERNAME);
     config.add_password(PASSWORD);
     config.max_packet_size = 60;
-    assert_ok!(config.properties.push(Property::ReceiveMaximum(20)));
+    assert_ok!(config.add_receive_maximum(20));
     let mut recv_buffer = [0; 100];
     let mut write_buffer = [0; 100];
 
@@ -353,12 +353,12 @@ async fn receive_multiple_second_unsub<const TOPICS: usize>(
         "[Receiver] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = { client.connect_to_broker().await };
+    let mut result = { client.connect_to_broker().await.map(|_| ()) };
     assert_ok!(result);
 
     info!(
         "[Receiver] Subscribing to topics {}, {}",
-        topic_names.get(0).unwrap(),
+        topic_names.first().unwrap(),
         topic_names.get(1).unwrap()
     );
     result = client.subscribe_to_topics(topic_names).await;
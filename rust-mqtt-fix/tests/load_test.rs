@@ -38,13 +38,14 @@ use tokio_test::assert_ok;
 use rust_mqtt::client::client::MqttClient;
 use rust_mqtt::client::client_config::ClientConfig;
 use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::client_error::ClientError;
 use rust_mqtt::packet::v5::publish_packet::QualityOfService;
-use rust_mqtt::packet::v5::reason_codes::ReasonCode;
 use rust_mqtt::utils::rng_generator::CountingRng;
 use tokio::net::TcpStream;
 
 use embedded_io_adapters::tokio_1::FromTokio;
 pub type TokioNetwork = FromTokio<TcpStream>;
+type TestError = ClientError<std::io::Error>;
 
 static IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
 static PORT: u16 = 1883;
@@ -65,12 +66,12 @@ async fn publish_core<'b>(
     wait: u64,
     topic: &str,
     amount: u16,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     info!(
         "[Publisher] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = client.connect_to_broker().await;
+    let mut result = client.connect_to_broker().await.map(|_| ());
     assert_ok!(result);
     info!("[Publisher] Waiting {} seconds before sending", wait);
     sleep(Duration::from_secs(wait)).await;
@@ -102,11 +103,11 @@ async fn publish(
     qos: QualityOfService,
     topic: &str,
     amount: u16,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(ip.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(50000));
     config.add_max_subscribe_qos(qos);
@@ -131,12 +132,12 @@ async fn receive_core<'b>(
     client: &mut MqttClient<'b, TokioNetwork, 5, CountingRng>,
     topic: &str,
     amount: u16,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     info!(
         "[Receiver] Connection to broker with username {} and password {}",
         USERNAME, PASSWORD
     );
-    let mut result = client.connect_to_broker().await;
+    let mut result = client.connect_to_broker().await.map(|_| ());
     assert_ok!(result);
 
     info!("[Receiver] Subscribing to topic {}", topic);
@@ -166,11 +167,11 @@ async fn receive(
     qos: QualityOfService,
     topic: &str,
     amount: u16,
-) -> Result<(), ReasonCode> {
+) -> Result<(), TestError> {
     let addr = SocketAddr::new(ip.into(), PORT);
     let connection = TcpStream::connect(addr)
         .await
-        .map_err(|_| ReasonCode::NetworkError)?;
+        .map_err(ClientError::Transport)?;
     let connection = TokioNetwork::new(connection);
     let mut config = ClientConfig::new(MQTTv5, CountingRng(50000));
     config.add_max_subscribe_qos(qos);
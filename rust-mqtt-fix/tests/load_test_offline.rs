@@ -0,0 +1,215 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Load scenarios of `load_test` running against the scripted broker over the
+//! in-memory transport.
+use embedded_io::ErrorKind;
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::{duplex, MemoryTransport};
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+type TestClient<'a> = MqttClient<'a, MemoryTransport, 5, CountingRng>;
+type TestError = ClientError<ErrorKind>;
+
+static USERNAME: &str = "test";
+static PASSWORD: &str = "testPass";
+static MSG: &str = "testMessage";
+
+fn config(qos: QualityOfService) -> ClientConfig<'static, 5, CountingRng> {
+    let mut config = ClientConfig::new(MQTTv5, CountingRng(50000));
+    config.add_max_subscribe_qos(qos);
+    config.add_username(USERNAME);
+    config.add_password(PASSWORD);
+    config.keep_alive = 60000;
+    config.max_packet_size = 300;
+    config
+}
+
+async fn publish_core(
+    client: &mut TestClient<'_>,
+    qos: QualityOfService,
+    topic: &str,
+    amount: u16,
+) -> Result<(), TestError> {
+    client.connect_to_broker().await?;
+    for _ in 0..amount {
+        client
+            .send_message(topic, MSG.as_bytes(), qos, false)
+            .await?;
+    }
+    client.disconnect().await
+}
+
+async fn publish(qos: QualityOfService, topic: &str, amount: u16) {
+    let (client_end, broker_end) = duplex(1024);
+    let mut broker = ScriptedBroker::new(broker_end).expect_and_ack(PacketType::Connect);
+    for _ in 0..amount {
+        broker = broker.expect_and_ack(PacketType::Publish);
+    }
+    let broker = broker.expect(PacketType::Disconnect);
+
+    let mut recv_buffer = [0; 80];
+    let mut write_buffer = [0; 80];
+    let mut client = TestClient::new(
+        client_end,
+        &mut write_buffer,
+        80,
+        &mut recv_buffer,
+        80,
+        config(qos),
+    );
+    let (received, result) =
+        tokio::join!(broker.run(), publish_core(&mut client, qos, topic, amount));
+    assert_ok!(result);
+    assert_eq!(received.unwrap().len(), amount as usize + 2);
+}
+
+async fn receive_core(
+    client: &mut TestClient<'_>,
+    topic: &str,
+    amount: u16,
+) -> Result<(), TestError> {
+    client.connect_to_broker().await?;
+    client.subscribe_to_topic(topic).await?;
+    for _ in 0..amount {
+        let (_, payload) = client.receive_message().await?;
+        assert_eq!(payload, MSG.as_bytes());
+    }
+    client.disconnect().await
+}
+
+async fn receive(qos: QualityOfService, topic: &str, amount: u16) {
+    let (client_end, broker_end) = duplex(1024);
+    let mut broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Subscribe);
+    for i in 0..amount {
+        broker = broker.send(canned_packets::publish(topic, MSG.as_bytes(), qos, i + 1));
+        if qos == QualityOfService::QoS1 {
+            broker = broker.expect(PacketType::Puback);
+        }
+    }
+    let broker = broker.expect(PacketType::Disconnect);
+
+    let mut recv_buffer = [0; 500];
+    let mut write_buffer = [0; 500];
+    let mut client = TestClient::new(
+        client_end,
+        &mut write_buffer,
+        500,
+        &mut recv_buffer,
+        500,
+        config(qos),
+    );
+    let (received, result) = tokio::join!(broker.run(), receive_core(&mut client, topic, amount));
+    assert_ok!(result);
+    assert_ok!(received);
+}
+
+async fn load_test(qos: QualityOfService, topic: &str, amount: u16) {
+    tokio::join!(publish(qos, topic, amount), receive(qos, topic, amount));
+}
+
+#[tokio::test]
+async fn offline_load_test_ten() {
+    load_test(QualityOfService::QoS0, "test/recv/ten", 10).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_ten_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/ten/qos", 10).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_fifty() {
+    load_test(QualityOfService::QoS0, "test/recv/fifty", 50).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_fifty_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/fifty/qos", 50).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_hundred() {
+    load_test(QualityOfService::QoS0, "test/recv/hundred", 100).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_hundred_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/hundred/qos", 100).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_five_hundred() {
+    load_test(QualityOfService::QoS0, "test/recv/five/hundred", 500).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_five_hundred_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/five/hundred/qos", 500).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_thousand() {
+    load_test(QualityOfService::QoS0, "test/recv/thousand", 1000).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_thousand_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/thousand/qos", 1000).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_ten_thousand() {
+    load_test(QualityOfService::QoS0, "test/recv/ten/thousand", 10000).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_ten_thousand_qos() {
+    load_test(QualityOfService::QoS1, "test/recv/ten/thousand/qos", 10000).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_twenty_thousand() {
+    load_test(QualityOfService::QoS0, "test/recv/twenty/thousand", 20000).await;
+}
+
+#[tokio::test]
+async fn offline_load_test_twenty_thousand_qos() {
+    load_test(
+        QualityOfService::QoS1,
+        "test/recv/twenty/thousand/qos",
+        20000,
+    )
+    .await;
+}
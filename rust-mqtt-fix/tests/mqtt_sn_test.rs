@@ -0,0 +1,366 @@
+//! MQTT-SN clients talking over UDP sockets to the gateway, which is connected to the
+//! embedded `Broker` over the in-memory transport.
+use core::future::Future;
+use core::time::Duration;
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio_test::assert_ok;
+
+use rust_mqtt::broker::{Broker, BrokerError};
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::raw_client::RawMqttClient;
+use rust_mqtt::client::sn_client::{MqttSnClient, SnClientError, SnEvent};
+use rust_mqtt::gateway::MqttSnGateway;
+use rust_mqtt::network::datagram::DatagramTransport;
+use rust_mqtt::packet::sn::sn_packet::{ReturnCode, SnQoS, TopicId, TopicRef};
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::memory_transport::{duplex, MemoryTransport};
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+type TestBroker = Broker<3, 8, 4, 4>;
+type TestClient<'a> = MqttClient<'a, MemoryTransport, 5, CountingRng>;
+type TestSnClient<'a> = MqttSnClient<'a, UdpTransport>;
+type TestGateway<'a> = MqttSnGateway<'a, UdpTransport, MemoryTransport, CountingRng, 5, 4, 8, 4>;
+
+const PREDEFINED: &[(u16, &str)] = &[(1, "sensor/2/temperature")];
+
+struct UdpTransport(UdpSocket);
+
+impl DatagramTransport for UdpTransport {
+    type Error = io::ErrorKind;
+    type Address = SocketAddr;
+
+    async fn send_to(&mut self, address: SocketAddr, data: &[u8]) -> Result<(), io::ErrorKind> {
+        self.0
+            .send_to(data, address)
+            .await
+            .map(|_| ())
+            .map_err(|err| err.kind())
+    }
+
+    async fn receive_from(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> Result<(usize, SocketAddr), io::ErrorKind> {
+        self.0.recv_from(buffer).await.map_err(|err| err.kind())
+    }
+}
+
+async fn bind() -> (UdpTransport, SocketAddr) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    (UdpTransport(socket), address)
+}
+
+fn config(client_id: &str) -> ClientConfig<'_, 5, CountingRng> {
+    let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
+    config.add_client_id(client_id);
+    config.add_max_subscribe_qos(QualityOfService::QoS1);
+    config.max_packet_size = 100;
+    config
+}
+
+async fn serve(
+    broker: &TestBroker,
+    transport: MemoryTransport,
+) -> Result<(), BrokerError<embedded_io::ErrorKind>> {
+    let mut buffer = [0; 128];
+    let mut recv_buffer = [0; 128];
+    broker.serve(transport, &mut buffer, &mut recv_buffer).await
+}
+
+async fn run_gateway(transport: UdpTransport, broker_end: MemoryTransport) {
+    let (mut buffer, mut recv_buffer) = ([0; 128], [0; 128]);
+    let (mut sn_buffer, mut sn_recv_buffer) = ([0; 128], [0; 128]);
+    let broker = RawMqttClient::new(
+        broker_end,
+        &mut buffer,
+        128,
+        &mut recv_buffer,
+        128,
+        config("gateway"),
+    );
+    let mut gateway = TestGateway::new(transport, broker, 1, &mut sn_buffer, &mut sn_recv_buffer);
+    gateway.set_predefined_topics(PREDEFINED);
+    gateway.connect_to_broker().await.unwrap();
+    loop {
+        gateway.poll().await.unwrap();
+    }
+}
+
+/// Runs the `scenario` while the gateway serves the MQTT-SN clients on the `gateway` socket.
+async fn with_gateway(
+    broker: &TestBroker,
+    gateway: UdpTransport,
+    scenario: impl Future<Output = ()>,
+) {
+    let (gateway_end, broker_end) = duplex(512);
+    let run = async {
+        tokio::select! {
+            _ = run_gateway(gateway, gateway_end) => unreachable!(),
+            res = serve(broker, broker_end) => panic!("Gateway connection closed: {:?}", res),
+            _ = scenario => {}
+        }
+    };
+    tokio::time::timeout(Duration::from_secs(10), run)
+        .await
+        .expect("Scenario timed out");
+}
+
+#[tokio::test]
+async fn sn_messages_reach_broker_subscriber() {
+    let broker = TestBroker::new();
+    let (gateway, gateway_addr) = bind().await;
+    let (sub_end, sub_broker_end) = duplex(256);
+    let subscribed = Notify::new();
+
+    let subscriber = async {
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client = TestClient::new(
+            sub_end,
+            &mut buffer,
+            100,
+            &mut recv_buffer,
+            100,
+            config("sub"),
+        );
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("sensor/#").await?;
+        subscribed.notify_one();
+        for (topic, payload) in [
+            ("sensor/1/temperature", "21.5"),
+            ("sensor/2/temperature", "19.0"),
+            ("sensor/3", "ok"),
+        ] {
+            assert_eq!(client.receive_message().await?, (topic, payload.as_bytes()));
+        }
+        client.disconnect().await
+    };
+    let node = async {
+        let (transport, _) = bind().await;
+        let (mut buffer, mut recv_buffer) = ([0; 64], [0; 64]);
+        let mut client = TestSnClient::new(
+            transport,
+            gateway_addr,
+            "node",
+            &mut buffer,
+            &mut recv_buffer,
+        );
+        client.connect(60, true).await.unwrap();
+        subscribed.notified().await;
+
+        let topic_id = client.register("sensor/1/temperature").await.unwrap();
+        client
+            .publish(TopicId::Normal(topic_id), b"21.5", SnQoS::QoS1, false)
+            .await
+            .unwrap();
+        assert_eq!(
+            client
+                .publish(TopicId::Normal(99), b"x", SnQoS::QoS1, false)
+                .await,
+            Err(SnClientError::Rejected(ReturnCode::InvalidTopicId))
+        );
+        assert_eq!(
+            client
+                .publish(TopicId::Normal(topic_id), b"x", SnQoS::QoSMinusOne, false)
+                .await,
+            Err(SnClientError::InvalidQoS)
+        );
+        client.ping().await.unwrap();
+        client.disconnect().await.unwrap();
+    };
+    // QoS -1 publishes without connection
+    let sensor = async {
+        node.await;
+        let (transport, _) = bind().await;
+        let (mut buffer, mut recv_buffer) = ([0; 64], [0; 64]);
+        let mut client = TestSnClient::new(
+            transport,
+            gateway_addr,
+            "sensor",
+            &mut buffer,
+            &mut recv_buffer,
+        );
+        client
+            .publish(TopicId::Predefined(1), b"19.0", SnQoS::QoSMinusOne, false)
+            .await
+            .unwrap();
+        client.connect(60, true).await.unwrap();
+        let topic_id = client.register("sensor/3").await.unwrap();
+        client
+            .publish(TopicId::Normal(topic_id), b"ok", SnQoS::QoS0, false)
+            .await
+            .unwrap();
+        client.disconnect().await.unwrap();
+    };
+
+    with_gateway(&broker, gateway, async {
+        let (sub_res, _, sub_served) =
+            tokio::join!(subscriber, sensor, serve(&broker, sub_broker_end));
+        assert_ok!(sub_res);
+        assert_ok!(sub_served);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn broker_messages_reach_sn_clients() {
+    let broker = TestBroker::new();
+    let (gateway, gateway_addr) = bind().await;
+    assert_eq!(
+        broker.publish("room/1/light", b"on", QualityOfService::QoS1, true),
+        Ok(0)
+    );
+    assert_eq!(
+        broker.publish("ab", b"short", QualityOfService::QoS0, true),
+        Ok(0)
+    );
+
+    let node = async {
+        let (transport, _) = bind().await;
+        let (mut buffer, mut recv_buffer) = ([0; 64], [0; 64]);
+        let mut client = TestSnClient::new(
+            transport,
+            gateway_addr,
+            "node",
+            &mut buffer,
+            &mut recv_buffer,
+        );
+        client.connect(60, true).await.unwrap();
+
+        // Wildcard subscription, retained message is preceded by the registration
+        let (topic_id, qos) = client
+            .subscribe(TopicRef::Name("room/+/light"), SnQoS::QoS2)
+            .await
+            .unwrap();
+        assert_eq!((topic_id, qos), (0, SnQoS::QoS1));
+        let registered = match client.poll().await.unwrap() {
+            SnEvent::Registered {
+                topic_id,
+                topic_name,
+            } => {
+                assert_eq!(topic_name, "room/1/light");
+                topic_id
+            }
+            event => panic!("Unexpected event {:?}", event),
+        };
+        assert_eq!(
+            client.poll().await.unwrap(),
+            SnEvent::Message {
+                topic: TopicId::Normal(registered),
+                data: b"on",
+                retain: false,
+            }
+        );
+
+        client
+            .subscribe(TopicRef::Short(*b"ab"), SnQoS::QoS0)
+            .await
+            .unwrap();
+        assert_eq!(
+            client.poll().await.unwrap(),
+            SnEvent::Message {
+                topic: TopicId::Short(*b"ab"),
+                data: b"short",
+                retain: false,
+            }
+        );
+
+        // Subscription is active at the broker now
+        assert_eq!(
+            broker.publish("room/1/light", b"off", QualityOfService::QoS0, false),
+            Ok(1)
+        );
+        assert_eq!(
+            client.poll().await.unwrap(),
+            SnEvent::Message {
+                topic: TopicId::Normal(registered),
+                data: b"off",
+                retain: false,
+            }
+        );
+        client
+            .unsubscribe(TopicRef::Name("room/+/light"))
+            .await
+            .unwrap();
+        client.disconnect().await.unwrap();
+    };
+
+    with_gateway(&broker, gateway, node).await;
+}
+
+#[tokio::test]
+async fn sleeping_sn_client_gets_buffered_messages() {
+    let broker = TestBroker::new();
+    let (gateway, gateway_addr) = bind().await;
+    assert_eq!(
+        broker.publish("alarm", b"armed", QualityOfService::QoS0, true),
+        Ok(0)
+    );
+
+    let node = async {
+        let (transport, _) = bind().await;
+        let (mut buffer, mut recv_buffer) = ([0; 64], [0; 64]);
+        let mut client = TestSnClient::new(
+            transport,
+            gateway_addr,
+            "node",
+            &mut buffer,
+            &mut recv_buffer,
+        );
+        client.connect(60, true).await.unwrap();
+        let (topic_id, _) = client
+            .subscribe(TopicRef::Name("alarm"), SnQoS::QoS1)
+            .await
+            .unwrap();
+        assert_ne!(topic_id, 0);
+        assert!(matches!(
+            client.poll().await.unwrap(),
+            SnEvent::Message { data: b"armed", .. }
+        ));
+
+        client.sleep(300).await.unwrap();
+        assert_eq!(
+            broker.publish("alarm", b"triggered", QualityOfService::QoS1, false),
+            Ok(1)
+        );
+        // Nothing is delivered while the client sleeps
+        let res = tokio::time::timeout(Duration::from_millis(100), client.poll()).await;
+        assert!(res.is_err());
+
+        let mut received = false;
+        while !received {
+            client.check_in().await.unwrap();
+            loop {
+                match client.poll().await.unwrap() {
+                    SnEvent::Message { topic, data, .. } => {
+                        assert_eq!(topic, TopicId::Normal(topic_id));
+                        assert_eq!(data, b"triggered");
+                        received = true;
+                    }
+                    SnEvent::Pingresp => break,
+                    event => panic!("Unexpected event {:?}", event),
+                }
+            }
+        }
+
+        // Waking up by connect keeps the subscription
+        client.connect(60, false).await.unwrap();
+        assert_eq!(
+            broker.publish("alarm", b"reset", QualityOfService::QoS0, false),
+            Ok(1)
+        );
+        assert!(matches!(
+            client.poll().await.unwrap(),
+            SnEvent::Message { data: b"reset", .. }
+        ));
+        client.disconnect().await.unwrap();
+    };
+
+    with_gateway(&broker, gateway, node).await;
+}
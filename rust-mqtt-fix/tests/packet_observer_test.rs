@@ -0,0 +1,100 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Packet observer notified about the traffic with the scripted broker.
+mod common;
+
+use std::sync::atomic::AtomicU64;
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::packet_observer::{Direction, ObservedPacket, PacketObserver};
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::test_support::memory_transport::duplex;
+
+use common::{config, receive_core, receiver_broker, topics, StepClock, TestBuffers, MSG};
+
+/// Direction, type, packet identifier, topic and timestamp of an observed packet.
+type Record = (Direction, PacketType, Option<u16>, Option<String>, u64);
+
+#[derive(Default)]
+struct Recorder(StdVec<Record>);
+
+impl PacketObserver for Recorder {
+    fn on_packet(&mut self, packet: &ObservedPacket<'_>) {
+        let summary = packet.summary.as_ref().unwrap();
+        assert_eq!(PacketType::from(packet.raw[0]), summary.packet_type);
+        self.0.push((
+            packet.direction,
+            summary.packet_type,
+            summary.packet_identifier,
+            summary.topic.map(String::from),
+            packet.timestamp_micros,
+        ));
+    }
+}
+
+#[tokio::test]
+async fn offline_packet_observer() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = receiver_broker(
+        broker_end,
+        QualityOfService::QoS1,
+        &[("test/observed", MSG)],
+    )
+    .expect(PacketType::Disconnect);
+    let (clock, mut recorder) = (StepClock(AtomicU64::new(0)), Recorder::default());
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    client.set_packet_observer(&mut recorder, &clock);
+    let (topic_names, messages) = (topics::<1>(&["test/observed"]), [MSG]);
+    let (received, result) = tokio::join!(
+        broker.run(),
+        receive_core(&mut client, &topic_names, &messages)
+    );
+    assert_ok!(received);
+    assert_ok!(result);
+    drop(client);
+
+    use Direction::{Inbound, Outbound};
+    let topic = || Some(String::from("test/observed"));
+    let expected = [
+        (Outbound, PacketType::Connect, None, None),
+        (Inbound, PacketType::Connack, None, None),
+        (Outbound, PacketType::Subscribe, Some(20001), topic()),
+        (Inbound, PacketType::Suback, Some(20001), None),
+        (Inbound, PacketType::Publish, Some(1), topic()),
+        (Outbound, PacketType::Puback, Some(1), None),
+        (Outbound, PacketType::Disconnect, None, None),
+    ];
+    assert_eq!(recorder.0.len(), expected.len());
+    for (i, (observed, expected)) in recorder.0.iter().zip(expected).enumerate() {
+        assert_eq!(
+            (observed.0, observed.1, observed.2, observed.3.clone()),
+            expected
+        );
+        assert_eq!(observed.4, i as u64 * 1000);
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Clean start, session present flag and CONNECT sent on reconnect.
+mod common;
+
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::client::client_error::{ClientError, ProtocolViolation};
+use rust_mqtt::client::connection_state::{ConnectionState, DisconnectReason};
+use rust_mqtt::packet::v5::connect_packet::ConnectPacket;
+use rust_mqtt::packet::v5::mqtt_packet::Packet;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::property_kind::{MaximumPacketSize, SessionExpiryInterval};
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, TestBuffers, TestError};
+
+/// Connects with the clean start flag `clean_start`, broker answers with `connack`.
+async fn connect_session(
+    clean_start: bool,
+    connack: StdVec<u8>,
+) -> (Result<bool, TestError>, StdVec<StdVec<u8>>) {
+    let (client_end, broker_end) = duplex(256);
+    let broker =
+        ScriptedBroker::new(broker_end).expect_and_reply(PacketType::Connect, move |_| connack);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut config = config(QualityOfService::QoS1);
+    config.add_clean_start(clean_start);
+    config.add_session_expiry_interval(3600);
+    let mut client = buffers.client(client_end, config);
+    let (received, result) = tokio::join!(broker.run(), client.connect_to_broker());
+    (result, received.unwrap())
+}
+
+#[tokio::test]
+async fn offline_resumed_session() {
+    let (result, received) =
+        connect_session(false, canned_packets::connack_session_present()).await;
+    assert_eq!(result, Ok(true));
+    let connect: ConnectPacket<5, 0> = decode(&received[0]);
+    assert_eq!(connect.connect_flags & 0x02, 0);
+    assert_eq!(connect.get::<SessionExpiryInterval>(), Some(3600));
+
+    let (result, _) = connect_session(false, canned_packets::connack(ReasonCode::Success)).await;
+    assert_eq!(result, Ok(false));
+}
+
+#[tokio::test]
+async fn offline_session_present_with_clean_start() {
+    let (result, received) = connect_session(true, canned_packets::connack_session_present()).await;
+    assert_eq!(
+        result,
+        Err(ClientError::Protocol(
+            ProtocolViolation::UnexpectedSessionPresent
+        ))
+    );
+    let connect: ConnectPacket<5, 0> = decode(&received[0]);
+    assert_eq!(connect.connect_flags & 0x02, 0x02);
+}
+
+#[tokio::test]
+async fn offline_reconnect_sends_same_connect() {
+    let (client_end, broker_end) = duplex(256);
+    let (second_client_end, second_broker_end) = duplex(256);
+    // The first connection is lost, the client reconnects over a new connection.
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .disconnect();
+    let second_broker = ScriptedBroker::new(second_broker_end).expect_and_ack(PacketType::Connect);
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    let client_part = async {
+        client.connect_to_broker().await?;
+        assert_eq!(
+            client.receive_message().await.map(|_| ()),
+            Err(ClientError::NotConnected)
+        );
+        assert_eq!(
+            client.state(),
+            ConnectionState::Disconnected(DisconnectReason::Transport)
+        );
+        client.set_network_driver(second_client_end)?;
+        client.connect_to_broker().await
+    };
+    let (received, second_received, result) =
+        tokio::join!(broker.run(), second_broker.run(), client_part);
+    assert_ok!(result);
+    assert!(client.is_connected());
+    let received = received.unwrap();
+    assert_eq!(received[0], second_received.unwrap()[0]);
+    let connect: ConnectPacket<5, 0> = decode(&received[0]);
+    assert_eq!(connect.properties.len(), 2);
+    assert_eq!(connect.get::<MaximumPacketSize>(), Some(100));
+}
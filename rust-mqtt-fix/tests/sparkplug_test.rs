@@ -0,0 +1,190 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Sparkplug B edge node session with the scripted broker.
+mod common;
+
+use std::sync::atomic::AtomicU64;
+use std::vec::Vec as StdVec;
+
+use tokio_test::assert_ok;
+
+use rust_mqtt::packet::v5::connect_packet::ConnectPacket;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::{PublishPacket, QualityOfService};
+use rust_mqtt::packet::v5::subscription_packet::SubscriptionPacket;
+use rust_mqtt::sparkplug::payload::{Metric, MetricValue, Payload, PayloadWriter};
+use rust_mqtt::sparkplug::EdgeNode;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, decode, MinuteClock, TestBuffers, TestError};
+
+fn sparkplug_command(metric: Metric<'_>) -> StdVec<u8> {
+    let mut buffer = [0; 64];
+    let mut writer = PayloadWriter::new(&mut buffer, Some(1), None).unwrap();
+    writer.add_data_metric(&metric).unwrap();
+    let len = writer.finish();
+    buffer[..len].to_vec()
+}
+
+#[tokio::test]
+async fn offline_sparkplug_session() {
+    const NODE_METRICS: [Metric<'static>; 1] = [Metric::new(
+        "Node Control/Rebirth",
+        MetricValue::Boolean(false),
+    )];
+    const SPEED: Metric<'static> = Metric::new("speed", MetricValue::UInt32(0)).with_alias(1);
+    let clock = MinuteClock(AtomicU64::new(1_700_000_000_000_000));
+    let mut node = EdgeNode::new("plant", "gateway1", &clock).unwrap();
+    node.set_bd_seq(255);
+    let death = node.death_certificate().unwrap();
+    let mut client_config = config(QualityOfService::QoS0);
+    death.configure_will(&mut client_config);
+    let rebirth = sparkplug_command(Metric::new(
+        "Node Control/Rebirth",
+        MetricValue::Boolean(true),
+    ));
+    let set_speed = sparkplug_command(SPEED.with_value(MetricValue::UInt32(1200)));
+    let (client_end, broker_end) = duplex(1024);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Subscribe)
+        .expect(PacketType::Publish)
+        .expect(PacketType::Publish)
+        .expect(PacketType::Publish)
+        .send(canned_packets::publish(
+            "spBv1.0/plant/NCMD/gateway1",
+            &rebirth,
+            QualityOfService::QoS0,
+            0,
+        ))
+        .expect(PacketType::Publish)
+        .expect(PacketType::Publish)
+        .send(canned_packets::publish(
+            "spBv1.0/plant/DCMD/gateway1/pump",
+            &set_speed,
+            QualityOfService::QoS0,
+            0,
+        ));
+    let mut payload_buffer = [0; 256];
+    let mut buffers = TestBuffers::<256>::new();
+    let mut client = buffers.client(client_end, client_config);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        node.subscribe_commands(&mut client).await?;
+        node.publish_birth(&mut client, &NODE_METRICS, &mut payload_buffer)
+            .await?;
+        node.publish_device_birth(&mut client, "pump", &[SPEED], &mut payload_buffer)
+            .await?;
+        node.publish_device_data(
+            &mut client,
+            "pump",
+            &[SPEED.with_value(MetricValue::UInt32(900))],
+            &mut payload_buffer,
+        )
+        .await?;
+        let (topic, payload) = client.receive_message().await?;
+        let command = node.command(topic, payload).unwrap().unwrap();
+        assert_eq!(command.device_id, None);
+        assert!(command.payload.is_rebirth_request());
+        node.publish_birth(&mut client, &NODE_METRICS, &mut payload_buffer)
+            .await?;
+        node.publish_device_birth(&mut client, "pump", &[SPEED], &mut payload_buffer)
+            .await?;
+        let (topic, payload) = client.receive_message().await?;
+        let command = node.command(topic, payload).unwrap().unwrap();
+        assert_eq!(command.device_id, Some("pump"));
+        assert_eq!(
+            command.payload.metric(&SPEED).map(|metric| metric.value),
+            Some(MetricValue::UInt32(1200))
+        );
+        Ok::<(), TestError>(())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(result);
+    let received = received.unwrap();
+
+    let connect: ConnectPacket<5, 0> = decode(&received[0]);
+    assert_eq!(connect.will_topic.string, "spBv1.0/plant/NDEATH/gateway1");
+    assert_eq!(connect.will_qos(), QualityOfService::QoS1);
+    assert!(!connect.will_retain());
+    let will = Payload::decode(connect.will_payload.bin).unwrap();
+    assert_eq!(will.seq, None);
+    assert_eq!(
+        will.metrics().collect::<StdVec<_>>(),
+        [Metric::new("bdSeq", MetricValue::Int64(255))]
+    );
+    assert_eq!(node.bd_seq(), 255);
+    assert_eq!(node.death_certificate().unwrap().bd_seq(), 0);
+
+    let subscribe: SubscriptionPacket<2, 0> = decode(&received[1]);
+    assert_eq!(
+        subscribe
+            .topic_filters
+            .iter()
+            .map(|filter| filter.filter.string)
+            .collect::<StdVec<_>>(),
+        [
+            "spBv1.0/plant/NCMD/gateway1",
+            "spBv1.0/plant/DCMD/gateway1/+"
+        ]
+    );
+
+    let expected = [
+        ("spBv1.0/plant/NBIRTH/gateway1", 0),
+        ("spBv1.0/plant/DBIRTH/gateway1/pump", 1),
+        ("spBv1.0/plant/DDATA/gateway1/pump", 2),
+        ("spBv1.0/plant/NBIRTH/gateway1", 0),
+        ("spBv1.0/plant/DBIRTH/gateway1/pump", 1),
+    ];
+    for (packet, (topic, seq)) in received[2..].iter().zip(expected) {
+        let publish: PublishPacket<2> = decode(packet);
+        assert_eq!(publish.topic_name.string, topic);
+        let payload = Payload::decode(publish.message.unwrap()).unwrap();
+        assert_eq!(payload.seq, Some(seq));
+        assert!(payload.timestamp.unwrap() >= 1_700_000_000_000);
+        let metrics: StdVec<_> = payload.metrics().collect();
+        match seq {
+            0 => assert_eq!(
+                metrics,
+                [
+                    Metric::new("bdSeq", MetricValue::Int64(255)),
+                    NODE_METRICS[0]
+                ]
+            ),
+            1 => assert_eq!(metrics, [SPEED]),
+            _ => assert_eq!(
+                metrics,
+                [Metric {
+                    name: None,
+                    alias: Some(1),
+                    timestamp: None,
+                    value: MetricValue::UInt32(900),
+                }]
+            ),
+        }
+    }
+    assert_eq!(received.len(), 7);
+}
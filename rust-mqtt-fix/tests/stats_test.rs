@@ -0,0 +1,92 @@
+/*
+ * MIT License
+ *
+ * Copyright (c) [2022] [Ondrej Babec <ond.babec@gmail.com>]
+ *
+ * Permission is hereby granted, free of charge, to any person obtaining a copy
+ * of this software and associated documentation files (the "Software"), to deal
+ * in the Software without restriction, including without limitation the rights
+ * to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+ * copies of the Software, and to permit persons to whom the Software is
+ * furnished to do so, subject to the following conditions:
+ *
+ * The above copyright notice and this permission notice shall be included in all
+ * copies or substantial portions of the Software.
+ *
+ * THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+ * IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+ * FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+ * AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+ * LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+ * OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+ * SOFTWARE.
+ */
+//! Client statistics collected during a session with the scripted broker.
+mod common;
+
+use std::sync::atomic::AtomicU64;
+
+use tokio_test::{assert_err, assert_ok};
+
+use rust_mqtt::client::packet_observer::Direction;
+use rust_mqtt::client::stats::ClientStats;
+use rust_mqtt::packet::v5::packet_type::PacketType;
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::packet::v5::reason_codes::ReasonCode;
+use rust_mqtt::test_support::canned_packets;
+use rust_mqtt::test_support::memory_transport::duplex;
+use rust_mqtt::test_support::scripted_broker::ScriptedBroker;
+
+use common::{config, StepClock, TestBuffers, MSG};
+
+#[tokio::test]
+async fn offline_client_stats() {
+    let (client_end, broker_end) = duplex(256);
+    let broker = ScriptedBroker::new(broker_end)
+        .expect_and_ack(PacketType::Connect)
+        .expect_and_ack(PacketType::Publish)
+        .expect_and_ack(PacketType::Pingreq)
+        .expect(PacketType::Publish)
+        .send(canned_packets::puback(20002, ReasonCode::QuotaExceeded))
+        .send(canned_packets::disconnect(ReasonCode::ServerShuttingDown));
+    let (clock, stats) = (StepClock(AtomicU64::new(0)), ClientStats::new());
+    let mut buffers = TestBuffers::<100>::new();
+    let mut client = buffers.client(client_end, config(QualityOfService::QoS1));
+    client.set_stats(&stats, &clock);
+    let client_part = async {
+        client.connect_to_broker().await?;
+        client
+            .send_message("test/stats", MSG.as_bytes(), QualityOfService::QoS1, false)
+            .await?;
+        client.send_ping().await?;
+        assert_err!(
+            client
+                .send_message("test/stats", MSG.as_bytes(), QualityOfService::QoS1, false)
+                .await
+        );
+        client.receive_message().await.map(|_| ())
+    };
+    let (received, result) = tokio::join!(broker.run(), client_part);
+    assert_ok!(received);
+    assert_err!(result);
+
+    assert_eq!(stats.connects(), 1);
+    assert_eq!(stats.packets(Direction::Outbound, PacketType::Publish), 2);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Puback), 2);
+    assert_eq!(stats.packets(Direction::Inbound, PacketType::Disconnect), 1);
+    assert_eq!(stats.bytes(Direction::Outbound, PacketType::Pingreq), 2);
+    assert_eq!(stats.bytes(Direction::Inbound, PacketType::Pingresp), 2);
+    assert_eq!(stats.publish_failures(), 1);
+    assert_eq!(stats.disconnects(), 1);
+    assert_eq!(
+        stats.last_disconnect_reason(),
+        Some(ReasonCode::ServerShuttingDown)
+    );
+    assert_eq!(stats.disconnects_with(ReasonCode::ServerShuttingDown), 1);
+    // Clock advances by one millisecond on every reading
+    assert_eq!(stats.ack_latency().samples, 2);
+    assert_eq!(stats.ack_latency().max_micros, 1000);
+    assert_eq!(stats.ping_rtt().samples, 1);
+    assert_eq!(stats.ping_rtt().avg_micros, 1000);
+    assert_eq!(stats.transport_errors(), 0);
+}
@@ -0,0 +1,298 @@
+//! `MqttClient` over both TLS transports talking to the embedded `Broker` behind a local
+//! rustls server with certificates issued by a self-signed CA.
+use core::time::Duration;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use embedded_io_adapters::tokio_1::FromTokio;
+use embedded_io_async::{Read, Write};
+use p256::pkcs8::DecodePrivateKey;
+use rand_core::OsRng;
+use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tokio_test::assert_ok;
+
+use rust_mqtt::broker::Broker;
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::client::client_error::ClientError;
+use rust_mqtt::network::tls::embedded::TlsClock;
+use rust_mqtt::network::tls::{EmbeddedTlsTransport, RustlsTransport, TlsConfig, TlsError};
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+type TestBroker = Broker<2, 4, 2, 4>;
+
+struct SystemClock;
+
+impl TlsClock for SystemClock {
+    fn now() -> Option<u64> {
+        Some(SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs())
+    }
+}
+
+struct Pki {
+    ca: Vec<u8>,
+    server_cert: Vec<u8>,
+    server_key: Vec<u8>,
+    client_cert: Vec<u8>,
+    client_key: Vec<u8>,
+}
+
+fn ca() -> (rcgen::Certificate, KeyPair) {
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(Vec::new()).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    (params.self_signed(&key).unwrap(), key)
+}
+
+fn pki() -> Pki {
+    let (ca, ca_key) = ca();
+    let issue = |name: &str| {
+        let key = KeyPair::generate().unwrap();
+        let params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        let cert = params.signed_by(&key, &ca, &ca_key).unwrap();
+        (cert.der().to_vec(), key.serialize_der())
+    };
+    let (server_cert, server_key) = issue("localhost");
+    let (client_cert, client_key) = issue("client");
+    Pki {
+        ca: ca.der().to_vec(),
+        server_cert,
+        server_key,
+        client_cert,
+        client_key,
+    }
+}
+
+/// Embedded-tls accepts SEC1 keys only.
+fn sec1(pkcs8_key: &[u8]) -> Vec<u8> {
+    let key = p256::SecretKey::from_pkcs8_der(pkcs8_key).unwrap();
+    key.to_sec1_der().unwrap().to_vec()
+}
+
+/// Accepts one connection and serves it by the broker. Client certificate issued by the CA
+/// is required.
+async fn serve_tls(broker: &TestBroker, listener: TcpListener, pki: &Pki) {
+    let mut roots = RootCertStore::empty();
+    roots.add(CertificateDer::from(pki.ca.clone())).unwrap();
+    let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+        .build()
+        .unwrap();
+    let config = ServerConfig::builder()
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(
+            vec![CertificateDer::from(pki.server_cert.clone())],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(pki.server_key.clone())),
+        )
+        .unwrap();
+
+    let (stream, _) = listener.accept().await.unwrap();
+    let Ok(stream) = TlsAcceptor::from(Arc::new(config)).accept(stream).await else {
+        return;
+    };
+    let (mut buffer, mut recv_buffer) = ([0; 128], [0; 128]);
+    let _ = broker
+        .serve(FromTokio::new(stream), &mut buffer, &mut recv_buffer)
+        .await;
+}
+
+async fn session<T: Read + Write>(transport: T) -> Result<(), ClientError<T::Error>> {
+    let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
+    config.add_client_id("tls-client");
+    config.add_max_subscribe_qos(QualityOfService::QoS1);
+    config.max_packet_size = 100;
+    let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+    let mut client: MqttClient<'_, T, 5, CountingRng> =
+        MqttClient::new(transport, &mut buffer, 100, &mut recv_buffer, 100, config);
+    client.connect_to_broker().await?;
+    client.subscribe_to_topic("tls/test").await?;
+    client
+        .send_message("tls/test", b"encrypted", QualityOfService::QoS1, false)
+        .await?;
+    assert_eq!(
+        client.receive_message().await?,
+        ("tls/test", &b"encrypted"[..])
+    );
+    client.disconnect().await
+}
+
+fn tls_config<'a>(pki: &'a Pki, client_key: &'a [u8]) -> TlsConfig<'a> {
+    let mut config = TlsConfig::new();
+    config.add_server_name("localhost");
+    config.add_ca(&pki.ca);
+    config.add_client_cert(&pki.client_cert, client_key);
+    config
+}
+
+async fn listen() -> (TcpListener, FromTokio<TcpStream>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap())
+        .await
+        .unwrap();
+    (listener, FromTokio::new(stream))
+}
+
+async fn with_timeout<F: core::future::Future>(scenario: F) -> F::Output {
+    tokio::time::timeout(Duration::from_secs(10), scenario)
+        .await
+        .expect("Scenario timed out")
+}
+
+#[tokio::test]
+async fn embedded_tls_client_certificate() {
+    let (broker, pki) = (TestBroker::new(), pki());
+    let (listener, stream) = listen().await;
+    let client_key = sec1(&pki.client_key);
+    let config = tls_config(&pki, &client_key);
+
+    let client = async {
+        let (mut read_buffer, mut write_buffer) = (vec![0; 16640], vec![0; 4096]);
+        let transport = EmbeddedTlsTransport::connect::<SystemClock>(
+            stream,
+            OsRng,
+            &config,
+            &mut read_buffer,
+            &mut write_buffer,
+        )
+        .await?;
+        session(transport).await
+    };
+    let (res, _) =
+        with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+    assert_ok!(res);
+}
+
+#[tokio::test]
+async fn rustls_client_certificate() {
+    let (broker, pki) = (TestBroker::new(), pki());
+    let (listener, stream) = listen().await;
+    let config = tls_config(&pki, &pki.client_key);
+
+    let client = async {
+        let transport = RustlsTransport::connect(stream, &config).await?;
+        session(transport).await
+    };
+    let (res, _) =
+        with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+    assert_ok!(res);
+}
+
+#[tokio::test]
+async fn untrusted_server_is_rejected() {
+    let (broker, pki) = (TestBroker::new(), pki());
+    let other_ca = ca().0.der().to_vec();
+    let client_key = sec1(&pki.client_key);
+
+    // Other CA pinned, or certificate not valid for the server name
+    let mut other_ca_config = tls_config(&pki, &client_key);
+    other_ca_config.add_ca(&other_ca);
+    let mut other_name_config = tls_config(&pki, &client_key);
+    other_name_config.add_server_name("example.com");
+
+    for config in [&other_ca_config, &other_name_config] {
+        let (listener, stream) = listen().await;
+        let client = async {
+            let (mut read_buffer, mut write_buffer) = (vec![0; 16640], vec![0; 4096]);
+            EmbeddedTlsTransport::connect::<SystemClock>(
+                stream,
+                OsRng,
+                config,
+                &mut read_buffer,
+                &mut write_buffer,
+            )
+            .await
+            .map(|_| ())
+        };
+        let (res, _) =
+            with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+        let err: ClientError<_> = res.unwrap_err().into();
+        assert_eq!(
+            err,
+            ClientError::Transport(TlsError::CertificateVerification)
+        );
+
+        let (listener, stream) = listen().await;
+        let mut config = config.clone();
+        config.add_client_cert(&pki.client_cert, &pki.client_key);
+        let client = async { RustlsTransport::connect(stream, &config).await.map(|_| ()) };
+        let (res, _) =
+            with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+        assert!(matches!(res, Err(TlsError::CertificateVerification)));
+    }
+}
+
+#[tokio::test]
+async fn self_signed_server_requires_ca() {
+    let (broker, mut pki) = (TestBroker::new(), pki());
+    let key = KeyPair::generate().unwrap();
+    let params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    pki.server_cert = params.self_signed(&key).unwrap().der().to_vec();
+    pki.server_key = key.serialize_der();
+    let client_key = sec1(&pki.client_key);
+    let mut config = TlsConfig::new();
+    config.add_server_name("localhost");
+    config.add_client_cert(&pki.client_cert, &client_key);
+
+    let (listener, stream) = listen().await;
+    let client = async {
+        let (mut read_buffer, mut write_buffer) = (vec![0; 16640], vec![0; 4096]);
+        EmbeddedTlsTransport::connect::<SystemClock>(
+            stream,
+            OsRng,
+            &config,
+            &mut read_buffer,
+            &mut write_buffer,
+        )
+        .await
+        .map(|_| ())
+    };
+    let (res, _) =
+        with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+    assert_eq!(res, Err(TlsError::Configuration));
+
+    // Explicit opt-in connects to any server
+    config.insecure_skip_verify();
+    let (listener, stream) = listen().await;
+    let client = async {
+        let (mut read_buffer, mut write_buffer) = (vec![0; 16640], vec![0; 4096]);
+        let transport = EmbeddedTlsTransport::connect::<SystemClock>(
+            stream,
+            OsRng,
+            &config,
+            &mut read_buffer,
+            &mut write_buffer,
+        )
+        .await?;
+        session(transport).await
+    };
+    let (res, _) =
+        with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+    assert_ok!(res);
+}
+
+#[tokio::test]
+async fn rejected_client_certificate() {
+    let (broker, pki) = (TestBroker::new(), pki());
+    let (listener, stream) = listen().await;
+    let mut config = TlsConfig::new();
+    config.add_server_name("localhost");
+    config.add_ca(&pki.ca);
+
+    // TLS 1.3 server sends the alert after the handshake
+    let client = async {
+        let transport = RustlsTransport::connect(stream, &config).await?;
+        session(transport).await
+    };
+    let (res, _) =
+        with_timeout(async { tokio::join!(client, serve_tls(&broker, listener, &pki)) }).await;
+    assert!(matches!(
+        res,
+        Err(ClientError::Transport(TlsError::Protocol))
+    ));
+}
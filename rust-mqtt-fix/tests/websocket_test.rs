@@ -0,0 +1,146 @@
+//! `MqttClient` over `WebSocketTransport` talking to the embedded `Broker` behind a local
+//! WebSocket server (tokio-tungstenite), which bridges binary messages to the broker.
+use core::time::Duration;
+
+use embedded_io_adapters::tokio_1::FromTokio;
+use futures::{SinkExt, StreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_test::assert_ok;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::Message;
+
+use rust_mqtt::broker::Broker;
+use rust_mqtt::client::client::MqttClient;
+use rust_mqtt::client::client_config::ClientConfig;
+use rust_mqtt::client::client_config::MqttVersion::MQTTv5;
+use rust_mqtt::network::websocket::{WebSocketError, WebSocketTransport};
+use rust_mqtt::packet::v5::publish_packet::QualityOfService;
+use rust_mqtt::utils::rng_generator::CountingRng;
+
+type TestBroker = Broker<2, 4, 2, 4>;
+type WsClient<'a> =
+    MqttClient<'a, WebSocketTransport<FromTokio<TcpStream>, CountingRng>, 5, CountingRng>;
+
+/// Accepts one WebSocket connection with the `protocol` subprotocol and bridges it to the
+/// broker until either side closes.
+async fn serve_websocket(broker: &TestBroker, listener: TcpListener, protocol: &'static str) {
+    let (stream, _) = listener.accept().await.unwrap();
+    // Signature is given by tungstenite
+    #[allow(clippy::result_large_err)]
+    let callback = |_: &Request, mut response: Response| {
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", HeaderValue::from_static(protocol));
+        Ok(response)
+    };
+    let Ok(websocket) = tokio_tungstenite::accept_hdr_async(stream, callback).await else {
+        return;
+    };
+    let (mut ws_sink, mut ws_stream) = websocket.split();
+    let (bridge_end, broker_end) = tokio::io::duplex(1024);
+    let (mut bridge_reader, mut bridge_writer) = tokio::io::split(bridge_end);
+
+    let to_broker = async {
+        while let Some(Ok(message)) = ws_stream.next().await {
+            if let Message::Binary(data) = message {
+                if bridge_writer.write_all(&data).await.is_err() {
+                    break;
+                }
+            }
+        }
+        let _ = bridge_writer.shutdown().await;
+    };
+    let to_client = async {
+        let mut buffer = [0; 256];
+        loop {
+            let len = bridge_reader.read(&mut buffer).await.unwrap_or(0);
+            if len == 0 {
+                break;
+            }
+            // Split the data into two frames to exercise reassembly on the client
+            let (first, second) = buffer[..len].split_at(len / 2);
+            for part in [first, second] {
+                if ws_sink.send(Message::Binary(part.to_vec())).await.is_err() {
+                    return;
+                }
+            }
+        }
+        let _ = ws_sink.close().await;
+    };
+    let serve = async {
+        let (mut buffer, mut recv_buffer) = ([0; 128], [0; 128]);
+        let _ = broker
+            .serve(FromTokio::new(broker_end), &mut buffer, &mut recv_buffer)
+            .await;
+    };
+    tokio::join!(to_broker, to_client, serve);
+}
+
+#[tokio::test]
+async fn client_over_websocket() {
+    let broker = TestBroker::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = async {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut handshake_buffer = [0; 256];
+        let transport = WebSocketTransport::connect(
+            FromTokio::new(stream),
+            CountingRng(1),
+            "localhost",
+            "/mqtt",
+            &mut handshake_buffer,
+        )
+        .await
+        .unwrap();
+
+        let mut config = ClientConfig::new(MQTTv5, CountingRng(20000));
+        config.add_client_id("ws-client");
+        config.add_max_subscribe_qos(QualityOfService::QoS1);
+        config.max_packet_size = 100;
+        let (mut buffer, mut recv_buffer) = ([0; 100], [0; 100]);
+        let mut client: WsClient =
+            MqttClient::new(transport, &mut buffer, 100, &mut recv_buffer, 100, config);
+        client.connect_to_broker().await?;
+        client.subscribe_to_topic("ws/test").await?;
+        client
+            .send_message("ws/test", b"over websocket", QualityOfService::QoS1, false)
+            .await?;
+        let (topic, payload) = client.receive_message().await?;
+        assert_eq!((topic, payload), ("ws/test", &b"over websocket"[..]));
+        client.disconnect().await
+    };
+
+    let (res, _) = tokio::time::timeout(Duration::from_secs(10), async {
+        tokio::join!(client, serve_websocket(&broker, listener, "mqtt"))
+    })
+    .await
+    .expect("Scenario timed out");
+    assert_ok!(res);
+}
+
+#[tokio::test]
+async fn websocket_refuses_other_subprotocol() {
+    let broker = TestBroker::new();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let client = async {
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let mut handshake_buffer = [0; 256];
+        WebSocketTransport::connect(
+            FromTokio::new(stream),
+            CountingRng(1),
+            "localhost",
+            "/mqtt",
+            &mut handshake_buffer,
+        )
+        .await
+        .map(|_| ())
+    };
+    let (res, _) = tokio::join!(client, serve_websocket(&broker, listener, "chat"));
+    assert!(matches!(res, Err(WebSocketError::Handshake)));
+}